@@ -0,0 +1,15 @@
+-- Each stored order as it stood after every change to it, for order book replays
+CREATE TABLE order_events (
+    seq BIGSERIAL PRIMARY KEY,
+    order_id UUID REFERENCES orders(id),
+    symbol VARCHAR(20) NOT NULL,
+    side order_side NOT NULL,
+    price DECIMAL,
+    quantity DECIMAL NOT NULL,
+    filled_quantity DECIMAL NOT NULL,
+    status order_status NOT NULL,
+    resting BOOLEAN NOT NULL,
+    occurred_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX order_events_symbol_time ON order_events (symbol, occurred_at);
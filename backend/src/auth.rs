@@ -0,0 +1,455 @@
+use std::future::Future;
+use std::pin::Pin;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{http::header, web, FromRequest, HttpMessage, HttpRequest};
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use crate::config::AdminConfig;
+use crate::errors::AppError;
+use crate::models::Scope;
+use crate::services::api_key_service::{ApiKeyService, SignedRequest};
+use crate::services::auth_service::{AuthService, Claims};
+
+pub const API_KEY_HEADER: &str = "X-API-KEY";
+pub const API_TIMESTAMP_HEADER: &str = "X-API-TIMESTAMP";
+pub const API_SIGNATURE_HEADER: &str = "X-API-SIGNATURE";
+pub const ADMIN_TOKEN_HEADER: &str = "X-ADMIN-TOKEN";
+
+/// The caller authenticated by a `Bearer` access token. Extracting it fails
+/// with `401` when the token is missing, invalid, expired or logged out.
+pub struct AuthUser {
+    pub user_id: Uuid,
+    pub claims: Claims,
+}
+
+impl AuthUser {
+    /// Fails with `403` unless the token was granted `scope`.
+    pub fn require(&self, scope: Scope) -> Result<(), AppError> {
+        require_scope(&self.claims.scopes, scope, "Access token")
+    }
+}
+
+fn require_scope(granted: &[Scope], scope: Scope, credential: &str) -> Result<(), AppError> {
+    if granted.contains(&scope) {
+        Ok(())
+    } else {
+        Err(AppError::Authorization(format!("{} lacks the {} scope", credential, scope)))
+    }
+}
+
+impl FromRequest for AuthUser {
+    type Error = AppError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let auth_service = req.app_data::<web::Data<AuthService>>().cloned();
+        let token = req.headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::to_string);
+
+        Box::pin(async move {
+            let auth_service = auth_service
+                .ok_or_else(|| AppError::Internal("Authentication is not configured".to_string()))?;
+            let token = token
+                .ok_or_else(|| AppError::Authentication("Missing bearer token".to_string()))?;
+
+            let claims = auth_service.verify_access_token(&token).await?;
+            Ok(AuthUser {
+                user_id: claims.sub,
+                claims,
+            })
+        })
+    }
+}
+
+/// Raw body of an API key request, kept for signature verification.
+#[derive(Clone)]
+struct SignedBody(web::Bytes);
+
+/// Buffers the body of requests carrying an API key so `ApiKeyUser` can check
+/// the signature over it, then hands the same body on to the handler.
+pub async fn buffer_signed_body(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if req.headers().contains_key(API_KEY_HEADER) {
+        let body = req.extract::<web::Bytes>().await?;
+        req.extensions_mut().insert(SignedBody(body.clone()));
+        req.set_payload(Payload::from(body));
+    }
+    next.call(req).await
+}
+
+/// The caller authenticated by an HMAC signed request: an API key, a
+/// millisecond timestamp and a signature over timestamp, method, path and
+/// body. Extracting it fails with `401` when any of them is missing or wrong,
+/// or the timestamp is outside the replay window.
+pub struct ApiKeyUser {
+    pub user_id: Uuid,
+    pub key_id: Uuid,
+    pub scopes: Vec<Scope>,
+}
+
+impl ApiKeyUser {
+    /// Fails with `403` unless the key was granted `scope`.
+    pub fn require(&self, scope: Scope) -> Result<(), AppError> {
+        require_scope(&self.scopes, scope, "API key")
+    }
+}
+
+impl FromRequest for ApiKeyUser {
+    type Error = AppError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let api_keys = req.app_data::<web::Data<ApiKeyService>>().cloned();
+        let header = |name: &str| req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let key = header(API_KEY_HEADER);
+        let timestamp = header(API_TIMESTAMP_HEADER);
+        let signature = header(API_SIGNATURE_HEADER);
+        let method = req.method().to_string();
+        let path = req.uri().path_and_query().map_or_else(|| req.path().to_string(), |path| path.to_string());
+        let body = req.extensions().get::<SignedBody>().map(|body| body.0.clone()).unwrap_or_default();
+
+        Box::pin(async move {
+            let api_keys = api_keys
+                .ok_or_else(|| AppError::Internal("API key authentication is not configured".to_string()))?;
+            let (Some(key), Some(timestamp), Some(signature)) = (key, timestamp, signature) else {
+                return Err(AppError::Authentication("Missing API key, timestamp or signature".to_string()));
+            };
+            let timestamp = timestamp.parse()
+                .map_err(|_| AppError::Authentication("Malformed request timestamp".to_string()))?;
+
+            let api_key = api_keys.verify_signature(&SignedRequest {
+                key: &key,
+                timestamp,
+                signature: &signature,
+                method: &method,
+                path: &path,
+                body: &body,
+            }, Utc::now().timestamp_millis()).await?;
+
+            Ok(ApiKeyUser {
+                user_id: api_key.user_id,
+                key_id: api_key.id,
+                scopes: api_key.scopes,
+            })
+        })
+    }
+}
+
+/// A caller authenticated either way: signed with an API key when the
+/// request carries one, by bearer token otherwise. Handlers check the scope
+/// they need with `require`.
+pub enum Caller {
+    User(AuthUser),
+    ApiKey(ApiKeyUser),
+}
+
+impl Caller {
+    pub fn user_id(&self) -> Uuid {
+        match self {
+            Caller::User(user) => user.user_id,
+            Caller::ApiKey(api_key) => api_key.user_id,
+        }
+    }
+
+    pub fn scopes(&self) -> &[Scope] {
+        match self {
+            Caller::User(user) => &user.claims.scopes,
+            Caller::ApiKey(api_key) => &api_key.scopes,
+        }
+    }
+
+    pub fn require(&self, scope: Scope) -> Result<(), AppError> {
+        match self {
+            Caller::User(user) => user.require(scope),
+            Caller::ApiKey(api_key) => api_key.require(scope),
+        }
+    }
+}
+
+impl FromRequest for Caller {
+    type Error = AppError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        if req.headers().contains_key(API_KEY_HEADER) {
+            let api_key = ApiKeyUser::from_request(req, payload);
+            Box::pin(async move { Ok(Caller::ApiKey(api_key.await?)) })
+        } else {
+            let user = AuthUser::from_request(req, payload);
+            Box::pin(async move { Ok(Caller::User(user.await?)) })
+        }
+    }
+}
+
+/// An operator holding the configured admin token. Extracting it fails with
+/// `401` when the token is missing or wrong, and `403` when no admin token is
+/// configured at all.
+pub struct Admin;
+
+impl FromRequest for Admin {
+    type Error = AppError;
+    type Future = std::future::Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let configured = req.app_data::<web::Data<AdminConfig>>().and_then(|config| config.token.clone());
+        let presented = req.headers()
+            .get(ADMIN_TOKEN_HEADER)
+            .and_then(|value| value.to_str().ok());
+
+        std::future::ready(match (configured, presented) {
+            (None, _) => Err(AppError::Authorization("Admin endpoints are disabled".to_string())),
+            (Some(_), None) => Err(AppError::Authentication("Missing admin token".to_string())),
+            // Compare digests so the comparison time doesn't depend on how much of the token matched
+            (Some(expected), Some(presented)) if Sha256::digest(&expected) == Sha256::digest(presented) => Ok(Admin),
+            (Some(_), Some(_)) => Err(AppError::Authentication("Invalid admin token".to_string())),
+        })
+    }
+}
+
+#[cfg(all(test, not(feature = "database")))]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, middleware, test, App};
+    use rust_decimal::Decimal;
+    use crate::config::{AccountConfig, FeeConfig, JwtConfig};
+    use crate::handlers;
+    use crate::handlers::orders::OrderQuery;
+    use crate::services::balance_service::BalanceService;
+    use crate::services::fee_service::FeeService;
+    use crate::services::order_service::{OrderService, OrderServiceBuilder};
+    use crate::services::password_service::PasswordService;
+    use crate::services::secret_cipher::SecretCipher;
+    use crate::services::totp_service::TotpService;
+
+    const JWT_SECRET: &str = "test-secret";
+    const ADMIN_TOKEN: &str = "operator";
+
+    struct Fixture {
+        api_keys: ApiKeyService,
+        balances: BalanceService,
+        orders: OrderService,
+        user_id: Uuid,
+    }
+
+    impl Fixture {
+        async fn new() -> Self {
+            let balances = BalanceService::new(AccountConfig { transfers_enabled: true, ..AccountConfig::default() });
+            let user_id = Uuid::new_v4();
+            balances.deposit(user_id, "USD", Decimal::new(1_000, 0)).await.unwrap();
+            let orders = OrderServiceBuilder::default().balances(balances.clone()).build();
+            Self {
+                api_keys: ApiKeyService::new(SecretCipher::new("test-key")),
+                balances,
+                orders,
+                user_id,
+            }
+        }
+
+        /// Places a resting buy for `user_id` straight through the service.
+        async fn place_order(&self, user_id: Uuid) -> Uuid {
+            let request = serde_json::from_str(ORDER).unwrap();
+            self.orders.create_order(user_id, request).await.unwrap().id
+        }
+
+        async fn status(&self, request: test::TestRequest) -> StatusCode {
+            let auth_service = AuthService::new(
+                PasswordService::new(Default::default()),
+                TotpService::new("test-key"),
+                JwtConfig {
+                    secret: JWT_SECRET.to_string(),
+                    expiration: 900,
+                    refresh_expiration: 3600,
+                },
+            );
+            let app = test::init_service(
+                App::new()
+                    .app_data(web::Data::new(AdminConfig { token: Some(ADMIN_TOKEN.to_string()), ..AdminConfig::default() }))
+                    .app_data(web::Data::new(self.api_keys.clone()))
+                    .app_data(web::Data::new(auth_service))
+                    .app_data(web::Data::new(self.balances.clone()))
+                    .app_data(web::Data::new(FeeService::new(FeeConfig::default())))
+                    .app_data(web::Data::new(self.orders.clone()))
+                    .app_data(web::Data::new(crate::config::PrecisionConfig::default()))
+                    .wrap(middleware::from_fn(buffer_signed_body))
+                    .configure(handlers::orders::configure)
+                    .configure(handlers::account::configure),
+            ).await;
+            test::call_service(&app, request.to_request()).await.status()
+        }
+
+        /// A request signed with a new key holding only `scopes`.
+        async fn signed(&self, scopes: Vec<Scope>, method: &str, path: &str, body: &str) -> test::TestRequest {
+            let (api_key, secret) = self.api_keys.create(self.user_id, scopes).await.unwrap();
+            let timestamp = Utc::now().timestamp_millis();
+            let signature = ApiKeyService::sign(&secret, timestamp, method, path, body.as_bytes());
+            let request = match method {
+                "GET" => test::TestRequest::get(),
+                "PUT" => test::TestRequest::put(),
+                _ => test::TestRequest::post(),
+            };
+            request
+                .uri(path)
+                .insert_header(("Content-Type", "application/json"))
+                .insert_header((API_KEY_HEADER, api_key.key))
+                .insert_header((API_TIMESTAMP_HEADER, timestamp.to_string()))
+                .insert_header((API_SIGNATURE_HEADER, signature))
+                .set_payload(body.to_string())
+        }
+    }
+
+    const ORDER: &str = r#"{"symbol":"BTC/USD","side":"Buy","order_type":"Limit","quantity":"1","price":"100"}"#;
+    const WITHDRAWAL: &str = r#"{"asset":"USD","amount":"10"}"#;
+
+    #[actix_web::test]
+    async fn test_read_scope_gate() {
+        let fixture = Fixture::new().await;
+        let denied = fixture.signed(vec![Scope::Trade], "GET", "/account/fees", "").await;
+        assert_eq!(fixture.status(denied).await, StatusCode::FORBIDDEN);
+
+        let allowed = fixture.signed(vec![Scope::Read], "GET", "/account/fees", "").await;
+        assert_eq!(fixture.status(allowed).await, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_trade_scope_gate() {
+        let fixture = Fixture::new().await;
+        let denied = fixture.signed(vec![Scope::Read, Scope::Withdraw], "POST", "/orders/orders", ORDER).await;
+        assert_eq!(fixture.status(denied).await, StatusCode::FORBIDDEN);
+
+        let allowed = fixture.signed(vec![Scope::Trade], "POST", "/orders/orders", ORDER).await;
+        assert_eq!(fixture.status(allowed).await, StatusCode::CREATED);
+    }
+
+    #[actix_web::test]
+    async fn test_order_reads_need_read_scope_and_ownership() {
+        let fixture = Fixture::new().await;
+        let own = fixture.place_order(fixture.user_id).await;
+        let someone_elses = fixture.place_order(Uuid::new_v4()).await;
+        let paths = |order_id: Uuid| [
+            format!("/orders/orders/{}", order_id),
+            format!("/orders/orders/{}/queue-position", order_id),
+            format!("/orders/orders/{}/trades", order_id),
+            format!("/orders/orders/{}/fills", order_id),
+        ];
+
+        for path in paths(own).iter().chain([&"/orders/orders".to_string()]) {
+            let denied = fixture.signed(vec![Scope::Trade], "GET", path, "").await;
+            assert_eq!(fixture.status(denied).await, StatusCode::FORBIDDEN, "{}", path);
+            let allowed = fixture.signed(vec![Scope::Read], "GET", path, "").await;
+            assert_eq!(fixture.status(allowed).await, StatusCode::OK, "{}", path);
+        }
+        for path in paths(someone_elses) {
+            let hidden = fixture.signed(vec![Scope::Read], "GET", &path, "").await;
+            assert_eq!(fixture.status(hidden).await, StatusCode::NOT_FOUND, "{}", path);
+        }
+        let query = OrderQuery { symbol: None, status: None, limit: None, offset: None };
+        let listed = fixture.orders.get_orders(fixture.user_id, &query).await.unwrap();
+        assert_eq!(listed.iter().map(|order| order.id).collect::<Vec<_>>(), vec![own]);
+    }
+
+    #[actix_web::test]
+    async fn test_cancel_and_reduce_need_trade_scope_and_ownership() {
+        let fixture = Fixture::new().await;
+        let own = fixture.place_order(fixture.user_id).await;
+        let someone_elses = fixture.place_order(Uuid::new_v4()).await;
+        let reduce = |order_id: Uuid| format!("/orders/orders/{}/reduce", order_id);
+        let cancel = |order_id: Uuid| format!("/orders/orders/{}/cancel", order_id);
+        const REDUCTION: &str = r#"{"quantity":"0.5"}"#;
+
+        let denied = fixture.signed(vec![Scope::Read], "PUT", &reduce(own), REDUCTION).await;
+        assert_eq!(fixture.status(denied).await, StatusCode::FORBIDDEN);
+        let denied = fixture.signed(vec![Scope::Read], "PUT", &cancel(own), "").await;
+        assert_eq!(fixture.status(denied).await, StatusCode::FORBIDDEN);
+
+        let hidden = fixture.signed(vec![Scope::Trade], "PUT", &reduce(someone_elses), REDUCTION).await;
+        assert_eq!(fixture.status(hidden).await, StatusCode::NOT_FOUND);
+        let hidden = fixture.signed(vec![Scope::Trade], "PUT", &cancel(someone_elses), "").await;
+        assert_eq!(fixture.status(hidden).await, StatusCode::NOT_FOUND);
+        assert!(matches!(fixture.orders.get_order(someone_elses).await.unwrap().status, crate::models::OrderStatus::Open));
+
+        let allowed = fixture.signed(vec![Scope::Trade], "PUT", &reduce(own), REDUCTION).await;
+        assert_eq!(fixture.status(allowed).await, StatusCode::OK);
+        let allowed = fixture.signed(vec![Scope::Trade], "PUT", &cancel(own), "").await;
+        assert_eq!(fixture.status(allowed).await, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_withdraw_scope_gate() {
+        let fixture = Fixture::new().await;
+        let denied = fixture.signed(vec![Scope::Read, Scope::Trade], "POST", "/account/withdraw", WITHDRAWAL).await;
+        assert_eq!(fixture.status(denied).await, StatusCode::FORBIDDEN);
+
+        let allowed = fixture.signed(vec![Scope::Withdraw], "POST", "/account/withdraw", WITHDRAWAL).await;
+        assert_eq!(fixture.status(allowed).await, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_access_token_scopes_are_enforced() {
+        let fixture = Fixture::new().await;
+        let now = Utc::now().timestamp();
+        let claims = Claims {
+            sub: fixture.user_id,
+            jti: Uuid::new_v4(),
+            iat: now,
+            exp: now + 900,
+            scopes: vec![Scope::Read],
+        };
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &claims,
+            &jsonwebtoken::EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+        ).unwrap();
+        let bearer = |request: test::TestRequest| request
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .insert_header(("Content-Type", "application/json"));
+
+        let fees = bearer(test::TestRequest::get().uri("/account/fees"));
+        assert_eq!(fixture.status(fees).await, StatusCode::OK);
+
+        let withdrawal = bearer(test::TestRequest::post().uri("/account/withdraw")).set_payload(WITHDRAWAL);
+        assert_eq!(fixture.status(withdrawal).await, StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn test_deposit_needs_admin_token() {
+        let fixture = Fixture::new().await;
+        let now = Utc::now().timestamp();
+        let claims = Claims {
+            sub: fixture.user_id,
+            jti: Uuid::new_v4(),
+            iat: now,
+            exp: now + 900,
+            scopes: vec![Scope::Read, Scope::Trade, Scope::Withdraw],
+        };
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &claims,
+            &jsonwebtoken::EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+        ).unwrap();
+        let deposit = || test::TestRequest::post()
+            .uri("/account/deposit")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(WITHDRAWAL);
+
+        assert_eq!(fixture.status(deposit()).await, StatusCode::UNAUTHORIZED);
+        let signed = fixture.signed(vec![Scope::Read, Scope::Trade, Scope::Withdraw], "POST", "/account/deposit", WITHDRAWAL).await;
+        assert_eq!(fixture.status(signed).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(fixture.balances.get_balance(fixture.user_id, "USD").await.available, Decimal::new(1_000, 0));
+
+        let operator = deposit().insert_header((ADMIN_TOKEN_HEADER, ADMIN_TOKEN));
+        assert_eq!(fixture.status(operator).await, StatusCode::OK);
+        assert_eq!(fixture.balances.get_balance(fixture.user_id, "USD").await.available, Decimal::new(1_010, 0));
+    }
+}
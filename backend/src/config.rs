@@ -1,16 +1,46 @@
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::env;
+use rust_decimal::{Decimal, RoundingStrategy};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use crate::models::MaintenanceWindow;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
     pub server: ServerConfig,
+    pub trading: TradingConfig,
+    #[serde(default)]
+    pub fees: FeeConfig,
+    pub account: AccountConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub persistence: PersistenceConfig,
+    #[serde(default)]
+    pub admin: AdminConfig,
+    #[serde(default)]
+    pub rate_limits: RateLimitConfig,
+    #[serde(default)]
+    pub precision: PrecisionConfig,
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    #[serde(default)]
+    pub rounding: RoundingConfig,
+    #[serde(default)]
+    pub seed: SeedConfig,
+    #[serde(default)]
+    pub risk: RiskConfig,
+    #[cfg(feature = "fix")]
+    #[serde(default)]
+    pub fix: FixConfig,
+    #[serde(default = "default_markets")]
+    pub markets: Vec<MarketConfig>,
     #[cfg(feature = "database")]
     pub database: DatabaseConfig,
     #[cfg(feature = "database")]
     pub redis: RedisConfig,
-    #[cfg(feature = "database")]
     pub jwt: JwtConfig,
-    #[cfg(feature = "database")]
     pub cors: CorsConfig,
 }
 
@@ -18,14 +48,444 @@ pub struct Config {
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
+    /// Seconds a request may run before it is abandoned with `504`.
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+}
+
+fn default_request_timeout_secs() -> u64 {
+    30
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TradingConfig {
+    /// Maximum number of simultaneously open orders per user across all symbols.
+    pub max_open_orders_per_user: usize,
+    /// Maximum number of simultaneously open orders per user on a single symbol.
+    pub max_open_orders_per_symbol: usize,
+    /// Store orders that fail validation with status `Rejected` for auditing.
+    pub persist_rejected_orders: bool,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AccountConfig {
+    /// Enables the deposit and withdrawal endpoints. Intended for test and
+    /// admin environments only, as there is no real custody integration.
+    pub transfers_enabled: bool,
+    /// Runs matching as usual but settles trades against virtual balances,
+    /// which users can reset. Real balances and the ledger are untouched.
+    #[serde(default)]
+    pub paper_trading: bool,
+    /// Asset -> Amount every user starts with, and returns to on reset, in
+    /// paper trading mode.
+    #[serde(default)]
+    pub paper_balances: HashMap<String, Decimal>,
+}
+
+/// Most decimal places accepted on order prices and quantities, whatever the
+/// market. Catches client bugs such as unrounded floats before they reach
+/// matching.
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct PrecisionConfig {
+    pub max_price_scale: u32,
+    pub max_quantity_scale: u32,
+}
+
+impl Default for PrecisionConfig {
+    fn default() -> Self {
+        Self {
+            max_price_scale: 8,
+            max_quantity_scale: 8,
+        }
+    }
+}
+
+/// How computed amounts are rounded, everywhere the exchange rounds them.
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct RoundingConfig {
+    #[serde(default)]
+    pub mode: RoundingMode,
+    /// Decimal places fees, settled notionals and converted prices keep.
+    pub amount_scale: u32,
+}
+
+impl Default for RoundingConfig {
+    fn default() -> Self {
+        Self {
+            mode: RoundingMode::default(),
+            amount_scale: 8,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundingMode {
+    /// Midpoints away from zero: 0.125 becomes 0.13.
+    HalfUp,
+    /// Midpoints to the even neighbour: 0.125 becomes 0.12, 0.135 becomes 0.14.
+    #[default]
+    HalfEven,
+    /// Towards zero: 0.129 becomes 0.12, and -0.129 becomes -0.12.
+    Down,
+}
+
+impl RoundingMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().replace('-', "_").as_str() {
+            "half_up" => Some(Self::HalfUp),
+            "half_even" => Some(Self::HalfEven),
+            "down" => Some(Self::Down),
+            _ => None,
+        }
+    }
+
+    pub fn strategy(self) -> RoundingStrategy {
+        match self {
+            Self::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            Self::HalfEven => RoundingStrategy::MidpointNearestEven,
+            Self::Down => RoundingStrategy::ToZero,
+        }
+    }
+
+    pub fn round(self, value: Decimal, decimal_places: u32) -> Decimal {
+        value.round_dp_with_strategy(decimal_places, self.strategy())
+    }
+}
+
+/// A maintenance window planned ahead, e.g. for a migration. One scheduled
+/// through the admin API takes precedence.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct MaintenanceConfig {
+    #[serde(default)]
+    pub start: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl MaintenanceConfig {
+    /// The configured window, when both ends are set.
+    pub fn window(&self) -> Option<MaintenanceWindow> {
+        Some(MaintenanceWindow {
+            start: self.start?,
+            end: self.end?,
+            reason: None,
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match (self.start, self.end) {
+            (Some(start), Some(end)) if end <= start => Err("maintenance.end must be after maintenance.start".to_string()),
+            (Some(_), None) | (None, Some(_)) => Err("maintenance.start and maintenance.end must be set together".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Resting orders to start the book from, e.g. when migrating from another
+/// venue. The file holds one book snapshot, or a list of them, in the format
+/// the admin freeze endpoint returns.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SeedConfig {
+    #[serde(default)]
+    pub snapshot_path: Option<String>,
+}
+
+/// Per-user risk limits, checked before an order reaches the book.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct RiskConfig {
+    #[serde(default)]
+    pub limits: Vec<RiskLimit>,
+}
+
+/// Limits for one user, one market, or one user in one market; an unset
+/// scope matches everything. Each limit is taken from the most specific
+/// entry that sets it, so a user's entry overrides the market's.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct RiskLimit {
+    #[serde(default)]
+    pub user_id: Option<Uuid>,
+    #[serde(default)]
+    pub symbol: Option<String>,
+    /// Most orders, including stops, open at once in the market.
+    #[serde(default)]
+    pub max_open_orders: Option<usize>,
+    /// Largest holding of the market's base asset, long or short, the user
+    /// could reach if every open order on one side filled.
+    #[serde(default)]
+    pub max_position: Option<Decimal>,
+    /// Largest price times quantity of a single order, in the quote asset.
+    #[serde(default)]
+    pub max_order_notional: Option<Decimal>,
+}
+
+/// The FIX order entry gateway, built with the `fix` feature.
+#[cfg(feature = "fix")]
+#[derive(Debug, Deserialize, Clone)]
+pub struct FixConfig {
+    /// TCP port the gateway listens on, on the server's host.
+    pub port: u16,
+    /// Our `SenderCompID` on outgoing messages.
+    pub sender_comp_id: String,
+}
+
+#[cfg(feature = "fix")]
+impl Default for FixConfig {
+    fn default() -> Self {
+        Self {
+            port: 9878,
+            sender_comp_id: "EXCHANGE".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AuthConfig {
+    /// Algorithm used for newly hashed passwords. Existing hashes keep
+    /// verifying with whichever algorithm produced them.
+    #[serde(default)]
+    pub password_hash_algorithm: PasswordHashAlgorithm,
+    /// Key that two-factor secrets are encrypted with at rest. Falls back to
+    /// the JWT secret when unset.
+    #[serde(default)]
+    pub totp_encryption_key: Option<String>,
+    /// Key that API key secrets are encrypted with at rest. Falls back to the
+    /// JWT secret when unset.
+    #[serde(default)]
+    pub api_key_encryption_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AdminConfig {
+    /// Shared token operators send in `X-ADMIN-TOKEN` to reach the admin
+    /// endpoints. The admin endpoints are disabled while it is unset.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Lets operators empty a book in memory through the admin API, for
+    /// integration tests and recovery drills. Leave it off in production.
+    #[serde(default)]
+    pub allow_book_reset: bool,
+}
+
+/// A token bucket: `burst` requests at once, refilled at `per_second`.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_second: u32,
+}
+
+/// Request limits per client for each class of route, so reads can't be
+/// starved by order flood protection and vice versa.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RateLimitConfig {
+    /// `GET` requests.
+    pub read: RateLimit,
+    /// Placing, cancelling and amending orders.
+    pub order: RateLimit,
+    /// Every other mutating request.
+    pub write: RateLimit,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            read: RateLimit { burst: 100, per_second: 50 },
+            order: RateLimit { burst: 20, per_second: 10 },
+            write: RateLimit { burst: 10, per_second: 5 },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PersistenceConfig {
+    /// Trades and order events written in one batch at most.
+    pub trade_batch_size: usize,
+    /// Longest a trade or order event waits for its batch to fill before being
+    /// written anyway.
+    pub trade_flush_interval_ms: u64,
+}
+
+impl Default for PersistenceConfig {
+    fn default() -> Self {
+        Self {
+            trade_batch_size: 500,
+            trade_flush_interval_ms: 5,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PasswordHashAlgorithm {
+    #[default]
+    Bcrypt,
+    Argon2,
+}
+
+impl PasswordHashAlgorithm {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "bcrypt" => Some(Self::Bcrypt),
+            "argon2" => Some(Self::Argon2),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct MarketConfig {
+    pub symbol: String,
+    /// Absolute lowest accepted limit price.
+    #[serde(default)]
+    pub price_floor: Option<Decimal>,
+    /// Absolute highest accepted limit price.
+    #[serde(default)]
+    pub price_ceiling: Option<Decimal>,
+    /// Dust threshold: fills smaller than this are never produced.
+    #[serde(default)]
+    pub min_trade_quantity: Option<Decimal>,
+    /// Cap on orders resting on the book. Once reached, orders that would
+    /// only rest are rejected until matching or cancellation frees space.
+    #[serde(default)]
+    pub max_resting_orders: Option<usize>,
+    /// Longest an order may stay open, in seconds. Orders still open this
+    /// long after they were placed are expired by the sweeper. Off when unset.
+    #[serde(default)]
+    pub default_order_ttl_secs: Option<u64>,
+    /// Decimal places prices are shown with in API responses, e.g. `2` shows
+    /// `1.5` as `1.50`. Stored prices keep their precision.
+    #[serde(default)]
+    pub price_scale: Option<u32>,
+    /// Decimal places quantities are shown with in API responses.
+    #[serde(default)]
+    pub quantity_scale: Option<u32>,
+    /// Asset fees are charged in, such as the base asset or a platform token,
+    /// converted at market prices. The quote asset when unset.
+    #[serde(default)]
+    pub fee_asset: Option<String>,
+    /// Accept zero and negative prices, for instruments such as spreads
+    /// that can trade below zero.
+    #[serde(default)]
+    pub allow_negative_price: bool,
+    /// Shortest time, in milliseconds, an order must rest before its owner
+    /// may cancel it, to deter flickering quotes. Off when unset.
+    #[serde(default)]
+    pub min_quote_life_ms: Option<u64>,
+    /// Price increments the order book may be grouped by for display, e.g.
+    /// `[0.5, 1, 10]`. The book can't be grouped when empty.
+    #[serde(default)]
+    pub book_groupings: Vec<Decimal>,
+}
+
+impl MarketConfig {
+    pub fn new(symbol: &str) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            price_floor: None,
+            price_ceiling: None,
+            min_trade_quantity: None,
+            max_resting_orders: None,
+            default_order_ttl_secs: None,
+            price_scale: None,
+            quantity_scale: None,
+            fee_asset: None,
+            allow_negative_price: false,
+            min_quote_life_ms: None,
+            book_groupings: Vec::new(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        crate::models::validate_symbol(&self.symbol).map_err(|error| format!("markets: {}", error))
+    }
+}
+
+pub fn default_markets() -> Vec<MarketConfig> {
+    ["BTC/USD", "ETH/USD", "ETH/BTC"]
+        .into_iter()
+        .map(MarketConfig::new)
+        .collect()
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct FeeConfig {
+    /// Volume tiers, ordered by ascending `min_volume_30d`.
+    pub tiers: Vec<FeeTier>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct FeeTier {
+    /// Minimum 30-day traded notional volume required to qualify for this tier.
+    pub min_volume_30d: Decimal,
+    pub maker_rate: Decimal,
+    pub taker_rate: Decimal,
+}
+
+impl Default for FeeConfig {
+    fn default() -> Self {
+        Self {
+            tiers: vec![
+                FeeTier {
+                    min_volume_30d: Decimal::ZERO,
+                    maker_rate: Decimal::new(10, 4),  // 0.10%
+                    taker_rate: Decimal::new(20, 4),  // 0.20%
+                },
+                FeeTier {
+                    min_volume_30d: Decimal::new(100_000, 0),
+                    maker_rate: Decimal::new(8, 4),   // 0.08%
+                    taker_rate: Decimal::new(16, 4),  // 0.16%
+                },
+                FeeTier {
+                    min_volume_30d: Decimal::new(1_000_000, 0),
+                    maker_rate: Decimal::new(5, 4),   // 0.05%
+                    taker_rate: Decimal::new(10, 4),  // 0.10%
+                },
+            ],
+        }
+    }
 }
 
 #[cfg(feature = "database")]
 #[derive(Debug, Deserialize, Clone)]
 pub struct DatabaseConfig {
     pub url: String,
+    /// Read replica for read-only queries; they use `url` when unset.
+    #[serde(default)]
+    pub replica_url: Option<String>,
     pub max_connections: u32,
     pub min_connections: u32,
+    /// Longest a query waits for a pooled connection before failing.
+    #[serde(default = "default_acquire_timeout_secs")]
+    pub acquire_timeout_secs: u64,
+    #[serde(default)]
+    pub connect_retry: ConnectRetryConfig,
+}
+
+#[cfg(feature = "database")]
+fn default_acquire_timeout_secs() -> u64 {
+    30
+}
+
+/// How startup keeps trying to reach a dependency that isn't up yet, as
+/// happens when containers start together.
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct ConnectRetryConfig {
+    /// Attempts before giving up, the first included.
+    pub max_attempts: u32,
+    /// Wait after the first failed attempt, doubling after each one after it.
+    pub initial_backoff_ms: u64,
+    /// Longest wait between two attempts.
+    pub max_backoff_ms: u64,
+}
+
+impl Default for ConnectRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 10_000,
+        }
+    }
 }
 
 #[cfg(feature = "database")]
@@ -34,19 +494,45 @@ pub struct RedisConfig {
     pub url: String,
 }
 
-#[cfg(feature = "database")]
 #[derive(Debug, Deserialize, Clone)]
 pub struct JwtConfig {
     pub secret: String,
+    /// Access token lifetime in seconds.
     pub expiration: u64,
+    /// Refresh token lifetime in seconds.
+    pub refresh_expiration: u64,
 }
 
-#[cfg(feature = "database")]
 #[derive(Debug, Deserialize, Clone)]
 pub struct CorsConfig {
+    /// Origins allowed to call the API, or `*` for any.
     pub allowed_origins: Vec<String>,
     pub allowed_methods: Vec<String>,
     pub allowed_headers: Vec<String>,
+    /// How long browsers may cache a preflight response, in seconds.
+    #[serde(default = "default_cors_max_age")]
+    pub max_age: usize,
+    /// Lets browsers send cookies and other credentials. Only valid with
+    /// explicitly listed origins, as browsers refuse credentials with `*`.
+    #[serde(default)]
+    pub allow_credentials: bool,
+}
+
+fn default_cors_max_age() -> usize {
+    3600
+}
+
+impl CorsConfig {
+    pub fn allows_any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|origin| origin == "*")
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.allow_credentials && self.allows_any_origin() {
+            return Err("cors.allow_credentials requires explicit cors.allowed_origins, not \"*\"".to_string());
+        }
+        Ok(())
+    }
 }
 
 impl Config {
@@ -56,16 +542,40 @@ impl Config {
             let config = config::Config::builder()
                 .set_default("server.host", "0.0.0.0")?
                 .set_default("server.port", 8080)?
+                .set_default("trading.max_open_orders_per_user", 200)?
+                .set_default("trading.max_open_orders_per_symbol", 50)?
+                .set_default("trading.persist_rejected_orders", false)?
+                .set_default("account.transfers_enabled", false)?
+                .set_default("auth.password_hash_algorithm", "bcrypt")?
+                .set_default("persistence.trade_batch_size", 500)?
+                .set_default("persistence.trade_flush_interval_ms", 5)?
+                .set_default("rate_limits.read.burst", 100)?
+                .set_default("rate_limits.read.per_second", 50)?
+                .set_default("rate_limits.order.burst", 20)?
+                .set_default("rate_limits.order.per_second", 10)?
+                .set_default("rate_limits.write.burst", 10)?
+                .set_default("rate_limits.write.per_second", 5)?
                 .set_default("database.max_connections", 10)?
                 .set_default("database.min_connections", 2)?
                 .set_default("jwt.expiration", 86400)?
+                .set_default("jwt.refresh_expiration", 2_592_000)?
                 .set_default("cors.allowed_origins", vec!["*"])?
                 .set_default("cors.allowed_methods", vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])?
                 .set_default("cors.allowed_headers", vec!["*"])?
-                .add_source(config::Environment::default().separator("__"))
+                .set_default("cors.max_age", 3600)?
+                .set_default("cors.allow_credentials", false)?
+                .add_source(
+                    config::Environment::default()
+                        .separator("__")
+                        .try_parsing(true)
+                        .list_separator(",")
+                        .with_list_parse_key("cors.allowed_origins")
+                        .with_list_parse_key("cors.allowed_methods")
+                        .with_list_parse_key("cors.allowed_headers"),
+                )
                 .build()?;
 
-            config.try_deserialize()
+            Self::validated(config.try_deserialize()?)
         }
 
         #[cfg(not(feature = "database"))]
@@ -73,12 +583,51 @@ impl Config {
             let config = config::Config::builder()
                 .set_default("server.host", "0.0.0.0")?
                 .set_default("server.port", 8080)?
-                .add_source(config::Environment::default().separator("__"))
+                .set_default("trading.max_open_orders_per_user", 200)?
+                .set_default("trading.max_open_orders_per_symbol", 50)?
+                .set_default("trading.persist_rejected_orders", false)?
+                .set_default("account.transfers_enabled", false)?
+                .set_default("auth.password_hash_algorithm", "bcrypt")?
+                .set_default("persistence.trade_batch_size", 500)?
+                .set_default("persistence.trade_flush_interval_ms", 5)?
+                .set_default("rate_limits.read.burst", 100)?
+                .set_default("rate_limits.read.per_second", 50)?
+                .set_default("rate_limits.order.burst", 20)?
+                .set_default("rate_limits.order.per_second", 10)?
+                .set_default("rate_limits.write.burst", 10)?
+                .set_default("rate_limits.write.per_second", 5)?
+                .set_default("jwt.secret", "insecure-mock-jwt-secret")?
+                .set_default("jwt.expiration", 86400)?
+                .set_default("jwt.refresh_expiration", 2_592_000)?
+                .set_default("cors.allowed_origins", vec!["*"])?
+                .set_default("cors.allowed_methods", vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])?
+                .set_default("cors.allowed_headers", vec!["*"])?
+                .set_default("cors.max_age", 3600)?
+                .set_default("cors.allow_credentials", false)?
+                .add_source(
+                    config::Environment::default()
+                        .separator("__")
+                        .try_parsing(true)
+                        .list_separator(",")
+                        .with_list_parse_key("cors.allowed_origins")
+                        .with_list_parse_key("cors.allowed_methods")
+                        .with_list_parse_key("cors.allowed_headers"),
+                )
                 .build()?;
 
-            config.try_deserialize()
+            Self::validated(config.try_deserialize()?)
         }
     }
+
+    /// Rejects settings that can't work together, so they fail at startup.
+    fn validated(config: Config) -> Result<Self, config::ConfigError> {
+        config.cors.validate().map_err(config::ConfigError::Message)?;
+        config.maintenance.validate().map_err(config::ConfigError::Message)?;
+        for market in &config.markets {
+            market.validate().map_err(config::ConfigError::Message)?;
+        }
+        Ok(config)
+    }
 }
 
 impl From<config::Config> for Config {
@@ -89,11 +638,76 @@ impl From<config::Config> for Config {
                 server: ServerConfig {
                     host: config.get_string("server.host").unwrap_or_else(|_| "0.0.0.0".to_string()),
                     port: config.get_int("server.port").unwrap_or(8080) as u16,
+                    request_timeout_secs: config.get_int("server.request_timeout_secs").unwrap_or(30) as u64,
+                },
+                trading: TradingConfig {
+                    max_open_orders_per_user: config.get_int("trading.max_open_orders_per_user").unwrap_or(200) as usize,
+                    max_open_orders_per_symbol: config.get_int("trading.max_open_orders_per_symbol").unwrap_or(50) as usize,
+                    persist_rejected_orders: config.get_bool("trading.persist_rejected_orders").unwrap_or(false),
+                },
+                fees: FeeConfig::default(),
+                markets: default_markets(),
+                account: AccountConfig {
+                    transfers_enabled: config.get_bool("account.transfers_enabled").unwrap_or(false),
+                    paper_trading: config.get_bool("account.paper_trading").unwrap_or(false),
+                    paper_balances: config.get("account.paper_balances").unwrap_or_default(),
+                },
+                auth: AuthConfig {
+                    password_hash_algorithm: config.get_string("auth.password_hash_algorithm").ok()
+                        .and_then(|value| PasswordHashAlgorithm::parse(&value))
+                        .unwrap_or_default(),
+                    totp_encryption_key: config.get_string("auth.totp_encryption_key").ok(),
+                    api_key_encryption_key: config.get_string("auth.api_key_encryption_key").ok(),
+                },
+                persistence: PersistenceConfig {
+                    trade_batch_size: config.get_int("persistence.trade_batch_size").unwrap_or(500) as usize,
+                    trade_flush_interval_ms: config.get_int("persistence.trade_flush_interval_ms").unwrap_or(5) as u64,
+                },
+                admin: AdminConfig {
+                    token: config.get_string("admin.token").ok(),
+                    allow_book_reset: config.get_bool("admin.allow_book_reset").unwrap_or(false),
+                },
+                rate_limits: RateLimitConfig {
+                    read: rate_limit(&config, "read", RateLimitConfig::default().read),
+                    order: rate_limit(&config, "order", RateLimitConfig::default().order),
+                    write: rate_limit(&config, "write", RateLimitConfig::default().write),
+                },
+                precision: PrecisionConfig {
+                    max_price_scale: config.get_int("precision.max_price_scale")
+                        .map_or(PrecisionConfig::default().max_price_scale, |scale| scale as u32),
+                    max_quantity_scale: config.get_int("precision.max_quantity_scale")
+                        .map_or(PrecisionConfig::default().max_quantity_scale, |scale| scale as u32),
+                },
+                maintenance: MaintenanceConfig {
+                    start: config.get_string("maintenance.start").ok().and_then(|start| start.parse().ok()),
+                    end: config.get_string("maintenance.end").ok().and_then(|end| end.parse().ok()),
+                },
+                rounding: RoundingConfig {
+                    mode: config.get_string("rounding.mode").ok()
+                        .and_then(|mode| RoundingMode::parse(&mode))
+                        .unwrap_or_default(),
+                    amount_scale: config.get_int("rounding.amount_scale")
+                        .map_or(RoundingConfig::default().amount_scale, |scale| scale as u32),
+                },
+                seed: SeedConfig {
+                    snapshot_path: config.get_string("seed.snapshot_path").ok(),
+                },
+                risk: RiskConfig {
+                    limits: config.get("risk.limits").unwrap_or_default(),
+                },
+                #[cfg(feature = "fix")]
+                fix: FixConfig {
+                    port: config.get_int("fix.port").map_or(FixConfig::default().port, |port| port as u16),
+                    sender_comp_id: config.get_string("fix.sender_comp_id")
+                        .unwrap_or_else(|_| FixConfig::default().sender_comp_id),
                 },
                 database: DatabaseConfig {
                     url: env::var("DATABASE_URL").expect("DATABASE_URL must be set"),
+                    replica_url: config.get_string("database.replica_url").ok(),
                     max_connections: config.get_int("database.max_connections").unwrap_or(10) as u32,
                     min_connections: config.get_int("database.min_connections").unwrap_or(2) as u32,
+                    acquire_timeout_secs: config.get_int("database.acquire_timeout_secs").map_or(default_acquire_timeout_secs(), |secs| secs as u64),
+                    connect_retry: config.get("database.connect_retry").unwrap_or_default(),
                 },
                 redis: RedisConfig {
                     url: env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".to_string()),
@@ -101,24 +715,9 @@ impl From<config::Config> for Config {
                 jwt: JwtConfig {
                     secret: env::var("JWT_SECRET").expect("JWT_SECRET must be set"),
                     expiration: config.get_int("jwt.expiration").unwrap_or(86400) as u64,
+                    refresh_expiration: config.get_int("jwt.refresh_expiration").unwrap_or(2_592_000) as u64,
                 },
-                cors: CorsConfig {
-                    allowed_origins: config.get_array("cors.allowed_origins")
-                        .unwrap_or_else(|_| vec![config::Value::from("*")])
-                        .into_iter()
-                        .filter_map(|v| v.into_string().ok())
-                        .collect(),
-                    allowed_methods: config.get_array("cors.allowed_methods")
-                        .unwrap_or_else(|_| vec![config::Value::from("GET"), config::Value::from("POST")])
-                        .into_iter()
-                        .filter_map(|v| v.into_string().ok())
-                        .collect(),
-                    allowed_headers: config.get_array("cors.allowed_headers")
-                        .unwrap_or_else(|_| vec![config::Value::from("*")])
-                        .into_iter()
-                        .filter_map(|v| v.into_string().ok())
-                        .collect(),
-                },
+                cors: cors_config(&config),
             }
         }
 
@@ -128,8 +727,131 @@ impl From<config::Config> for Config {
                 server: ServerConfig {
                     host: config.get_string("server.host").unwrap_or_else(|_| "0.0.0.0".to_string()),
                     port: config.get_int("server.port").unwrap_or(8080) as u16,
+                    request_timeout_secs: config.get_int("server.request_timeout_secs").unwrap_or(30) as u64,
+                },
+                trading: TradingConfig {
+                    max_open_orders_per_user: config.get_int("trading.max_open_orders_per_user").unwrap_or(200) as usize,
+                    max_open_orders_per_symbol: config.get_int("trading.max_open_orders_per_symbol").unwrap_or(50) as usize,
+                    persist_rejected_orders: config.get_bool("trading.persist_rejected_orders").unwrap_or(false),
+                },
+                fees: FeeConfig::default(),
+                markets: default_markets(),
+                account: AccountConfig {
+                    transfers_enabled: config.get_bool("account.transfers_enabled").unwrap_or(false),
+                    paper_trading: config.get_bool("account.paper_trading").unwrap_or(false),
+                    paper_balances: config.get("account.paper_balances").unwrap_or_default(),
+                },
+                auth: AuthConfig {
+                    password_hash_algorithm: config.get_string("auth.password_hash_algorithm").ok()
+                        .and_then(|value| PasswordHashAlgorithm::parse(&value))
+                        .unwrap_or_default(),
+                    totp_encryption_key: config.get_string("auth.totp_encryption_key").ok(),
+                    api_key_encryption_key: config.get_string("auth.api_key_encryption_key").ok(),
+                },
+                persistence: PersistenceConfig {
+                    trade_batch_size: config.get_int("persistence.trade_batch_size").unwrap_or(500) as usize,
+                    trade_flush_interval_ms: config.get_int("persistence.trade_flush_interval_ms").unwrap_or(5) as u64,
+                },
+                admin: AdminConfig {
+                    token: config.get_string("admin.token").ok(),
+                    allow_book_reset: config.get_bool("admin.allow_book_reset").unwrap_or(false),
+                },
+                rate_limits: RateLimitConfig {
+                    read: rate_limit(&config, "read", RateLimitConfig::default().read),
+                    order: rate_limit(&config, "order", RateLimitConfig::default().order),
+                    write: rate_limit(&config, "write", RateLimitConfig::default().write),
+                },
+                precision: PrecisionConfig {
+                    max_price_scale: config.get_int("precision.max_price_scale")
+                        .map_or(PrecisionConfig::default().max_price_scale, |scale| scale as u32),
+                    max_quantity_scale: config.get_int("precision.max_quantity_scale")
+                        .map_or(PrecisionConfig::default().max_quantity_scale, |scale| scale as u32),
+                },
+                maintenance: MaintenanceConfig {
+                    start: config.get_string("maintenance.start").ok().and_then(|start| start.parse().ok()),
+                    end: config.get_string("maintenance.end").ok().and_then(|end| end.parse().ok()),
+                },
+                rounding: RoundingConfig {
+                    mode: config.get_string("rounding.mode").ok()
+                        .and_then(|mode| RoundingMode::parse(&mode))
+                        .unwrap_or_default(),
+                    amount_scale: config.get_int("rounding.amount_scale")
+                        .map_or(RoundingConfig::default().amount_scale, |scale| scale as u32),
+                },
+                seed: SeedConfig {
+                    snapshot_path: config.get_string("seed.snapshot_path").ok(),
                 },
+                risk: RiskConfig {
+                    limits: config.get("risk.limits").unwrap_or_default(),
+                },
+                #[cfg(feature = "fix")]
+                fix: FixConfig {
+                    port: config.get_int("fix.port").map_or(FixConfig::default().port, |port| port as u16),
+                    sender_comp_id: config.get_string("fix.sender_comp_id")
+                        .unwrap_or_else(|_| FixConfig::default().sender_comp_id),
+                },
+                jwt: JwtConfig {
+                    secret: env::var("JWT_SECRET").unwrap_or_else(|_| "insecure-mock-jwt-secret".to_string()),
+                    expiration: config.get_int("jwt.expiration").unwrap_or(86400) as u64,
+                    refresh_expiration: config.get_int("jwt.refresh_expiration").unwrap_or(2_592_000) as u64,
+                },
+                cors: cors_config(&config),
             }
         }
     }
-} 
\ No newline at end of file
+} 
+
+fn rate_limit(config: &config::Config, class: &str, default: RateLimit) -> RateLimit {
+    RateLimit {
+        burst: config.get_int(&format!("rate_limits.{}.burst", class)).map_or(default.burst, |burst| burst as u32),
+        per_second: config.get_int(&format!("rate_limits.{}.per_second", class)).map_or(default.per_second, |rate| rate as u32),
+    }
+}
+
+fn cors_config(config: &config::Config) -> CorsConfig {
+    let strings = |key: &str, default: &[&str]| config.get_array(key)
+        .unwrap_or_else(|_| default.iter().map(|v| config::Value::from(*v)).collect())
+        .into_iter()
+        .filter_map(|v| v.into_string().ok())
+        .collect();
+    CorsConfig {
+        allowed_origins: strings("cors.allowed_origins", &["*"]),
+        allowed_methods: strings("cors.allowed_methods", &["GET", "POST"]),
+        allowed_headers: strings("cors.allowed_headers", &["*"]),
+        max_age: config.get_int("cors.max_age").map_or_else(|_| default_cors_max_age(), |max_age| max_age as usize),
+        allow_credentials: config.get_bool("cors.allow_credentials").unwrap_or(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cors(origins: &[&str], allow_credentials: bool) -> CorsConfig {
+        CorsConfig {
+            allowed_origins: origins.iter().map(|origin| origin.to_string()).collect(),
+            allowed_methods: vec!["GET".to_string()],
+            allowed_headers: vec!["*".to_string()],
+            max_age: 600,
+            allow_credentials,
+        }
+    }
+
+    #[test]
+    fn test_credentials_with_wildcard_origin_is_invalid() {
+        assert!(cors(&["*"], true).validate().is_err());
+        assert!(cors(&["https://app.example.com", "*"], true).validate().is_err());
+
+        assert!(cors(&["https://app.example.com"], true).validate().is_ok());
+        assert!(cors(&["*"], false).validate().is_ok());
+    }
+
+    #[test]
+    fn test_market_needs_distinct_base_and_quote() {
+        assert!(MarketConfig::new("BTC/BTC").validate().is_err());
+        assert!(MarketConfig::new("btc/BTC").validate().is_err());
+        assert!(MarketConfig::new("BTCUSD").validate().is_err());
+        assert!(MarketConfig::new("/USD").validate().is_err());
+        assert!(default_markets().iter().all(|market| market.validate().is_ok()));
+    }
+}
@@ -0,0 +1,102 @@
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderMap, HeaderValue};
+use actix_web::middleware::Next;
+use crate::errors::AppError;
+
+pub const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+
+/// Media types under which a client can ask for MessagePack.
+const MSGPACK_MEDIA_TYPES: &[&str] = &[MSGPACK_CONTENT_TYPE, "application/x-msgpack"];
+
+/// Whether `headers` ask for MessagePack over JSON. Responses stay JSON
+/// unless a MessagePack type is accepted.
+fn wants_msgpack(headers: &HeaderMap) -> bool {
+    headers.get_all(header::ACCEPT)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|media_type| media_type.split(';').next())
+        .any(|media_type| MSGPACK_MEDIA_TYPES.iter().any(|msgpack| media_type.trim().eq_ignore_ascii_case(msgpack)))
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers.get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"))
+}
+
+/// Re-encodes JSON responses as MessagePack for clients that send
+/// `Accept: application/msgpack`. Every value keeps its JSON form, so ids,
+/// decimals and timestamps are strings and maps are keyed by field name.
+/// Other responses, such as event streams, pass through untouched.
+pub async fn negotiate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let msgpack = wants_msgpack(req.headers());
+    let response = next.call(req).await?;
+    if !msgpack || !is_json(response.headers()) {
+        return Ok(response.map_into_boxed_body());
+    }
+
+    let (req, response) = response.into_parts();
+    let (mut response, body) = response.into_parts();
+    let json = body::to_bytes(body).await
+        .map_err(|_| AppError::Internal("Failed to read response body".to_string()))?;
+    let value: serde_json::Value = serde_json::from_slice(&json)
+        .map_err(|error| AppError::Internal(format!("Response body is not valid JSON: {}", error)))?;
+    let encoded = rmp_serde::to_vec_named(&value)
+        .map_err(|error| AppError::Internal(format!("Failed to encode response as MessagePack: {}", error)))?;
+
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(MSGPACK_CONTENT_TYPE));
+    headers.remove(header::CONTENT_LENGTH);
+    headers.insert(header::VARY, HeaderValue::from_static("Accept"));
+    Ok(ServiceResponse::new(req, response.set_body(encoded).map_into_boxed_body()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{middleware, test, web, App, HttpResponse};
+    use chrono::Utc;
+    use rust_decimal::Decimal;
+    use crate::models::{OrderBook, OrderBookEntry};
+
+    async fn order_book() -> HttpResponse {
+        HttpResponse::Ok().json(OrderBook {
+            symbol: "BTC/USD".to_string(),
+            bids: vec![OrderBookEntry { price: Decimal::new(10050, 2), quantity: Decimal::new(2, 0), order_count: 3 }],
+            asks: Vec::new(),
+            last_updated: Utc::now(),
+            requested_depth: None,
+            returned_depth: None,
+        })
+    }
+
+    #[actix_web::test]
+    async fn test_msgpack_is_returned_when_accepted() {
+        let app = test::init_service(
+            App::new()
+                .wrap(middleware::from_fn(negotiate))
+                .route("/book", web::get().to(order_book)),
+        ).await;
+
+        let request = test::TestRequest::get()
+            .uri("/book")
+            .insert_header((header::ACCEPT, "application/msgpack, application/json;q=0.5"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.headers().get(header::CONTENT_TYPE).unwrap(), MSGPACK_CONTENT_TYPE);
+        let book: OrderBook = rmp_serde::from_slice(&test::read_body(response).await).unwrap();
+        assert_eq!(book.symbol, "BTC/USD");
+        assert_eq!((book.bids[0].price, book.bids[0].quantity, book.bids[0].order_count), (Decimal::new(10050, 2), Decimal::new(2, 0), 3));
+        assert!(book.asks.is_empty());
+
+        // Without asking for it, the response stays JSON
+        let response = test::call_service(&app, test::TestRequest::get().uri("/book").to_request()).await;
+        assert_eq!(response.headers().get(header::CONTENT_TYPE).unwrap(), "application/json");
+        let book: OrderBook = test::read_body_json(response).await;
+        assert_eq!(book.bids[0].price, Decimal::new(10050, 2));
+    }
+}
@@ -35,9 +35,21 @@ pub enum AppError {
     
     #[error("Not found: {0}")]
     NotFound(String),
+
+    #[error("Gone: {0}")]
+    Gone(String),
     
     #[error("Bad request: {0}")]
     BadRequest(String),
+
+    #[error("Rate limited: {0}")]
+    RateLimited(String),
+
+    #[error("Service unavailable: {0}")]
+    Unavailable(String),
+
+    #[error("Timed out: {0}")]
+    Timeout(String),
 }
 
 #[derive(Serialize)]
@@ -91,10 +103,26 @@ impl ResponseError for AppError {
                 actix_web::http::StatusCode::NOT_FOUND,
                 msg.clone(),
             ),
+            AppError::Gone(msg) => (
+                actix_web::http::StatusCode::GONE,
+                msg.clone(),
+            ),
             AppError::BadRequest(msg) => (
                 actix_web::http::StatusCode::BAD_REQUEST,
                 msg.clone(),
             ),
+            AppError::RateLimited(msg) => (
+                actix_web::http::StatusCode::TOO_MANY_REQUESTS,
+                msg.clone(),
+            ),
+            AppError::Unavailable(msg) => (
+                actix_web::http::StatusCode::SERVICE_UNAVAILABLE,
+                msg.clone(),
+            ),
+            AppError::Timeout(msg) => (
+                actix_web::http::StatusCode::GATEWAY_TIMEOUT,
+                msg.clone(),
+            ),
         };
 
         HttpResponse::build(status_code).json(ErrorResponse {
@@ -122,8 +150,26 @@ impl From<bcrypt::BcryptError> for AppError {
     }
 }
 
+impl From<argon2::password_hash::Error> for AppError {
+    fn from(error: argon2::password_hash::Error) -> Self {
+        AppError::Internal(format!("Password hashing error: {}", error))
+    }
+}
+
 impl From<serde_json::Error> for AppError {
     fn from(error: serde_json::Error) -> Self {
         AppError::BadRequest(format!("JSON error: {}", error))
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+
+    #[test]
+    fn test_timeout_is_a_gateway_timeout() {
+        let response = AppError::Timeout("Request took longer than 30000ms".to_string()).error_response();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+}
@@ -0,0 +1,658 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rust_decimal::Decimal;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+use crate::config::{FixConfig, PrecisionConfig};
+use crate::models::{CreateOrderRequest, OrderResponse, OrderSide, OrderStatus, OrderType, Scope};
+use crate::services::api_key_service::{ApiKeyService, SignedRequest};
+use crate::services::order_service::OrderService;
+
+const BEGIN_STRING: &str = "FIX.4.4";
+const SOH: u8 = 0x01;
+/// Largest message accepted. A client sending more is disconnected.
+const MAX_MESSAGE_BYTES: usize = 8 * 1024;
+/// `SendingTime` layout, with optional milliseconds.
+const SENDING_TIME_FORMAT: &str = "%Y%m%d-%H:%M:%S%.f";
+/// What a Logon's `Password` signs, after the timestamp and before the
+/// `SenderCompID`, in the API key request signature scheme.
+const LOGON_SIGNATURE_METHOD: &str = "FIX";
+const LOGON_SIGNATURE_PATH: &str = "/logon";
+
+mod tag {
+    pub const AVG_PX: u32 = 6;
+    pub const BEGIN_STRING: u32 = 8;
+    pub const BODY_LENGTH: u32 = 9;
+    pub const CHECK_SUM: u32 = 10;
+    pub const CL_ORD_ID: u32 = 11;
+    pub const CUM_QTY: u32 = 14;
+    pub const EXEC_ID: u32 = 17;
+    pub const EXEC_INST: u32 = 18;
+    pub const MSG_SEQ_NUM: u32 = 34;
+    pub const MSG_TYPE: u32 = 35;
+    pub const ORDER_ID: u32 = 37;
+    pub const ORDER_QTY: u32 = 38;
+    pub const ORD_STATUS: u32 = 39;
+    pub const ORD_TYPE: u32 = 40;
+    pub const ORIG_CL_ORD_ID: u32 = 41;
+    pub const PRICE: u32 = 44;
+    pub const REF_SEQ_NUM: u32 = 45;
+    pub const SENDER_COMP_ID: u32 = 49;
+    pub const SENDING_TIME: u32 = 52;
+    pub const SIDE: u32 = 54;
+    pub const SYMBOL: u32 = 55;
+    pub const TARGET_COMP_ID: u32 = 56;
+    pub const TEXT: u32 = 58;
+    pub const TRANSACT_TIME: u32 = 60;
+    pub const ENCRYPT_METHOD: u32 = 98;
+    pub const STOP_PX: u32 = 99;
+    pub const ORD_REJ_REASON: u32 = 103;
+    pub const HEART_BT_INT: u32 = 108;
+    pub const TEST_REQ_ID: u32 = 112;
+    pub const EXEC_TYPE: u32 = 150;
+    pub const LEAVES_QTY: u32 = 151;
+    pub const PEG_OFFSET_VALUE: u32 = 211;
+    pub const REF_MSG_TYPE: u32 = 372;
+    pub const CXL_REJ_RESPONSE_TO: u32 = 434;
+    pub const USERNAME: u32 = 553;
+    pub const PASSWORD: u32 = 554;
+}
+
+mod msg_type {
+    pub const HEARTBEAT: &str = "0";
+    pub const TEST_REQUEST: &str = "1";
+    pub const REJECT: &str = "3";
+    pub const LOGOUT: &str = "5";
+    pub const EXECUTION_REPORT: &str = "8";
+    pub const ORDER_CANCEL_REJECT: &str = "9";
+    pub const LOGON: &str = "A";
+    pub const NEW_ORDER_SINGLE: &str = "D";
+    pub const ORDER_CANCEL_REQUEST: &str = "F";
+}
+
+/// A FIX message: its type and every other field in order, without the
+/// `BeginString`, `BodyLength` and `CheckSum` framing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    msg_type: String,
+    fields: Vec<(u32, String)>,
+}
+
+impl Message {
+    fn new(msg_type: &str) -> Self {
+        Self {
+            msg_type: msg_type.to_string(),
+            fields: Vec::new(),
+        }
+    }
+
+    fn with(mut self, tag: u32, value: impl ToString) -> Self {
+        self.fields.push((tag, value.to_string()));
+        self
+    }
+
+    /// Replaces the value of `tag`, or adds it.
+    fn set(mut self, tag: u32, value: impl ToString) -> Self {
+        match self.fields.iter_mut().find(|(field, _)| *field == tag) {
+            Some((_, existing)) => *existing = value.to_string(),
+            None => self.fields.push((tag, value.to_string())),
+        }
+        self
+    }
+
+    pub fn msg_type(&self) -> &str {
+        &self.msg_type
+    }
+
+    pub fn get(&self, tag: u32) -> Option<&str> {
+        self.fields.iter().find(|(field, _)| *field == tag).map(|(_, value)| value.as_str())
+    }
+
+    fn require(&self, tag: u32) -> Result<&str, String> {
+        self.get(tag).ok_or_else(|| format!("Missing tag {}", tag))
+    }
+
+    /// Frames the message with its length and checksum.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = format!("{}={}\x01", tag::MSG_TYPE, self.msg_type);
+        for (tag, value) in &self.fields {
+            body.push_str(&format!("{}={}\x01", tag, value));
+        }
+        let mut message = format!("{}={}\x01{}={}\x01{}", tag::BEGIN_STRING, BEGIN_STRING, tag::BODY_LENGTH, body.len(), body)
+            .into_bytes();
+        let checksum = message.iter().map(|byte| *byte as u32).sum::<u32>() % 256;
+        message.extend_from_slice(format!("{}={:03}\x01", tag::CHECK_SUM, checksum).as_bytes());
+        message
+    }
+
+    /// Parses one whole framed message, checking its length and checksum.
+    pub fn decode(frame: &[u8]) -> Result<Self, String> {
+        let text = std::str::from_utf8(frame).map_err(|_| "Message is not UTF-8".to_string())?;
+        let mut fields = Vec::new();
+        for field in text.strip_suffix('\x01').ok_or("Message must end with SOH")?.split('\x01') {
+            let (tag, value) = field.split_once('=').ok_or_else(|| format!("Malformed field {}", field))?;
+            let tag = tag.parse::<u32>().map_err(|_| format!("Malformed tag {}", tag))?;
+            fields.push((tag, value.to_string()));
+        }
+
+        let checksum_at = frame.len().checked_sub(7).ok_or("Message too short")?;
+        let checksum = frame[..checksum_at].iter().map(|byte| *byte as u32).sum::<u32>() % 256;
+        let header_len = text.find("\x0135=").ok_or("MsgType must follow BodyLength")? + 1;
+        match (fields.first(), fields.get(1), fields.get(2), fields.last()) {
+            (
+                Some((tag::BEGIN_STRING, begin)),
+                Some((tag::BODY_LENGTH, length)),
+                Some((tag::MSG_TYPE, msg_type)),
+                Some((tag::CHECK_SUM, sent_checksum)),
+            ) => {
+                if begin != BEGIN_STRING {
+                    return Err(format!("Unsupported BeginString {}", begin));
+                }
+                if length.parse::<usize>().ok() != Some(checksum_at - header_len) {
+                    return Err("BodyLength does not match the message".to_string());
+                }
+                if sent_checksum.parse::<u32>().ok() != Some(checksum) {
+                    return Err("CheckSum does not match the message".to_string());
+                }
+                Ok(Self {
+                    msg_type: msg_type.clone(),
+                    fields: fields[3..fields.len() - 1].to_vec(),
+                })
+            }
+            _ => Err("Message must start with BeginString, BodyLength and MsgType and end with CheckSum".to_string()),
+        }
+    }
+}
+
+/// Length of the first message in `buffer` once it has all arrived.
+fn frame_len(buffer: &[u8]) -> Result<Option<usize>, String> {
+    let prefix = format!("{}={}\x01{}=", tag::BEGIN_STRING, BEGIN_STRING, tag::BODY_LENGTH);
+    let prefix = prefix.as_bytes();
+    if buffer.len() < prefix.len() {
+        return if prefix.starts_with(buffer) { Ok(None) } else { Err("Expected a FIX.4.4 message".to_string()) };
+    }
+    if !buffer.starts_with(prefix) {
+        return Err("Expected a FIX.4.4 message".to_string());
+    }
+
+    let Some(length_end) = buffer[prefix.len()..].iter().position(|byte| *byte == SOH) else {
+        return if buffer.len() > prefix.len() + 6 { Err("Malformed BodyLength".to_string()) } else { Ok(None) };
+    };
+    let body_start = prefix.len() + length_end + 1;
+    let body_length: usize = std::str::from_utf8(&buffer[prefix.len()..body_start - 1]).ok()
+        .and_then(|length| length.parse().ok())
+        .ok_or("Malformed BodyLength")?;
+    // "10=nnn" and its SOH follow the body
+    let total = body_start + body_length + 7;
+    if total > MAX_MESSAGE_BYTES {
+        return Err(format!("Messages are limited to {} bytes", MAX_MESSAGE_BYTES));
+    }
+    Ok((buffer.len() >= total).then_some(total))
+}
+
+fn parse_sending_time(value: &str) -> Result<DateTime<Utc>, String> {
+    NaiveDateTime::parse_from_str(value, SENDING_TIME_FORMAT)
+        .map(|time| time.and_utc())
+        .map_err(|_| format!("Malformed SendingTime {}", value))
+}
+
+fn ord_status(status: &OrderStatus) -> &'static str {
+    match status {
+        OrderStatus::New | OrderStatus::Open => "0",
+        OrderStatus::PartiallyFilled => "1",
+        OrderStatus::Filled => "2",
+        OrderStatus::Cancelled => "4",
+        OrderStatus::Rejected => "8",
+        OrderStatus::Expired => "C",
+    }
+}
+
+/// `ExecType` of the report sent back for a newly placed order.
+fn placed_exec_type(status: &OrderStatus) -> &'static str {
+    match status {
+        OrderStatus::PartiallyFilled | OrderStatus::Filled => "F",
+        status => ord_status(status),
+    }
+}
+
+fn side_code(side: &OrderSide) -> &'static str {
+    match side {
+        OrderSide::Buy => "1",
+        OrderSide::Sell => "2",
+    }
+}
+
+fn ord_type_code(order_type: &OrderType) -> &'static str {
+    match order_type {
+        OrderType::Market => "1",
+        OrderType::Limit => "2",
+        OrderType::Stop => "3",
+        OrderType::StopLimit => "4",
+        OrderType::Pegged => "P",
+    }
+}
+
+fn decimal(message: &Message, tag: u32) -> Result<Option<Decimal>, String> {
+    message.get(tag)
+        .map(|value| value.parse().map_err(|_| format!("Tag {} must be a number", tag)))
+        .transpose()
+}
+
+/// The order a NewOrderSingle asks for.
+fn order_request(message: &Message) -> Result<CreateOrderRequest, String> {
+    let side = match message.require(tag::SIDE)? {
+        "1" => OrderSide::Buy,
+        "2" => OrderSide::Sell,
+        side => return Err(format!("Unsupported Side {}", side)),
+    };
+    let order_type = match message.require(tag::ORD_TYPE)? {
+        "1" => OrderType::Market,
+        "2" => OrderType::Limit,
+        "3" => OrderType::Stop,
+        "4" => OrderType::StopLimit,
+        "P" => OrderType::Pegged,
+        order_type => return Err(format!("Unsupported OrdType {}", order_type)),
+    };
+
+    Ok(CreateOrderRequest {
+        symbol: message.require(tag::SYMBOL)?.to_string(),
+        side,
+        quantity: decimal(message, tag::ORDER_QTY)?.ok_or_else(|| format!("Missing tag {}", tag::ORDER_QTY))?,
+        price: decimal(message, tag::PRICE)?,
+        order_type,
+        // ExecInst G is all-or-none
+        all_or_none: message.get(tag::EXEC_INST).is_some_and(|inst| inst.split(' ').any(|inst| inst == "G")),
+        stop_price: decimal(message, tag::STOP_PX)?,
+        trigger_symbol: None,
+        peg_offset: decimal(message, tag::PEG_OFFSET_VALUE)?,
+        protection_price: None,
+        client_order_id: Some(message.require(tag::CL_ORD_ID)?.to_string()),
+        metadata: None,
+    })
+}
+
+fn execution_report(order: &OrderResponse, exec_type: &str, average_price: Option<Decimal>) -> Message {
+    let leaves = if order.status.is_open() { order.quantity - order.filled_quantity } else { Decimal::ZERO };
+    let mut report = Message::new(msg_type::EXECUTION_REPORT)
+        .with(tag::ORDER_ID, order.id)
+        .with(tag::CL_ORD_ID, order.client_order_id.as_deref().unwrap_or_default())
+        .with(tag::EXEC_ID, Uuid::new_v4())
+        .with(tag::EXEC_TYPE, exec_type)
+        .with(tag::ORD_STATUS, ord_status(&order.status))
+        .with(tag::SYMBOL, &order.symbol)
+        .with(tag::SIDE, side_code(&order.side))
+        .with(tag::ORD_TYPE, ord_type_code(&order.order_type))
+        .with(tag::ORDER_QTY, order.quantity);
+    if let Some(price) = order.price {
+        report = report.with(tag::PRICE, price);
+    }
+    report = report
+        .with(tag::CUM_QTY, order.filled_quantity)
+        .with(tag::LEAVES_QTY, leaves)
+        .with(tag::AVG_PX, average_price.unwrap_or_default())
+        .with(tag::TRANSACT_TIME, Utc::now().format("%Y%m%d-%H:%M:%S%.3f"));
+    match &order.reject_reason {
+        Some(reason) => report.with(tag::TEXT, reason),
+        None => report,
+    }
+}
+
+/// An ExecutionReport for a NewOrderSingle that never became an order.
+fn rejected_order(request: &Message, reason: &str) -> Message {
+    let mut report = Message::new(msg_type::EXECUTION_REPORT)
+        .with(tag::ORDER_ID, "NONE")
+        .with(tag::CL_ORD_ID, request.get(tag::CL_ORD_ID).unwrap_or_default())
+        .with(tag::EXEC_ID, Uuid::new_v4())
+        .with(tag::EXEC_TYPE, "8")
+        .with(tag::ORD_STATUS, "8");
+    for tag in [tag::SYMBOL, tag::SIDE, tag::ORD_TYPE, tag::ORDER_QTY] {
+        if let Some(value) = request.get(tag) {
+            report = report.with(tag, value);
+        }
+    }
+    report
+        .with(tag::CUM_QTY, 0)
+        .with(tag::LEAVES_QTY, 0)
+        .with(tag::AVG_PX, 0)
+        .with(tag::ORD_REJ_REASON, 99)
+        .with(tag::TEXT, reason)
+}
+
+/// A session-level Reject of `message`.
+fn reject(message: &Message, reason: &str) -> Message {
+    Message::new(msg_type::REJECT)
+        .with(tag::REF_SEQ_NUM, message.get(tag::MSG_SEQ_NUM).unwrap_or("0"))
+        .with(tag::REF_MSG_TYPE, message.msg_type())
+        .with(tag::TEXT, reason)
+}
+
+fn logout(reason: &str) -> Message {
+    Message::new(msg_type::LOGOUT).with(tag::TEXT, reason)
+}
+
+/// One client connection: who logged on, and our outgoing sequence.
+#[derive(Debug, Default)]
+struct Session {
+    user_id: Option<Uuid>,
+    counterparty: String, // Their SenderCompID, our TargetCompID
+    sent: u64, // Last MsgSeqNum we sent
+}
+
+impl Session {
+    /// Adds the standard header to `message` and frames it.
+    fn encode(&mut self, message: Message, sender_comp_id: &str) -> Vec<u8> {
+        self.sent += 1;
+        let mut header = Message::new(&message.msg_type)
+            .with(tag::SENDER_COMP_ID, sender_comp_id)
+            .with(tag::TARGET_COMP_ID, &self.counterparty)
+            .with(tag::MSG_SEQ_NUM, self.sent)
+            .with(tag::SENDING_TIME, Utc::now().format("%Y%m%d-%H:%M:%S%.3f"));
+        header.fields.extend(message.fields);
+        header.encode()
+    }
+}
+
+/// A minimal FIX 4.4 order entry gateway in front of `OrderService`. Clients
+/// log on with an API key holding the trade scope, then place orders with
+/// NewOrderSingle and cancel them by ClOrdID with OrderCancelRequest; each is
+/// answered with an ExecutionReport, or an OrderCancelReject. Fills of
+/// resting orders are not pushed, and incoming sequence numbers are not
+/// checked or replayed.
+#[derive(Clone)]
+pub struct FixGateway {
+    orders: OrderService,
+    api_keys: ApiKeyService,
+    precision: PrecisionConfig,
+    sender_comp_id: String,
+}
+
+impl FixGateway {
+    pub fn new(orders: OrderService, api_keys: ApiKeyService, config: FixConfig, precision: PrecisionConfig) -> Self {
+        Self {
+            orders,
+            api_keys,
+            precision,
+            sender_comp_id: config.sender_comp_id,
+        }
+    }
+
+    /// Serves sessions on `listener` until `shutdown`, which logs every
+    /// session out.
+    pub async fn run(self, listener: TcpListener, shutdown: CancellationToken) {
+        loop {
+            let (stream, peer) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(error) => {
+                        tracing::warn!(%error, "FIX gateway failed to accept a connection");
+                        continue;
+                    }
+                },
+                _ = shutdown.cancelled() => break,
+            };
+            let gateway = self.clone();
+            let shutdown = shutdown.clone();
+            actix_rt::spawn(async move {
+                match gateway.serve(stream, shutdown).await {
+                    Ok(()) => tracing::info!(%peer, "FIX session closed"),
+                    Err(error) => tracing::info!(%peer, %error, "FIX session dropped"),
+                }
+            });
+        }
+    }
+
+    async fn serve(&self, mut stream: TcpStream, shutdown: CancellationToken) -> Result<(), String> {
+        let mut session = Session::default();
+        let mut buffer = Vec::new();
+        let mut chunk = [0u8; 4096];
+        loop {
+            while let Some(len) = frame_len(&buffer)? {
+                let frame: Vec<u8> = buffer.drain(..len).collect();
+                // Garbled messages are ignored, as FIX asks
+                let message = match Message::decode(&frame) {
+                    Ok(message) => message,
+                    Err(error) => {
+                        tracing::debug!(%error, "Ignoring garbled FIX message");
+                        continue;
+                    }
+                };
+                if session.counterparty.is_empty() {
+                    session.counterparty = message.get(tag::SENDER_COMP_ID).unwrap_or_default().to_string();
+                }
+
+                let replies = self.handle(&mut session, &message).await;
+                let done = replies.last().is_some_and(|reply| reply.msg_type() == msg_type::LOGOUT);
+                for reply in replies {
+                    stream.write_all(&session.encode(reply, &self.sender_comp_id)).await.map_err(|e| e.to_string())?;
+                }
+                if done {
+                    return Ok(());
+                }
+            }
+
+            let read = tokio::select! {
+                read = stream.read(&mut chunk) => read.map_err(|e| e.to_string())?,
+                _ = shutdown.cancelled() => {
+                    let goodbye = session.encode(logout("Server shutting down"), &self.sender_comp_id);
+                    return stream.write_all(&goodbye).await.map_err(|e| e.to_string());
+                }
+            };
+            if read == 0 {
+                return Ok(());
+            }
+            buffer.extend_from_slice(&chunk[..read]);
+        }
+    }
+
+    /// The replies to `message`. A Logout last ends the session.
+    async fn handle(&self, session: &mut Session, message: &Message) -> Vec<Message> {
+        let Some(user_id) = session.user_id else {
+            if message.msg_type() != msg_type::LOGON {
+                return vec![logout("Logon first")];
+            }
+            return match self.logon(message).await {
+                Ok(user_id) => {
+                    session.user_id = Some(user_id);
+                    vec![Message::new(msg_type::LOGON)
+                        .with(tag::ENCRYPT_METHOD, 0)
+                        .with(tag::HEART_BT_INT, message.get(tag::HEART_BT_INT).unwrap_or("30"))]
+                }
+                Err(error) => vec![logout(&error)],
+            };
+        };
+
+        match message.msg_type() {
+            msg_type::NEW_ORDER_SINGLE => vec![self.new_order(user_id, message).await],
+            msg_type::ORDER_CANCEL_REQUEST => vec![self.cancel(user_id, message).await],
+            msg_type::TEST_REQUEST => vec![Message::new(msg_type::HEARTBEAT)
+                .with(tag::TEST_REQ_ID, message.get(tag::TEST_REQ_ID).unwrap_or_default())],
+            msg_type::HEARTBEAT => Vec::new(),
+            msg_type::LOGOUT => vec![Message::new(msg_type::LOGOUT)],
+            msg_type::LOGON => vec![reject(message, "Already logged on")],
+            _ => vec![reject(message, "Unsupported message type")],
+        }
+    }
+
+    /// Checks a Logon's credentials: `Username` is an API key with the trade
+    /// scope, and `Password` the key's signature of the `SendingTime` in epoch
+    /// milliseconds, `FIX`, `/logon` and the `SenderCompID`, concatenated.
+    async fn logon(&self, message: &Message) -> Result<Uuid, String> {
+        let sender_comp_id = message.require(tag::SENDER_COMP_ID)?;
+        let sent_at = parse_sending_time(message.require(tag::SENDING_TIME)?)?;
+        let api_key = self.api_keys.verify_signature(&SignedRequest {
+            key: message.require(tag::USERNAME)?,
+            timestamp: sent_at.timestamp_millis(),
+            signature: message.require(tag::PASSWORD)?,
+            method: LOGON_SIGNATURE_METHOD,
+            path: LOGON_SIGNATURE_PATH,
+            body: sender_comp_id.as_bytes(),
+        }, Utc::now().timestamp_millis()).await.map_err(|e| e.to_string())?;
+
+        if !api_key.scopes.contains(&Scope::Trade) {
+            return Err("The API key lacks the trade scope".to_string());
+        }
+        Ok(api_key.user_id)
+    }
+
+    async fn new_order(&self, user_id: Uuid, message: &Message) -> Message {
+        let request = match order_request(message) {
+            Ok(request) => {
+                let allow_negative_price = self.orders.allows_negative_price(&request.symbol).await;
+                request.validate_with(allow_negative_price)
+                    .and_then(|()| request.validate_precision(&self.precision))
+                    .map(|()| request)
+            }
+            Err(error) => Err(error),
+        };
+        let placed = match request {
+            Ok(request) => self.orders.create_order(user_id, request).await.map_err(|e| e.to_string()),
+            Err(error) => Err(error),
+        };
+
+        match placed {
+            Ok(order) => {
+                let average_price = self.average_price(&order).await;
+                execution_report(&order, placed_exec_type(&order.status), average_price)
+            }
+            Err(error) => rejected_order(message, &error),
+        }
+    }
+
+    /// Cancels the user's order with the request's `OrigClOrdID`.
+    async fn cancel(&self, user_id: Uuid, message: &Message) -> Message {
+        let orig_cl_ord_id = message.get(tag::ORIG_CL_ORD_ID).unwrap_or_default();
+        let cancelled = match self.orders.get_order_by_client_id(user_id, orig_cl_ord_id).await {
+            Ok(order) => self.orders.cancel_order(order.id).await,
+            Err(error) => Err(error),
+        };
+
+        match cancelled {
+            Ok(order) => {
+                let average_price = self.average_price(&order).await;
+                execution_report(&order, "4", average_price)
+                    .set(tag::CL_ORD_ID, message.get(tag::CL_ORD_ID).unwrap_or_default())
+                    .with(tag::ORIG_CL_ORD_ID, orig_cl_ord_id)
+            }
+            Err(error) => Message::new(msg_type::ORDER_CANCEL_REJECT)
+                .with(tag::ORDER_ID, "NONE")
+                .with(tag::CL_ORD_ID, message.get(tag::CL_ORD_ID).unwrap_or_default())
+                .with(tag::ORIG_CL_ORD_ID, orig_cl_ord_id)
+                .with(tag::ORD_STATUS, "8")
+                .with(tag::CXL_REJ_RESPONSE_TO, 1)
+                .with(tag::TEXT, error.to_string()),
+        }
+    }
+
+    async fn average_price(&self, order: &OrderResponse) -> Option<Decimal> {
+        if order.filled_quantity.is_zero() {
+            return None;
+        }
+        self.orders.get_order_fills(order.id).await.ok().and_then(|fills| fills.average_price)
+    }
+}
+
+#[cfg(all(test, not(feature = "database")))]
+mod tests {
+    use super::*;
+    use crate::services::order_service::OrderServiceBuilder;
+    use crate::services::secret_cipher::SecretCipher;
+
+    /// A message as a client would type it, `|` standing for SOH, framed with
+    /// its length and checksum.
+    fn raw(fields: &str) -> Vec<u8> {
+        let body = format!("{}|", fields).replace('|', "\x01");
+        let mut message = format!("8=FIX.4.4\x019={}\x01{}", body.len(), body).into_bytes();
+        let checksum = message.iter().map(|byte| *byte as u32).sum::<u32>() % 256;
+        message.extend_from_slice(format!("10={:03}\x01", checksum).as_bytes());
+        message
+    }
+
+    async fn exchange(stream: &mut TcpStream, buffer: &mut Vec<u8>, request: &[u8]) -> Message {
+        stream.write_all(request).await.unwrap();
+        loop {
+            if let Some(len) = frame_len(buffer).unwrap() {
+                let frame: Vec<u8> = buffer.drain(..len).collect();
+                return Message::decode(&frame).unwrap();
+            }
+            let mut chunk = [0u8; 1024];
+            let read = tokio::time::timeout(std::time::Duration::from_secs(5), stream.read(&mut chunk))
+                .await
+                .expect("no reply in time")
+                .unwrap();
+            assert!(read > 0, "session closed");
+            buffer.extend_from_slice(&chunk[..read]);
+        }
+    }
+
+    #[test]
+    fn test_messages_round_trip_and_bad_checksums_fail() {
+        let message = Message::new(msg_type::HEARTBEAT).with(tag::TEST_REQ_ID, "ping");
+        let encoded = message.encode();
+        assert_eq!(frame_len(&encoded), Ok(Some(encoded.len())));
+        assert_eq!(frame_len(&encoded[..encoded.len() - 1]), Ok(None));
+        assert_eq!(Message::decode(&encoded), Ok(message));
+
+        let mut corrupted = encoded.clone();
+        let at = corrupted.len() - 3;
+        corrupted[at] = if corrupted[at] == b'0' { b'1' } else { b'0' };
+        assert!(Message::decode(&corrupted).unwrap_err().contains("CheckSum"));
+        assert!(frame_len(b"8=FIX.4.2\x019=5\x01").is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_new_order_single_is_answered_with_execution_report() {
+        let orders = OrderServiceBuilder::default().build();
+        let api_keys = ApiKeyService::new(SecretCipher::new("fix-test-key"));
+        let (api_key, secret) = api_keys.create(Uuid::new_v4(), vec![Scope::Trade]).await.unwrap();
+        let gateway = FixGateway::new(orders, api_keys, FixConfig::default(), PrecisionConfig::default());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let shutdown = CancellationToken::new();
+        actix_rt::spawn(gateway.run(listener, shutdown.clone()));
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let mut buffer = Vec::new();
+
+        // Orders before logon end the session
+        let order = raw("35=D|49=CLIENT|56=EXCHANGE|34=1|52=20260101-00:00:00|11=c1|55=BTC/USD|54=1|38=1|40=2|44=100");
+        let reply = exchange(&mut stream, &mut buffer, &order).await;
+        assert_eq!(reply.msg_type(), msg_type::LOGOUT);
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let mut buffer = Vec::new();
+        let now = Utc::now();
+        let signature = ApiKeyService::sign(&secret, now.timestamp_millis(), LOGON_SIGNATURE_METHOD, LOGON_SIGNATURE_PATH, b"CLIENT");
+        let logon = raw(&format!(
+            "35=A|49=CLIENT|56=EXCHANGE|34=1|52={}|98=0|108=30|553={}|554={}",
+            now.format("%Y%m%d-%H:%M:%S%.3f"), api_key.key, signature,
+        ));
+        let reply = exchange(&mut stream, &mut buffer, &logon).await;
+        assert_eq!(reply.msg_type(), msg_type::LOGON);
+        assert_eq!(reply.get(tag::TARGET_COMP_ID), Some("CLIENT"));
+
+        let reply = exchange(&mut stream, &mut buffer, &order).await;
+        assert_eq!(reply.msg_type(), msg_type::EXECUTION_REPORT);
+        assert_eq!(reply.get(tag::CL_ORD_ID), Some("c1"));
+        assert_eq!(reply.get(tag::EXEC_TYPE), Some("0"));
+        assert_eq!(reply.get(tag::ORD_STATUS), Some("0"));
+        assert_eq!(reply.get(tag::LEAVES_QTY), Some("1"));
+        let order_id = reply.get(tag::ORDER_ID).unwrap().to_string();
+
+        let invalid = raw("35=D|49=CLIENT|56=EXCHANGE|34=3|52=20260101-00:00:00|11=c2|55=BTC/USD|54=1|38=-1|40=2|44=100");
+        let reply = exchange(&mut stream, &mut buffer, &invalid).await;
+        assert_eq!(reply.get(tag::ORD_STATUS), Some("8"));
+        assert!(reply.get(tag::TEXT).is_some());
+
+        let cancel = raw("35=F|49=CLIENT|56=EXCHANGE|34=4|52=20260101-00:00:00|11=c3|41=c1|55=BTC/USD|54=1");
+        let reply = exchange(&mut stream, &mut buffer, &cancel).await;
+        assert_eq!(reply.msg_type(), msg_type::EXECUTION_REPORT);
+        assert_eq!(reply.get(tag::ORDER_ID), Some(order_id.as_str()));
+        assert_eq!(reply.get(tag::ORD_STATUS), Some("4"));
+        assert_eq!(reply.get(tag::ORIG_CL_ORD_ID), Some("c1"));
+
+        let reply = exchange(&mut stream, &mut buffer, &cancel).await;
+        assert_eq!(reply.msg_type(), msg_type::ORDER_CANCEL_REJECT);
+        shutdown.cancel();
+    }
+}
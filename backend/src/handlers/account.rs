@@ -0,0 +1,127 @@
+use actix_web::{web, HttpResponse, get, post, delete};
+use uuid::Uuid;
+use crate::auth::{Admin, AuthUser, Caller};
+use crate::errors::AppError;
+use crate::models::{ApiKeyResponse, CreateApiKeyRequest, CreatedApiKeyResponse, Scope, TransferRequest};
+use crate::services::api_key_service::ApiKeyService;
+use crate::services::balance_service::BalanceService;
+use crate::services::fee_service::FeeService;
+use crate::services::order_service::OrderService;
+
+#[get("/fees")]
+pub async fn get_fees(
+    caller: Caller,
+    fee_service: web::Data<FeeService>,
+) -> Result<HttpResponse, AppError> {
+    caller.require(Scope::Read)?;
+
+    let user_id = caller.user_id();
+    let fees = fee_service.account_fees(user_id).await;
+    Ok(HttpResponse::Ok().json(fees))
+}
+
+#[get("/summary")]
+pub async fn get_summary(
+    caller: Caller,
+    order_service: web::Data<OrderService>,
+) -> Result<HttpResponse, AppError> {
+    caller.require(Scope::Read)?;
+
+    let summary = order_service.account_summary(caller.user_id()).await?;
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+/// Credits the user's account. Real custody is out of scope, so deposits are
+/// reserved to operators and tests holding the admin token.
+#[post("/deposit")]
+pub async fn deposit(
+    _admin: Admin,
+    user: AuthUser,
+    request: web::Json<TransferRequest>,
+    balance_service: web::Data<BalanceService>,
+) -> Result<HttpResponse, AppError> {
+    request.validate().map_err(AppError::Validation)?;
+
+    let user_id = user.user_id;
+    let balance = balance_service.deposit(user_id, &request.asset, request.amount).await?;
+    Ok(HttpResponse::Ok().json(balance))
+}
+
+#[post("/withdraw")]
+pub async fn withdraw(
+    caller: Caller,
+    request: web::Json<TransferRequest>,
+    balance_service: web::Data<BalanceService>,
+) -> Result<HttpResponse, AppError> {
+    caller.require(Scope::Withdraw)?;
+    request.validate().map_err(AppError::Validation)?;
+
+    let user_id = caller.user_id();
+    let balance = balance_service.withdraw(user_id, &request.asset, request.amount).await?;
+    Ok(HttpResponse::Ok().json(balance))
+}
+
+/// Puts the caller's virtual balances back to their starting amounts. Only
+/// available when the exchange runs in paper trading mode.
+#[post("/reset")]
+pub async fn reset_balances(
+    caller: Caller,
+    balance_service: web::Data<BalanceService>,
+) -> Result<HttpResponse, AppError> {
+    caller.require(Scope::Trade)?;
+
+    let balances = balance_service.reset_paper_balances(caller.user_id()).await?;
+    Ok(HttpResponse::Ok().json(balances))
+}
+
+/// Creates an API key. The response is the only time its secret is returned.
+#[post("/api-keys")]
+pub async fn create_api_key(
+    user: AuthUser,
+    request: web::Json<CreateApiKeyRequest>,
+    api_key_service: web::Data<ApiKeyService>,
+) -> Result<HttpResponse, AppError> {
+    request.validate().map_err(AppError::Validation)?;
+
+    let (api_key, secret) = api_key_service.create(user.user_id, request.into_inner().scopes).await?;
+    Ok(HttpResponse::Created().json(CreatedApiKeyResponse {
+        api_key: api_key.into(),
+        secret,
+    }))
+}
+
+#[get("/api-keys")]
+pub async fn list_api_keys(
+    user: AuthUser,
+    api_key_service: web::Data<ApiKeyService>,
+) -> Result<HttpResponse, AppError> {
+    let api_keys: Vec<ApiKeyResponse> = api_key_service.list(user.user_id).await?
+        .into_iter()
+        .map(ApiKeyResponse::from)
+        .collect();
+    Ok(HttpResponse::Ok().json(api_keys))
+}
+
+#[delete("/api-keys/{id}")]
+pub async fn revoke_api_key(
+    user: AuthUser,
+    path: web::Path<Uuid>,
+    api_key_service: web::Data<ApiKeyService>,
+) -> Result<HttpResponse, AppError> {
+    let api_key = api_key_service.revoke(user.user_id, path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiKeyResponse::from(api_key)))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/account")
+            .service(get_fees)
+            .service(get_summary)
+            .service(deposit)
+            .service(withdraw)
+            .service(reset_balances)
+            .service(create_api_key)
+            .service(list_api_keys)
+            .service(revoke_api_key)
+    );
+}
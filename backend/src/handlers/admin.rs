@@ -0,0 +1,258 @@
+use actix_web::{web, HttpResponse, delete, get, post, put};
+use serde::Deserialize;
+use crate::auth::Admin;
+use crate::config::AdminConfig;
+use crate::errors::AppError;
+use crate::handlers::market::SymbolQuery;
+use crate::logging::LogLevel;
+use crate::models::{LogLevelRequest, MaintenanceRequest, MaintenanceWindow};
+use crate::services::maintenance_service::MaintenanceService;
+use crate::services::market_service::MarketService;
+use crate::services::order_book_service::OrderBookService;
+use crate::services::order_service::OrderService;
+
+/// Swaps the log filter without a restart, e.g. to turn on debug logging
+/// for the matching engine while investigating an incident.
+#[put("/log-level")]
+pub async fn set_log_level(
+    _admin: Admin,
+    request: web::Json<LogLevelRequest>,
+    log_level: web::Data<LogLevel>,
+) -> Result<HttpResponse, AppError> {
+    log_level.set(&request.filter)?;
+    tracing::info!(filter = %request.filter, "Log filter changed");
+
+    Ok(HttpResponse::Ok().json(LogLevelRequest {
+        filter: log_level.current()?,
+    }))
+}
+
+/// Every resting order of a symbol's book, as it stood at one instant.
+#[get("/order-book/freeze")]
+pub async fn freeze_order_book(
+    _admin: Admin,
+    query: web::Query<SymbolQuery>,
+    markets: web::Data<MarketService>,
+    order_book: web::Data<OrderBookService>,
+) -> Result<HttpResponse, AppError> {
+    markets.require_market(&query.symbol).await?;
+    let frozen = order_book.freeze(&query.symbol).await;
+    tracing::info!(symbol = %frozen.symbol, sequence = frozen.sequence, "Order book frozen for audit");
+
+    Ok(HttpResponse::Ok().json(frozen))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayQuery {
+    pub symbol: String,
+    pub from: chrono::DateTime<chrono::Utc>,
+    pub to: chrono::DateTime<chrono::Utc>,
+}
+
+/// A symbol's book rebuilt from the order event journal over a window of at
+/// most an hour, for debugging and backtesting.
+#[get("/order-book/replay")]
+pub async fn replay_order_book(
+    _admin: Admin,
+    query: web::Query<ReplayQuery>,
+    markets: web::Data<MarketService>,
+    order_service: web::Data<OrderService>,
+) -> Result<HttpResponse, AppError> {
+    markets.require_market(&query.symbol).await?;
+    let replay = order_service.replay_book(&query.symbol, query.from, query.to).await?;
+
+    Ok(HttpResponse::Ok().json(replay))
+}
+
+/// Delists a market: it stops taking orders, every open order on it is
+/// cancelled and its book is emptied. Its history stays readable.
+#[delete("/markets/{symbol:.+}")]
+pub async fn delist_market(
+    _admin: Admin,
+    symbol: web::Path<String>,
+    order_service: web::Data<OrderService>,
+) -> Result<HttpResponse, AppError> {
+    let delisting = order_service.delist_market(&symbol).await?;
+    tracing::warn!(symbol = %delisting.symbol, cancelled = delisting.cancelled_orders.len(), "Market delisted");
+
+    Ok(HttpResponse::Ok().json(delisting))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetBookQuery {
+    /// Cancel the cleared orders on record too.
+    #[serde(default)]
+    pub cancel: bool,
+}
+
+/// Empties a symbol's book in memory, for integration tests and recovery
+/// drills. Refused with `403` unless `admin.allow_book_reset` is set.
+#[post("/orderbook/{symbol:.+}/clear")]
+pub async fn reset_order_book(
+    _admin: Admin,
+    symbol: web::Path<String>,
+    query: web::Query<ResetBookQuery>,
+    admin_config: web::Data<AdminConfig>,
+    order_service: web::Data<OrderService>,
+) -> Result<HttpResponse, AppError> {
+    if !admin_config.allow_book_reset {
+        return Err(AppError::Authorization("Order book reset is disabled".to_string()));
+    }
+    let reset = order_service.reset_book(&symbol, query.cancel).await?;
+    tracing::warn!(symbol = %reset.symbol, cleared = reset.cleared_orders.len(), cancelled = reset.cancelled, "Order book reset");
+
+    Ok(HttpResponse::Ok().json(reset))
+}
+
+/// Recomputes what every user should have locked from their open orders and
+/// lists the balances that disagree. Any entry points to a hold that was
+/// taken or released wrongly as an order changed.
+#[get("/balances/reconcile")]
+pub async fn reconcile_balances(
+    _admin: Admin,
+    order_service: web::Data<OrderService>,
+) -> Result<HttpResponse, AppError> {
+    let reconciliation = order_service.reconcile_balances().await?;
+    if !reconciliation.discrepancies.is_empty() {
+        tracing::error!(discrepancies = reconciliation.discrepancies.len(), "Locked balances disagree with open orders");
+    }
+
+    Ok(HttpResponse::Ok().json(reconciliation))
+}
+
+/// The scheduled maintenance window, current or not, or `null`.
+#[get("/maintenance")]
+pub async fn get_maintenance(
+    _admin: Admin,
+    maintenance: web::Data<MaintenanceService>,
+) -> Result<HttpResponse, AppError> {
+    Ok(HttpResponse::Ok().json(maintenance.current().await))
+}
+
+/// Schedules a window during which writes are refused, e.g. for a migration.
+#[put("/maintenance")]
+pub async fn schedule_maintenance(
+    _admin: Admin,
+    request: web::Json<MaintenanceRequest>,
+    maintenance: web::Data<MaintenanceService>,
+) -> Result<HttpResponse, AppError> {
+    let request = request.into_inner();
+    let window = maintenance.schedule(MaintenanceWindow {
+        start: request.start.unwrap_or_else(chrono::Utc::now),
+        end: request.end,
+        reason: request.reason,
+    }).await?;
+    tracing::warn!(start = %window.start, end = %window.end, "Maintenance window scheduled");
+
+    Ok(HttpResponse::Ok().json(window))
+}
+
+/// Ends the current maintenance window early.
+#[delete("/maintenance")]
+pub async fn end_maintenance(
+    _admin: Admin,
+    maintenance: web::Data<MaintenanceService>,
+) -> Result<HttpResponse, AppError> {
+    let window = maintenance.end().await?
+        .ok_or_else(|| AppError::NotFound("No maintenance window in progress".to_string()))?;
+    tracing::warn!(start = %window.start, "Maintenance window ended early");
+
+    Ok(HttpResponse::Ok().json(window))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(delist_market);
+    cfg.service(
+        web::scope("/admin")
+            .service(set_log_level)
+            .service(freeze_order_book)
+            .service(replay_order_book)
+            .service(reset_order_book)
+            .service(reconcile_balances)
+            .service(get_maintenance)
+            .service(schedule_maintenance)
+            .service(end_maintenance)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test, App};
+    use crate::auth::ADMIN_TOKEN_HEADER;
+
+    #[actix_web::test]
+    async fn test_set_log_level_requires_admin_token() {
+        let (_filter, log_level) = LogLevel::reloadable("info");
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AdminConfig { token: Some("operator".to_string()), ..AdminConfig::default() }))
+                .app_data(web::Data::new(log_level.clone()))
+                .configure(configure),
+        ).await;
+        let request = |token: Option<&str>, filter: &str| {
+            let request = test::TestRequest::put()
+                .uri("/admin/log-level")
+                .set_json(LogLevelRequest { filter: filter.to_string() });
+            match token {
+                Some(token) => request.insert_header((ADMIN_TOKEN_HEADER, token)),
+                None => request,
+            }.to_request()
+        };
+
+        let response = test::call_service(&app, request(None, "debug")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = test::call_service(&app, request(Some("guess"), "debug")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(log_level.current().unwrap(), "info");
+
+        let response = test::call_service(&app, request(Some("operator"), "not a filter=")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response: LogLevelRequest = test::call_and_read_body_json(&app, request(Some("operator"), "debug")).await;
+        assert_eq!(response.filter, "debug");
+        assert_eq!(log_level.current().unwrap(), "debug");
+    }
+
+    #[cfg(not(feature = "database"))]
+    #[actix_web::test]
+    async fn test_reset_clears_the_book_only_when_allowed() {
+        use crate::models::{BookReset, CreateOrderRequest, OrderSide, OrderStatus};
+        use crate::services::order_service::OrderServiceBuilder;
+        use rust_decimal::Decimal;
+        use uuid::Uuid;
+
+        let order_book = OrderBookService::new();
+        let order_service = OrderServiceBuilder::default().order_book(order_book.clone()).build();
+        let mut order_ids = Vec::new();
+        for (side, price) in [(OrderSide::Buy, 99), (OrderSide::Sell, 101)] {
+            let request = CreateOrderRequest::limit("BTC/USD", side, Decimal::ONE, Decimal::new(price, 0));
+            let order = order_service.create_order(Uuid::new_v4(), request).await.unwrap();
+            order_ids.push(order.id);
+        }
+
+        let app = |allow_book_reset| test::init_service(
+            App::new()
+                .app_data(web::Data::new(AdminConfig { token: Some("operator".to_string()), allow_book_reset }))
+                .app_data(web::Data::new(order_service.clone()))
+                .configure(configure),
+        );
+        let request = |cancel: bool| test::TestRequest::post()
+            .uri(&format!("/admin/orderbook/BTC/USD/clear?cancel={}", cancel))
+            .insert_header((ADMIN_TOKEN_HEADER, "operator"))
+            .to_request();
+
+        // Off unless configured
+        let response = test::call_service(&app(false).await, request(true)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(order_book.get_order_book("BTC/USD").await.bids.len(), 1);
+
+        let reset: BookReset = test::call_and_read_body_json(&app(true).await, request(true)).await;
+        assert_eq!(reset.cleared_orders.len(), 2);
+        let book = order_book.get_order_book("BTC/USD").await;
+        assert!(book.bids.is_empty() && book.asks.is_empty());
+        for order_id in order_ids {
+            assert!(matches!(order_service.get_order(order_id).await.unwrap().status, OrderStatus::Cancelled));
+        }
+    }
+}
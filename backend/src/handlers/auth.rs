@@ -0,0 +1,82 @@
+use actix_web::{web, HttpResponse, post};
+use crate::auth::AuthUser;
+use crate::errors::AppError;
+use crate::models::{CreateUserRequest, LoginRequest, RefreshRequest, TwoFactorVerifyRequest};
+use crate::services::auth_service::AuthService;
+
+#[post("/register")]
+pub async fn register(
+    request: web::Json<CreateUserRequest>,
+    auth_service: web::Data<AuthService>,
+) -> Result<HttpResponse, AppError> {
+    let user = auth_service.register(request.into_inner()).await?;
+    Ok(HttpResponse::Created().json(user))
+}
+
+#[post("/login")]
+pub async fn login(
+    request: web::Json<LoginRequest>,
+    auth_service: web::Data<AuthService>,
+) -> Result<HttpResponse, AppError> {
+    let response = auth_service.login(request.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[post("/refresh")]
+pub async fn refresh(
+    request: web::Json<RefreshRequest>,
+    auth_service: web::Data<AuthService>,
+) -> Result<HttpResponse, AppError> {
+    let tokens = auth_service.refresh(&request.refresh_token).await?;
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+#[post("/revoke")]
+pub async fn revoke(
+    request: web::Json<RefreshRequest>,
+    auth_service: web::Data<AuthService>,
+) -> Result<HttpResponse, AppError> {
+    auth_service.revoke_refresh_token(&request.refresh_token).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post("/logout")]
+pub async fn logout(
+    user: AuthUser,
+    auth_service: web::Data<AuthService>,
+) -> Result<HttpResponse, AppError> {
+    auth_service.logout(&user.claims).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post("/2fa/enable")]
+pub async fn enable_two_factor(
+    user: AuthUser,
+    auth_service: web::Data<AuthService>,
+) -> Result<HttpResponse, AppError> {
+    let setup = auth_service.enable_two_factor(user.user_id).await?;
+    Ok(HttpResponse::Ok().json(setup))
+}
+
+#[post("/2fa/verify")]
+pub async fn verify_two_factor(
+    user: AuthUser,
+    request: web::Json<TwoFactorVerifyRequest>,
+    auth_service: web::Data<AuthService>,
+) -> Result<HttpResponse, AppError> {
+    auth_service.verify_two_factor(user.user_id, &request.code).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/auth")
+            .service(register)
+            .service(login)
+            .service(refresh)
+            .service(revoke)
+            .service(logout)
+            .service(enable_two_factor)
+            .service(verify_two_factor)
+    );
+}
@@ -1,21 +1,214 @@
-use actix_web::{get, HttpResponse, Responder};
+use std::time::Instant;
+use actix_web::{get, web, HttpResponse, Responder};
+use prometheus::{Encoder, Registry, TextEncoder};
 use serde::Serialize;
 use chrono::Utc;
+use crate::services::dependencies::{Dependencies, DependencyStatus};
+use crate::services::order_service::{EngineState, OrderService};
+
+/// When the process started, for the uptime reported by `/health/live`.
+#[derive(Clone, Copy)]
+pub struct StartedAt(pub Instant);
+
+impl StartedAt {
+    pub fn now() -> Self {
+        Self(Instant::now())
+    }
+}
+
+#[derive(Serialize)]
+struct BuildInfo {
+    git_commit: &'static str,
+    rust_version: &'static str,
+}
 
 #[derive(Serialize)]
 struct HealthResponse {
     status: String,
     timestamp: String,
     version: String,
+    uptime_seconds: u64,
+    build: BuildInfo,
+}
+
+#[derive(Serialize)]
+struct ReadinessResponse {
+    engine: EngineState,
+    dependencies: Vec<DependencyStatus>,
 }
 
-#[get("/health")]
-pub async fn health_check() -> impl Responder {
+#[derive(Serialize)]
+struct ServerTimeResponse {
+    server_time: i64, // Unix epoch milliseconds
+}
+
+/// Whether the process is up. It never looks at dependencies, so an outage
+/// elsewhere doesn't get the process restarted.
+pub async fn liveness(started_at: web::Data<StartedAt>) -> impl Responder {
     let health = HealthResponse {
         status: "healthy".to_string(),
         timestamp: Utc::now().to_rfc3339(),
         version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_seconds: started_at.0.elapsed().as_secs(),
+        // Set by build.rs
+        build: BuildInfo {
+            git_commit: option_env!("GIT_COMMIT").unwrap_or("unknown"),
+            rust_version: option_env!("RUSTC_VERSION").unwrap_or("unknown"),
+        },
     };
 
     HttpResponse::Ok().json(health)
-} 
\ No newline at end of file
+} 
+
+/// Whether the API can serve traffic: `503` while the engine warms up after
+/// a restart, or while any dependency is down.
+pub async fn readiness(
+    order_service: web::Data<OrderService>,
+    dependencies: web::Data<Dependencies>,
+) -> impl Responder {
+    let engine = order_service.engine_state().await;
+    let dependencies = dependencies.check().await;
+    let ready = engine == EngineState::Ready && dependencies.iter().all(|dependency| dependency.up);
+    let response = ReadinessResponse { engine, dependencies };
+    if ready {
+        HttpResponse::Ok().json(response)
+    } else {
+        HttpResponse::ServiceUnavailable().json(response)
+    }
+}
+
+/// Liveness and readiness probes. Registered both under `/api/v1` and at the
+/// root, where orchestrators look for them. `/health` and `/ready` are kept
+/// as aliases.
+pub fn configure_probes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/health", web::get().to(liveness))
+        .route("/health/live", web::get().to(liveness))
+        .route("/health/ready", web::get().to(readiness))
+        .route("/ready", web::get().to(readiness));
+}
+
+/// Metrics in the Prometheus text format, for scraping.
+#[get("/metrics")]
+pub async fn metrics(registry: web::Data<Registry>) -> impl Responder {
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    match encoder.encode(&registry.gather(), &mut body) {
+        Ok(()) => HttpResponse::Ok().content_type(encoder.format_type()).body(body),
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to encode metrics");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Server clock, for clients to measure drift before signing requests.
+#[get("/time")]
+pub async fn server_time() -> impl Responder {
+    HttpResponse::Ok().json(ServerTimeResponse {
+        server_time: Utc::now().timestamp_millis(),
+    })
+}
+
+#[cfg(all(test, not(feature = "database")))]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use actix_web::{http::StatusCode, test, App};
+    use crate::services::dependencies::{DependencyProbe, ProbeFuture};
+    use crate::services::order_service::OrderServiceBuilder;
+
+    /// A dependency that is either up or down, as told.
+    struct FixedProbe(&'static str, bool);
+
+    impl DependencyProbe for FixedProbe {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn check(&self) -> ProbeFuture<'_> {
+            let up = self.1;
+            Box::pin(async move { if up { Ok(()) } else { Err("connection refused".to_string()) } })
+        }
+    }
+
+    fn order_service() -> OrderService {
+        OrderServiceBuilder::default().build()
+    }
+
+    #[actix_web::test]
+    async fn test_readiness_follows_engine_and_dependencies() {
+        let orders = order_service();
+        for (redis_up, warming, expected) in [
+            (true, false, StatusCode::OK),
+            (false, false, StatusCode::SERVICE_UNAVAILABLE),
+            (true, true, StatusCode::SERVICE_UNAVAILABLE),
+        ] {
+            if warming {
+                orders.begin_warmup().await;
+            }
+            let dependencies = Dependencies::new()
+                .with(FixedProbe("database", true))
+                .with(FixedProbe("redis", redis_up));
+            let app = test::init_service(
+                App::new()
+                    .app_data(web::Data::new(orders.clone()))
+                    .app_data(web::Data::new(dependencies))
+                    .app_data(web::Data::new(StartedAt::now()))
+                    .configure(configure_probes),
+            ).await;
+
+            // Liveness ignores both
+            for uri in ["/health", "/health/live"] {
+                let response = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+                assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+            }
+
+            let response = test::call_service(&app, test::TestRequest::get().uri("/health/ready").to_request()).await;
+            assert_eq!(response.status(), expected);
+            let body: serde_json::Value = test::read_body_json(response).await;
+            assert_eq!(body["engine"], if warming { "warming" } else { "ready" });
+            assert_eq!(body["dependencies"][1]["name"], "redis");
+            assert_eq!(body["dependencies"][1]["up"], redis_up);
+            assert_eq!(body["dependencies"][1].get("error").is_some(), !redis_up);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_server_time_is_close_to_local_clock() {
+        let app = test::init_service(App::new().service(server_time)).await;
+        let before = Utc::now().timestamp_millis();
+        let response: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            test::TestRequest::get().uri("/time").to_request(),
+        ).await;
+        let after = Utc::now().timestamp_millis();
+
+        let time = response["server_time"].as_i64().unwrap();
+        assert!(time >= before - 1_000 && time <= after + 1_000);
+    }
+
+    #[actix_web::test]
+    async fn test_health_reports_uptime_and_build() {
+        let started_at = StartedAt(Instant::now() - Duration::from_secs(5));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(started_at))
+                .configure(configure_probes),
+        ).await;
+        let health = |app| async move {
+            test::call_and_read_body_json::<_, _, serde_json::Value>(
+                app,
+                test::TestRequest::get().uri("/health").to_request(),
+            ).await
+        };
+
+        let first = health(&app).await;
+        assert!(first["uptime_seconds"].as_u64().unwrap() >= 5);
+        assert!(!first["build"]["git_commit"].as_str().unwrap().is_empty());
+        assert!(!first["build"]["rust_version"].as_str().unwrap().is_empty());
+
+        tokio::time::sleep(Duration::from_millis(1_100)).await;
+        let second = health(&app).await;
+        assert!(second["uptime_seconds"].as_u64().unwrap() > first["uptime_seconds"].as_u64().unwrap());
+    }
+}
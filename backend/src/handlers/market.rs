@@ -0,0 +1,435 @@
+use actix_web::{web, HttpResponse, get};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use crate::errors::AppError;
+use crate::services::market_data_service::MarketDataService;
+use crate::services::market_service::MarketService;
+use crate::services::order_book_service::{OrderBookService, DEFAULT_BOOK_DEPTH};
+
+/// Most levels per side a client can ask the order book for.
+const MAX_BOOK_DEPTH: usize = 500;
+
+#[derive(Deserialize)]
+pub struct SymbolQuery {
+    pub symbol: String,
+}
+
+#[derive(Deserialize)]
+pub struct OrderBookQuery {
+    pub symbol: String,
+    /// Levels per side, 10 unless given.
+    pub depth: Option<usize>,
+    /// Adds `requested_depth` and `returned_depth` to the response.
+    #[serde(default)]
+    pub metadata: bool,
+    /// Price increment to group levels by, one of the market's `book_groupings`.
+    pub group: Option<Decimal>,
+}
+
+#[derive(Deserialize)]
+pub struct MarketDataQuery {
+    pub symbol: String,
+    /// Optional reference currency to express prices in, e.g. `USD`.
+    pub convert: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AllMarketDataQuery {
+    /// Comma-separated symbols to limit the response to, e.g. `BTC/USD,ETH/USD`.
+    pub symbols: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SlippageQuery {
+    /// `buy` or `sell`.
+    pub side: String,
+    pub quantity: Decimal,
+}
+
+#[get("/ticker")]
+pub async fn get_ticker(
+    query: web::Query<SymbolQuery>,
+    markets: web::Data<MarketService>,
+    order_book: web::Data<OrderBookService>,
+) -> Result<HttpResponse, AppError> {
+    markets.require_market(&query.symbol).await?;
+    let ticker = order_book.get_ticker(&query.symbol).await;
+    Ok(HttpResponse::Ok().json(ticker))
+}
+
+/// Top levels of a listed market's book, optionally grouped into wider price
+/// levels. A listed market with no orders has
+/// an empty book; an unknown symbol is `404` rather than an empty book, so
+/// typos don't go unnoticed.
+#[get("/orderbook")]
+pub async fn get_order_book(
+    query: web::Query<OrderBookQuery>,
+    markets: web::Data<MarketService>,
+    order_book: web::Data<OrderBookService>,
+) -> Result<HttpResponse, AppError> {
+    let depth = query.depth.unwrap_or(DEFAULT_BOOK_DEPTH);
+    if depth == 0 || depth > MAX_BOOK_DEPTH {
+        return Err(AppError::Validation(format!("Depth must be between 1 and {}", MAX_BOOK_DEPTH)));
+    }
+    let market = markets.require_market(&query.symbol).await?;
+
+    let mut book = match query.group {
+        Some(group) => {
+            // Only the configured presets, so clients can't ask for arbitrarily fine groupings
+            if !market.book_groupings.contains(&group) {
+                return Err(AppError::Validation(format!("Grouping {} is not offered for {}", group, query.symbol)));
+            }
+            order_book.get_grouped_order_book(&query.symbol, depth, group).await
+        }
+        None => order_book.get_order_book_depth(&query.symbol, depth).await,
+    };
+    if query.metadata {
+        book.requested_depth = Some(depth);
+        book.returned_depth = Some(book.bids.len().max(book.asks.len()));
+    }
+    Ok(HttpResponse::Ok().json(book))
+}
+
+#[get("/data")]
+pub async fn get_market_data(
+    query: web::Query<MarketDataQuery>,
+    market_data: web::Data<MarketDataService>,
+) -> Result<HttpResponse, AppError> {
+    let data = match query.convert {
+        Some(ref currency) => market_data.get_market_data_in(&query.symbol, currency).await?,
+        None => market_data.get_market_data(&query.symbol).await?,
+    };
+    Ok(HttpResponse::Ok().json(data))
+}
+
+/// Market data for every listed market in one call, for tickers showing all
+/// of them. Markets that haven't traded yet are included with zero prices.
+#[get("/market-data")]
+pub async fn get_all_market_data(
+    query: web::Query<AllMarketDataQuery>,
+    markets: web::Data<MarketService>,
+    market_data: web::Data<MarketDataService>,
+) -> Result<HttpResponse, AppError> {
+    let symbols = match query.symbols {
+        Some(ref symbols) => {
+            let mut requested = Vec::new();
+            for symbol in symbols.split(',').map(str::trim).filter(|symbol| !symbol.is_empty()) {
+                markets.require_market(symbol).await?;
+                if !requested.iter().any(|seen| seen == symbol) {
+                    requested.push(symbol.to_string());
+                }
+            }
+            requested
+        }
+        None => markets.symbols().await,
+    };
+    Ok(HttpResponse::Ok().json(market_data.get_all_market_data(&symbols).await))
+}
+
+/// Cost of a market order of `quantity` before sending it: average and
+/// worst fill price and slippage against mid, from the book as it stands.
+#[get("/orderbook/{symbol:.+}/slippage")]
+pub async fn get_slippage(
+    symbol: web::Path<String>,
+    query: web::Query<SlippageQuery>,
+    markets: web::Data<MarketService>,
+    order_book: web::Data<OrderBookService>,
+) -> Result<HttpResponse, AppError> {
+    let side = query.side.parse().map_err(AppError::Validation)?;
+    if query.quantity <= Decimal::ZERO {
+        return Err(AppError::Validation("Quantity must be positive".to_string()));
+    }
+    markets.require_market(&symbol).await?;
+
+    let estimate = order_book.estimate_slippage(&symbol, side, query.quantity).await;
+    Ok(HttpResponse::Ok().json(estimate))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_all_market_data);
+    cfg.service(get_slippage);
+    cfg.service(
+        web::scope("/market")
+            .service(get_ticker)
+            .service(get_order_book)
+            .service(get_market_data)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test, App};
+    use rust_decimal::Decimal;
+    use uuid::Uuid;
+    use crate::models::{Order, OrderSide, OrderStatus, OrderType, Trade};
+
+    #[actix_web::test]
+    async fn test_unknown_symbol_is_not_found_but_empty_market_is_valid() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(MarketService::new(crate::config::default_markets())))
+                .app_data(web::Data::new(OrderBookService::new()))
+                .configure(configure),
+        ).await;
+
+        for uri in ["/market/orderbook?symbol=BTC/USDD", "/market/ticker?symbol=BTC/USDD"] {
+            let response = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", uri);
+        }
+
+        let book: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            test::TestRequest::get().uri("/market/orderbook?symbol=BTC/USD").to_request(),
+        ).await;
+        assert_eq!(book["symbol"], "BTC/USD");
+        assert_eq!(book["bids"], serde_json::json!([]));
+        assert_eq!(book["asks"], serde_json::json!([]));
+    }
+
+    #[actix_web::test]
+    async fn test_depth_beyond_book_reports_thin_book() {
+        let order_book = OrderBookService::new();
+        for price in [100, 101, 102] {
+            order_book.add_order(&Order {
+                id: Uuid::new_v4(),
+                user_id: Uuid::new_v4(),
+                symbol: "BTC/USD".to_string(),
+                side: OrderSide::Sell,
+                quantity: Decimal::ONE,
+                price: Some(Decimal::new(price, 0)),
+                order_type: OrderType::Limit,
+                all_or_none: false,
+                stop_price: None,
+                trigger_symbol: None,
+                peg_offset: None,
+                protection_price: None,
+                oco_group_id: None,
+                client_order_id: None,
+                metadata: None,
+                status: OrderStatus::New,
+                filled_quantity: Decimal::ZERO,
+                reject_reason: None,
+                acceptance_seq: 0,
+                created_at: chrono::Utc::now(),
+                updated_at: chrono::Utc::now(),
+            }).await.unwrap();
+        }
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(MarketService::new(crate::config::default_markets())))
+                .app_data(web::Data::new(order_book))
+                .configure(configure),
+        ).await;
+
+        let book: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            test::TestRequest::get().uri("/market/orderbook?symbol=BTC/USD&depth=100&metadata=true").to_request(),
+        ).await;
+        assert_eq!(book["asks"].as_array().unwrap().len(), 3);
+        assert_eq!(book["requested_depth"], 100);
+        assert_eq!(book["returned_depth"], 3);
+
+        // Without opting in the shape is unchanged
+        let book: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            test::TestRequest::get().uri("/market/orderbook?symbol=BTC/USD&depth=2").to_request(),
+        ).await;
+        assert_eq!(book["asks"].as_array().unwrap().len(), 2);
+        assert!(book.get("requested_depth").is_none() && book.get("returned_depth").is_none());
+
+        let response = test::call_service(
+            &app,
+            test::TestRequest::get().uri("/market/orderbook?symbol=BTC/USD&depth=0").to_request(),
+        ).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_book_groups_only_by_configured_increments() {
+        let order_book = OrderBookService::new();
+        for (side, price) in [
+            (OrderSide::Buy, 981),
+            (OrderSide::Buy, 989),
+            (OrderSide::Buy, 975),
+            (OrderSide::Sell, 1001),
+            (OrderSide::Sell, 1009),
+        ] {
+            order_book.add_order(&Order {
+                id: Uuid::new_v4(),
+                user_id: Uuid::new_v4(),
+                symbol: "BTC/USD".to_string(),
+                side,
+                quantity: Decimal::ONE,
+                price: Some(Decimal::new(price, 1)),
+                order_type: OrderType::Limit,
+                all_or_none: false,
+                stop_price: None,
+                trigger_symbol: None,
+                peg_offset: None,
+                protection_price: None,
+                oco_group_id: None,
+                client_order_id: None,
+                metadata: None,
+                status: OrderStatus::New,
+                filled_quantity: Decimal::ZERO,
+                reject_reason: None,
+                acceptance_seq: 0,
+                created_at: chrono::Utc::now(),
+                updated_at: chrono::Utc::now(),
+            }).await.unwrap();
+        }
+        let market = crate::config::MarketConfig {
+            book_groupings: vec![Decimal::ONE, Decimal::TEN],
+            ..crate::config::MarketConfig::new("BTC/USD")
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(MarketService::new(vec![market])))
+                .app_data(web::Data::new(order_book))
+                .configure(configure),
+        ).await;
+
+        let book: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            test::TestRequest::get().uri("/market/orderbook?symbol=BTC/USD&group=1").to_request(),
+        ).await;
+        // 98.9 and 98.1 share a bucket; asks round up so 100.1 shows at 101
+        assert_eq!(book["bids"], serde_json::json!([
+            {"price": "98", "quantity": "2", "order_count": 2},
+            {"price": "97", "quantity": "1", "order_count": 1},
+        ]));
+        assert_eq!(book["asks"], serde_json::json!([
+            {"price": "101", "quantity": "2", "order_count": 2},
+        ]));
+
+        let response = test::call_service(
+            &app,
+            test::TestRequest::get().uri("/market/orderbook?symbol=BTC/USD&group=5").to_request(),
+        ).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_slippage_walks_the_book_without_trading() {
+        let order_book = OrderBookService::new();
+        for (side, quantity, price) in [
+            (OrderSide::Buy, 1, 98),
+            (OrderSide::Sell, 1, 100),
+            (OrderSide::Sell, 2, 101),
+            (OrderSide::Sell, 3, 103),
+        ] {
+            order_book.add_order(&Order {
+                id: Uuid::new_v4(),
+                user_id: Uuid::new_v4(),
+                symbol: "BTC/USD".to_string(),
+                side,
+                quantity: Decimal::new(quantity, 0),
+                price: Some(Decimal::new(price, 0)),
+                order_type: OrderType::Limit,
+                all_or_none: false,
+                stop_price: None,
+                trigger_symbol: None,
+                peg_offset: None,
+                protection_price: None,
+                oco_group_id: None,
+                client_order_id: None,
+                metadata: None,
+                status: OrderStatus::New,
+                filled_quantity: Decimal::ZERO,
+                reject_reason: None,
+                acceptance_seq: 0,
+                created_at: chrono::Utc::now(),
+                updated_at: chrono::Utc::now(),
+            }).await.unwrap();
+        }
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(MarketService::new(crate::config::default_markets())))
+                .app_data(web::Data::new(order_book.clone()))
+                .configure(configure),
+        ).await;
+        let estimate = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+        // 1 at 100 and 2 at 101 against a mid of 99
+        let buy: serde_json::Value = test::call_and_read_body_json(&app, estimate("/orderbook/BTC/USD/slippage?side=buy&quantity=3")).await;
+        assert_eq!(buy["filled_quantity"], "3");
+        assert_eq!(buy["worst_price"], "101");
+        assert_eq!(buy["mid_price"], "99");
+        assert_eq!(buy["slippage_bps"], "168.3502");
+        assert_eq!(buy["fully_fillable"], true);
+
+        let sell: serde_json::Value = test::call_and_read_body_json(&app, estimate("/orderbook/BTC/USD/slippage?side=Sell&quantity=1")).await;
+        assert_eq!(sell["average_price"], "98");
+        assert_eq!(sell["slippage_bps"], "101.0101");
+
+        // More than the book holds fills what it can and says so
+        let too_big: serde_json::Value = test::call_and_read_body_json(&app, estimate("/orderbook/BTC/USD/slippage?side=buy&quantity=10")).await;
+        assert_eq!(too_big["filled_quantity"], "6");
+        assert_eq!(too_big["worst_price"], "103");
+        assert_eq!(too_big["fully_fillable"], false);
+
+        // Nothing traded
+        assert_eq!(order_book.get_order_book("BTC/USD").await.asks.len(), 3);
+
+        for (uri, status) in [
+            ("/orderbook/BTC/USD/slippage?side=hold&quantity=1", StatusCode::BAD_REQUEST),
+            ("/orderbook/BTC/USD/slippage?side=buy&quantity=0", StatusCode::BAD_REQUEST),
+            ("/orderbook/DOGE/USD/slippage?side=buy&quantity=1", StatusCode::NOT_FOUND),
+        ] {
+            assert_eq!(test::call_service(&app, estimate(uri)).await.status(), status, "{}", uri);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_bulk_market_data_covers_every_listed_market() {
+        let markets = crate::config::default_markets();
+        let market_data = MarketDataService::new();
+        market_data.record_trades(&[Trade {
+            id: Uuid::new_v4(),
+            seq: 1,
+            order_id: Uuid::new_v4(),
+            taker_order_id: Uuid::new_v4(),
+            maker_user_id: Uuid::new_v4(),
+            taker_user_id: Uuid::new_v4(),
+            taker_side: OrderSide::Buy,
+            symbol: "BTC/USD".to_string(),
+            quantity: Decimal::new(2, 0),
+            price: Decimal::new(50_000, 0),
+            taker_limit_price: None,
+            executed_at: chrono::Utc::now(),
+        }]).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(MarketService::new(markets.clone())))
+                .app_data(web::Data::new(market_data))
+                .configure(configure),
+        ).await;
+
+        let data: Vec<serde_json::Value> = test::call_and_read_body_json(
+            &app,
+            test::TestRequest::get().uri("/market-data").to_request(),
+        ).await;
+        let mut expected: Vec<&str> = markets.iter().map(|market| market.symbol.as_str()).collect();
+        expected.sort();
+        let returned: Vec<&str> = data.iter().map(|entry| entry["symbol"].as_str().unwrap()).collect();
+        assert_eq!(returned, expected);
+        let btc = data.iter().find(|entry| entry["symbol"] == "BTC/USD").unwrap();
+        assert_eq!(btc["last_price"], "50000");
+        assert_eq!(btc["volume_24h"], "2");
+
+        let data: Vec<serde_json::Value> = test::call_and_read_body_json(
+            &app,
+            test::TestRequest::get().uri("/market-data?symbols=ETH/USD,BTC/USD").to_request(),
+        ).await;
+        let returned: Vec<&str> = data.iter().map(|entry| entry["symbol"].as_str().unwrap()).collect();
+        assert_eq!(returned, ["ETH/USD", "BTC/USD"]);
+        assert_eq!(data[0]["last_price"], "0");
+
+        let response = test::call_service(
+            &app,
+            test::TestRequest::get().uri("/market-data?symbols=BTC/USD,DOGE/USD").to_request(),
+        ).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
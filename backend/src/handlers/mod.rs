@@ -1,2 +1,9 @@
+pub mod account;
+pub mod admin;
+pub mod auth;
 pub mod health;
-pub mod orders; 
\ No newline at end of file
+pub mod market;
+pub mod orders;
+pub mod stream;
+pub mod trades;
+pub mod ws;
\ No newline at end of file
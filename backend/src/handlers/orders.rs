@@ -1,76 +1,335 @@
-use actix_web::{web, HttpResponse, get, post, put, delete};
+use actix_web::{web, HttpResponse, get, post, put, patch, delete};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use crate::models::{CreateOrderRequest, OrderResponse, Order, OrderStatus};
+use crate::models::{CreateOrderRequest, CreateOcoRequest, PatchOrderRequest, ReduceOrderRequest, OrderResponse, Order, OrderStatus, Scope};
+use crate::auth::Caller;
+use crate::config::PrecisionConfig;
 use crate::errors::AppError;
 use crate::services::order_service::OrderService;
 
 #[derive(Deserialize)]
 pub struct OrderQuery {
     pub symbol: Option<String>,
+    /// One or more comma-separated statuses, e.g. `new,open,partially_filled`.
     pub status: Option<String>,
     pub limit: Option<i64>,
     pub offset: Option<i64>,
 }
 
+impl OrderQuery {
+    /// The statuses to filter on; empty when no filter was given.
+    pub fn statuses(&self) -> Result<Vec<OrderStatus>, AppError> {
+        let Some(status) = &self.status else {
+            return Ok(Vec::new());
+        };
+        status.split(',')
+            .map(|value| value.trim().parse().map_err(AppError::Validation))
+            .collect()
+    }
+}
+
+/// The caller's orders, newest first.
 #[get("/orders")]
 pub async fn get_orders(
+    caller: Caller,
     query: web::Query<OrderQuery>,
     order_service: web::Data<OrderService>,
 ) -> Result<HttpResponse, AppError> {
-    let orders = order_service.get_orders(&query).await?;
+    caller.require(Scope::Read)?;
+
+    let orders = order_service.get_orders(caller.user_id(), &query).await?;
     Ok(HttpResponse::Ok().json(orders))
 }
 
 #[get("/orders/{id}")]
 pub async fn get_order(
+    caller: Caller,
     path: web::Path<Uuid>,
     order_service: web::Data<OrderService>,
 ) -> Result<HttpResponse, AppError> {
+    caller.require(Scope::Read)?;
+
     let order_id = path.into_inner();
+    order_service.owned_order(caller.user_id(), order_id).await?;
     let order = order_service.get_order(order_id).await?;
     Ok(HttpResponse::Ok().json(order))
 }
 
+/// The caller's order with their own `client_order_id`, for reconciling
+/// against their records.
+#[get("/by-client-id/{client_order_id}")]
+pub async fn get_order_by_client_id(
+    caller: Caller,
+    path: web::Path<String>,
+    order_service: web::Data<OrderService>,
+) -> Result<HttpResponse, AppError> {
+    caller.require(Scope::Read)?;
+
+    let order = order_service.get_order_by_client_id(caller.user_id(), &path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(order))
+}
+
 #[post("/orders")]
 pub async fn create_order(
+    caller: Caller,
     order_request: web::Json<CreateOrderRequest>,
     order_service: web::Data<OrderService>,
+    precision: web::Data<PrecisionConfig>,
 ) -> Result<HttpResponse, AppError> {
+    caller.require(Scope::Trade)?;
+
     // Validate the request
-    order_request.validate().map_err(|e| AppError::Validation(e))?;
+    let allow_negative_price = order_service.allows_negative_price(&order_request.symbol).await;
+    order_request.validate_with(allow_negative_price).map_err(|e| AppError::Validation(e))?;
+    order_request.validate_precision(&precision).map_err(AppError::Validation)?;
     
-    let order = order_service.create_order(order_request.into_inner()).await?;
+    let user_id = caller.user_id();
+    let order = order_service.create_order(user_id, order_request.into_inner()).await?;
+    if matches!(order.status, OrderStatus::Rejected) {
+        return Ok(HttpResponse::BadRequest().json(order));
+    }
     Ok(HttpResponse::Created().json(order))
 }
 
+/// Dry run of placing an order: the fills it would get against the book as
+/// it stands, without placing it.
+#[post("/preview")]
+pub async fn preview_order(
+    caller: Caller,
+    order_request: web::Json<CreateOrderRequest>,
+    order_service: web::Data<OrderService>,
+    precision: web::Data<PrecisionConfig>,
+) -> Result<HttpResponse, AppError> {
+    caller.require(Scope::Read)?;
+    let allow_negative_price = order_service.allows_negative_price(&order_request.symbol).await;
+    order_request.validate_with(allow_negative_price).map_err(AppError::Validation)?;
+    order_request.validate_precision(&precision).map_err(AppError::Validation)?;
+
+    let preview = order_service.preview_order(caller.user_id(), order_request.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(preview))
+}
+
+#[post("/orders/oco")]
+pub async fn create_oco_order(
+    caller: Caller,
+    oco_request: web::Json<CreateOcoRequest>,
+    order_service: web::Data<OrderService>,
+    precision: web::Data<PrecisionConfig>,
+) -> Result<HttpResponse, AppError> {
+    caller.require(Scope::Trade)?;
+    for order in &oco_request.orders {
+        let allow_negative_price = order_service.allows_negative_price(&order.symbol).await;
+        order.validate_with(allow_negative_price).map_err(AppError::Validation)?;
+        order.validate_precision(&precision).map_err(AppError::Validation)?;
+    }
+
+    let orders = order_service.create_oco(caller.user_id(), oco_request.into_inner().orders).await?;
+    Ok(HttpResponse::Created().json(orders))
+}
+
 #[put("/orders/{id}/cancel")]
 pub async fn cancel_order(
+    caller: Caller,
     path: web::Path<Uuid>,
     order_service: web::Data<OrderService>,
 ) -> Result<HttpResponse, AppError> {
+    caller.require(Scope::Trade)?;
+
     let order_id = path.into_inner();
+    order_service.owned_order(caller.user_id(), order_id).await?;
     let order = order_service.cancel_order(order_id).await?;
     Ok(HttpResponse::Ok().json(order))
 }
 
+/// Most ids a single batch cancel accepts.
+const MAX_CANCEL_BATCH: usize = 100;
+
+#[post("/orders/cancel")]
+pub async fn cancel_orders(
+    caller: Caller,
+    order_ids: web::Json<Vec<Uuid>>,
+    order_service: web::Data<OrderService>,
+) -> Result<HttpResponse, AppError> {
+    caller.require(Scope::Trade)?;
+    if order_ids.is_empty() || order_ids.len() > MAX_CANCEL_BATCH {
+        return Err(AppError::Validation(format!(
+            "Between 1 and {} order ids can be cancelled at once",
+            MAX_CANCEL_BATCH
+        )));
+    }
+
+    let results = order_service.cancel_orders(caller.user_id(), &order_ids).await;
+    Ok(HttpResponse::Ok().json(results))
+}
+
+#[put("/orders/{id}/reduce")]
+pub async fn reduce_order(
+    caller: Caller,
+    path: web::Path<Uuid>,
+    reduce_request: web::Json<ReduceOrderRequest>,
+    order_service: web::Data<OrderService>,
+) -> Result<HttpResponse, AppError> {
+    caller.require(Scope::Trade)?;
+    reduce_request.validate().map_err(AppError::Validation)?;
+
+    let order_id = path.into_inner();
+    order_service.owned_order(caller.user_id(), order_id).await?;
+    let order = order_service.reduce_order(order_id, reduce_request.quantity).await?;
+    Ok(HttpResponse::Ok().json(order))
+}
+
+/// Changes the caller's order in place: only the price and quantity given
+/// change, and the order keeps its id.
+#[patch("/orders/{id}")]
+pub async fn patch_order(
+    caller: Caller,
+    path: web::Path<Uuid>,
+    patch_request: web::Json<PatchOrderRequest>,
+    order_service: web::Data<OrderService>,
+    precision: web::Data<PrecisionConfig>,
+) -> Result<HttpResponse, AppError> {
+    caller.require(Scope::Trade)?;
+    patch_request.validate_precision(&precision).map_err(AppError::Validation)?;
+
+    let order = order_service.patch_order(caller.user_id(), path.into_inner(), &patch_request).await?;
+    Ok(HttpResponse::Ok().json(order))
+}
+
+#[get("/orders/{id}/queue-position")]
+pub async fn get_queue_position(
+    caller: Caller,
+    path: web::Path<Uuid>,
+    order_service: web::Data<OrderService>,
+) -> Result<HttpResponse, AppError> {
+    caller.require(Scope::Read)?;
+
+    let order_id = path.into_inner();
+    order_service.owned_order(caller.user_id(), order_id).await?;
+    let position = order_service.queue_position(order_id).await?;
+    Ok(HttpResponse::Ok().json(position))
+}
+
 #[get("/orders/{id}/trades")]
 pub async fn get_order_trades(
+    caller: Caller,
     path: web::Path<Uuid>,
     order_service: web::Data<OrderService>,
 ) -> Result<HttpResponse, AppError> {
+    caller.require(Scope::Read)?;
+
     let order_id = path.into_inner();
+    order_service.owned_order(caller.user_id(), order_id).await?;
     let trades = order_service.get_order_trades(order_id).await?;
     Ok(HttpResponse::Ok().json(trades))
 }
 
+/// The order's trades summed per execution price, with its average price.
+#[get("/orders/{id}/fills")]
+pub async fn get_order_fills(
+    caller: Caller,
+    path: web::Path<Uuid>,
+    order_service: web::Data<OrderService>,
+) -> Result<HttpResponse, AppError> {
+    caller.require(Scope::Read)?;
+
+    let order_id = path.into_inner();
+    order_service.owned_order(caller.user_id(), order_id).await?;
+    let fills = order_service.get_order_fills(order_id).await?;
+    Ok(HttpResponse::Ok().json(fills))
+}
+
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/orders")
             .service(get_orders)
             .service(get_order)
+            .service(get_order_by_client_id)
             .service(create_order)
+            .service(create_oco_order)
+            .service(preview_order)
             .service(cancel_order)
+            .service(cancel_orders)
+            .service(reduce_order)
+            .service(patch_order)
             .service(get_order_trades)
+            .service(get_order_fills)
+            .service(get_queue_position)
     );
-} 
\ No newline at end of file
+} 
+#[cfg(all(test, not(feature = "database")))]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, middleware, test, App};
+    use crate::auth::{buffer_signed_body, API_KEY_HEADER, API_SIGNATURE_HEADER, API_TIMESTAMP_HEADER};
+    use crate::services::api_key_service::ApiKeyService;
+    use crate::services::order_service::OrderServiceBuilder;
+    use crate::services::secret_cipher::SecretCipher;
+
+    /// Places an order signed with a new key holding `scopes`.
+    async fn place_order_with(scopes: Vec<Scope>) -> StatusCode {
+        let api_keys = ApiKeyService::new(SecretCipher::new("test-key"));
+        let (api_key, secret) = api_keys.create(Uuid::new_v4(), scopes).await.unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(api_keys))
+                .app_data(web::Data::new(OrderServiceBuilder::default().build()))
+                .app_data(web::Data::new(PrecisionConfig::default()))
+                .wrap(middleware::from_fn(buffer_signed_body))
+                .configure(configure),
+        ).await;
+
+        let body = r#"{"symbol":"BTC/USD","side":"Buy","order_type":"Limit","quantity":"1","price":"100"}"#;
+        let timestamp = chrono::Utc::now().timestamp_millis();
+        let signature = ApiKeyService::sign(&secret, timestamp, "POST", "/orders/orders", body.as_bytes());
+        let request = test::TestRequest::post()
+            .uri("/orders/orders")
+            .insert_header(("Content-Type", "application/json"))
+            .insert_header((API_KEY_HEADER, api_key.key))
+            .insert_header((API_TIMESTAMP_HEADER, timestamp.to_string()))
+            .insert_header((API_SIGNATURE_HEADER, signature))
+            .set_payload(body)
+            .to_request();
+        test::call_service(&app, request).await.status()
+    }
+
+    #[actix_web::test]
+    async fn test_over_precise_order_is_a_bad_request() {
+        let api_keys = ApiKeyService::new(SecretCipher::new("test-key"));
+        let (api_key, secret) = api_keys.create(Uuid::new_v4(), vec![Scope::Trade]).await.unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(api_keys))
+                .app_data(web::Data::new(OrderServiceBuilder::default().build()))
+                .app_data(web::Data::new(PrecisionConfig { max_price_scale: 2, max_quantity_scale: 3 }))
+                .wrap(middleware::from_fn(buffer_signed_body))
+                .configure(configure),
+        ).await;
+        let place = |body: &'static str| {
+            let timestamp = chrono::Utc::now().timestamp_millis();
+            let signature = ApiKeyService::sign(&secret, timestamp, "POST", "/orders/orders", body.as_bytes());
+            test::TestRequest::post()
+                .uri("/orders/orders")
+                .insert_header(("Content-Type", "application/json"))
+                .insert_header((API_KEY_HEADER, api_key.key.clone()))
+                .insert_header((API_TIMESTAMP_HEADER, timestamp.to_string()))
+                .insert_header((API_SIGNATURE_HEADER, signature))
+                .set_payload(body)
+                .to_request()
+        };
+
+        for body in [
+            r#"{"symbol":"BTC/USD","side":"Buy","order_type":"Limit","quantity":"1","price":"100.001"}"#,
+            r#"{"symbol":"BTC/USD","side":"Buy","order_type":"Limit","quantity":"0.0001","price":"100"}"#,
+        ] {
+            assert_eq!(test::call_service(&app, place(body)).await.status(), StatusCode::BAD_REQUEST, "{}", body);
+        }
+        let body = r#"{"symbol":"BTC/USD","side":"Buy","order_type":"Limit","quantity":"0.001","price":"100.01"}"#;
+        assert_eq!(test::call_service(&app, place(body)).await.status(), StatusCode::CREATED);
+    }
+
+    #[actix_web::test]
+    async fn test_read_only_key_cannot_place_orders() {
+        assert_eq!(place_order_with(vec![Scope::Read]).await, StatusCode::FORBIDDEN);
+        assert_eq!(place_order_with(vec![Scope::Read, Scope::Trade]).await, StatusCode::CREATED);
+    }
+}
@@ -0,0 +1,233 @@
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use crate::errors::AppError;
+use crate::services::market_service::MarketService;
+use crate::services::order_book_service::OrderBookService;
+
+/// Header browsers send when reconnecting, with the id of the last event seen.
+const LAST_EVENT_ID_HEADER: &str = "Last-Event-ID";
+/// How long clients wait before reconnecting, in milliseconds.
+const RECONNECT_DELAY_MS: u64 = 3_000;
+/// Longest the stream stays silent, so proxies don't close it as idle.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+/// Events queued for a client before the producer waits for it to catch up.
+const EVENT_BUFFER: usize = 64;
+
+/// A response body fed by a task sending event stream chunks. It ends when
+/// the task drops its sender.
+struct EventStream(mpsc::Receiver<Bytes>);
+
+impl MessageBody for EventStream {
+    type Error = Infallible;
+
+    fn size(&self) -> BodySize {
+        BodySize::Stream
+    }
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        self.get_mut().0.poll_recv(cx).map(|chunk| chunk.map(Ok))
+    }
+}
+
+fn event(id: i64, name: &str, data: &impl Serialize) -> Bytes {
+    let data = serde_json::to_string(data).unwrap_or_default();
+    Bytes::from(format!("id: {}\nevent: {}\ndata: {}\n\n", id, name, data))
+}
+
+/// The `snapshot` event: the book's top levels, with the `bbo` sequence it
+/// reflects as its id.
+async fn snapshot(order_book: &OrderBookService, symbol: &str) -> (i64, Bytes) {
+    let sequence = order_book.bbo_sequence();
+    let book = order_book.get_order_book(symbol).await;
+    (sequence, event(sequence, "snapshot", &book))
+}
+
+/// Writes `symbol`'s book events to `events` until the client goes away.
+/// Resuming clients get the `bbo` events they missed, when they are still
+/// kept, and a fresh snapshot otherwise.
+async fn stream_order_book(
+    symbol: String,
+    last_event_id: Option<i64>,
+    order_book: OrderBookService,
+    events: mpsc::Sender<Bytes>,
+) {
+    // Subscribed first, so nothing published while catching up is lost
+    let mut updates = order_book.subscribe_bbo();
+    if events.send(Bytes::from(format!("retry: {}\n\n", RECONNECT_DELAY_MS))).await.is_err() {
+        return;
+    }
+
+    let missed = last_event_id.and_then(|id| Some((id, order_book.bbo_since(&symbol, id)?)));
+    let mut sent = match missed {
+        Some((id, missed)) => {
+            let mut sent = id;
+            for update in missed {
+                sent = update.sequence;
+                if events.send(event(update.sequence, "bbo", &update)).await.is_err() {
+                    return;
+                }
+            }
+            sent
+        }
+        None => {
+            let (sequence, snapshot) = snapshot(&order_book, &symbol).await;
+            if events.send(snapshot).await.is_err() {
+                return;
+            }
+            sequence
+        }
+    };
+
+    let mut keep_alive = tokio::time::interval_at(tokio::time::Instant::now() + KEEP_ALIVE_INTERVAL, KEEP_ALIVE_INTERVAL);
+    loop {
+        let chunk = tokio::select! {
+            update = updates.recv() => match update {
+                Ok(update) if update.symbol == symbol && update.sequence > sent => {
+                    sent = update.sequence;
+                    event(update.sequence, "bbo", &update)
+                }
+                Ok(_) => continue,
+                // Updates were dropped, so the client starts over from a snapshot
+                Err(RecvError::Lagged(_)) => {
+                    let (sequence, snapshot) = snapshot(&order_book, &symbol).await;
+                    sent = sequence;
+                    snapshot
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = keep_alive.tick() => Bytes::from_static(b": keep-alive\n\n"),
+            _ = events.closed() => break,
+        };
+        if events.send(chunk).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Server-sent events for one symbol's book: a `snapshot` of its top levels,
+/// then a `bbo` event per top of book change. The same updates feed the `bbo`
+/// websocket channel.
+#[get("/orderbook/{symbol:.+}")]
+pub async fn order_book_events(
+    req: HttpRequest,
+    symbol: web::Path<String>,
+    markets: web::Data<MarketService>,
+    order_book: web::Data<OrderBookService>,
+) -> Result<HttpResponse, AppError> {
+    let symbol = symbol.into_inner();
+    markets.require_market(&symbol).await?;
+    let last_event_id = req.headers()
+        .get(LAST_EVENT_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok());
+
+    let (events, body) = mpsc::channel(EVENT_BUFFER);
+    actix_rt::spawn(stream_order_book(symbol, last_event_id, order_book.get_ref().clone(), events));
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .body(EventStream(body)))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/stream")
+            .service(order_book_events)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test, App};
+    use rust_decimal::Decimal;
+    use uuid::Uuid;
+    use crate::config::default_markets;
+    use crate::models::{Order, OrderSide, OrderStatus, OrderType};
+
+    fn bid(price: i64) -> Order {
+        Order {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            symbol: "BTC/USD".to_string(),
+            side: OrderSide::Buy,
+            quantity: Decimal::ONE,
+            price: Some(Decimal::new(price, 0)),
+            order_type: OrderType::Limit,
+            all_or_none: false,
+            stop_price: None,
+            trigger_symbol: None,
+            peg_offset: None,
+            protection_price: None,
+            oco_group_id: None,
+            client_order_id: None,
+            metadata: None,
+            status: OrderStatus::New,
+            filled_quantity: Decimal::ZERO,
+            reject_reason: None,
+            acceptance_seq: 0,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    /// Reads `body` until it has produced an event named `name`, returning
+    /// everything read.
+    async fn read_until_event(body: &mut Pin<Box<impl MessageBody>>, name: &str) -> String {
+        let mut text = String::new();
+        while !text.contains(&format!("event: {}\n", name)) {
+            let chunk = tokio::time::timeout(Duration::from_secs(5), std::future::poll_fn(|cx| body.as_mut().poll_next(cx)))
+                .await
+                .expect("no event in time")
+                .expect("stream ended");
+            text.push_str(std::str::from_utf8(&chunk.ok().expect("body error")).unwrap());
+        }
+        text
+    }
+
+    #[actix_web::test]
+    async fn test_stream_sends_book_changes_and_resumes_after_last_event() {
+        let order_book = OrderBookService::new();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(MarketService::new(default_markets())))
+                .app_data(web::Data::new(order_book.clone()))
+                .configure(configure),
+        ).await;
+
+        let response = test::call_service(&app, test::TestRequest::get().uri("/stream/orderbook/BTC/USD").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get(header::CONTENT_TYPE).unwrap(), "text/event-stream");
+        let mut body = Box::pin(response.into_body());
+        assert!(read_until_event(&mut body, "snapshot").await.contains("retry: 3000"));
+
+        order_book.add_order(&bid(100)).await.unwrap();
+        let text = read_until_event(&mut body, "bbo").await;
+        assert!(text.contains("id: 1\n"));
+        assert!(text.contains(r#""bid":"100""#));
+
+        // A client reconnecting with the id it last saw gets only what came after
+        order_book.add_order(&bid(101)).await.unwrap();
+        let response = test::call_service(&app, test::TestRequest::get()
+            .uri("/stream/orderbook/BTC/USD")
+            .insert_header((LAST_EVENT_ID_HEADER, "1"))
+            .to_request()).await;
+        let mut body = Box::pin(response.into_body());
+        let text = read_until_event(&mut body, "bbo").await;
+        assert!(!text.contains("event: snapshot"));
+        assert!(text.contains("id: 2\n") && text.contains(r#""bid":"101""#));
+
+        let response = test::call_service(&app, test::TestRequest::get().uri("/stream/orderbook/DOGE/USD").to_request()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,59 @@
+use actix_web::{web, HttpResponse, get};
+use serde::Deserialize;
+use uuid::Uuid;
+use crate::auth::Caller;
+use crate::errors::AppError;
+use crate::models::{AggTrade, Scope};
+use crate::services::market_service::MarketService;
+use crate::services::order_service::OrderService;
+
+/// Trades aggregated per request when no limit is given.
+const DEFAULT_AGG_TRADES_LIMIT: usize = 500;
+/// Most trades a client can have aggregated in one request.
+const MAX_AGG_TRADES_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct AggTradesQuery {
+    /// How many of the latest trades to aggregate, 500 unless given.
+    pub limit: Option<usize>,
+}
+
+/// A trade the caller was the maker or taker of; `404` for any other.
+#[get("/by-id/{trade_id}")]
+pub async fn get_trade(
+    caller: Caller,
+    path: web::Path<Uuid>,
+    order_service: web::Data<OrderService>,
+) -> Result<HttpResponse, AppError> {
+    caller.require(Scope::Read)?;
+
+    let trade = order_service.get_trade(caller.user_id(), path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(trade))
+}
+
+/// The latest trades on a market, oldest first, with consecutive trades at
+/// the same price and taker side merged into one record.
+#[get("/aggTrades/{symbol:.+}")]
+pub async fn get_agg_trades(
+    symbol: web::Path<String>,
+    query: web::Query<AggTradesQuery>,
+    markets: web::Data<MarketService>,
+    order_service: web::Data<OrderService>,
+) -> Result<HttpResponse, AppError> {
+    let limit = query.limit.unwrap_or(DEFAULT_AGG_TRADES_LIMIT);
+    if limit == 0 || limit > MAX_AGG_TRADES_LIMIT {
+        return Err(AppError::Validation(format!("Limit must be between 1 and {}", MAX_AGG_TRADES_LIMIT)));
+    }
+    markets.require_market(&symbol).await?;
+
+    let trades = order_service.get_recent_trades(&symbol, limit).await?;
+    Ok(HttpResponse::Ok().json(AggTrade::aggregate(trades)))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_agg_trades);
+    cfg.service(
+        web::scope("/trades")
+            .service(get_trade)
+    );
+}
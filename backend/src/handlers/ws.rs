@@ -0,0 +1,478 @@
+use std::collections::{HashSet, VecDeque};
+use actix::{fut, Actor, ActorContext, ActorFutureExt, Addr, AsyncContext, Handler, Message, StreamHandler};
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+use crate::handlers::market::SymbolQuery;
+use crate::models::{BestBidOffer, ExecutionReport, Trade};
+use crate::services::auth_service::AuthService;
+use crate::services::order_book_service::OrderBookService;
+use crate::services::order_service::OrderService;
+
+/// An engine event forwarded to a session, which decides whether its client wants it.
+#[derive(Message)]
+#[rtype(result = "()")]
+struct Push<T>(T);
+
+/// Forwards `updates` to `session` until either side goes away.
+fn forward<T, A>(mut updates: broadcast::Receiver<T>, session: Addr<A>)
+where
+    T: Clone + Send + 'static,
+    A: Actor<Context = ws::WebsocketContext<A>> + Handler<Push<T>>,
+{
+    actix_rt::spawn(async move {
+        loop {
+            let update = match updates.recv().await {
+                Ok(update) => update,
+                // A lagging client skips to the newest update
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+            if !session.connected() {
+                break;
+            }
+            session.do_send(Push(update));
+        }
+    });
+}
+
+/// One client subscribed to a symbol's `bbo` channel. The current top of book
+/// is sent on connect, then one message per change.
+struct BboSession {
+    symbol: String,
+    order_book: OrderBookService,
+    snapshot: Option<BestBidOffer>,
+}
+
+impl Actor for BboSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        if let Some(snapshot) = self.snapshot.take() {
+            ctx.notify(Push(snapshot));
+        }
+        forward(self.order_book.subscribe_bbo(), ctx.address());
+    }
+}
+
+impl Handler<Push<BestBidOffer>> for BboSession {
+    type Result = ();
+
+    fn handle(&mut self, update: Push<BestBidOffer>, ctx: &mut Self::Context) {
+        if update.0.symbol != self.symbol {
+            return;
+        }
+        if let Ok(text) = serde_json::to_string(&update.0) {
+            ctx.text(text);
+        }
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for BboSession {
+    fn handle(&mut self, message: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match message {
+            Ok(ws::Message::Ping(bytes)) => ctx.pong(&bytes),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Err(_) => ctx.stop(),
+            _ => {}
+        }
+    }
+}
+
+#[get("/bbo")]
+pub async fn bbo(
+    req: HttpRequest,
+    stream: web::Payload,
+    query: web::Query<SymbolQuery>,
+    order_book: web::Data<OrderBookService>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ticker = order_book.get_ticker(&query.symbol).await;
+    let session = BboSession {
+        symbol: query.into_inner().symbol,
+        order_book: order_book.get_ref().clone(),
+        snapshot: Some(BestBidOffer {
+            symbol: ticker.symbol,
+            bid: ticker.best_bid,
+            bid_quantity: ticker.best_bid_quantity,
+            ask: ticker.best_ask,
+            ask_quantity: ticker.best_ask_quantity,
+            sequence: order_book.bbo_sequence(),
+            timestamp: ticker.timestamp,
+        }),
+    };
+    ws::start(session, &req, stream)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    /// Top of book changes for one symbol.
+    Bbo,
+    /// The authenticated user's own trades, as maker or taker.
+    Fills,
+    /// Execution reports for each step of the authenticated user's orders.
+    Executions,
+}
+
+impl Channel {
+    fn name(self) -> &'static str {
+        match self {
+            Channel::Bbo => "bbo",
+            Channel::Fills => "fills",
+            Channel::Executions => "executions",
+        }
+    }
+
+    fn is_private(self) -> bool {
+        matches!(self, Channel::Fills | Channel::Executions)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ClientMessage {
+    Auth {
+        token: String,
+    },
+    Subscribe {
+        channel: Channel,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ServerMessage {
+    Authenticated { user_id: Uuid },
+    Subscribed { channel: Channel, symbol: Option<String> },
+    Error { message: String },
+    Bbo(BestBidOffer),
+    Fill(Trade),
+    Execution(ExecutionReport),
+}
+
+/// What a connection has been told so far: who it belongs to, if anyone,
+/// and what it subscribed to.
+#[derive(Debug, Default)]
+struct Connection {
+    user_id: Option<Uuid>,
+    /// Set by the first message; authentication is only accepted before it.
+    started: bool,
+    bbo_symbols: HashSet<String>,
+    fills: bool,
+    executions: bool,
+}
+
+impl Connection {
+    /// Records the arrival of a message, failing for an `auth` that isn't the first.
+    fn receive(&mut self, message: &ClientMessage) -> Result<(), String> {
+        let first = !self.started;
+        self.started = true;
+        match message {
+            ClientMessage::Auth { .. } if !first => Err("Authentication must be the first message".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    fn subscribe(&mut self, channel: Channel, symbol: Option<String>) -> Result<ServerMessage, String> {
+        if channel.is_private() && self.user_id.is_none() {
+            return Err(format!("Authenticate before subscribing to the private {} channel", channel.name()));
+        }
+
+        match channel {
+            Channel::Bbo => {
+                let symbol = symbol.ok_or_else(|| "The bbo channel needs a symbol".to_string())?;
+                self.bbo_symbols.insert(symbol.clone());
+                Ok(ServerMessage::Subscribed { channel, symbol: Some(symbol) })
+            }
+            Channel::Fills => {
+                self.fills = true;
+                Ok(ServerMessage::Subscribed { channel, symbol: None })
+            }
+            Channel::Executions => {
+                self.executions = true;
+                Ok(ServerMessage::Subscribed { channel, symbol: None })
+            }
+        }
+    }
+
+    fn wants_bbo(&self, update: &BestBidOffer) -> bool {
+        self.bbo_symbols.contains(&update.symbol)
+    }
+
+    fn wants_fill(&self, trade: &Trade) -> bool {
+        self.fills && self.user_id.is_some_and(|user_id| trade.maker_user_id == user_id || trade.taker_user_id == user_id)
+    }
+
+    fn wants_execution(&self, report: &ExecutionReport) -> bool {
+        self.executions && self.user_id == Some(report.user_id)
+    }
+}
+
+/// A multiplexed connection. Clients subscribe to channels with
+/// `{"type": "subscribe", "channel": ..}` messages; private channels need an
+/// `{"type": "auth", "token": ..}` first message, so the access token never
+/// appears in a URL.
+struct StreamSession {
+    connection: Connection,
+    auth_service: AuthService,
+    order_book: OrderBookService,
+    order_service: OrderService,
+    /// Messages that arrived while the token was being verified, handled once it is.
+    queued: Option<VecDeque<ClientMessage>>,
+}
+
+impl StreamSession {
+    fn send(&self, ctx: &mut ws::WebsocketContext<Self>, message: &ServerMessage) {
+        if let Ok(text) = serde_json::to_string(message) {
+            ctx.text(text);
+        }
+    }
+
+    fn error(&self, ctx: &mut ws::WebsocketContext<Self>, message: String) {
+        self.send(ctx, &ServerMessage::Error { message });
+    }
+
+    fn receive(&mut self, message: ClientMessage, ctx: &mut ws::WebsocketContext<Self>) {
+        if let Some(queued) = self.queued.as_mut() {
+            queued.push_back(message);
+            return;
+        }
+        if let Err(message) = self.connection.receive(&message) {
+            return self.error(ctx, message);
+        }
+
+        match message {
+            ClientMessage::Auth { token } => {
+                self.queued = Some(VecDeque::new());
+                let auth_service = self.auth_service.clone();
+                let verify = fut::wrap_future(async move { auth_service.verify_access_token(&token).await });
+                ctx.spawn(verify.map(|result, session: &mut Self, ctx| {
+                    match result {
+                        Ok(claims) => {
+                            session.connection.user_id = Some(claims.sub);
+                            session.send(ctx, &ServerMessage::Authenticated { user_id: claims.sub });
+                        }
+                        Err(error) => {
+                            session.error(ctx, error.to_string());
+                            ctx.close(Some(ws::CloseCode::Policy.into()));
+                            ctx.stop();
+                            return;
+                        }
+                    }
+                    for message in session.queued.take().unwrap_or_default() {
+                        session.receive(message, ctx);
+                    }
+                }));
+            }
+            ClientMessage::Subscribe { channel, symbol } => {
+                match self.connection.subscribe(channel, symbol) {
+                    Ok(reply) => self.send(ctx, &reply),
+                    Err(message) => self.error(ctx, message),
+                }
+            }
+        }
+    }
+}
+
+impl Actor for StreamSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        forward(self.order_book.subscribe_bbo(), ctx.address());
+        forward(self.order_book.subscribe_trades(), ctx.address());
+        forward(self.order_service.subscribe_executions(), ctx.address());
+    }
+}
+
+impl Handler<Push<BestBidOffer>> for StreamSession {
+    type Result = ();
+
+    fn handle(&mut self, update: Push<BestBidOffer>, ctx: &mut Self::Context) {
+        if self.connection.wants_bbo(&update.0) {
+            self.send(ctx, &ServerMessage::Bbo(update.0));
+        }
+    }
+}
+
+impl Handler<Push<Trade>> for StreamSession {
+    type Result = ();
+
+    fn handle(&mut self, trade: Push<Trade>, ctx: &mut Self::Context) {
+        if self.connection.wants_fill(&trade.0) {
+            self.send(ctx, &ServerMessage::Fill(trade.0));
+        }
+    }
+}
+
+impl Handler<Push<ExecutionReport>> for StreamSession {
+    type Result = ();
+
+    fn handle(&mut self, report: Push<ExecutionReport>, ctx: &mut Self::Context) {
+        if self.connection.wants_execution(&report.0) {
+            self.send(ctx, &ServerMessage::Execution(report.0));
+        }
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for StreamSession {
+    fn handle(&mut self, message: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match message {
+            Ok(ws::Message::Text(text)) => match serde_json::from_str(&text) {
+                Ok(message) => self.receive(message, ctx),
+                Err(error) => self.error(ctx, format!("Malformed message: {}", error)),
+            },
+            Ok(ws::Message::Ping(bytes)) => ctx.pong(&bytes),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Err(_) => ctx.stop(),
+            _ => {}
+        }
+    }
+}
+
+#[get("")]
+pub async fn connect(
+    req: HttpRequest,
+    stream: web::Payload,
+    auth_service: web::Data<AuthService>,
+    order_book: web::Data<OrderBookService>,
+    order_service: web::Data<OrderService>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let session = StreamSession {
+        connection: Connection::default(),
+        auth_service: auth_service.get_ref().clone(),
+        order_book: order_book.get_ref().clone(),
+        order_service: order_service.get_ref().clone(),
+        queued: None,
+    };
+    ws::start(session, &req, stream)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/ws")
+            .service(connect)
+            .service(bbo)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use rust_decimal::Decimal;
+    use crate::models::OrderSide;
+
+    fn subscribe(connection: &mut Connection, text: &str) -> Result<ServerMessage, String> {
+        let message = serde_json::from_str(text).unwrap();
+        connection.receive(&message)?;
+        match message {
+            ClientMessage::Subscribe { channel, symbol } => connection.subscribe(channel, symbol),
+            ClientMessage::Auth { .. } => Err("expected a subscription".to_string()),
+        }
+    }
+
+    fn trade(maker: Uuid, taker: Uuid) -> Trade {
+        Trade {
+            id: Uuid::new_v4(),
+            seq: 1,
+            order_id: Uuid::new_v4(),
+            taker_order_id: Uuid::new_v4(),
+            maker_user_id: maker,
+            taker_user_id: taker,
+            taker_side: OrderSide::Buy,
+            symbol: "BTC/USD".to_string(),
+            quantity: Decimal::ONE,
+            price: Decimal::new(100, 0),
+            taker_limit_price: None,
+            executed_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_private_subscription_before_auth_is_rejected() {
+        let mut connection = Connection::default();
+
+        let result = subscribe(&mut connection, r#"{"type":"subscribe","channel":"fills"}"#);
+        assert!(result.unwrap_err().contains("Authenticate"));
+        assert!(!connection.fills);
+
+        // Public channels don't need a user
+        let result = subscribe(&mut connection, r#"{"type":"subscribe","channel":"bbo","symbol":"BTC/USD"}"#);
+        assert!(matches!(result, Ok(ServerMessage::Subscribed { channel: Channel::Bbo, .. })));
+
+        // Authentication is only accepted as the first message
+        let auth = serde_json::from_str(r#"{"type":"auth","token":"t"}"#).unwrap();
+        assert!(connection.receive(&auth).is_err());
+    }
+
+    #[test]
+    fn test_authenticated_connection_only_receives_own_fills() {
+        let mut connection = Connection::default();
+        let auth = serde_json::from_str(r#"{"type":"auth","token":"t"}"#).unwrap();
+        assert!(connection.receive(&auth).is_ok());
+        let user_id = Uuid::new_v4();
+        connection.user_id = Some(user_id);
+
+        let result = subscribe(&mut connection, r#"{"type":"subscribe","channel":"fills"}"#);
+        assert!(matches!(result, Ok(ServerMessage::Subscribed { channel: Channel::Fills, .. })));
+
+        assert!(connection.wants_fill(&trade(user_id, Uuid::new_v4())));
+        assert!(connection.wants_fill(&trade(Uuid::new_v4(), user_id)));
+        assert!(!connection.wants_fill(&trade(Uuid::new_v4(), Uuid::new_v4())));
+    }
+
+    #[test]
+    fn test_execution_reports_go_to_their_owner_only() {
+        let mut connection = Connection::default();
+        let result = subscribe(&mut connection, r#"{"type":"subscribe","channel":"executions"}"#);
+        assert!(result.unwrap_err().contains("Authenticate"));
+
+        let user_id = Uuid::new_v4();
+        connection.user_id = Some(user_id);
+        let result = subscribe(&mut connection, r#"{"type":"subscribe","channel":"executions"}"#);
+        assert!(matches!(result, Ok(ServerMessage::Subscribed { channel: Channel::Executions, .. })));
+
+        let mut trade = trade(user_id, Uuid::new_v4());
+        trade.order_id = Uuid::new_v4();
+        let order = crate::models::Order {
+            id: trade.order_id,
+            user_id,
+            symbol: trade.symbol.clone(),
+            side: OrderSide::Sell,
+            quantity: Decimal::new(2, 0),
+            price: Some(trade.price),
+            order_type: crate::models::OrderType::Limit,
+            all_or_none: false,
+            stop_price: None,
+            trigger_symbol: None,
+            peg_offset: None,
+            protection_price: None,
+            oco_group_id: None,
+            client_order_id: None,
+            metadata: None,
+            status: crate::models::OrderStatus::New,
+            filled_quantity: Decimal::ZERO,
+            reject_reason: None,
+            acceptance_seq: 0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        let report = ExecutionReport::fill(&order, &trade, Decimal::ONE);
+        assert!(connection.wants_execution(&report));
+        let text = serde_json::to_string(&ServerMessage::Execution(report.clone())).unwrap();
+        assert!(text.contains(r#""type":"execution""#) && text.contains(r#""exec_type":"PartiallyFilled""#));
+
+        let someone_elses = ExecutionReport { user_id: Uuid::new_v4(), ..report };
+        assert!(!connection.wants_execution(&someone_elses));
+    }
+}
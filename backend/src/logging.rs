@@ -0,0 +1,134 @@
+use tracing_subscriber::{layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry};
+use crate::errors::AppError;
+
+/// Output format for log lines, selected with the `LOG_FORMAT` environment variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable output, the default for local development.
+    Pretty,
+    /// One JSON object per line, including the fields of the enclosing spans.
+    Json,
+}
+
+impl LogFormat {
+    pub fn from_env() -> Self {
+        Self::parse(std::env::var("LOG_FORMAT").ok().as_deref())
+    }
+
+    pub fn parse(value: Option<&str>) -> Self {
+        match value.map(|v| v.trim().to_ascii_lowercase()) {
+            Some(v) if v == "json" => LogFormat::Json,
+            _ => LogFormat::Pretty,
+        }
+    }
+}
+
+/// Handle to the active log filter, which can be replaced while the server
+/// runs without touching the output layers.
+#[derive(Clone)]
+pub struct LogLevel {
+    handle: reload::Handle<EnvFilter, Registry>,
+}
+
+impl LogLevel {
+    /// A reloadable filter layer starting at `directives`, and the handle that controls it.
+    pub fn reloadable(directives: &str) -> (reload::Layer<EnvFilter, Registry>, Self) {
+        let (filter, handle) = reload::Layer::new(EnvFilter::new(directives));
+        (filter, Self { handle })
+    }
+
+    /// The active filter in `RUST_LOG` syntax.
+    pub fn current(&self) -> Result<String, AppError> {
+        self.handle
+            .with_current(|filter| filter.to_string())
+            .map_err(|error| AppError::Internal(format!("Log filter is unavailable: {}", error)))
+    }
+
+    /// Replaces the active filter with `directives`, in `RUST_LOG` syntax
+    /// (e.g. `info,exchange_api::services::order_book_service=debug`).
+    pub fn set(&self, directives: &str) -> Result<(), AppError> {
+        let filter = EnvFilter::try_new(directives)
+            .map_err(|error| AppError::Validation(format!("Invalid log filter: {}", error)))?;
+        self.handle
+            .reload(filter)
+            .map_err(|error| AppError::Internal(format!("Failed to reload log filter: {}", error)))
+    }
+}
+
+pub fn init(format: LogFormat) -> LogLevel {
+    let (filter, log_level) = LogLevel::reloadable(
+        &std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into()),
+    );
+
+    let (pretty, json) = match format {
+        LogFormat::Pretty => (Some(tracing_subscriber::fmt::layer()), None),
+        LogFormat::Json => (
+            None,
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(true),
+            ),
+        ),
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(pretty)
+        .with(json)
+        .init();
+
+    log_level
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tracing_subscriber::layer::{Context, Layer};
+    use super::*;
+
+    #[test]
+    fn test_log_format_selection() {
+        assert_eq!(LogFormat::parse(None), LogFormat::Pretty);
+        assert_eq!(LogFormat::parse(Some("pretty")), LogFormat::Pretty);
+        assert_eq!(LogFormat::parse(Some("json")), LogFormat::Json);
+        assert_eq!(LogFormat::parse(Some(" JSON ")), LogFormat::Json);
+        assert_eq!(LogFormat::parse(Some("xml")), LogFormat::Pretty);
+    }
+
+    /// Counts the events that reach it through the filter.
+    struct CountEvents(Arc<AtomicUsize>);
+
+    impl<S: tracing::Subscriber> Layer<S> for CountEvents {
+        fn on_event(&self, _: &tracing::Event<'_>, _: Context<'_, S>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_changing_level_changes_emitted_events() {
+        let (filter, log_level) = LogLevel::reloadable("info");
+        let events = Arc::new(AtomicUsize::new(0));
+        let subscriber = tracing_subscriber::registry()
+            .with(filter)
+            .with(CountEvents(events.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!(target: "matching", "suppressed at info");
+            tracing::info!(target: "matching", "emitted");
+            assert_eq!(events.load(Ordering::SeqCst), 1);
+
+            log_level.set("info,matching=debug").unwrap();
+            assert_eq!(log_level.current().unwrap(), "matching=debug,info");
+            tracing::debug!(target: "matching", "emitted at debug");
+            tracing::debug!(target: "other", "still suppressed");
+            assert_eq!(events.load(Ordering::SeqCst), 2);
+
+            assert!(matches!(log_level.set("matching=loud"), Err(AppError::Validation(_))));
+            tracing::debug!(target: "matching", "filter unchanged");
+            assert_eq!(events.load(Ordering::SeqCst), 3);
+        });
+    }
+}
@@ -1,17 +1,46 @@
 use actix_web::{web, App, HttpServer, middleware, HttpResponse, get};
+use actix_web::dev::Service;
 use actix_cors::Cors;
-use tracing::info;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tracing::{info, Instrument};
+use uuid::Uuid;
 
+mod auth;
 mod config;
+mod encoding;
+mod logging;
+mod rounding;
+mod timeout;
+mod units;
 mod models;
 mod handlers;
 mod services;
 mod errors;
+#[cfg(feature = "fix")]
+mod fix;
 
-use config::Config;
+use config::{Config, CorsConfig};
 use services::order_service::OrderService;
 use services::order_book_service::OrderBookService;
+use services::fee_service::FeeService;
+use services::balance_service::BalanceService;
+use services::risk_service::RiskService;
+use services::market_service::MarketService;
+use services::market_data_service::MarketDataService;
+use services::password_service::PasswordService;
+use services::auth_service::AuthService;
+use services::totp_service::TotpService;
+use services::secret_cipher::SecretCipher;
+use services::api_key_service::ApiKeyService;
+use services::rate_limiter::{self, RateLimiter};
+use services::background_tasks::BackgroundTasks;
+use services::dependencies::Dependencies;
+use services::maintenance_service::{self, MaintenanceService};
+#[cfg(feature = "database")]
+use services::database_pools::DatabasePools;
+#[cfg(feature = "database")]
+use services::trade_persister::TradePersister;
+#[cfg(feature = "database")]
+use services::dependencies::{PostgresProbe, RedisProbe};
 
 // Simple OpenAPI specification
 const OPENAPI_SPEC: &str = include_str!("../openapi.json");
@@ -77,54 +106,249 @@ async fn openapi_spec() -> HttpResponse {
         .body(OPENAPI_SPEC)
 }
 
+fn cors(config: &CorsConfig) -> Cors {
+    let mut cors = Cors::default().max_age(config.max_age);
+    if config.allows_any_origin() {
+        cors = cors.allow_any_origin();
+    } else {
+        for origin in &config.allowed_origins {
+            cors = cors.allowed_origin(origin);
+        }
+    }
+    if config.allowed_methods.iter().any(|method| method == "*") {
+        cors = cors.allow_any_method();
+    } else {
+        cors = cors.allowed_methods(config.allowed_methods.iter().map(String::as_str));
+    }
+    if config.allowed_headers.iter().any(|header| header == "*") {
+        cors = cors.allow_any_header();
+    } else {
+        cors = cors.allowed_headers(config.allowed_headers.iter().map(String::as_str));
+    }
+    if config.allow_credentials {
+        cors = cors.supports_credentials();
+    }
+    cors
+}
+
+/// Reads the book snapshots to seed the engine with: one snapshot, or a list.
+fn read_snapshots(path: &str) -> Result<Vec<models::FrozenBook>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+    let snapshots = match serde_json::from_str::<serde_json::Value>(&contents) {
+        Ok(serde_json::Value::Array(_)) => serde_json::from_str(&contents),
+        _ => serde_json::from_str(&contents).map(|snapshot| vec![snapshot]),
+    };
+    snapshots.map_err(|error| format!("{}: {}", path, error))
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // Initialize tracing
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into()),
-        ))
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    // Initialize tracing (set LOG_FORMAT=json for machine-parseable logs)
+    let log_level = logging::init(logging::LogFormat::from_env());
 
+    let started_at = handlers::health::StartedAt::now();
     info!("Starting Exchange API server...");
 
     // Load configuration
     let config = Config::from_env().expect("Failed to load configuration");
+    rounding::configure(config.rounding);
 
     // Create services
     let order_book = OrderBookService::new();
+    for market in &config.markets {
+        if let Some(min_trade_quantity) = market.min_trade_quantity {
+            order_book.set_min_trade_quantity(&market.symbol, min_trade_quantity).await;
+        }
+        if let Some(max_resting_orders) = market.max_resting_orders {
+            order_book.set_max_resting_orders(&market.symbol, max_resting_orders).await;
+        }
+        if let Some(min_quote_life_ms) = market.min_quote_life_ms {
+            order_book.set_min_quote_life(&market.symbol, chrono::Duration::milliseconds(min_quote_life_ms as i64)).await;
+        }
+    }
+    let market_service = MarketService::new(config.markets.clone());
+    let market_data_service = MarketDataService::new();
+    let fee_service = FeeService::new(config.fees.clone());
+    let balance_service = BalanceService::new(config.account.clone());
+    let risk_service = RiskService::new(config.risk.limits.clone());
+    let password_service = PasswordService::new(config.auth.clone());
+    let totp_service = TotpService::new(config.auth.totp_encryption_key.as_deref().unwrap_or(&config.jwt.secret));
+    let api_key_cipher = SecretCipher::new(config.auth.api_key_encryption_key.as_deref().unwrap_or(&config.jwt.secret));
     
     #[cfg(feature = "database")]
-    let order_service = {
-        use sqlx::PgPool;
-        let pool = PgPool::connect(&config.database.url)
-            .await
-            .expect("Failed to connect to database");
-        OrderService::new(pool, order_book)
+    let (order_service, auth_service, api_key_service, maintenance_service, trade_persister, dependencies) = {
+        use sqlx::postgres::PgPoolOptions;
+        let pool_options = PgPoolOptions::new()
+            .max_connections(config.database.max_connections)
+            .min_connections(config.database.min_connections)
+            .acquire_timeout(std::time::Duration::from_secs(config.database.acquire_timeout_secs));
+        let pool = services::dependencies::connect_with_retry("database", config.database.connect_retry, || {
+            pool_options.clone().connect(&config.database.url)
+        })
+        .await
+        .expect("Failed to connect to database");
+        let replica = match &config.database.replica_url {
+            Some(url) => Some(
+                services::dependencies::connect_with_retry("database replica", config.database.connect_retry, || {
+                    pool_options.clone().connect(url)
+                })
+                .await
+                .expect("Failed to connect to the database replica"),
+            ),
+            None => None,
+        };
+        let trade_persister = TradePersister::new(pool.clone(), config.persistence.clone());
+        let redis = redis::Client::open(config.redis.url.as_str()).expect("Invalid Redis URL");
+        let dependencies = Dependencies::new()
+            .with(PostgresProbe(pool.clone()))
+            .with(RedisProbe(redis.clone()));
+        (
+            OrderService::new(DatabasePools::new(pool.clone(), replica), trade_persister.clone(), order_book.clone(), market_service.clone(), market_data_service.clone(), fee_service.clone(), balance_service.clone(), risk_service.clone(), config.trading.clone()),
+            AuthService::new(
+                pool.clone(),
+                redis,
+                password_service.clone(),
+                totp_service.clone(),
+                config.jwt.clone(),
+            ),
+            ApiKeyService::new(pool.clone(), api_key_cipher),
+            MaintenanceService::new(pool, config.maintenance.window()),
+            trade_persister,
+            dependencies,
+        )
     };
 
     #[cfg(not(feature = "database"))]
-    let order_service = OrderService::new(order_book);
+    let order_service = OrderService::new(order_book.clone(), market_service.clone(), market_data_service.clone(), fee_service.clone(), balance_service.clone(), risk_service.clone(), config.trading.clone());
+    #[cfg(not(feature = "database"))]
+    let auth_service = AuthService::new(password_service.clone(), totp_service.clone(), config.jwt.clone());
+    #[cfg(not(feature = "database"))]
+    let api_key_service = ApiKeyService::new(api_key_cipher);
+    #[cfg(not(feature = "database"))]
+    let maintenance_service = MaintenanceService::new(config.maintenance.window());
+    #[cfg(not(feature = "database"))]
+    let dependencies = Dependencies::new();
+
+    maintenance_service.load().await.expect("Failed to load the maintenance window");
+    if let Some(window) = maintenance_service.current().await {
+        info!(start = %window.start, end = %window.end, "Maintenance window scheduled, writes are refused during it");
+    }
+
+    // A bad snapshot stops startup rather than opening on a partial book
+    let snapshots = match &config.seed.snapshot_path {
+        Some(path) => read_snapshots(path).expect("Failed to read the order book snapshot"),
+        None => Vec::new(),
+    };
+    for snapshot in &snapshots {
+        OrderBookService::check_snapshot(snapshot)
+            .expect("Invalid order book snapshot");
+    }
+
+    // Rebuild the book from stored orders while the server comes up; orders
+    // are refused with 503 until it is done
+    order_service.begin_warmup().await;
+    let recovering = order_service.clone();
+    actix_rt::spawn(async move {
+        if !snapshots.is_empty() {
+            match recovering.seed(&snapshots).await {
+                Ok(count) => info!(count, "Order book seeded from snapshot"),
+                Err(error) => {
+                    tracing::error!(%error, "Order book seeding failed, still refusing orders");
+                    return;
+                }
+            }
+        }
+        match recovering.recover().await {
+            Ok(count) => info!(count, "Order book recovered, accepting orders"),
+            Err(error) => tracing::error!(%error, "Order book recovery failed, still refusing orders"),
+        }
+    });
+
+    // Expire orders that outlive their market's default TTL, and act on the
+    // conditional orders trades activate
+    let mut background_tasks = BackgroundTasks::new();
+    let sweeper = order_service.clone();
+    background_tasks.spawn("expiry_sweeper", move |shutdown| sweeper.run_expiry_sweeper(std::time::Duration::from_secs(1), shutdown));
+    let watcher = order_service.clone();
+    background_tasks.spawn("trade_watcher", move |shutdown| watcher.run_trade_watcher(shutdown));
+    let peg_watcher = order_service.clone();
+    background_tasks.spawn("peg_watcher", move |shutdown| peg_watcher.run_peg_watcher(shutdown));
+    #[cfg(feature = "database")]
+    background_tasks.spawn("trade_persister", move |shutdown| trade_persister.run(shutdown));
+
+    #[cfg(feature = "fix")]
+    {
+        let gateway = fix::FixGateway::new(order_service.clone(), api_key_service.clone(), config.fix.clone(), config.precision);
+        let listener = tokio::net::TcpListener::bind((config.server.host.as_str(), config.fix.port)).await?;
+        info!("FIX gateway listening on {}:{}", config.server.host, config.fix.port);
+        background_tasks.spawn("fix_gateway", move |shutdown| gateway.run(listener, shutdown));
+    }
+
+    let admin_config = config.admin.clone();
+    let rate_limiter = RateLimiter::new(config.rate_limits.clone());
+    let precision = config.precision;
+    let request_timeout = timeout::RequestTimeout(std::time::Duration::from_secs(config.server.request_timeout_secs));
+    let metrics_registry = prometheus::Registry::new();
+    metrics_registry
+        .register(Box::new(order_book.matching_latency().clone()))
+        .expect("Failed to register matching latency metrics");
+    let cors_config = config.cors.clone();
 
     // Create HTTP server
     let server = HttpServer::new(move || {
         App::new()
             .wrap(middleware::Logger::default())
-            .wrap(
-                Cors::default()
-                    .allow_any_origin()
-                    .allow_any_method()
-                    .allow_any_header()
-                    .max_age(3600),
-            )
+            .wrap_fn(|req, srv| {
+                // Attach a request span so every event logged while handling the
+                // request carries its id, method and path
+                let span = tracing::info_span!(
+                    "request",
+                    request_id = %Uuid::new_v4(),
+                    method = %req.method(),
+                    path = %req.path(),
+                );
+                srv.call(req).instrument(span)
+            })
+            .wrap(cors(&cors_config))
             .app_data(web::Data::new(order_service.clone()))
+            .app_data(web::Data::new(order_book.clone()))
+            .app_data(web::Data::new(market_service.clone()))
+            .app_data(web::Data::new(market_data_service.clone()))
+            .app_data(web::Data::new(fee_service.clone()))
+            .app_data(web::Data::new(balance_service.clone()))
+            .app_data(web::Data::new(password_service.clone()))
+            .app_data(web::Data::new(auth_service.clone()))
+            .app_data(web::Data::new(api_key_service.clone()))
+            .app_data(web::Data::new(maintenance_service.clone()))
+            .app_data(web::Data::new(admin_config.clone()))
+            .app_data(web::Data::new(log_level.clone()))
+            .app_data(web::Data::new(rate_limiter.clone()))
+            .app_data(web::Data::new(precision))
+            .app_data(web::Data::new(request_timeout))
+            .app_data(web::Data::new(started_at))
+            .app_data(web::Data::new(metrics_registry.clone()))
+            .app_data(web::Data::new(dependencies.clone()))
+            .service(handlers::health::metrics)
+            .configure(handlers::health::configure_probes)
             .service(swagger_ui)
             .service(openapi_spec)
             .service(
                 web::scope("/api/v1")
-                    .service(handlers::health::health_check)
+                    .wrap(middleware::from_fn(auth::buffer_signed_body))
+                    .wrap(middleware::from_fn(rate_limiter::limit_requests))
+                    .wrap(middleware::from_fn(maintenance_service::block_writes))
+                    .wrap(middleware::from_fn(timeout::limit_duration))
+                    .wrap(middleware::from_fn(encoding::negotiate))
+                    .configure(handlers::health::configure_probes)
+                    .service(handlers::health::server_time)
                     .configure(handlers::orders::configure)
+                    .configure(handlers::trades::configure)
+                    .configure(handlers::market::configure)
+                    .configure(handlers::account::configure)
+                    .configure(handlers::auth::configure)
+                    .configure(handlers::ws::configure)
+                    .configure(handlers::stream::configure)
+                    .configure(handlers::admin::configure)
             )
     })
     .bind(format!("{}:{}", config.server.host, config.server.port))?
@@ -133,5 +357,10 @@ async fn main() -> std::io::Result<()> {
     info!("Server running on {}:{}", config.server.host, config.server.port);
     info!("Swagger UI available at: http://{}:{}/swagger-ui", config.server.host, config.server.port);
 
-    server.await
+    // The server stops on SIGINT/SIGTERM once in-flight requests finish; the
+    // background tasks go after it so their last writes land
+    let result = server.await;
+    info!("Stopping background tasks...");
+    background_tasks.shutdown().await;
+    result
 } 
\ No newline at end of file
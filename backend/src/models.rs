@@ -5,14 +5,24 @@ use uuid::Uuid;
 use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
 use validator::Validate;
+use crate::config::PrecisionConfig;
+
+/// Largest `metadata` blob an order may carry, in bytes of compact JSON.
+pub const MAX_ORDER_METADATA_BYTES: usize = 1024;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[cfg_attr(feature = "database", derive(FromRow))]
 pub struct User {
     pub id: Uuid,
     pub email: String,
+    #[serde(skip_serializing)]
     pub password_hash: String,
     pub status: UserStatus,
+    /// Encrypted TOTP secret, set once two-factor setup has started.
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<String>,
+    /// Whether login requires a TOTP code. Only set after the secret is verified.
+    pub totp_enabled: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -34,15 +44,38 @@ pub struct Order {
     pub symbol: String,
     pub side: OrderSide,
     pub quantity: Decimal,
-    pub price: Decimal,
+    pub price: Option<Decimal>,
     pub order_type: OrderType,
+    /// Rests on the book but only ever executes in full, in a single match.
+    pub all_or_none: bool,
+    /// Trade price at which a stop order activates.
+    pub stop_price: Option<Decimal>,
+    /// Symbol whose trades trigger a stop order, when not its own.
+    pub trigger_symbol: Option<String>,
+    /// How far from the best bid (buys) or ask (sells) a pegged order rests.
+    pub peg_offset: Option<Decimal>,
+    /// Worst price a market order may execute at: the most a buy pays, the
+    /// least a sell takes.
+    pub protection_price: Option<Decimal>,
+    /// Shared by the legs of a one-cancels-other pair.
+    pub oco_group_id: Option<Uuid>,
+    /// The client's own id for the order, unique per user.
+    pub client_order_id: Option<String>,
+    /// Opaque JSON the client attached, e.g. a strategy id. Never read by matching.
+    pub metadata: Option<serde_json::Value>,
     pub status: OrderStatus,
     pub filled_quantity: Decimal,
+    /// Why the order was rejected, when `status` is `Rejected`.
+    pub reject_reason: Option<String>,
+    /// When the engine accepted the order, in one sequence across all
+    /// markets. Decides time priority, since timestamps can collide.
+    #[serde(default)]
+    pub acceptance_seq: i64,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "database", derive(sqlx::Type))]
 #[cfg_attr(feature = "database", sqlx(type_name = "order_side", rename_all = "lowercase"))]
 pub enum OrderSide {
@@ -50,6 +83,19 @@ pub enum OrderSide {
     Sell,
 }
 
+impl std::str::FromStr for OrderSide {
+    type Err = String;
+
+    /// Parses a side case-insensitively, as query parameters spell it.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "buy" => Ok(OrderSide::Buy),
+            "sell" => Ok(OrderSide::Sell),
+            _ => Err(format!("Unknown order side {}", value)),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[cfg_attr(feature = "database", derive(sqlx::Type))]
 #[cfg_attr(feature = "database", sqlx(type_name = "order_type", rename_all = "lowercase"))]
@@ -58,9 +104,12 @@ pub enum OrderType {
     Limit,
     Stop,
     StopLimit,
+    /// Rests at the best price on its own side plus `peg_offset`, and is
+    /// repriced as that best price moves.
+    Pegged,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "database", derive(sqlx::Type))]
 #[cfg_attr(feature = "database", sqlx(type_name = "order_status", rename_all = "lowercase"))]
 pub enum OrderStatus {
@@ -70,44 +119,334 @@ pub enum OrderStatus {
     Filled,
     Cancelled,
     Rejected,
+    /// Reached its market's maximum order lifetime while still open.
+    Expired,
+}
+
+impl OrderStatus {
+    /// Whether an order in this status can still trade or be cancelled.
+    pub fn is_open(&self) -> bool {
+        matches!(self, OrderStatus::New | OrderStatus::Open | OrderStatus::PartiallyFilled)
+    }
+}
+
+impl std::str::FromStr for OrderStatus {
+    type Err = String;
+
+    /// Parses a status case-insensitively, with or without underscores, so
+    /// `partially_filled`, `partiallyfilled` and `PartiallyFilled` all match.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.replace('_', "").to_lowercase().as_str() {
+            "new" => Ok(OrderStatus::New),
+            "open" => Ok(OrderStatus::Open),
+            "partiallyfilled" => Ok(OrderStatus::PartiallyFilled),
+            "filled" => Ok(OrderStatus::Filled),
+            "cancelled" => Ok(OrderStatus::Cancelled),
+            "rejected" => Ok(OrderStatus::Rejected),
+            "expired" => Ok(OrderStatus::Expired),
+            _ => Err(format!("Unknown order status {}", value)),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[cfg_attr(feature = "database", derive(FromRow))]
 pub struct Trade {
     pub id: Uuid,
+    /// Engine sequence number; orders trades that share an `executed_at`.
+    pub seq: i64,
+    /// The resting (maker) order that was hit.
     pub order_id: Uuid,
+    pub taker_order_id: Uuid,
+    pub maker_user_id: Uuid,
+    pub taker_user_id: Uuid,
+    pub taker_side: OrderSide,
     pub symbol: String,
     pub quantity: Decimal,
     pub price: Decimal,
+    /// The taker's limit price; `None` for market orders.
+    pub taker_limit_price: Option<Decimal>,
     pub executed_at: DateTime<Utc>,
 }
 
+impl Trade {
+    /// How much better than its limit the taker traded, in quote per unit:
+    /// below the limit for a buy, above it for a sell.
+    pub fn price_improvement(&self) -> Option<Decimal> {
+        let limit = self.taker_limit_price?;
+        Some(match self.taker_side {
+            OrderSide::Buy => limit - self.price,
+            OrderSide::Sell => self.price - limit,
+        })
+    }
+}
+
+/// Splits a `BASE/QUOTE` symbol into its two assets.
+pub fn split_symbol(symbol: &str) -> Option<(&str, &str)> {
+    let (base, quote) = symbol.split_once('/')?;
+    if base.is_empty() || quote.is_empty() {
+        return None;
+    }
+    Some((base, quote))
+}
+
+/// Checks that `symbol` names two different assets, as `BASE/QUOTE`.
+pub fn validate_symbol(symbol: &str) -> Result<(), String> {
+    match split_symbol(symbol) {
+        None => Err(format!("Symbol {} must be BASE/QUOTE", symbol)),
+        Some((base, quote)) if base.eq_ignore_ascii_case(quote) => {
+            Err(format!("Symbol {} must have different base and quote assets", symbol))
+        }
+        Some(_) => Ok(()),
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateOrderRequest {
     pub symbol: String,
     pub side: OrderSide,
     pub quantity: Decimal,
-    pub price: Decimal,
+    /// Limit price. Required for `Limit` and `StopLimit` orders.
+    #[serde(default)]
+    pub price: Option<Decimal>,
     pub order_type: OrderType,
+    /// Only match when the whole quantity can be filled at once.
+    #[serde(default)]
+    pub all_or_none: bool,
+    /// Required for `Stop` and `StopLimit` orders. A buy stop activates once
+    /// a trade prints at or above it, a sell stop at or below.
+    #[serde(default)]
+    pub stop_price: Option<Decimal>,
+    /// Symbol whose trades are compared to `stop_price`. Defaults to the
+    /// order's own symbol.
+    #[serde(default)]
+    pub trigger_symbol: Option<String>,
+    /// Required for `Pegged` orders: added to the best price on the order's
+    /// own side, ignoring other pegged orders, to give its price. Negative
+    /// offsets rest behind the best buy price, positive ones behind the best
+    /// sell price.
+    #[serde(default)]
+    pub peg_offset: Option<Decimal>,
+    /// Only for `Market` orders: the highest price a buy may execute at, or
+    /// the lowest a sell may. Whatever the book can't fill within it is
+    /// cancelled rather than taken at a worse price.
+    #[serde(default)]
+    pub protection_price: Option<Decimal>,
+    /// The client's own id for the order, unique per user. Placing an order
+    /// with an id already used returns the existing order instead.
+    #[serde(default)]
+    pub client_order_id: Option<String>,
+    /// Opaque JSON echoed back on the order and its trades, up to
+    /// `MAX_ORDER_METADATA_BYTES`.
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
 }
 
 impl CreateOrderRequest {
     pub fn validate(&self) -> Result<(), String> {
+        self.validate_with(false)
+    }
+
+    /// Validates the request for a market that may take zero and negative
+    /// prices, when `allow_negative_price` is set.
+    pub fn validate_with(&self, allow_negative_price: bool) -> Result<(), String> {
         if self.symbol.is_empty() || self.symbol.len() > 20 {
             return Err("Symbol must be between 1 and 20 characters".to_string());
         }
+        validate_symbol(&self.symbol)?;
         
         if self.quantity <= Decimal::ZERO {
             return Err("Quantity must be greater than 0".to_string());
         }
         
-        if self.price <= Decimal::ZERO {
-            return Err("Price must be greater than 0".to_string());
+        match (&self.order_type, self.price) {
+            (OrderType::Market, Some(_)) => {
+                return Err("Price must not be set for market orders".to_string());
+            }
+            (OrderType::Stop, Some(_)) => {
+                return Err("Price must not be set for stop orders, which execute at market".to_string());
+            }
+            (OrderType::Pegged, Some(_)) => {
+                return Err("Price must not be set for pegged orders, which take it from the book".to_string());
+            }
+            (OrderType::Market | OrderType::Stop | OrderType::Pegged, None) => {}
+            (_, None) => {
+                return Err("Price is required for limit and stop-limit orders".to_string());
+            }
+            (_, Some(price)) if price <= Decimal::ZERO && !allow_negative_price => {
+                return Err("Price must be greater than 0".to_string());
+            }
+            _ => {}
+        }
+
+        match (&self.order_type, self.stop_price) {
+            (OrderType::Stop | OrderType::StopLimit, None) => {
+                return Err("Stop price is required for stop and stop-limit orders".to_string());
+            }
+            (OrderType::Stop | OrderType::StopLimit, Some(stop_price)) if stop_price <= Decimal::ZERO && !allow_negative_price => {
+                return Err("Stop price must be greater than 0".to_string());
+            }
+            (OrderType::Market | OrderType::Limit | OrderType::Pegged, Some(_)) => {
+                return Err("Stop price is only allowed on stop and stop-limit orders".to_string());
+            }
+            _ => {}
+        }
+
+        match (&self.order_type, self.peg_offset) {
+            (OrderType::Pegged, None) => {
+                return Err("Peg offset is required for pegged orders".to_string());
+            }
+            (OrderType::Pegged, Some(_)) | (_, None) => {}
+            (_, Some(_)) => {
+                return Err("Peg offset is only allowed on pegged orders".to_string());
+            }
+        }
+
+        match (&self.order_type, self.protection_price) {
+            (OrderType::Market, Some(price)) if price <= Decimal::ZERO && !allow_negative_price => {
+                return Err("Protection price must be greater than 0".to_string());
+            }
+            (OrderType::Market, _) | (_, None) => {}
+            (_, Some(_)) => {
+                return Err("Protection price is only allowed on market orders".to_string());
+            }
+        }
+
+        if self.trigger_symbol.is_some() && self.stop_price.is_none() {
+            return Err("Trigger symbol is only allowed on stop and stop-limit orders".to_string());
+        }
+
+        if let Some(client_order_id) = &self.client_order_id {
+            if client_order_id.is_empty() || client_order_id.len() > 64 {
+                return Err("Client order id must be between 1 and 64 characters".to_string());
+            }
+        }
+
+        if let Some(metadata) = &self.metadata {
+            if metadata.to_string().len() > MAX_ORDER_METADATA_BYTES {
+                return Err(format!("Metadata must be at most {} bytes of JSON", MAX_ORDER_METADATA_BYTES));
+            }
         }
         
         Ok(())
     }
+
+    /// Rejects prices and quantities with more decimal places than
+    /// `precision` allows. Trailing zeros don't count, so `1.500` has one.
+    pub fn validate_precision(&self, precision: &PrecisionConfig) -> Result<(), String> {
+        let too_precise = |value: Decimal, max_scale: u32| value.normalize().scale() > max_scale;
+
+        if too_precise(self.quantity, precision.max_quantity_scale) {
+            return Err(format!("Quantity must have at most {} decimal places", precision.max_quantity_scale));
+        }
+        for price in [self.price, self.stop_price, self.peg_offset, self.protection_price].into_iter().flatten() {
+            if too_precise(price, precision.max_price_scale) {
+                return Err(format!("Prices must have at most {} decimal places", precision.max_price_scale));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl CreateOrderRequest {
+    /// A plain limit order with every optional field left unset.
+    pub fn limit(symbol: &str, side: OrderSide, quantity: Decimal, price: Decimal) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            side,
+            quantity,
+            price: Some(price),
+            order_type: OrderType::Limit,
+            all_or_none: false,
+            stop_price: None,
+            trigger_symbol: None,
+            peg_offset: None,
+            protection_price: None,
+            client_order_id: None,
+            metadata: None,
+        }
+    }
+}
+
+/// Orders placed together as a one-cancels-other group.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateOcoRequest {
+    pub orders: Vec<CreateOrderRequest>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReduceOrderRequest {
+    /// New total order quantity, including anything already filled.
+    pub quantity: Decimal,
+}
+
+impl ReduceOrderRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.quantity <= Decimal::ZERO {
+            return Err("Quantity must be greater than 0".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// Changes to an open order's price and total quantity; a field left out
+/// stays as it is. The symbol, side and type are accepted only to be refused,
+/// since changing them takes a new order.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PatchOrderRequest {
+    pub price: Option<Decimal>,
+    /// New total order quantity, including anything already filled.
+    pub quantity: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub side: Option<OrderSide>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order_type: Option<OrderType>,
+}
+
+impl PatchOrderRequest {
+    pub fn validate_with(&self, allow_negative_price: bool) -> Result<(), String> {
+        for (field, present) in [
+            ("symbol", self.symbol.is_some()),
+            ("side", self.side.is_some()),
+            ("order_type", self.order_type.is_some()),
+        ] {
+            if present {
+                return Err(format!("An order's {} can't be changed; cancel it and place a new one", field));
+            }
+        }
+
+        if self.price.is_none() && self.quantity.is_none() {
+            return Err("Give a new price, quantity or both".to_string());
+        }
+
+        if self.quantity.is_some_and(|quantity| quantity <= Decimal::ZERO) {
+            return Err("Quantity must be greater than 0".to_string());
+        }
+
+        if self.price.is_some_and(|price| price <= Decimal::ZERO) && !allow_negative_price {
+            return Err("Price must be greater than 0".to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn validate_precision(&self, precision: &PrecisionConfig) -> Result<(), String> {
+        let too_precise = |value: Decimal, max_scale: u32| value.normalize().scale() > max_scale;
+
+        if self.quantity.is_some_and(|quantity| too_precise(quantity, precision.max_quantity_scale)) {
+            return Err(format!("Quantity must have at most {} decimal places", precision.max_quantity_scale));
+        }
+        if self.price.is_some_and(|price| too_precise(price, precision.max_price_scale)) {
+            return Err(format!("Prices must have at most {} decimal places", precision.max_price_scale));
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
@@ -122,34 +461,475 @@ pub struct CreateUserRequest {
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
+    /// Current TOTP code, required when two-factor authentication is enabled.
+    #[serde(default)]
+    pub totp_code: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoginResponse {
     pub token: String,
+    pub refresh_token: String,
     pub user: User,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorSetupResponse {
+    /// Base32 secret for manual entry into an authenticator app.
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorVerifyRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub token: String,
+    pub refresh_token: String,
+}
+
+/// A stored refresh token. Only the SHA-256 hash of the token is kept.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "database", derive(FromRow))]
+pub struct RefreshToken {
+    pub token_hash: String,
+    pub user_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// Hash of the token this one was rotated into, once used.
+    pub replaced_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A permission carried by an access token or API key.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "database", derive(sqlx::Type))]
+#[cfg_attr(feature = "database", sqlx(type_name = "api_key_scope", rename_all = "lowercase"))]
+pub enum Scope {
+    Read,
+    Trade,
+    Withdraw,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Scope::Read, Scope::Trade, Scope::Withdraw];
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scope::Read => write!(f, "read"),
+            Scope::Trade => write!(f, "trade"),
+            Scope::Withdraw => write!(f, "withdraw"),
+        }
+    }
+}
+
+/// A key pair for HMAC signed requests. Verifying a signature needs the
+/// secret itself, so it is stored encrypted rather than hashed.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "database", derive(FromRow))]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub key: String,
+    pub secret_encrypted: String,
+    pub scopes: Vec<Scope>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateApiKeyRequest {
+    /// Defaults to read-only.
+    #[serde(default = "default_api_key_scopes")]
+    pub scopes: Vec<Scope>,
+}
+
+fn default_api_key_scopes() -> Vec<Scope> {
+    vec![Scope::Read]
+}
+
+impl CreateApiKeyRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.scopes.is_empty() {
+            return Err("At least one scope is required".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// An API key as listed to its owner. The secret is never included.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyResponse {
+    pub id: Uuid,
+    pub key: String,
+    pub scopes: Vec<Scope>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<ApiKey> for ApiKeyResponse {
+    fn from(api_key: ApiKey) -> Self {
+        Self {
+            id: api_key.id,
+            key: api_key.key,
+            scopes: api_key.scopes,
+            revoked_at: api_key.revoked_at,
+            created_at: api_key.created_at,
+        }
+    }
+}
+
+/// Returned once, when a key is created: the only time the secret is shown.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatedApiKeyResponse {
+    #[serde(flatten)]
+    pub api_key: ApiKeyResponse,
+    pub secret: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OrderResponse {
     pub id: Uuid,
     pub symbol: String,
     pub side: OrderSide,
     pub quantity: Decimal,
-    pub price: Decimal,
+    pub price: Option<Decimal>,
     pub order_type: OrderType,
+    pub all_or_none: bool,
+    pub stop_price: Option<Decimal>,
+    pub trigger_symbol: Option<String>,
+    pub peg_offset: Option<Decimal>,
+    pub protection_price: Option<Decimal>,
+    pub oco_group_id: Option<Uuid>,
+    pub client_order_id: Option<String>,
+    pub metadata: Option<serde_json::Value>,
     pub status: OrderStatus,
     pub filled_quantity: Decimal,
+    pub reject_reason: Option<String>,
+    /// Position in which the engine accepted the order, for auditing its
+    /// time priority.
+    pub acceptance_seq: i64,
     pub created_at: DateTime<Utc>,
 }
 
+impl OrderResponse {
+    /// Pads prices to `price_scale` and quantities to `quantity_scale` decimal
+    /// places, so they serialize with a fixed number of decimals. Values with
+    /// more places than that are left as they are rather than rounded.
+    pub fn with_scale(mut self, price_scale: Option<u32>, quantity_scale: Option<u32>) -> Self {
+        if let Some(scale) = price_scale {
+            self.price = self.price.map(|price| pad_scale(price, scale));
+            self.stop_price = self.stop_price.map(|price| pad_scale(price, scale));
+            self.protection_price = self.protection_price.map(|price| pad_scale(price, scale));
+        }
+        if let Some(scale) = quantity_scale {
+            self.quantity = pad_scale(self.quantity, scale);
+            self.filled_quantity = pad_scale(self.filled_quantity, scale);
+        }
+        self
+    }
+}
+
+fn pad_scale(mut value: Decimal, scale: u32) -> Decimal {
+    if value.scale() < scale {
+        value.rescale(scale);
+    }
+    value
+}
+
+/// Outcome of cancelling one order in a batch.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CancelResult {
+    pub order_id: Uuid,
+    /// HTTP status the order's own cancel request would have returned.
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<OrderResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Everything an order executed at one price.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct PriceFill {
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub trade_count: usize,
+}
+
+impl PriceFill {
+    /// Sums `(price, quantity)` fills per price, lowest price first.
+    pub fn group(fills: impl IntoIterator<Item = (Decimal, Decimal)>) -> Vec<PriceFill> {
+        let mut levels: std::collections::BTreeMap<Decimal, PriceFill> = std::collections::BTreeMap::new();
+        for (price, quantity) in fills {
+            let level = levels.entry(price).or_insert_with(|| PriceFill {
+                price,
+                quantity: Decimal::ZERO,
+                trade_count: 0,
+            });
+            level.quantity += quantity;
+            level.trade_count += 1;
+        }
+        levels.into_values().collect()
+    }
+
+    /// Quantity-weighted average price of `fills`; `None` when nothing filled.
+    pub fn average_price(fills: &[PriceFill]) -> Option<Decimal> {
+        let quantity: Decimal = fills.iter().map(|fill| fill.quantity).sum();
+        let notional: Decimal = fills.iter().map(|fill| fill.price * fill.quantity).sum();
+        (!quantity.is_zero()).then(|| notional / quantity)
+    }
+}
+
+/// An order's fills grouped by execution price, for transaction cost analysis.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrderFills {
+    pub order_id: Uuid,
+    pub fills: Vec<PriceFill>, // Lowest price first
+    pub filled_quantity: Decimal,
+    /// Quantity-weighted average execution price; `None` before any fill.
+    pub average_price: Option<Decimal>,
+}
+
+/// What an order would do if placed now, from a dry run against the book.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrderPreview {
+    pub symbol: String,
+    pub side: OrderSide,
+    pub quantity: Decimal,
+    pub fills: Vec<PriceFill>, // Lowest price first
+    pub filled_quantity: Decimal,
+    /// Quantity-weighted average execution price; `None` when nothing would fill.
+    pub average_price: Option<Decimal>,
+    pub remaining_quantity: Decimal,
+    /// Whether the remaining quantity would rest on the book.
+    pub would_rest: bool,
+}
+
+/// The outcome of delisting a market.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MarketDelisting {
+    pub symbol: String,
+    /// Orders that were open on the market, cancelled oldest first.
+    pub cancelled_orders: Vec<Uuid>,
+}
+
+/// The outcome of clearing a symbol's book in memory.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BookReset {
+    pub symbol: String,
+    /// Orders taken off the book.
+    pub cleared_orders: Vec<Uuid>,
+    /// Whether they were cancelled on record too. Otherwise they stay open
+    /// there and rest on the book again at the next recovery.
+    pub cancelled: bool,
+}
+
+/// What a market order of a given size would cost against the book as it
+/// stands, from walking the opposite side.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SlippageEstimate {
+    pub symbol: String,
+    pub side: OrderSide,
+    pub quantity: Decimal,
+    pub filled_quantity: Decimal,
+    /// Quantity-weighted average fill price; `None` when nothing would fill.
+    pub average_price: Option<Decimal>,
+    /// Price of the last level reached.
+    pub worst_price: Option<Decimal>,
+    pub mid_price: Option<Decimal>,
+    /// How much worse than mid the average fill is, in basis points. `None`
+    /// without a mid, or when mid is at or below zero.
+    pub slippage_bps: Option<Decimal>,
+    /// Whether the book holds enough to fill the whole quantity.
+    pub fully_fillable: bool,
+}
+
+/// A resting order's place in line at its price level.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueuePosition {
+    pub order_id: Uuid,
+    pub symbol: String,
+    pub side: OrderSide,
+    pub price: Decimal,
+    /// Orders at the same price that will fill first.
+    pub orders_ahead: usize,
+    /// Remaining quantity of those orders.
+    pub quantity_ahead: Decimal,
+}
+
+/// Consecutive trades at one price with the same taker side, merged into
+/// one record, as busy markets publish them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AggTrade {
+    pub symbol: String,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub taker_side: OrderSide,
+    pub first_trade_id: Uuid,
+    pub last_trade_id: Uuid,
+    pub first_seq: i64,
+    pub last_seq: i64,
+    pub trade_count: usize,
+    /// When the first of the merged trades executed.
+    pub executed_at: DateTime<Utc>,
+}
+
+impl AggTrade {
+    /// Merges runs of `trades`, which must be in execution order, that share
+    /// a price and taker side.
+    pub fn aggregate(trades: impl IntoIterator<Item = Trade>) -> Vec<AggTrade> {
+        let mut aggregated: Vec<AggTrade> = Vec::new();
+        for trade in trades {
+            if let Some(last) = aggregated.last_mut() {
+                if last.price == trade.price && last.taker_side == trade.taker_side && last.symbol == trade.symbol {
+                    last.quantity += trade.quantity;
+                    last.last_trade_id = trade.id;
+                    last.last_seq = trade.seq;
+                    last.trade_count += 1;
+                    continue;
+                }
+            }
+            aggregated.push(AggTrade {
+                symbol: trade.symbol,
+                price: trade.price,
+                quantity: trade.quantity,
+                taker_side: trade.taker_side,
+                first_trade_id: trade.id,
+                last_trade_id: trade.id,
+                first_seq: trade.seq,
+                last_seq: trade.seq,
+                trade_count: 1,
+                executed_at: trade.executed_at,
+            });
+        }
+        aggregated
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TradeResponse {
     pub id: Uuid,
+    pub seq: i64,
     pub symbol: String,
     pub quantity: Decimal,
     pub price: Decimal,
+    pub taker_limit_price: Option<Decimal>,
+    pub price_improvement: Option<Decimal>,
     pub executed_at: DateTime<Utc>,
+    /// Metadata of the caller's order in the trade.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+/// What happened to an order in an `ExecutionReport`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionType {
+    /// Taken by the book, or parked until its stop price is reached.
+    Accepted,
+    /// Price or quantity changed by its owner.
+    Replaced,
+    Rejected,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Expired,
+}
+
+/// The trade behind a fill's `ExecutionReport`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExecutionFill {
+    pub trade_id: Uuid,
+    pub seq: i64,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    /// Whether the order was the resting side of the trade.
+    pub maker: bool,
+}
+
+/// One step in an order's life, with the order as it stood after it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExecutionReport {
+    pub exec_type: ExecutionType,
+    pub order_id: Uuid,
+    pub user_id: Uuid,
+    pub client_order_id: Option<String>,
+    pub symbol: String,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    pub price: Option<Decimal>,
+    pub quantity: Decimal,
+    /// Filled so far, including `last_fill`.
+    pub filled_quantity: Decimal,
+    /// Still to fill; zero once the order is done.
+    pub leaves_quantity: Decimal,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_fill: Option<ExecutionFill>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reject_reason: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl ExecutionReport {
+    /// A report of `exec_type` for `order` as it stands.
+    pub fn new(exec_type: ExecutionType, order: &Order) -> Self {
+        let leaves_quantity = if order.status.is_open() {
+            (order.quantity - order.filled_quantity).max(Decimal::ZERO)
+        } else {
+            Decimal::ZERO
+        };
+        ExecutionReport {
+            exec_type,
+            order_id: order.id,
+            user_id: order.user_id,
+            client_order_id: order.client_order_id.clone(),
+            symbol: order.symbol.clone(),
+            side: order.side.clone(),
+            order_type: order.order_type.clone(),
+            price: order.price,
+            quantity: order.quantity,
+            filled_quantity: order.filled_quantity,
+            leaves_quantity,
+            last_fill: None,
+            reject_reason: order.reject_reason.clone(),
+            timestamp: order.updated_at,
+        }
+    }
+
+    /// The fill report for `order`'s side of `trade`, where `filled_quantity`
+    /// is what the order had filled once `trade` executed.
+    pub fn fill(order: &Order, trade: &Trade, filled_quantity: Decimal) -> Self {
+        let leaves_quantity = (order.quantity - filled_quantity).max(Decimal::ZERO);
+        let exec_type = if leaves_quantity.is_zero() {
+            ExecutionType::Filled
+        } else {
+            ExecutionType::PartiallyFilled
+        };
+        ExecutionReport {
+            filled_quantity,
+            leaves_quantity,
+            last_fill: Some(ExecutionFill {
+                trade_id: trade.id,
+                seq: trade.seq,
+                price: trade.price,
+                quantity: trade.quantity,
+                maker: trade.order_id == order.id,
+            }),
+            timestamp: trade.executed_at,
+            ..ExecutionReport::new(exec_type, order)
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -165,14 +945,250 @@ pub struct OrderBook {
     pub bids: Vec<OrderBookEntry>,
     pub asks: Vec<OrderBookEntry>,
     pub last_updated: DateTime<Utc>,
+    /// Levels asked for, when the client opted into depth metadata.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requested_depth: Option<usize>,
+    /// Levels on the deeper side. Below `requested_depth` the book is thin
+    /// rather than truncated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub returned_depth: Option<usize>,
+}
+
+/// Every order resting at one price, in queue order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrozenLevel {
+    pub price: Decimal,
+    pub quantity: Decimal, // Remaining quantity across `orders`
+    pub orders: Vec<Order>,
+}
+
+/// A full copy of one symbol's book at a single point in time, for audits.
+/// Unlike `OrderBook` it keeps every level and every order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrozenBook {
+    pub symbol: String,
+    pub bids: Vec<FrozenLevel>, // Highest price first
+    pub asks: Vec<FrozenLevel>, // Lowest price first
+    /// Engine sequence when taken: the book reflects every trade up to it.
+    pub sequence: i64,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub frozen_at: DateTime<Utc>,
+}
+
+/// A stored order as it stood after one change to it: accepted, resting,
+/// filled, reduced or closed. The journal of these is what order book
+/// replays are rebuilt from.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "database", derive(FromRow))]
+pub struct OrderEvent {
+    /// Journal position, assigned when the event is written.
+    pub seq: i64,
+    pub order_id: Uuid,
+    pub symbol: String,
+    pub side: OrderSide,
+    pub price: Option<Decimal>,
+    pub quantity: Decimal,
+    pub filled_quantity: Decimal,
+    pub status: OrderStatus,
+    /// Whether the order rests on the book after the change.
+    pub resting: bool,
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl OrderEvent {
+    /// An event for `order` as it stands, not yet given a journal position.
+    pub fn new(order: &Order, resting: bool, occurred_at: DateTime<Utc>) -> Self {
+        Self {
+            seq: 0,
+            order_id: order.id,
+            symbol: order.symbol.clone(),
+            side: order.side.clone(),
+            price: order.price,
+            quantity: order.quantity,
+            filled_quantity: order.filled_quantity,
+            status: order.status.clone(),
+            resting,
+            occurred_at,
+        }
+    }
+}
+
+/// The book right after a journalled order change.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BookReplayStep {
+    pub event: OrderEvent,
+    pub bids: Vec<OrderBookEntry>, // Highest price first
+    pub asks: Vec<OrderBookEntry>, // Lowest price first
+}
+
+/// A symbol's book replayed from the order event journal over `[from, to)`:
+/// how it stood at `from`, then after each change within the window, with
+/// the trades executed meanwhile.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BookReplay {
+    pub symbol: String,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub bids: Vec<OrderBookEntry>,
+    pub asks: Vec<OrderBookEntry>,
+    pub steps: Vec<BookReplayStep>,
+    pub trades: Vec<Trade>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Ticker {
+    pub symbol: String,
+    pub best_bid: Option<Decimal>,
+    pub best_bid_quantity: Option<Decimal>,
+    pub best_ask: Option<Decimal>,
+    pub best_ask_quantity: Option<Decimal>,
+    pub mid_price: Option<Decimal>,
+    /// Size-weighted mid: `(bid * ask_qty + ask * bid_qty) / (bid_qty + ask_qty)`.
+    pub microprice: Option<Decimal>,
+    /// Bid share of resting volume over the top levels, from 0 (all asks) to 1 (all bids).
+    pub imbalance: Option<Decimal>,
+    /// `(ask - bid) / mid * 10000`, rounded to 4 decimal places.
+    pub spread_bps: Option<Decimal>,
+    /// Milliseconds since the book last changed, for spotting a stale book.
+    pub last_update_age_ms: Option<i64>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Best bid and offer of one symbol, as pushed on the `bbo` websocket channel
+/// and the order book event stream.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BestBidOffer {
+    pub symbol: String,
+    pub bid: Option<Decimal>,
+    pub bid_quantity: Option<Decimal>,
+    pub ask: Option<Decimal>,
+    pub ask_quantity: Option<Decimal>,
+    /// Grows with every update, on any symbol. Streams use it as the event id.
+    pub sequence: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Balance {
+    pub asset: String,
+    pub available: Decimal,
+    pub locked: Decimal,
+}
+
+/// A stored `locked` balance that disagrees with what the user's open orders
+/// hold.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LockedDiscrepancy {
+    pub user_id: Uuid,
+    pub asset: String,
+    /// What the open orders hold: the quote notional of buys, the base
+    /// quantity of sells.
+    pub expected: Decimal,
+    pub locked: Decimal,
+}
+
+/// The outcome of checking every stored `locked` balance against the open
+/// orders.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BalanceReconciliation {
+    pub open_orders: usize,
+    pub discrepancies: Vec<LockedDiscrepancy>,
+}
+
+/// One side of a double-entry ledger posting. Every transfer writes a pair of
+/// entries whose amounts sum to zero.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LedgerEntry {
+    pub id: Uuid,
+    pub transfer_id: Uuid,
+    pub user_id: Uuid,
+    pub asset: String,
+    /// Ledger account the amount is posted to, e.g. `available` or `external`.
+    pub account: String,
+    pub amount: Decimal,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransferRequest {
+    pub asset: String,
+    pub amount: Decimal,
+}
+
+impl TransferRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.asset.is_empty() || self.asset.len() > 10 {
+            return Err("Asset must be between 1 and 10 characters".to_string());
+        }
+
+        if self.amount <= Decimal::ZERO {
+            return Err("Amount must be greater than 0".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// A log filter in `RUST_LOG` syntax, e.g. `info,exchange_api::services::order_book_service=debug`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogLevelRequest {
+    pub filter: String,
+}
+
+/// A period during which the API refuses writes while reads keep working.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaintenanceWindow {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl MaintenanceWindow {
+    pub fn contains(&self, at: DateTime<Utc>) -> bool {
+        self.start <= at && at < self.end
+    }
+}
+
+/// Schedules a maintenance window, starting straight away unless `start` is given.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MaintenanceRequest {
+    #[serde(default)]
+    pub start: Option<DateTime<Utc>>,
+    pub end: DateTime<Utc>,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountSummary {
+    pub balances: Vec<Balance>,
+    pub open_orders: usize,
+    /// Funds held by open orders, per asset. Assets with nothing locked are omitted.
+    pub locked_funds: Vec<Balance>,
+    /// Traded notional over the last 24 hours, as maker or taker.
+    pub volume_24h: Decimal,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountFees {
+    /// Zero-based index into the configured fee tier table.
+    pub tier: usize,
+    pub volume_30d: Decimal,
+    pub maker_rate: Decimal,
+    pub taker_rate: Decimal,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MarketData {
     pub symbol: String,
+    /// Currency that `last_price`, `high_24h` and `low_24h` are expressed in.
+    pub quote_currency: String,
     pub last_price: Decimal,
     pub volume_24h: Decimal,
-    pub change_24h: Decimal,
+    /// Percent change from the last price 24 hours ago. `None` for a market
+    /// that hasn't traded for that long yet.
+    pub change_24h: Option<Decimal>,
     pub high_24h: Decimal,
     pub low_24h: Decimal,
 }
@@ -203,8 +1219,15 @@ mod tests {
             symbol: "BTC/USD".to_string(),
             side: OrderSide::Buy,
             quantity: Decimal::new(100, 2), // 1.00
-            price: Decimal::new(5000000, 2), // 50000.00
+            price: Some(Decimal::new(5000000, 2)), // 50000.00
             order_type: OrderType::Limit,
+            all_or_none: false,
+            stop_price: None,
+            trigger_symbol: None,
+            peg_offset: None,
+            protection_price: None,
+            client_order_id: None,
+            metadata: None,
         };
         assert!(valid_request.validate().is_ok());
 
@@ -213,8 +1236,15 @@ mod tests {
             symbol: "".to_string(),
             side: OrderSide::Buy,
             quantity: Decimal::new(100, 2),
-            price: Decimal::new(5000000, 2),
+            price: Some(Decimal::new(5000000, 2)),
             order_type: OrderType::Limit,
+            all_or_none: false,
+            stop_price: None,
+            trigger_symbol: None,
+            peg_offset: None,
+            protection_price: None,
+            client_order_id: None,
+            metadata: None,
         };
         assert!(invalid_symbol.validate().is_err());
 
@@ -223,8 +1253,15 @@ mod tests {
             symbol: "BTC/USD".to_string(),
             side: OrderSide::Buy,
             quantity: Decimal::ZERO,
-            price: Decimal::new(5000000, 2),
+            price: Some(Decimal::new(5000000, 2)),
             order_type: OrderType::Limit,
+            all_or_none: false,
+            stop_price: None,
+            trigger_symbol: None,
+            peg_offset: None,
+            protection_price: None,
+            client_order_id: None,
+            metadata: None,
         };
         assert!(invalid_quantity.validate().is_err());
 
@@ -233,9 +1270,248 @@ mod tests {
             symbol: "BTC/USD".to_string(),
             side: OrderSide::Buy,
             quantity: Decimal::new(100, 2),
-            price: Decimal::new(-10000, 2), // -100.00
+            price: Some(Decimal::new(-10000, 2)), // -100.00
             order_type: OrderType::Limit,
+            all_or_none: false,
+            stop_price: None,
+            trigger_symbol: None,
+            peg_offset: None,
+            protection_price: None,
+            client_order_id: None,
+            metadata: None,
         };
         assert!(invalid_price.validate().is_err());
+        assert!(invalid_price.validate_with(true).is_ok());
+
+        // Test zero price on a limit order
+        let zero_price = CreateOrderRequest {
+            symbol: "BTC/USD".to_string(),
+            side: OrderSide::Buy,
+            quantity: Decimal::new(100, 2),
+            price: Some(Decimal::ZERO),
+            order_type: OrderType::Limit,
+            all_or_none: false,
+            stop_price: None,
+            trigger_symbol: None,
+            peg_offset: None,
+            protection_price: None,
+            client_order_id: None,
+            metadata: None,
+        };
+        assert!(zero_price.validate().is_err());
+        assert!(zero_price.validate_with(true).is_ok());
+    }
+
+    #[test]
+    fn test_market_order_price_is_optional() {
+        // Market order without price is accepted
+        let market_order = CreateOrderRequest {
+            symbol: "BTC/USD".to_string(),
+            side: OrderSide::Buy,
+            quantity: Decimal::new(100, 2),
+            price: None,
+            order_type: OrderType::Market,
+            all_or_none: false,
+            stop_price: None,
+            trigger_symbol: None,
+            peg_offset: None,
+            protection_price: None,
+            client_order_id: None,
+            metadata: None,
+        };
+        assert!(market_order.validate().is_ok());
+
+        // Market order carrying a price is rejected
+        let priced_market_order = CreateOrderRequest {
+            price: Some(Decimal::new(5000000, 2)),
+            ..market_order
+        };
+        assert!(priced_market_order.validate().is_err());
+
+        // Limit order without price is rejected
+        let limit_order = CreateOrderRequest {
+            symbol: "BTC/USD".to_string(),
+            side: OrderSide::Buy,
+            quantity: Decimal::new(100, 2),
+            price: None,
+            order_type: OrderType::Limit,
+            all_or_none: false,
+            stop_price: None,
+            trigger_symbol: None,
+            peg_offset: None,
+            protection_price: None,
+            client_order_id: None,
+            metadata: None,
+        };
+        assert!(limit_order.validate().is_err());
+
+        // Stop-limit order without price is rejected
+        let stop_limit_order = CreateOrderRequest {
+            order_type: OrderType::StopLimit,
+            ..limit_order
+        };
+        assert!(stop_limit_order.validate().is_err());
+    }
+
+    #[test]
+    fn test_stop_orders_need_a_stop_price() {
+        let stop_order = CreateOrderRequest {
+            symbol: "BTC/USD".to_string(),
+            side: OrderSide::Sell,
+            quantity: Decimal::new(100, 2),
+            price: None,
+            order_type: OrderType::Stop,
+            all_or_none: false,
+            stop_price: Some(Decimal::new(4500000, 2)),
+            trigger_symbol: Some("ETH/USD".to_string()),
+            peg_offset: None,
+            protection_price: None,
+            client_order_id: None,
+            metadata: None,
+        };
+        assert!(stop_order.validate().is_ok());
+
+        // Stops execute at market once triggered, so carry no limit price
+        let priced_stop = CreateOrderRequest {
+            price: Some(Decimal::new(4400000, 2)),
+            ..stop_order
+        };
+        assert!(priced_stop.validate().is_err());
+
+        let stop_limit = CreateOrderRequest {
+            order_type: OrderType::StopLimit,
+            ..priced_stop
+        };
+        assert!(stop_limit.validate().is_ok());
+
+        let missing_stop_price = CreateOrderRequest {
+            stop_price: None,
+            ..stop_limit
+        };
+        assert!(missing_stop_price.validate().is_err());
+
+        // A plain limit order can't carry a trigger
+        let triggered_limit = CreateOrderRequest {
+            order_type: OrderType::Limit,
+            stop_price: None,
+            ..missing_stop_price
+        };
+        assert!(triggered_limit.validate().is_err());
+    }
+
+    #[test]
+    fn test_symbol_needs_two_different_assets() {
+        let order = |symbol: &str| CreateOrderRequest {
+            symbol: symbol.to_string(),
+            side: OrderSide::Buy,
+            quantity: Decimal::ONE,
+            price: Some(Decimal::new(50000, 0)),
+            order_type: OrderType::Limit,
+            all_or_none: false,
+            stop_price: None,
+            trigger_symbol: None,
+            peg_offset: None,
+            protection_price: None,
+            client_order_id: None,
+            metadata: None,
+        };
+
+        assert!(order("BTC/USD").validate().is_ok());
+        for symbol in ["BTC/BTC", "eth/ETH", "BTCUSD", "BTC/", "/USD"] {
+            assert!(order(symbol).validate().is_err(), "{}", symbol);
+        }
+    }
+
+    #[test]
+    fn test_over_precise_price_and_quantity_are_rejected() {
+        let precision = PrecisionConfig { max_price_scale: 2, max_quantity_scale: 4 };
+        let order = |quantity: &str, price: &str, stop_price: Option<&str>| CreateOrderRequest {
+            symbol: "BTC/USD".to_string(),
+            side: OrderSide::Buy,
+            quantity: quantity.parse().unwrap(),
+            price: Some(price.parse().unwrap()),
+            order_type: if stop_price.is_some() { OrderType::StopLimit } else { OrderType::Limit },
+            all_or_none: false,
+            stop_price: stop_price.map(|stop_price| stop_price.parse().unwrap()),
+            trigger_symbol: None,
+            peg_offset: None,
+            protection_price: None,
+            client_order_id: None,
+            metadata: None,
+        };
+
+        assert!(order("1.2345", "100.25", Some("99.50")).validate_precision(&precision).is_ok());
+        // Trailing zeros add no precision
+        assert!(order("1.23450000", "100.2500", None).validate_precision(&precision).is_ok());
+
+        let error = order("1.23456", "100.25", None).validate_precision(&precision).unwrap_err();
+        assert!(error.contains("Quantity"), "{}", error);
+        let error = order("1", "100.251", None).validate_precision(&precision).unwrap_err();
+        assert!(error.contains("Prices"), "{}", error);
+        assert!(order("1", "100", Some("99.999")).validate_precision(&precision).is_err());
+        assert!(order("1", "0.00000000000000000001", None).validate_precision(&PrecisionConfig::default()).is_err());
+    }
+
+    #[test]
+    fn test_order_metadata_is_capped() {
+        let with_note = |len: usize| CreateOrderRequest {
+            symbol: "BTC/USD".to_string(),
+            side: OrderSide::Buy,
+            quantity: Decimal::ONE,
+            price: Some(Decimal::new(50000, 0)),
+            order_type: OrderType::Limit,
+            all_or_none: false,
+            stop_price: None,
+            trigger_symbol: None,
+            peg_offset: None,
+            protection_price: None,
+            client_order_id: None,
+            // {"note":"..."} is 11 bytes around the note
+            metadata: Some(serde_json::json!({ "note": "x".repeat(len - 11) })),
+        };
+
+        assert!(with_note(MAX_ORDER_METADATA_BYTES).validate().is_ok());
+        let error = with_note(MAX_ORDER_METADATA_BYTES + 1).validate().unwrap_err();
+        assert!(error.contains("Metadata"), "{}", error);
+    }
+
+    #[test]
+    fn test_agg_trades_merge_runs_of_price_and_taker_side() {
+        let trade = |seq: i64, side: OrderSide, price: i64, quantity: i64| Trade {
+            id: Uuid::new_v4(),
+            seq,
+            order_id: Uuid::new_v4(),
+            taker_order_id: Uuid::new_v4(),
+            maker_user_id: Uuid::new_v4(),
+            taker_user_id: Uuid::new_v4(),
+            taker_side: side,
+            symbol: "BTC/USD".to_string(),
+            quantity: Decimal::new(quantity, 0),
+            price: Decimal::new(price, 0),
+            taker_limit_price: None,
+            executed_at: Utc::now(),
+        };
+        let trades = vec![
+            trade(1, OrderSide::Buy, 100, 1),
+            trade(2, OrderSide::Buy, 100, 2),
+            trade(3, OrderSide::Buy, 101, 1), // New price
+            trade(4, OrderSide::Sell, 101, 3), // Same price, other side
+            trade(5, OrderSide::Sell, 101, 1),
+            trade(6, OrderSide::Buy, 100, 4), // Back at 100, but not consecutive with 1 and 2
+        ];
+        let (first, last) = (trades[0].id, trades[1].id);
+
+        let aggregated = AggTrade::aggregate(trades);
+        let summary: Vec<(i64, i64, OrderSide, Decimal, usize)> = aggregated.iter()
+            .map(|agg| (agg.first_seq, agg.last_seq, agg.taker_side.clone(), agg.quantity, agg.trade_count))
+            .collect();
+        assert_eq!(summary, vec![
+            (1, 2, OrderSide::Buy, Decimal::new(3, 0), 2),
+            (3, 3, OrderSide::Buy, Decimal::ONE, 1),
+            (4, 5, OrderSide::Sell, Decimal::new(4, 0), 2),
+            (6, 6, OrderSide::Buy, Decimal::new(4, 0), 1),
+        ]);
+        assert_eq!((aggregated[0].first_trade_id, aggregated[0].last_trade_id), (first, last));
+        assert!(AggTrade::aggregate(Vec::new()).is_empty());
     }
 } 
\ No newline at end of file
@@ -0,0 +1,63 @@
+use std::sync::OnceLock;
+use rust_decimal::Decimal;
+use crate::config::RoundingConfig;
+
+static ROUNDING: OnceLock<RoundingConfig> = OnceLock::new();
+
+/// Sets the rounding used across the process. Only the first call takes
+/// effect; until then the defaults apply.
+pub fn configure(config: RoundingConfig) {
+    if ROUNDING.set(config).is_err() {
+        tracing::warn!("Rounding was already configured; keeping the first configuration");
+    }
+}
+
+fn config() -> RoundingConfig {
+    ROUNDING.get().copied().unwrap_or_default()
+}
+
+/// Rounds a money amount, such as a fee, a settled notional or a converted
+/// price, to the configured scale.
+pub fn amount(value: Decimal) -> Decimal {
+    let config = config();
+    config.mode.round(value, config.amount_scale)
+}
+
+/// Rounds a derived figure with a precision of its own, such as a percentage.
+pub fn to_places(value: Decimal, decimal_places: u32) -> Decimal {
+    config().mode.round(value, decimal_places)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::RoundingMode;
+    use rust_decimal::Decimal;
+
+    fn round(mode: RoundingMode, value: &str) -> String {
+        mode.round(value.parse::<Decimal>().unwrap(), 2).to_string()
+    }
+
+    #[test]
+    fn test_each_mode_on_boundary_values() {
+        let cases = [
+            // value, half up, half even, down
+            ("0.125", "0.13", "0.12", "0.12"),
+            ("0.135", "0.14", "0.14", "0.13"),
+            ("-0.125", "-0.13", "-0.12", "-0.12"),
+            ("0.1249999", "0.12", "0.12", "0.12"),
+            ("0.1250001", "0.13", "0.13", "0.12"),
+            ("0.129", "0.13", "0.13", "0.12"),
+            ("-0.129", "-0.13", "-0.13", "-0.12"),
+            ("0.12", "0.12", "0.12", "0.12"),
+        ];
+        for (value, half_up, half_even, down) in cases {
+            assert_eq!(round(RoundingMode::HalfUp, value), half_up, "half up of {}", value);
+            assert_eq!(round(RoundingMode::HalfEven, value), half_even, "half even of {}", value);
+            assert_eq!(round(RoundingMode::Down, value), down, "down of {}", value);
+        }
+
+        assert_eq!(RoundingMode::parse("half-up"), Some(RoundingMode::HalfUp));
+        assert_eq!(RoundingMode::parse("HALF_EVEN"), Some(RoundingMode::HalfEven));
+        assert_eq!(RoundingMode::parse("up"), None);
+    }
+}
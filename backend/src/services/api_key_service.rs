@@ -0,0 +1,293 @@
+#[cfg(feature = "database")]
+use sqlx::PgPool;
+use std::sync::Arc;
+#[cfg(not(feature = "database"))]
+use std::collections::HashMap;
+#[cfg(not(feature = "database"))]
+use tokio::sync::RwLock;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use uuid::Uuid;
+use crate::models::{ApiKey, Scope};
+use crate::errors::AppError;
+use super::secret_cipher::SecretCipher;
+
+/// Signed requests whose timestamp is further than this from the server
+/// clock are rejected, which bounds how long a captured request can be replayed.
+pub const SIGNATURE_WINDOW_MS: i64 = 5_000;
+
+/// The parts of a request covered by its signature.
+pub struct SignedRequest<'a> {
+    pub key: &'a str,
+    pub timestamp: i64, // Unix epoch milliseconds
+    pub signature: &'a str,
+    pub method: &'a str,
+    pub path: &'a str, // Path and query string
+    pub body: &'a [u8],
+}
+
+/// API key pairs for programmatic access. Requests are signed with
+/// `hex(HMAC-SHA256(secret, timestamp + method + path + body))`.
+#[derive(Clone)]
+pub struct ApiKeyService {
+    #[cfg(feature = "database")]
+    pool: Arc<PgPool>,
+    #[cfg(not(feature = "database"))]
+    keys: Arc<RwLock<HashMap<String, ApiKey>>>, // Key -> ApiKey
+    cipher: SecretCipher,
+}
+
+impl ApiKeyService {
+    #[cfg(feature = "database")]
+    pub fn new(pool: PgPool, cipher: SecretCipher) -> Self {
+        Self {
+            pool: Arc::new(pool),
+            cipher,
+        }
+    }
+
+    #[cfg(not(feature = "database"))]
+    pub fn new(cipher: SecretCipher) -> Self {
+        Self {
+            keys: Arc::new(RwLock::new(HashMap::new())),
+            cipher,
+        }
+    }
+
+    /// Issues a new key pair for `user_id`, returning the stored key and the
+    /// plaintext secret. The secret can't be retrieved again afterwards.
+    pub async fn create(&self, user_id: Uuid, scopes: Vec<Scope>) -> Result<(ApiKey, String), AppError> {
+        let secret = Self::random_hex::<32>();
+        let api_key = ApiKey {
+            id: Uuid::new_v4(),
+            user_id,
+            key: Self::random_hex::<16>(),
+            secret_encrypted: self.cipher.encrypt(&secret)?,
+            scopes,
+            revoked_at: None,
+            created_at: Utc::now(),
+        };
+
+        #[cfg(feature = "database")]
+        {
+            sqlx::query!(
+                "INSERT INTO api_keys (id, user_id, key, secret_encrypted, scopes, created_at) VALUES ($1, $2, $3, $4, $5, $6)",
+                api_key.id,
+                api_key.user_id,
+                api_key.key,
+                api_key.secret_encrypted,
+                &api_key.scopes as &[Scope],
+                api_key.created_at
+            )
+            .execute(&*self.pool)
+            .await?;
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            self.keys.write().await.insert(api_key.key.clone(), api_key.clone());
+        }
+
+        Ok((api_key, secret))
+    }
+
+    /// All of a user's keys, revoked ones included, newest first.
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<ApiKey>, AppError> {
+        #[cfg(feature = "database")]
+        {
+            let api_keys = sqlx::query_as!(
+                ApiKey,
+                r#"
+                SELECT id, user_id, key, secret_encrypted, scopes as "scopes: Vec<Scope>", revoked_at, created_at
+                FROM api_keys WHERE user_id = $1 ORDER BY created_at DESC
+                "#,
+                user_id
+            )
+            .fetch_all(&*self.pool)
+            .await?;
+            Ok(api_keys)
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            let mut api_keys: Vec<ApiKey> = self.keys.read().await.values()
+                .filter(|api_key| api_key.user_id == user_id)
+                .cloned()
+                .collect();
+            api_keys.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+            Ok(api_keys)
+        }
+    }
+
+    /// Revokes one of `user_id`'s keys. Signatures made with it stop
+    /// verifying immediately.
+    pub async fn revoke(&self, user_id: Uuid, id: Uuid) -> Result<ApiKey, AppError> {
+        let not_found = || AppError::NotFound(format!("API key {} not found", id));
+
+        #[cfg(feature = "database")]
+        {
+            let api_key = sqlx::query_as!(
+                ApiKey,
+                r#"
+                UPDATE api_keys SET revoked_at = COALESCE(revoked_at, NOW())
+                WHERE id = $1 AND user_id = $2
+                RETURNING id, user_id, key, secret_encrypted, scopes as "scopes: Vec<Scope>", revoked_at, created_at
+                "#,
+                id,
+                user_id
+            )
+            .fetch_optional(&*self.pool)
+            .await?
+            .ok_or_else(not_found)?;
+            Ok(api_key)
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            let mut keys = self.keys.write().await;
+            let api_key = keys.values_mut()
+                .find(|api_key| api_key.id == id && api_key.user_id == user_id)
+                .ok_or_else(not_found)?;
+            api_key.revoked_at.get_or_insert_with(Utc::now);
+            Ok(api_key.clone())
+        }
+    }
+
+    /// Checks a signed request at `now` (epoch milliseconds) and returns the
+    /// key that signed it.
+    pub async fn verify_signature(&self, request: &SignedRequest<'_>, now: i64) -> Result<ApiKey, AppError> {
+        if (now - request.timestamp).abs() > SIGNATURE_WINDOW_MS {
+            return Err(AppError::Authentication("Request timestamp is outside the allowed window".to_string()));
+        }
+
+        let invalid = || AppError::Authentication("Invalid API key or signature".to_string());
+        let api_key = self.find_by_key(request.key).await?
+            .filter(|api_key| api_key.revoked_at.is_none())
+            .ok_or_else(invalid)?;
+        let secret = self.cipher.decrypt(&api_key.secret_encrypted)?;
+        let signature = hex::decode(request.signature).map_err(|_| invalid())?;
+
+        // verify_slice compares in constant time
+        Self::mac(&secret, request.timestamp, request.method, request.path, request.body)
+            .verify_slice(&signature)
+            .map_err(|_| invalid())?;
+
+        Ok(api_key)
+    }
+
+    /// The signature a client sends for a request.
+    pub fn sign(secret: &str, timestamp: i64, method: &str, path: &str, body: &[u8]) -> String {
+        hex::encode(Self::mac(secret, timestamp, method, path, body).finalize().into_bytes())
+    }
+
+    fn mac(secret: &str, timestamp: i64, method: &str, path: &str, body: &[u8]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+        mac.update(timestamp.to_string().as_bytes());
+        mac.update(method.as_bytes());
+        mac.update(path.as_bytes());
+        mac.update(body);
+        mac
+    }
+
+    fn random_hex<const N: usize>() -> String {
+        let mut bytes = [0u8; N];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        hex::encode(bytes)
+    }
+
+    async fn find_by_key(&self, key: &str) -> Result<Option<ApiKey>, AppError> {
+        #[cfg(feature = "database")]
+        {
+            let api_key = sqlx::query_as!(
+                ApiKey,
+                r#"
+                SELECT id, user_id, key, secret_encrypted, scopes as "scopes: Vec<Scope>", revoked_at, created_at
+                FROM api_keys WHERE key = $1
+                "#,
+                key
+            )
+            .fetch_optional(&*self.pool)
+            .await?;
+            Ok(api_key)
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            Ok(self.keys.read().await.get(key).cloned())
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "database")))]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000_000;
+    const BODY: &[u8] = br#"{"symbol":"BTC/USD","side":"Buy","quantity":"1"}"#;
+
+    async fn signed(service: &ApiKeyService) -> (ApiKey, String) {
+        let (api_key, secret) = service.create(Uuid::new_v4(), vec![Scope::Read]).await.unwrap();
+        let signature = ApiKeyService::sign(&secret, NOW, "POST", "/api/v1/orders/orders", BODY);
+        (api_key, signature)
+    }
+
+    fn request<'a>(api_key: &'a ApiKey, signature: &'a str, body: &'a [u8]) -> SignedRequest<'a> {
+        SignedRequest {
+            key: &api_key.key,
+            timestamp: NOW,
+            signature,
+            method: "POST",
+            path: "/api/v1/orders/orders",
+            body,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_valid_signature_is_accepted() {
+        let service = ApiKeyService::new(SecretCipher::new("test-key"));
+        let (api_key, signature) = signed(&service).await;
+
+        let verified = service.verify_signature(&request(&api_key, &signature, BODY), NOW + 1_000).await.unwrap();
+        assert_eq!(verified.id, api_key.id);
+        assert_eq!(verified.user_id, api_key.user_id);
+    }
+
+    #[tokio::test]
+    async fn test_tampered_body_is_rejected() {
+        let service = ApiKeyService::new(SecretCipher::new("test-key"));
+        let (api_key, signature) = signed(&service).await;
+
+        let tampered = br#"{"symbol":"BTC/USD","side":"Buy","quantity":"100"}"#;
+        let result = service.verify_signature(&request(&api_key, &signature, tampered), NOW).await;
+        assert!(matches!(result, Err(AppError::Authentication(_))));
+    }
+
+    #[tokio::test]
+    async fn test_expired_timestamp_is_rejected() {
+        let service = ApiKeyService::new(SecretCipher::new("test-key"));
+        let (api_key, signature) = signed(&service).await;
+
+        let result = service.verify_signature(&request(&api_key, &signature, BODY), NOW + SIGNATURE_WINDOW_MS + 1).await;
+        assert!(matches!(result, Err(AppError::Authentication(_))));
+    }
+
+    #[tokio::test]
+    async fn test_revoked_key_is_rejected() {
+        let service = ApiKeyService::new(SecretCipher::new("test-key"));
+        let (api_key, signature) = signed(&service).await;
+
+        // Other users can't revoke it
+        let result = service.revoke(Uuid::new_v4(), api_key.id).await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+
+        service.revoke(api_key.user_id, api_key.id).await.unwrap();
+        let result = service.verify_signature(&request(&api_key, &signature, BODY), NOW).await;
+        assert!(matches!(result, Err(AppError::Authentication(_))));
+
+        let listed = service.list(api_key.user_id).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0].revoked_at.is_some());
+    }
+}
@@ -0,0 +1,656 @@
+#[cfg(feature = "database")]
+use sqlx::PgPool;
+use std::sync::Arc;
+#[cfg(not(feature = "database"))]
+use std::collections::HashMap;
+#[cfg(not(feature = "database"))]
+use tokio::sync::RwLock;
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+#[cfg(feature = "database")]
+use redis::AsyncCommands;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use validator::Validate;
+use crate::config::JwtConfig;
+use crate::models::{CreateUserRequest, LoginRequest, LoginResponse, RefreshToken, Scope, TokenResponse, TwoFactorSetupResponse, User, UserStatus};
+use crate::errors::AppError;
+use super::password_service::PasswordService;
+use super::totp_service::TotpService;
+
+/// Claims carried by an access token.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: Uuid,
+    pub jti: Uuid,
+    pub iat: i64,
+    pub exp: i64,
+    /// Granted permissions. Tokens issued before scopes existed carry none.
+    #[serde(default)]
+    pub scopes: Vec<Scope>,
+}
+
+#[derive(Clone)]
+pub struct AuthService {
+    #[cfg(feature = "database")]
+    pool: Arc<PgPool>,
+    #[cfg(feature = "database")]
+    redis: redis::Client,
+    #[cfg(not(feature = "database"))]
+    users: Arc<RwLock<HashMap<Uuid, User>>>,
+    #[cfg(not(feature = "database"))]
+    refresh_tokens: Arc<RwLock<HashMap<String, RefreshToken>>>, // Token hash -> Token
+    #[cfg(not(feature = "database"))]
+    denylist: Arc<RwLock<HashMap<Uuid, i64>>>, // Access token jti -> exp
+    passwords: PasswordService,
+    totp: TotpService,
+    jwt: Arc<JwtConfig>,
+}
+
+impl AuthService {
+    #[cfg(feature = "database")]
+    pub fn new(pool: PgPool, redis: redis::Client, passwords: PasswordService, totp: TotpService, jwt: JwtConfig) -> Self {
+        Self {
+            pool: Arc::new(pool),
+            redis,
+            passwords,
+            totp,
+            jwt: Arc::new(jwt),
+        }
+    }
+
+    #[cfg(not(feature = "database"))]
+    pub fn new(passwords: PasswordService, totp: TotpService, jwt: JwtConfig) -> Self {
+        Self {
+            users: Arc::new(RwLock::new(HashMap::new())),
+            refresh_tokens: Arc::new(RwLock::new(HashMap::new())),
+            denylist: Arc::new(RwLock::new(HashMap::new())),
+            passwords,
+            totp,
+            jwt: Arc::new(jwt),
+        }
+    }
+
+    pub async fn register(&self, request: CreateUserRequest) -> Result<User, AppError> {
+        request.validate()?;
+        let password_hash = self.passwords.hash(&request.password)?;
+
+        #[cfg(feature = "database")]
+        {
+            let existing = sqlx::query_scalar!("SELECT id FROM users WHERE email = $1", request.email)
+                .fetch_optional(&*self.pool)
+                .await?;
+            if existing.is_some() {
+                return Err(AppError::User("Email is already registered".to_string()));
+            }
+
+            let user = sqlx::query_as!(
+                User,
+                r#"
+                INSERT INTO users (id, email, password_hash, status)
+                VALUES ($1, $2, $3, $4)
+                RETURNING id, email, password_hash, status as "status: UserStatus", totp_secret, totp_enabled, created_at, updated_at
+                "#,
+                Uuid::new_v4(),
+                request.email,
+                password_hash,
+                UserStatus::Active as UserStatus
+            )
+            .fetch_one(&*self.pool)
+            .await?;
+
+            Ok(user)
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            let mut users = self.users.write().await;
+            if users.values().any(|user| user.email == request.email) {
+                return Err(AppError::User("Email is already registered".to_string()));
+            }
+
+            let now = Utc::now();
+            let user = User {
+                id: Uuid::new_v4(),
+                email: request.email,
+                password_hash,
+                status: UserStatus::Active,
+                totp_secret: None,
+                totp_enabled: false,
+                created_at: now,
+                updated_at: now,
+            };
+            users.insert(user.id, user.clone());
+
+            Ok(user)
+        }
+    }
+
+    /// Checks credentials and issues an access token and a refresh token.
+    /// A password stored with an algorithm other than the configured one is
+    /// rehashed on the way through.
+    pub async fn login(&self, request: LoginRequest) -> Result<LoginResponse, AppError> {
+        let invalid = || AppError::Authentication("Invalid email or password".to_string());
+        let mut user = self.find_user_by_email(&request.email).await?.ok_or_else(invalid)?;
+
+        if !self.passwords.verify(&request.password, &user.password_hash)? {
+            return Err(invalid());
+        }
+        if !matches!(user.status, UserStatus::Active) {
+            return Err(AppError::Authentication("Account is not active".to_string()));
+        }
+        if user.totp_enabled {
+            let code = request.totp_code.as_deref()
+                .ok_or_else(|| AppError::Authentication("Two-factor code required".to_string()))?;
+            if !self.check_totp(&user, code)? {
+                return Err(AppError::Authentication("Invalid two-factor code".to_string()));
+            }
+        }
+
+        if self.passwords.needs_rehash(&user.password_hash) {
+            user.password_hash = self.passwords.hash(&request.password)?;
+            self.update_password_hash(&user).await?;
+        }
+
+        Ok(LoginResponse {
+            token: self.issue_access_token(user.id)?,
+            refresh_token: self.issue_refresh_token(user.id).await?,
+            user,
+        })
+    }
+
+    /// Starts two-factor setup with a fresh secret. Login is unaffected until
+    /// the secret is confirmed with `verify_two_factor`.
+    pub async fn enable_two_factor(&self, user_id: Uuid) -> Result<TwoFactorSetupResponse, AppError> {
+        let user = self.find_user_by_id(user_id).await?
+            .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+        if user.totp_enabled {
+            return Err(AppError::User("Two-factor authentication is already enabled".to_string()));
+        }
+
+        let secret = self.totp.generate_secret();
+        self.update_two_factor(user_id, Some(self.totp.encrypt(&secret)?), false).await?;
+
+        Ok(TwoFactorSetupResponse {
+            otpauth_uri: self.totp.otpauth_uri(&user.email, &secret),
+            secret,
+        })
+    }
+
+    /// Confirms two-factor setup with a code from the authenticator, after
+    /// which login requires a code.
+    pub async fn verify_two_factor(&self, user_id: Uuid, code: &str) -> Result<(), AppError> {
+        let user = self.find_user_by_id(user_id).await?
+            .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+        if user.totp_secret.is_none() {
+            return Err(AppError::User("Two-factor setup has not been started".to_string()));
+        }
+        if !self.check_totp(&user, code)? {
+            return Err(AppError::Authentication("Invalid two-factor code".to_string()));
+        }
+
+        self.update_two_factor(user_id, user.totp_secret, true).await
+    }
+
+    fn check_totp(&self, user: &User, code: &str) -> Result<bool, AppError> {
+        let encrypted = user.totp_secret.as_deref()
+            .ok_or_else(|| AppError::Internal("Two-factor secret is missing".to_string()))?;
+        let secret = self.totp.decrypt(encrypted)?;
+        self.totp.verify(&secret, code, Utc::now().timestamp())
+    }
+
+    /// Exchanges a refresh token for a new access token. The refresh token is
+    /// rotated: it is spent by this call and a new one is returned. Presenting
+    /// an already rotated token again is treated as theft and revokes every
+    /// refresh token the user holds.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<TokenResponse, AppError> {
+        let invalid = || AppError::Authentication("Invalid refresh token".to_string());
+        let token_hash = Self::hash_token(refresh_token);
+        let (new_token, new_record) = self.new_refresh_token(Uuid::nil());
+
+        #[cfg(feature = "database")]
+        let user_id = {
+            let mut tx = self.pool.begin().await?;
+            let record = sqlx::query_as!(
+                RefreshToken,
+                "SELECT * FROM refresh_tokens WHERE token_hash = $1 FOR UPDATE",
+                token_hash
+            )
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(invalid)?;
+
+            if record.revoked_at.is_some() {
+                if record.replaced_by.is_some() {
+                    sqlx::query!(
+                        "UPDATE refresh_tokens SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL",
+                        record.user_id
+                    )
+                    .execute(&mut *tx)
+                    .await?;
+                    tx.commit().await?;
+                }
+                return Err(invalid());
+            }
+            if record.expires_at <= Utc::now() {
+                return Err(AppError::Authentication("Refresh token has expired".to_string()));
+            }
+
+            sqlx::query!(
+                "UPDATE refresh_tokens SET revoked_at = NOW(), replaced_by = $1 WHERE token_hash = $2",
+                new_record.token_hash,
+                token_hash
+            )
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query!(
+                "INSERT INTO refresh_tokens (token_hash, user_id, expires_at, created_at) VALUES ($1, $2, $3, $4)",
+                new_record.token_hash,
+                record.user_id,
+                new_record.expires_at,
+                new_record.created_at
+            )
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+
+            record.user_id
+        };
+
+        #[cfg(not(feature = "database"))]
+        let user_id = {
+            let mut tokens = self.refresh_tokens.write().await;
+            let record = tokens.get(&token_hash).cloned().ok_or_else(invalid)?;
+
+            if record.revoked_at.is_some() {
+                if record.replaced_by.is_some() {
+                    let now = Utc::now();
+                    for token in tokens.values_mut().filter(|t| t.user_id == record.user_id) {
+                        token.revoked_at.get_or_insert(now);
+                    }
+                }
+                return Err(invalid());
+            }
+            if record.expires_at <= Utc::now() {
+                return Err(AppError::Authentication("Refresh token has expired".to_string()));
+            }
+
+            if let Some(spent) = tokens.get_mut(&token_hash) {
+                spent.revoked_at = Some(Utc::now());
+                spent.replaced_by = Some(new_record.token_hash.clone());
+            }
+            tokens.insert(new_record.token_hash.clone(), RefreshToken {
+                user_id: record.user_id,
+                ..new_record
+            });
+
+            record.user_id
+        };
+
+        Ok(TokenResponse {
+            token: self.issue_access_token(user_id)?,
+            refresh_token: new_token,
+        })
+    }
+
+    /// Revokes a refresh token so it can no longer be exchanged.
+    pub async fn revoke_refresh_token(&self, refresh_token: &str) -> Result<(), AppError> {
+        let token_hash = Self::hash_token(refresh_token);
+
+        #[cfg(feature = "database")]
+        {
+            let result = sqlx::query!(
+                "UPDATE refresh_tokens SET revoked_at = COALESCE(revoked_at, NOW()) WHERE token_hash = $1",
+                token_hash
+            )
+            .execute(&*self.pool)
+            .await?;
+            if result.rows_affected() == 0 {
+                return Err(AppError::Authentication("Invalid refresh token".to_string()));
+            }
+            Ok(())
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            let mut tokens = self.refresh_tokens.write().await;
+            let token = tokens.get_mut(&token_hash)
+                .ok_or_else(|| AppError::Authentication("Invalid refresh token".to_string()))?;
+            token.revoked_at.get_or_insert(Utc::now());
+            Ok(())
+        }
+    }
+
+    /// Decodes an access token, rejecting expired, tampered and logged-out tokens.
+    pub async fn verify_access_token(&self, token: &str) -> Result<Claims, AppError> {
+        let claims = decode::<Claims>(
+            token,
+            &DecodingKey::from_secret(self.jwt.secret.as_bytes()),
+            &Validation::default(),
+        )?
+        .claims;
+
+        if self.is_denied(claims.jti).await? {
+            return Err(AppError::Authentication("Token has been revoked".to_string()));
+        }
+        Ok(claims)
+    }
+
+    /// Denylists an access token until it expires, so it stops working
+    /// immediately rather than at the end of its lifetime.
+    pub async fn logout(&self, claims: &Claims) -> Result<(), AppError> {
+        let ttl = (claims.exp - Utc::now().timestamp()).max(1);
+
+        #[cfg(feature = "database")]
+        {
+            let mut conn = self.redis.get_multiplexed_async_connection().await?;
+            conn.set_ex::<_, _, ()>(Self::denylist_key(claims.jti), 1, ttl as usize).await?;
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            let now = Utc::now().timestamp();
+            let mut denylist = self.denylist.write().await;
+            denylist.retain(|_, exp| *exp > now);
+            denylist.insert(claims.jti, now + ttl);
+        }
+
+        Ok(())
+    }
+
+    async fn is_denied(&self, jti: Uuid) -> Result<bool, AppError> {
+        #[cfg(feature = "database")]
+        {
+            let mut conn = self.redis.get_multiplexed_async_connection().await?;
+            Ok(conn.exists(Self::denylist_key(jti)).await?)
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            let now = Utc::now().timestamp();
+            Ok(self.denylist.read().await.get(&jti).is_some_and(|exp| *exp > now))
+        }
+    }
+
+    #[cfg(feature = "database")]
+    fn denylist_key(jti: Uuid) -> String {
+        format!("auth:denylist:{}", jti)
+    }
+
+    fn issue_access_token(&self, user_id: Uuid) -> Result<String, AppError> {
+        let now = Utc::now();
+        let claims = Claims {
+            sub: user_id,
+            jti: Uuid::new_v4(),
+            iat: now.timestamp(),
+            exp: (now + Duration::seconds(self.jwt.expiration as i64)).timestamp(),
+            // A user signed in with their password may do anything
+            scopes: Scope::ALL.to_vec(),
+        };
+        Ok(encode(&Header::default(), &claims, &EncodingKey::from_secret(self.jwt.secret.as_bytes()))?)
+    }
+
+    async fn issue_refresh_token(&self, user_id: Uuid) -> Result<String, AppError> {
+        let (token, record) = self.new_refresh_token(user_id);
+
+        #[cfg(feature = "database")]
+        {
+            sqlx::query!(
+                "INSERT INTO refresh_tokens (token_hash, user_id, expires_at, created_at) VALUES ($1, $2, $3, $4)",
+                record.token_hash,
+                record.user_id,
+                record.expires_at,
+                record.created_at
+            )
+            .execute(&*self.pool)
+            .await?;
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            self.refresh_tokens.write().await.insert(record.token_hash.clone(), record);
+        }
+
+        Ok(token)
+    }
+
+    /// A fresh random refresh token and the record to store for it.
+    fn new_refresh_token(&self, user_id: Uuid) -> (String, RefreshToken) {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let token = hex::encode(bytes);
+
+        let now = Utc::now();
+        let record = RefreshToken {
+            token_hash: Self::hash_token(&token),
+            user_id,
+            expires_at: now + Duration::seconds(self.jwt.refresh_expiration as i64),
+            revoked_at: None,
+            replaced_by: None,
+            created_at: now,
+        };
+        (token, record)
+    }
+
+    fn hash_token(token: &str) -> String {
+        hex::encode(Sha256::digest(token.as_bytes()))
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
+        #[cfg(feature = "database")]
+        {
+            let user = sqlx::query_as!(
+                User,
+                r#"SELECT id, email, password_hash, status as "status: UserStatus", totp_secret, totp_enabled, created_at, updated_at FROM users WHERE email = $1"#,
+                email
+            )
+            .fetch_optional(&*self.pool)
+            .await?;
+            Ok(user)
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            Ok(self.users.read().await.values().find(|user| user.email == email).cloned())
+        }
+    }
+
+    async fn find_user_by_id(&self, user_id: Uuid) -> Result<Option<User>, AppError> {
+        #[cfg(feature = "database")]
+        {
+            let user = sqlx::query_as!(
+                User,
+                r#"SELECT id, email, password_hash, status as "status: UserStatus", totp_secret, totp_enabled, created_at, updated_at FROM users WHERE id = $1"#,
+                user_id
+            )
+            .fetch_optional(&*self.pool)
+            .await?;
+            Ok(user)
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            Ok(self.users.read().await.get(&user_id).cloned())
+        }
+    }
+
+    async fn update_two_factor(&self, user_id: Uuid, totp_secret: Option<String>, totp_enabled: bool) -> Result<(), AppError> {
+        #[cfg(feature = "database")]
+        {
+            sqlx::query!(
+                "UPDATE users SET totp_secret = $1, totp_enabled = $2, updated_at = NOW() WHERE id = $3",
+                totp_secret,
+                totp_enabled,
+                user_id
+            )
+            .execute(&*self.pool)
+            .await?;
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            if let Some(stored) = self.users.write().await.get_mut(&user_id) {
+                stored.totp_secret = totp_secret;
+                stored.totp_enabled = totp_enabled;
+                stored.updated_at = Utc::now();
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn update_password_hash(&self, user: &User) -> Result<(), AppError> {
+        #[cfg(feature = "database")]
+        {
+            sqlx::query!(
+                "UPDATE users SET password_hash = $1, updated_at = NOW() WHERE id = $2",
+                user.password_hash,
+                user.id
+            )
+            .execute(&*self.pool)
+            .await?;
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            if let Some(stored) = self.users.write().await.get_mut(&user.id) {
+                stored.password_hash = user.password_hash.clone();
+                stored.updated_at = Utc::now();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, not(feature = "database")))]
+mod tests {
+    use super::*;
+    use crate::config::PasswordHashAlgorithm;
+    use crate::services::password_service::BcryptHasher;
+
+    fn login_request(totp_code: Option<String>) -> LoginRequest {
+        LoginRequest {
+            email: "trader@example.com".to_string(),
+            password: "correct horse".to_string(),
+            totp_code,
+        }
+    }
+
+    async fn service_with_user() -> (AuthService, LoginResponse) {
+        let passwords = PasswordService::with_hashers(
+            PasswordHashAlgorithm::Bcrypt,
+            vec![Box::new(BcryptHasher::new(4))],
+        );
+        let service = AuthService::new(passwords, TotpService::new("test-key"), JwtConfig {
+            secret: "test-secret".to_string(),
+            expiration: 900,
+            refresh_expiration: 3600,
+        });
+
+        service.register(CreateUserRequest {
+            email: "trader@example.com".to_string(),
+            password: "correct horse".to_string(),
+        }).await.unwrap();
+        let login = service.login(login_request(None)).await.unwrap();
+
+        (service, login)
+    }
+
+    #[tokio::test]
+    async fn test_refresh_issues_new_tokens() {
+        let (service, login) = service_with_user().await;
+
+        let refreshed = service.refresh(&login.refresh_token).await.unwrap();
+        assert!(!refreshed.token.is_empty());
+        assert_ne!(refreshed.refresh_token, login.refresh_token);
+
+        // Only the hash is stored
+        let tokens = service.refresh_tokens.read().await;
+        assert!(!tokens.contains_key(&refreshed.refresh_token));
+        assert!(tokens.contains_key(&AuthService::hash_token(&refreshed.refresh_token)));
+    }
+
+    #[tokio::test]
+    async fn test_reusing_rotated_refresh_token_is_rejected() {
+        let (service, login) = service_with_user().await;
+        let rotated = service.refresh(&login.refresh_token).await.unwrap();
+
+        let reuse = service.refresh(&login.refresh_token).await;
+        assert!(matches!(reuse, Err(AppError::Authentication(_))));
+
+        // Reuse revokes the whole chain, including the token it was rotated into
+        let result = service.refresh(&rotated.refresh_token).await;
+        assert!(matches!(result, Err(AppError::Authentication(_))));
+    }
+
+    #[tokio::test]
+    async fn test_revoked_refresh_token_is_rejected() {
+        let (service, login) = service_with_user().await;
+
+        service.revoke_refresh_token(&login.refresh_token).await.unwrap();
+        let result = service.refresh(&login.refresh_token).await;
+        assert!(matches!(result, Err(AppError::Authentication(_))));
+    }
+
+    #[tokio::test]
+    async fn test_logged_out_token_is_rejected() {
+        let (service, first) = service_with_user().await;
+        let second = service.login(login_request(None)).await.unwrap();
+
+        let claims = service.verify_access_token(&first.token).await.unwrap();
+        assert_eq!(claims.sub, first.user.id);
+        service.logout(&claims).await.unwrap();
+
+        let result = service.verify_access_token(&first.token).await;
+        assert!(matches!(result, Err(AppError::Authentication(_))));
+        assert!(service.verify_access_token(&second.token).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_login_rejects_wrong_password() {
+        let (service, _) = service_with_user().await;
+        let result = service.login(LoginRequest {
+            email: "trader@example.com".to_string(),
+            password: "battery staple".to_string(),
+            totp_code: None,
+        }).await;
+        assert!(matches!(result, Err(AppError::Authentication(_))));
+    }
+
+    #[tokio::test]
+    async fn test_two_factor_enable_and_verify() {
+        let (service, login) = service_with_user().await;
+        let setup = service.enable_two_factor(login.user.id).await.unwrap();
+        assert!(setup.otpauth_uri.starts_with("otpauth://totp/"));
+        assert!(setup.otpauth_uri.contains(&setup.secret));
+
+        // The secret is only stored encrypted
+        let stored = service.find_user_by_id(login.user.id).await.unwrap().unwrap();
+        assert_ne!(stored.totp_secret.as_deref(), Some(setup.secret.as_str()));
+        assert!(!stored.totp_enabled);
+
+        let result = service.verify_two_factor(login.user.id, "12345x").await;
+        assert!(matches!(result, Err(AppError::Authentication(_))));
+
+        let code = service.totp.code_at(&setup.secret, Utc::now().timestamp()).unwrap();
+        service.verify_two_factor(login.user.id, &code).await.unwrap();
+        assert!(service.find_user_by_id(login.user.id).await.unwrap().unwrap().totp_enabled);
+    }
+
+    #[tokio::test]
+    async fn test_login_requires_code_when_two_factor_enabled() {
+        let (service, login) = service_with_user().await;
+        let setup = service.enable_two_factor(login.user.id).await.unwrap();
+        let code = service.totp.code_at(&setup.secret, Utc::now().timestamp()).unwrap();
+        service.verify_two_factor(login.user.id, &code).await.unwrap();
+
+        let result = service.login(login_request(None)).await;
+        assert!(matches!(result, Err(AppError::Authentication(_))));
+
+        let code = service.totp.code_at(&setup.secret, Utc::now().timestamp()).unwrap();
+        assert!(service.login(login_request(Some(code))).await.is_ok());
+    }
+}
@@ -0,0 +1,61 @@
+use std::future::Future;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// Long-running engine tasks sharing one shutdown signal. Each task is handed
+/// a token and should return soon after it is cancelled, finishing any write
+/// it has in hand; `shutdown` cancels them all and waits until they have.
+#[derive(Default)]
+pub struct BackgroundTasks {
+    shutdown: CancellationToken,
+    handles: Vec<(&'static str, JoinHandle<()>)>,
+}
+
+impl BackgroundTasks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn<F, Fut>(&mut self, name: &'static str, task: F)
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: Future<Output = ()> + 'static,
+    {
+        let handle = actix_rt::spawn(task(self.shutdown.child_token()));
+        self.handles.push((name, handle));
+    }
+
+    /// Cancels every task and waits for each to finish.
+    pub async fn shutdown(self) {
+        self.shutdown.cancel();
+        for (name, handle) in self.handles {
+            match handle.await {
+                Ok(()) => tracing::info!(task = name, "Background task stopped"),
+                Err(error) => tracing::warn!(task = name, %error, "Background task failed"),
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "database")))]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::services::order_service::OrderServiceBuilder;
+
+    #[actix_web::test]
+    async fn test_shutdown_stops_every_task() {
+        let order_service = OrderServiceBuilder::default().build();
+        let mut tasks = BackgroundTasks::new();
+        let sweeper = order_service.clone();
+        tasks.spawn("expiry_sweeper", move |shutdown| sweeper.run_expiry_sweeper(Duration::from_millis(1), shutdown));
+        let watcher = order_service.clone();
+        tasks.spawn("trade_watcher", move |shutdown| watcher.run_trade_watcher(shutdown));
+
+        // Let the sweeper tick a few times first
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        tokio::time::timeout(Duration::from_secs(1), tasks.shutdown())
+            .await
+            .expect("background tasks did not stop");
+    }
+}
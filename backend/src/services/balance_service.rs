@@ -0,0 +1,702 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use rust_decimal::Decimal;
+use uuid::Uuid;
+use chrono::Utc;
+use crate::config::AccountConfig;
+use crate::models::{split_symbol, Balance, LedgerEntry, LockedDiscrepancy, Order, OrderSide, Trade};
+use crate::errors::AppError;
+use crate::rounding;
+use super::fee_service::{FeeAsset, TradeFees};
+
+const AVAILABLE_ACCOUNT: &str = "available";
+const EXTERNAL_ACCOUNT: &str = "external";
+const FEES_ACCOUNT: &str = "fees";
+
+#[derive(Default)]
+struct BalanceState {
+    balances: HashMap<(Uuid, String), Balance>, // (User, Asset) -> Balance
+    settled_trades: HashSet<Uuid>,
+    ledger: Vec<LedgerEntry>,
+    paper_balances: HashMap<(Uuid, String), Balance>, // (User, Asset) -> Virtual balance, once traded
+}
+
+impl BalanceState {
+    fn balance_mut(&mut self, user_id: Uuid, asset: &str) -> &mut Balance {
+        self.balances
+            .entry((user_id, asset.to_string()))
+            .or_insert_with(|| Balance {
+                asset: asset.to_string(),
+                ..Balance::default()
+            })
+    }
+
+    /// Posts a balanced pair of entries moving `amount` from `from` to `to`.
+    fn post_transfer(&mut self, user_id: Uuid, asset: &str, from: &str, to: &str, amount: Decimal) {
+        let transfer_id = Uuid::new_v4();
+        let created_at = Utc::now();
+        for (account, signed_amount) in [(from, -amount), (to, amount)] {
+            self.ledger.push(LedgerEntry {
+                id: Uuid::new_v4(),
+                transfer_id,
+                user_id,
+                asset: asset.to_string(),
+                account: account.to_string(),
+                amount: signed_amount,
+                created_at,
+            });
+        }
+    }
+
+    fn paper_balance_mut(&mut self, user_id: Uuid, asset: &str, starting: &HashMap<String, Decimal>) -> &mut Balance {
+        self.paper_balances
+            .entry((user_id, asset.to_string()))
+            .or_insert_with(|| starting_balance(asset, starting))
+    }
+
+    /// Charges `fee` from the user's available balance to the venue. A
+    /// negative fee is a rebate and flows the other way.
+    fn charge_fee(&mut self, user_id: Uuid, asset: &str, fee: Decimal) {
+        if fee.is_zero() {
+            return;
+        }
+        self.balance_mut(user_id, asset).available -= fee;
+        if fee.is_sign_positive() {
+            self.post_transfer(user_id, asset, AVAILABLE_ACCOUNT, FEES_ACCOUNT, fee);
+        } else {
+            self.post_transfer(user_id, asset, FEES_ACCOUNT, AVAILABLE_ACCOUNT, -fee);
+        }
+    }
+}
+
+fn starting_balance(asset: &str, starting: &HashMap<String, Decimal>) -> Balance {
+    Balance {
+        asset: asset.to_string(),
+        available: starting.get(asset).copied().unwrap_or_default(),
+        ..Balance::default()
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct BalanceService {
+    state: Arc<RwLock<BalanceState>>,
+    config: AccountConfig,
+}
+
+impl BalanceService {
+    pub fn new(mut config: AccountConfig) -> Self {
+        // Environment variable keys arrive lowercased
+        config.paper_balances = config.paper_balances.into_iter()
+            .map(|(asset, amount)| (asset.to_uppercase(), amount))
+            .collect();
+        Self {
+            state: Arc::default(),
+            config,
+        }
+    }
+
+    /// Balances are virtual in paper trading mode.
+    pub async fn get_balance(&self, user_id: Uuid, asset: &str) -> Balance {
+        self.balance_in(&*self.state.read().await, user_id, asset)
+    }
+
+    fn balance_in(&self, state: &BalanceState, user_id: Uuid, asset: &str) -> Balance {
+        if self.config.paper_trading {
+            return state.paper_balances
+                .get(&(user_id, asset.to_string()))
+                .cloned()
+                .unwrap_or_else(|| starting_balance(asset, &self.config.paper_balances));
+        }
+        state.balances
+            .get(&(user_id, asset.to_string()))
+            .cloned()
+            .unwrap_or_else(|| Balance {
+                asset: asset.to_string(),
+                ..Balance::default()
+            })
+    }
+
+    pub async fn get_balances(&self, user_id: Uuid) -> Vec<Balance> {
+        let state = self.state.read().await;
+        if self.config.paper_trading {
+            let mut balances: HashMap<&str, Balance> = self.config.paper_balances.keys()
+                .map(|asset| (asset.as_str(), starting_balance(asset, &self.config.paper_balances)))
+                .collect();
+            for ((owner, asset), balance) in &state.paper_balances {
+                if *owner == user_id {
+                    balances.insert(asset, balance.clone());
+                }
+            }
+            let mut balances: Vec<Balance> = balances.into_values().collect();
+            balances.sort_by(|a, b| a.asset.cmp(&b.asset));
+            return balances;
+        }
+        let mut balances: Vec<Balance> = state.balances.iter()
+            .filter(|((owner, _), _)| *owner == user_id)
+            .map(|(_, balance)| balance.clone())
+            .collect();
+        balances.sort_by(|a, b| a.asset.cmp(&b.asset));
+        balances
+    }
+
+    pub async fn get_ledger(&self, user_id: Uuid) -> Vec<LedgerEntry> {
+        let state = self.state.read().await;
+        state.ledger.iter()
+            .filter(|entry| entry.user_id == user_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Fees the venue has collected in `asset`, net of maker rebates paid out.
+    pub async fn net_fees(&self, asset: &str) -> Decimal {
+        let state = self.state.read().await;
+        state.ledger.iter()
+            .filter(|entry| entry.account == FEES_ACCOUNT && entry.asset == asset)
+            .map(|entry| entry.amount)
+            .sum()
+    }
+
+    pub async fn deposit(&self, user_id: Uuid, asset: &str, amount: Decimal) -> Result<Balance, AppError> {
+        self.ensure_transfers_enabled()?;
+
+        let mut state = self.state.write().await;
+        state.balance_mut(user_id, asset).available += amount;
+        state.post_transfer(user_id, asset, EXTERNAL_ACCOUNT, AVAILABLE_ACCOUNT, amount);
+
+        Ok(state.balance_mut(user_id, asset).clone())
+    }
+
+    /// Withdraws from `available` only; funds locked by open orders are never touched.
+    pub async fn withdraw(&self, user_id: Uuid, asset: &str, amount: Decimal) -> Result<Balance, AppError> {
+        self.ensure_transfers_enabled()?;
+
+        let mut state = self.state.write().await;
+        let balance = state.balance_mut(user_id, asset);
+        if balance.available < amount {
+            return Err(AppError::Validation(format!(
+                "Insufficient available {} balance: requested {}, available {}",
+                asset, amount, balance.available
+            )));
+        }
+
+        balance.available -= amount;
+        state.post_transfer(user_id, asset, AVAILABLE_ACCOUNT, EXTERNAL_ACCOUNT, amount);
+
+        Ok(state.balance_mut(user_id, asset).clone())
+    }
+
+    /// Puts the user's virtual balances back to the configured starting
+    /// balances. What open orders hold stays locked, out of the starting
+    /// balance. Only available in paper trading mode.
+    pub async fn reset_paper_balances(&self, user_id: Uuid) -> Result<Vec<Balance>, AppError> {
+        if !self.config.paper_trading {
+            return Err(AppError::Authorization("Balance reset is only available in paper trading mode".to_string()));
+        }
+        let mut state = self.state.write().await;
+        state.paper_balances.retain(|(owner, _), balance| *owner != user_id || !balance.locked.is_zero());
+        for ((owner, asset), balance) in state.paper_balances.iter_mut() {
+            if *owner == user_id {
+                let locked = balance.locked;
+                *balance = starting_balance(asset, &self.config.paper_balances);
+                balance.available -= locked;
+                balance.locked = locked;
+            }
+        }
+        drop(state);
+        Ok(self.get_balances(user_id).await)
+    }
+
+    fn ensure_transfers_enabled(&self) -> Result<(), AppError> {
+        if !self.config.transfers_enabled {
+            return Err(AppError::Authorization("Deposits and withdrawals are disabled".to_string()));
+        }
+        Ok(())
+    }
+
+    /// The asset and amount an open order holds: a buy its remaining notional
+    /// in the quote asset and a sell its remaining quantity in the base asset.
+    /// Market orders have no price to hold funds at, and closed orders hold
+    /// nothing.
+    fn hold(order: &Order) -> Option<(&str, Decimal)> {
+        let (Some(price), Some((base, quote))) = (order.price, split_symbol(&order.symbol)) else {
+            return None;
+        };
+        if !order.status.is_open() {
+            return None;
+        }
+        let remaining = order.quantity - order.filled_quantity;
+        Some(match order.side {
+            OrderSide::Buy => (quote, rounding::amount(remaining * price)),
+            OrderSide::Sell => (base, remaining),
+        })
+    }
+
+    /// Moves funds between `available` and `locked` as an order goes from
+    /// `before` to `after`, so `locked` always covers what the user's open
+    /// orders hold. `before` is `None` for an order just accepted or reloaded.
+    /// Settlement pays for fills from `available`, which the released hold
+    /// has just topped up. Orders aren't checked against the balance before
+    /// they are accepted, so a hold larger than `available` is still taken
+    /// and leaves `available` negative.
+    pub async fn move_hold(&self, before: Option<&Order>, after: &Order) {
+        let mut changes: HashMap<&str, Decimal> = HashMap::new();
+        if let Some((asset, amount)) = before.and_then(Self::hold) {
+            *changes.entry(asset).or_default() -= amount;
+        }
+        if let Some((asset, amount)) = Self::hold(after) {
+            *changes.entry(asset).or_default() += amount;
+        }
+
+        let mut state = self.state.write().await;
+        for (asset, amount) in changes.into_iter().filter(|(_, amount)| !amount.is_zero()) {
+            let balance = if self.config.paper_trading {
+                state.paper_balance_mut(after.user_id, asset, &self.config.paper_balances)
+            } else {
+                state.balance_mut(after.user_id, asset)
+            };
+            balance.available -= amount;
+            balance.locked += amount;
+        }
+    }
+
+    /// Recomputes what each user should have locked from `open_orders` and
+    /// reports every balance whose stored `locked` differs, which points to a
+    /// missed hold or release somewhere in the order lifecycle.
+    pub async fn reconcile_locked(&self, open_orders: &[Order]) -> Vec<LockedDiscrepancy> {
+        let mut expected: HashMap<(Uuid, String), Decimal> = HashMap::new();
+        for order in open_orders {
+            if let Some((asset, amount)) = Self::hold(order) {
+                *expected.entry((order.user_id, asset.to_string())).or_default() += amount;
+            }
+        }
+
+        let state = self.state.read().await;
+        let balances = if self.config.paper_trading { &state.paper_balances } else { &state.balances };
+        let keys: HashSet<&(Uuid, String)> = expected.keys().chain(balances.keys()).collect();
+        let mut discrepancies: Vec<LockedDiscrepancy> = keys.into_iter()
+            .filter_map(|key| {
+                let expected = expected.get(key).copied().unwrap_or_default();
+                let locked = balances.get(key).map(|balance| balance.locked).unwrap_or_default();
+                (expected != locked).then(|| LockedDiscrepancy {
+                    user_id: key.0,
+                    asset: key.1.clone(),
+                    expected,
+                    locked,
+                })
+            })
+            .collect();
+        discrepancies.sort_by(|a, b| (a.user_id, &a.asset).cmp(&(b.user_id, &b.asset)));
+        discrepancies
+    }
+
+    /// Settles each trade with the fees at the same index in `fees`.
+    pub async fn settle_trades(&self, trades: &[Trade], fees: &[TradeFees]) -> Result<(), AppError> {
+        if trades.len() != fees.len() {
+            return Err(AppError::Internal(format!(
+                "Cannot settle {} trades with {} fee entries",
+                trades.len(), fees.len()
+            )));
+        }
+        for (trade, fees) in trades.iter().zip(fees) {
+            self.settle_trade(trade, fees).await?;
+        }
+        Ok(())
+    }
+
+    /// Moves base and quote assets between buyer and seller and charges each
+    /// side its fee, in the market's fee asset when it has one. Settlement is
+    /// idempotent: a trade id that has already been settled is skipped and
+    /// `false` is returned.
+    pub async fn settle_trade(&self, trade: &Trade, fees: &TradeFees) -> Result<bool, AppError> {
+        let (base, quote) = split_symbol(&trade.symbol)
+            .ok_or_else(|| AppError::Trade(format!("Cannot settle trade on malformed symbol {}", trade.symbol)))?;
+
+        let (buyer, seller) = match trade.taker_side {
+            OrderSide::Buy => (trade.taker_user_id, trade.maker_user_id),
+            OrderSide::Sell => (trade.maker_user_id, trade.taker_user_id),
+        };
+        let notional = rounding::amount(trade.quantity * trade.price);
+
+        // Check and record under the same lock so concurrent retries can't both apply
+        let mut state = self.state.write().await;
+        if !state.settled_trades.insert(trade.id) {
+            return Ok(false);
+        }
+
+        if self.config.paper_trading {
+            // Fees come out of the virtual balances too, with no ledger entries
+            let starting = &self.config.paper_balances;
+            let mut apply = |user_id: Uuid, asset: &str, amount: Decimal| {
+                state.paper_balance_mut(user_id, asset, starting).available += amount;
+            };
+            apply(buyer, base, trade.quantity);
+            apply(buyer, quote, -notional);
+            apply(seller, base, -trade.quantity);
+            apply(seller, quote, notional);
+        } else {
+            state.balance_mut(buyer, base).available += trade.quantity;
+            state.balance_mut(buyer, quote).available -= notional;
+            state.balance_mut(seller, base).available -= trade.quantity;
+            state.balance_mut(seller, quote).available += notional;
+        }
+
+        // Charged once the trade has settled, so its proceeds can pay the fee
+        for (user_id, fee) in [(trade.maker_user_id, fees.maker), (trade.taker_user_id, fees.taker)] {
+            let proceeds = if user_id == buyer {
+                (base, Decimal::ONE.checked_div(trade.price).unwrap_or_default())
+            } else {
+                (quote, Decimal::ONE)
+            };
+            let (asset, fee) = self.fee_charge(&state, user_id, fee, quote, fees.charged_in.as_ref(), proceeds);
+            if self.config.paper_trading {
+                state.paper_balance_mut(user_id, &asset, &self.config.paper_balances).available -= fee;
+            } else {
+                state.charge_fee(user_id, &asset, fee);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// The asset and amount a quote-denominated `fee` is charged as. Fees go
+    /// in the market's fee asset when the user holds enough of it, and are
+    /// otherwise taken from the trade's proceeds (`proceeds` is the asset the
+    /// user received and its amount per unit of quote).
+    fn fee_charge(
+        &self,
+        state: &BalanceState,
+        user_id: Uuid,
+        fee: Decimal,
+        quote: &str,
+        fee_asset: Option<&FeeAsset>,
+        proceeds: (&str, Decimal),
+    ) -> (String, Decimal) {
+        let Some(fee_asset) = fee_asset else {
+            return (quote.to_string(), fee);
+        };
+
+        let in_fee_asset = rounding::amount(fee * fee_asset.per_quote);
+        let available = self.balance_in(state, user_id, &fee_asset.asset).available;
+        if in_fee_asset.is_sign_negative() || available >= in_fee_asset {
+            return (fee_asset.asset.clone(), in_fee_asset);
+        }
+
+        let (asset, per_quote) = proceeds;
+        if asset == quote {
+            return (quote.to_string(), fee);
+        }
+        (asset.to_string(), rounding::amount(fee * per_quote))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfers_enabled() -> BalanceService {
+        BalanceService::new(AccountConfig { transfers_enabled: true, ..AccountConfig::default() })
+    }
+
+    // Seller rests, buyer takes 2 BTC at 100 USD
+    fn trade(buyer: Uuid, seller: Uuid) -> Trade {
+        Trade {
+            id: Uuid::new_v4(),
+            seq: 1,
+            order_id: Uuid::new_v4(),
+            taker_order_id: Uuid::new_v4(),
+            maker_user_id: seller,
+            taker_user_id: buyer,
+            taker_side: OrderSide::Buy,
+            symbol: "BTC/USD".to_string(),
+            quantity: Decimal::new(2, 0),
+            price: Decimal::new(100, 0),
+            taker_limit_price: None,
+            executed_at: chrono::Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_settling_same_trade_twice_applies_once() {
+        let service = BalanceService::new(AccountConfig::default());
+        let buyer = Uuid::new_v4();
+        let seller = Uuid::new_v4();
+        let trade = trade(buyer, seller);
+
+        {
+            let mut state = service.state.write().await;
+            state.balance_mut(buyer, "USD").available = Decimal::new(1000, 0);
+            state.balance_mut(seller, "BTC").available = Decimal::new(5, 0);
+        }
+
+        assert!(service.settle_trade(&trade, &TradeFees::default()).await.unwrap());
+        assert!(!service.settle_trade(&trade, &TradeFees::default()).await.unwrap());
+
+        assert_eq!(service.get_balance(buyer, "BTC").await.available, Decimal::new(2, 0));
+        assert_eq!(service.get_balance(buyer, "USD").await.available, Decimal::new(800, 0));
+        assert_eq!(service.get_balance(seller, "BTC").await.available, Decimal::new(3, 0));
+        assert_eq!(service.get_balance(seller, "USD").await.available, Decimal::new(200, 0));
+    }
+
+    #[tokio::test]
+    async fn test_maker_rebate_is_credited() {
+        let service = BalanceService::new(AccountConfig::default());
+        let buyer = Uuid::new_v4();
+        let seller = Uuid::new_v4();
+        {
+            let mut state = service.state.write().await;
+            state.balance_mut(buyer, "USD").available = Decimal::new(1000, 0);
+            state.balance_mut(seller, "BTC").available = Decimal::new(5, 0);
+        }
+
+        // 0.4 USD taker fee, 0.1 USD maker rebate on 200 USD notional
+        let fees = TradeFees {
+            maker: Decimal::new(-1, 1),
+            taker: Decimal::new(4, 1),
+            ..TradeFees::default()
+        };
+        service.settle_trades(&[trade(buyer, seller)], &[fees]).await.unwrap();
+
+        assert_eq!(service.get_balance(seller, "USD").await.available, Decimal::new(2001, 1));
+        assert_eq!(service.get_balance(buyer, "USD").await.available, Decimal::new(7996, 1));
+        assert_eq!(service.net_fees("USD").await, Decimal::new(3, 1));
+
+        let maker_ledger = service.get_ledger(seller).await;
+        assert!(maker_ledger.iter().any(|e| e.account == AVAILABLE_ACCOUNT && e.amount == Decimal::new(1, 1)));
+        assert!(maker_ledger.iter().any(|e| e.account == FEES_ACCOUNT && e.amount == Decimal::new(-1, 1)));
+    }
+
+    // 0.2 USD maker and 0.4 USD taker fee on 200 USD notional
+    fn fees_in(charged_in: Option<FeeAsset>) -> TradeFees {
+        TradeFees {
+            maker: Decimal::new(2, 1),
+            taker: Decimal::new(4, 1),
+            charged_in,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fees_are_charged_in_quote_asset_by_default() {
+        let service = BalanceService::new(AccountConfig::default());
+        let buyer = Uuid::new_v4();
+        let seller = Uuid::new_v4();
+        {
+            let mut state = service.state.write().await;
+            state.balance_mut(buyer, "USD").available = Decimal::new(1000, 0);
+            state.balance_mut(seller, "BTC").available = Decimal::new(5, 0);
+        }
+
+        service.settle_trades(&[trade(buyer, seller)], &[fees_in(None)]).await.unwrap();
+
+        assert_eq!(service.get_balance(buyer, "BTC").await.available, Decimal::new(2, 0));
+        assert_eq!(service.get_balance(buyer, "USD").await.available, Decimal::new(7996, 1));
+        assert_eq!(service.get_balance(seller, "USD").await.available, Decimal::new(1998, 1));
+        assert_eq!(service.net_fees("USD").await, Decimal::new(6, 1));
+        assert_eq!(service.net_fees("BTC").await, Decimal::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_fees_in_base_asset_fall_back_to_proceeds() {
+        let service = BalanceService::new(AccountConfig::default());
+        let buyer = Uuid::new_v4();
+        let seller = Uuid::new_v4();
+        {
+            let mut state = service.state.write().await;
+            state.balance_mut(buyer, "USD").available = Decimal::new(1000, 0);
+            state.balance_mut(seller, "BTC").available = Decimal::new(5, 0);
+        }
+        let in_btc = || Some(FeeAsset { asset: "BTC".to_string(), per_quote: Decimal::new(1, 2) });
+
+        service.settle_trades(&[trade(buyer, seller)], &[fees_in(in_btc())]).await.unwrap();
+
+        // 0.004 BTC for the taker and 0.002 BTC for the maker
+        assert_eq!(service.get_balance(buyer, "BTC").await.available, Decimal::new(1996, 3));
+        assert_eq!(service.get_balance(buyer, "USD").await.available, Decimal::new(800, 0));
+        assert_eq!(service.get_balance(seller, "BTC").await.available, Decimal::new(2998, 3));
+        assert_eq!(service.get_balance(seller, "USD").await.available, Decimal::new(200, 0));
+        assert_eq!(service.net_fees("BTC").await, Decimal::new(6, 3));
+
+        // A seller left without the BTC to pay with is charged from its USD proceeds
+        let seller = Uuid::new_v4();
+        service.state.write().await.balance_mut(seller, "BTC").available = Decimal::new(2, 0);
+        service.settle_trades(&[trade(buyer, seller)], &[fees_in(in_btc())]).await.unwrap();
+
+        assert_eq!(service.get_balance(seller, "BTC").await.available, Decimal::ZERO);
+        assert_eq!(service.get_balance(seller, "USD").await.available, Decimal::new(1998, 1));
+        assert_eq!(service.net_fees("USD").await, Decimal::new(2, 1));
+    }
+
+    #[tokio::test]
+    async fn test_deposit_increases_available_and_writes_ledger() {
+        let service = transfers_enabled();
+        let user = Uuid::new_v4();
+
+        let balance = service.deposit(user, "USD", Decimal::new(500, 0)).await.unwrap();
+        assert_eq!(balance.available, Decimal::new(500, 0));
+        assert_eq!(balance.locked, Decimal::ZERO);
+
+        let ledger = service.get_ledger(user).await;
+        assert_eq!(ledger.len(), 2);
+        assert_eq!(ledger.iter().map(|e| e.amount).sum::<Decimal>(), Decimal::ZERO);
+        assert!(ledger.iter().any(|e| e.account == AVAILABLE_ACCOUNT && e.amount == Decimal::new(500, 0)));
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_rejects_more_than_available() {
+        let service = transfers_enabled();
+        let user = Uuid::new_v4();
+
+        service.deposit(user, "USD", Decimal::new(100, 0)).await.unwrap();
+        service.state.write().await.balance_mut(user, "USD").locked = Decimal::new(50, 0);
+
+        let result = service.withdraw(user, "USD", Decimal::new(120, 0)).await;
+        assert!(matches!(result, Err(AppError::Validation(_))));
+
+        let balance = service.withdraw(user, "USD", Decimal::new(40, 0)).await.unwrap();
+        assert_eq!(balance.available, Decimal::new(60, 0));
+        assert_eq!(balance.locked, Decimal::new(50, 0));
+        assert_eq!(service.get_ledger(user).await.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_transfers_disabled_by_default() {
+        let service = BalanceService::new(AccountConfig::default());
+        let result = service.deposit(Uuid::new_v4(), "USD", Decimal::new(100, 0)).await;
+        assert!(matches!(result, Err(AppError::Authorization(_))));
+    }
+
+    #[tokio::test]
+    async fn test_paper_trades_settle_virtually_and_reset() {
+        let service = BalanceService::new(AccountConfig {
+            paper_trading: true,
+            paper_balances: HashMap::from([("usd".to_string(), Decimal::new(1000, 0))]),
+            ..AccountConfig::default()
+        });
+        let buyer = Uuid::new_v4();
+        let seller = Uuid::new_v4();
+        let fees = TradeFees { maker: Decimal::ZERO, taker: Decimal::new(4, 1), ..TradeFees::default() };
+        service.settle_trades(&[trade(buyer, seller)], &[fees]).await.unwrap();
+
+        assert_eq!(service.get_balance(buyer, "BTC").await.available, Decimal::new(2, 0));
+        assert_eq!(service.get_balance(buyer, "USD").await.available, Decimal::new(7996, 1));
+        assert_eq!(service.get_balance(seller, "USD").await.available, Decimal::new(1200, 0));
+
+        // Nothing reaches the real balances or the ledger
+        {
+            let state = service.state.read().await;
+            assert!(state.balances.is_empty() && state.ledger.is_empty());
+        }
+        assert_eq!(service.net_fees("USD").await, Decimal::ZERO);
+
+        let balances = service.reset_paper_balances(buyer).await.unwrap();
+        assert_eq!(balances.len(), 1);
+        assert_eq!(balances[0].available, Decimal::new(1000, 0));
+        assert_eq!(service.get_balance(buyer, "BTC").await.available, Decimal::ZERO);
+        assert_eq!(service.get_balance(seller, "BTC").await.available, Decimal::new(-2, 0));
+    }
+
+    #[tokio::test]
+    async fn test_reconciliation_reports_locked_not_backed_by_orders() {
+        let service = BalanceService::new(AccountConfig::default());
+        let buyer = Uuid::new_v4();
+        let seller = Uuid::new_v4();
+        let order = |user_id, side, filled_quantity| Order {
+            id: Uuid::new_v4(),
+            user_id,
+            symbol: "BTC/USD".to_string(),
+            side,
+            quantity: Decimal::new(2, 0),
+            price: Some(Decimal::new(100, 0)),
+            order_type: crate::models::OrderType::Limit,
+            all_or_none: false,
+            stop_price: None,
+            trigger_symbol: None,
+            peg_offset: None,
+            protection_price: None,
+            oco_group_id: None,
+            client_order_id: None,
+            metadata: None,
+            status: crate::models::OrderStatus::PartiallyFilled,
+            filled_quantity,
+            reject_reason: None,
+            acceptance_seq: 0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        let open_orders = [order(buyer, OrderSide::Buy, Decimal::ONE), order(seller, OrderSide::Sell, Decimal::ZERO)];
+        for order in &open_orders {
+            service.move_hold(None, order).await;
+        }
+        assert_eq!(service.get_balance(buyer, "USD").await.locked, Decimal::new(100, 0));
+        assert_eq!(service.get_balance(buyer, "USD").await.available, Decimal::new(-100, 0));
+        assert!(service.reconcile_locked(&open_orders).await.is_empty());
+
+        // A settlement bug leaves the seller's USD locked with nothing to hold it
+        service.state.write().await.balance_mut(seller, "USD").locked = Decimal::new(5, 0);
+        assert_eq!(service.reconcile_locked(&open_orders).await, vec![LockedDiscrepancy {
+            user_id: seller,
+            asset: "USD".to_string(),
+            expected: Decimal::ZERO,
+            locked: Decimal::new(5, 0),
+        }]);
+
+        // So is an open order whose funds were never locked
+        let open_orders = [open_orders[0].clone(), order(buyer, OrderSide::Buy, Decimal::ZERO)];
+        let discrepancies = service.reconcile_locked(&open_orders).await;
+        assert_eq!(discrepancies.len(), 3);
+        assert!(discrepancies.iter().any(|d| d.user_id == buyer && d.expected == Decimal::new(300, 0) && d.locked == Decimal::new(100, 0)));
+
+        // Closing an order releases what it held
+        let cancelled = Order { status: crate::models::OrderStatus::Cancelled, ..open_orders[0].clone() };
+        service.move_hold(Some(&open_orders[0]), &cancelled).await;
+        let balance = service.get_balance(buyer, "USD").await;
+        assert_eq!((balance.available, balance.locked), (Decimal::ZERO, Decimal::ZERO));
+    }
+
+    #[tokio::test]
+    async fn test_hold_may_overdraw_available() {
+        let service = transfers_enabled();
+        let buyer = Uuid::new_v4();
+        service.deposit(buyer, "USD", Decimal::new(50, 0)).await.unwrap();
+        let order = Order {
+            id: Uuid::new_v4(),
+            user_id: buyer,
+            symbol: "BTC/USD".to_string(),
+            side: OrderSide::Buy,
+            quantity: Decimal::ONE,
+            price: Some(Decimal::new(100, 0)),
+            order_type: crate::models::OrderType::Limit,
+            all_or_none: false,
+            stop_price: None,
+            trigger_symbol: None,
+            peg_offset: None,
+            protection_price: None,
+            oco_group_id: None,
+            client_order_id: None,
+            metadata: None,
+            status: crate::models::OrderStatus::Open,
+            filled_quantity: Decimal::ZERO,
+            reject_reason: None,
+            acceptance_seq: 0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        service.move_hold(None, &order).await;
+        let balance = service.get_balance(buyer, "USD").await;
+        assert_eq!((balance.available, balance.locked), (Decimal::new(-50, 0), Decimal::new(100, 0)));
+
+        // Nothing can be withdrawn until the order closes
+        assert!(service.withdraw(buyer, "USD", Decimal::ONE).await.is_err());
+        let cancelled = Order { status: crate::models::OrderStatus::Cancelled, ..order.clone() };
+        service.move_hold(Some(&order), &cancelled).await;
+        let balance = service.get_balance(buyer, "USD").await;
+        assert_eq!((balance.available, balance.locked), (Decimal::new(50, 0), Decimal::ZERO));
+    }
+
+    #[tokio::test]
+    async fn test_reset_needs_paper_trading() {
+        let service = BalanceService::new(AccountConfig::default());
+        let result = service.reset_paper_balances(Uuid::new_v4()).await;
+        assert!(matches!(result, Err(AppError::Authorization(_))));
+    }
+}
\ No newline at end of file
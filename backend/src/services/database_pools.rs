@@ -0,0 +1,39 @@
+/// The connection pools a service queries. Writes, and reads that must see
+/// them, go to the primary. Other reads go to the read replica when one is
+/// configured, so they don't contend with the write path, and may trail the
+/// primary by the replica's lag.
+#[derive(Clone)]
+pub struct DatabasePools<P> {
+    primary: P,
+    replica: Option<P>,
+}
+
+impl<P> DatabasePools<P> {
+    pub fn new(primary: P, replica: Option<P>) -> Self {
+        Self { primary, replica }
+    }
+
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    /// Where read-only queries go: the replica, or the primary without one.
+    pub fn reader(&self) -> &P {
+        self.replica.as_ref().unwrap_or(&self.primary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_go_to_the_replica_when_configured() {
+        let pools = DatabasePools::new("primary", Some("replica"));
+        assert_eq!(*pools.reader(), "replica");
+        assert_eq!(*pools.primary(), "primary");
+
+        let pools = DatabasePools::new("primary", None);
+        assert_eq!(*pools.reader(), "primary");
+    }
+}
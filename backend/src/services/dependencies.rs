@@ -0,0 +1,171 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use serde::Serialize;
+use crate::config::ConnectRetryConfig;
+
+/// Longest a single dependency may take to answer before it counts as down.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub type ProbeFuture<'a> = Pin<Box<dyn Future<Output = Result<(), String>> + Send + 'a>>;
+
+/// Something the API needs to serve requests, such as the database.
+pub trait DependencyProbe: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Succeeds when the dependency answers, or says why it didn't.
+    fn check(&self) -> ProbeFuture<'_>;
+}
+
+#[cfg(feature = "database")]
+pub struct PostgresProbe(pub sqlx::PgPool);
+
+#[cfg(feature = "database")]
+impl DependencyProbe for PostgresProbe {
+    fn name(&self) -> &'static str {
+        "database"
+    }
+
+    fn check(&self) -> ProbeFuture<'_> {
+        Box::pin(async move {
+            sqlx::query("SELECT 1").execute(&self.0).await.map(|_| ()).map_err(|e| e.to_string())
+        })
+    }
+}
+
+#[cfg(feature = "database")]
+pub struct RedisProbe(pub redis::Client);
+
+#[cfg(feature = "database")]
+impl DependencyProbe for RedisProbe {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    fn check(&self) -> ProbeFuture<'_> {
+        Box::pin(async move {
+            let mut conn = self.0.get_multiplexed_async_connection().await.map_err(|e| e.to_string())?;
+            redis::cmd("PING").query_async::<_, String>(&mut conn).await.map(|_| ()).map_err(|e| e.to_string())
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyStatus {
+    pub name: &'static str,
+    pub up: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The external services readiness depends on. Without the database feature
+/// there are none.
+#[derive(Clone, Default)]
+pub struct Dependencies {
+    probes: Vec<Arc<dyn DependencyProbe>>,
+}
+
+impl Dependencies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, probe: impl DependencyProbe + 'static) -> Self {
+        self.probes.push(Arc::new(probe));
+        self
+    }
+
+    /// Probes each dependency in turn, giving each `PROBE_TIMEOUT` to answer.
+    pub async fn check(&self) -> Vec<DependencyStatus> {
+        let mut statuses = Vec::with_capacity(self.probes.len());
+        for probe in &self.probes {
+            let result = match tokio::time::timeout(PROBE_TIMEOUT, probe.check()).await {
+                Ok(result) => result,
+                Err(_) => Err(format!("No answer within {} ms", PROBE_TIMEOUT.as_millis())),
+            };
+            statuses.push(DependencyStatus {
+                name: probe.name(),
+                up: result.is_ok(),
+                error: result.err(),
+            });
+        }
+        statuses
+    }
+}
+
+/// Calls `connect` until it succeeds or `retry.max_attempts` have failed,
+/// backing off between attempts, and returns the last error when giving up.
+/// Each failed attempt is logged as `name` being unavailable.
+pub async fn connect_with_retry<T, E, F, Fut>(name: &str, retry: ConnectRetryConfig, mut connect: F) -> Result<T, E>
+where
+    E: Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let max_attempts = retry.max_attempts.max(1);
+    let mut backoff = Duration::from_millis(retry.initial_backoff_ms);
+    let mut attempt = 1;
+    loop {
+        match connect().await {
+            Ok(connection) => {
+                tracing::info!(dependency = name, attempt, "Connected");
+                return Ok(connection);
+            }
+            Err(error) if attempt >= max_attempts => {
+                tracing::error!(dependency = name, attempt, %error, "Still unavailable, giving up");
+                return Err(error);
+            }
+            Err(error) => {
+                tracing::warn!(
+                    dependency = name,
+                    attempt,
+                    max_attempts,
+                    retry_in_ms = backoff.as_millis() as u64,
+                    %error,
+                    "Unavailable, retrying"
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(Duration::from_millis(retry.max_backoff_ms));
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn retry(max_attempts: u32) -> ConnectRetryConfig {
+        ConnectRetryConfig {
+            max_attempts,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 2,
+        }
+    }
+
+    /// A connection that is refused until the `up_after`th attempt.
+    async fn connect(attempts: &AtomicU32, up_after: u32) -> Result<u32, String> {
+        let attempt = attempts.fetch_add(1, Ordering::SeqCst) + 1;
+        if attempt < up_after {
+            return Err("connection refused".to_string());
+        }
+        Ok(attempt)
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_the_dependency_comes_up() {
+        let attempts = AtomicU32::new(0);
+        let connected = connect_with_retry("database", retry(5), || connect(&attempts, 3)).await;
+        assert_eq!(connected, Ok(3));
+
+        // Gives up with the last error once the attempts run out
+        let attempts = AtomicU32::new(0);
+        let connected = connect_with_retry("database", retry(2), || connect(&attempts, 3)).await;
+        assert_eq!(connected, Err("connection refused".to_string()));
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+use crate::config::{FeeConfig, FeeTier};
+use crate::models::{AccountFees, Trade};
+use crate::rounding;
+
+const VOLUME_WINDOW_DAYS: i64 = 30;
+
+/// Fees owed on one trade, in the quote asset. A negative fee is a rebate
+/// paid to that side.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TradeFees {
+    pub maker: Decimal,
+    pub taker: Decimal,
+    /// The market's fee asset, when fees are charged in something other
+    /// than the quote asset.
+    pub charged_in: Option<FeeAsset>,
+}
+
+/// An asset fees are charged in, with how much of it one unit of the quote
+/// asset is worth.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeeAsset {
+    pub asset: String,
+    pub per_quote: Decimal,
+}
+
+#[derive(Clone)]
+pub struct FeeService {
+    tiers: Arc<Vec<FeeTier>>,
+    volumes: Arc<RwLock<HashMap<Uuid, VecDeque<(DateTime<Utc>, Decimal)>>>>, // User -> (executed_at, notional)
+}
+
+impl FeeService {
+    pub fn new(config: FeeConfig) -> Self {
+        let mut tiers = config.tiers;
+        if tiers.is_empty() {
+            tiers = FeeConfig::default().tiers;
+        }
+        tiers.sort_by_key(|tier| tier.min_volume_30d);
+        for tier in tiers.iter().filter(|tier| tier.maker_rate + tier.taker_rate < Decimal::ZERO) {
+            tracing::warn!(
+                "Fee tier from volume {} rebates makers more than it charges takers; the venue loses on every trade",
+                tier.min_volume_30d
+            );
+        }
+
+        Self {
+            tiers: Arc::new(tiers),
+            volumes: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Adds the notional of each trade to both the maker's and the taker's rolling volume.
+    pub async fn record_trades(&self, trades: &[Trade]) {
+        let mut volumes = self.volumes.write().await;
+        for trade in trades {
+            let notional = Self::notional(trade);
+            for user_id in [trade.maker_user_id, trade.taker_user_id] {
+                let entries = volumes.entry(user_id).or_default();
+                entries.push_back((trade.executed_at, notional));
+                Self::prune(entries, trade.executed_at);
+            }
+        }
+    }
+
+    /// Fees for each trade at the maker's and taker's current rates, rounded
+    /// as amounts, in the quote asset. Call this before `record_trades`, so a
+    /// trade doesn't count towards its own tier.
+    pub async fn trade_fees(&self, trades: &[Trade]) -> Vec<TradeFees> {
+        let mut fees = Vec::with_capacity(trades.len());
+        for trade in trades {
+            let notional = Self::notional(trade);
+            fees.push(TradeFees {
+                maker: rounding::amount(notional * self.account_fees(trade.maker_user_id).await.maker_rate),
+                taker: rounding::amount(notional * self.account_fees(trade.taker_user_id).await.taker_rate),
+                charged_in: None,
+            });
+        }
+        fees
+    }
+
+    /// Size of a trade for fees and volume. Markets that trade at negative
+    /// prices still pay fees on how much changed hands, so the sign is dropped.
+    fn notional(trade: &Trade) -> Decimal {
+        (trade.quantity * trade.price).abs()
+    }
+
+    /// Current tier and rates for a user, based on their volume over the last 30 days.
+    pub async fn account_fees(&self, user_id: Uuid) -> AccountFees {
+        self.account_fees_at(user_id, Utc::now()).await
+    }
+
+    async fn account_fees_at(&self, user_id: Uuid, now: DateTime<Utc>) -> AccountFees {
+        let volume_30d = {
+            let mut volumes = self.volumes.write().await;
+            match volumes.get_mut(&user_id) {
+                Some(entries) => {
+                    Self::prune(entries, now);
+                    entries.iter().map(|(_, notional)| *notional).sum()
+                }
+                None => Decimal::ZERO,
+            }
+        };
+
+        let (tier, fee_tier) = self.tier_for(volume_30d);
+        AccountFees {
+            tier,
+            volume_30d,
+            maker_rate: fee_tier.maker_rate,
+            taker_rate: fee_tier.taker_rate,
+        }
+    }
+
+    /// Traded notional for a user over the trailing `window`.
+    pub async fn traded_volume(&self, user_id: Uuid, window: Duration) -> Decimal {
+        let since = Utc::now() - window;
+        self.volumes.read().await
+            .get(&user_id)
+            .map(|entries| entries.iter()
+                .filter(|(executed_at, _)| *executed_at >= since)
+                .map(|(_, notional)| *notional)
+                .sum())
+            .unwrap_or(Decimal::ZERO)
+    }
+
+    fn tier_for(&self, volume: Decimal) -> (usize, &FeeTier) {
+        self.tiers.iter()
+            .enumerate()
+            .rev()
+            .find(|(_, tier)| volume >= tier.min_volume_30d)
+            .unwrap_or((0, &self.tiers[0]))
+    }
+
+    fn prune(entries: &mut VecDeque<(DateTime<Utc>, Decimal)>, now: DateTime<Utc>) {
+        let cutoff = now - Duration::days(VOLUME_WINDOW_DAYS);
+        while entries.front().is_some_and(|(executed_at, _)| *executed_at < cutoff) {
+            entries.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::OrderSide;
+
+    fn trade(maker: Uuid, taker: Uuid, quantity: i64, price: i64, executed_at: DateTime<Utc>) -> Trade {
+        Trade {
+            id: Uuid::new_v4(),
+            seq: 1,
+            order_id: Uuid::new_v4(),
+            taker_order_id: Uuid::new_v4(),
+            maker_user_id: maker,
+            taker_user_id: taker,
+            taker_side: OrderSide::Buy,
+            symbol: "BTC/USD".to_string(),
+            quantity: Decimal::new(quantity, 0),
+            price: Decimal::new(price, 0),
+            taker_limit_price: None,
+            executed_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_crossing_volume_threshold_lowers_fee_tier() {
+        let service = FeeService::new(FeeConfig::default());
+        let maker = Uuid::new_v4();
+        let taker = Uuid::new_v4();
+
+        let fees = service.account_fees(taker).await;
+        assert_eq!(fees.tier, 0);
+        assert_eq!(fees.volume_30d, Decimal::ZERO);
+
+        service.record_trades(&[trade(maker, taker, 1, 50_000, Utc::now())]).await;
+        let fees = service.account_fees(taker).await;
+        assert_eq!(fees.tier, 0);
+        assert_eq!(fees.volume_30d, Decimal::new(50_000, 0));
+
+        service.record_trades(&[trade(maker, taker, 1, 60_000, Utc::now())]).await;
+        let fees = service.account_fees(taker).await;
+        assert_eq!(fees.tier, 1);
+        assert!(fees.taker_rate < FeeConfig::default().tiers[0].taker_rate);
+
+        // The maker accrues the same volume
+        assert_eq!(service.account_fees(maker).await.tier, 1);
+    }
+
+    #[tokio::test]
+    async fn test_volume_older_than_window_is_ignored() {
+        let service = FeeService::new(FeeConfig::default());
+        let user = Uuid::new_v4();
+        let now = Utc::now();
+
+        service.record_trades(&[trade(Uuid::new_v4(), user, 2, 60_000, now - Duration::days(31))]).await;
+        service.record_trades(&[trade(Uuid::new_v4(), user, 1, 10_000, now)]).await;
+
+        let fees = service.account_fees_at(user, now).await;
+        assert_eq!(fees.tier, 0);
+        assert_eq!(fees.volume_30d, Decimal::new(10_000, 0));
+    }
+
+    #[tokio::test]
+    async fn test_negative_maker_rate_is_a_rebate() {
+        let service = FeeService::new(FeeConfig {
+            tiers: vec![FeeTier {
+                min_volume_30d: Decimal::ZERO,
+                maker_rate: Decimal::new(-2, 4), // -0.02%
+                taker_rate: Decimal::new(5, 4),  // 0.05%
+            }],
+        });
+
+        let fees = service.trade_fees(&[trade(Uuid::new_v4(), Uuid::new_v4(), 2, 10_000, Utc::now())]).await;
+        assert_eq!(fees, vec![TradeFees {
+            maker: Decimal::new(-4, 0),
+            taker: Decimal::new(10, 0),
+            charged_in: None,
+        }]);
+    }
+}
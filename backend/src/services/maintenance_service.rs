@@ -0,0 +1,204 @@
+#[cfg(feature = "database")]
+use sqlx::PgPool;
+use std::sync::Arc;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::web;
+use chrono::{DateTime, Utc};
+use tokio::sync::RwLock;
+use crate::errors::AppError;
+use crate::models::MaintenanceWindow;
+
+/// Route groups that stay writable during maintenance: admin, so operators
+/// can end it, and auth, so users can still sign in to read.
+const WRITABLE_DURING_MAINTENANCE: [&str; 2] = ["admin", "auth"];
+
+/// The maintenance window, if any. A window scheduled through the admin API
+/// is stored, so it survives restarts and replaces the configured one.
+#[derive(Clone)]
+pub struct MaintenanceService {
+    #[cfg(feature = "database")]
+    pool: Arc<PgPool>,
+    window: Arc<RwLock<Option<MaintenanceWindow>>>,
+}
+
+impl MaintenanceService {
+    #[cfg(feature = "database")]
+    pub fn new(pool: PgPool, configured: Option<MaintenanceWindow>) -> Self {
+        Self {
+            pool: Arc::new(pool),
+            window: Arc::new(RwLock::new(configured)),
+        }
+    }
+
+    #[cfg(not(feature = "database"))]
+    pub fn new(configured: Option<MaintenanceWindow>) -> Self {
+        Self {
+            window: Arc::new(RwLock::new(configured)),
+        }
+    }
+
+    /// Restores the stored window, which wins over the configured one.
+    pub async fn load(&self) -> Result<(), AppError> {
+        #[cfg(feature = "database")]
+        {
+            let stored = sqlx::query!("SELECT start_at, end_at, reason FROM maintenance_window")
+                .fetch_optional(&*self.pool)
+                .await?;
+            if let Some(row) = stored {
+                *self.window.write().await = Some(MaintenanceWindow {
+                    start: row.start_at,
+                    end: row.end_at,
+                    reason: row.reason,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn current(&self) -> Option<MaintenanceWindow> {
+        self.window.read().await.clone()
+    }
+
+    /// The window `at` falls in, if it is one.
+    pub async fn active_at(&self, at: DateTime<Utc>) -> Option<MaintenanceWindow> {
+        self.window.read().await.clone().filter(|window| window.contains(at))
+    }
+
+    /// Replaces the window.
+    pub async fn schedule(&self, window: MaintenanceWindow) -> Result<MaintenanceWindow, AppError> {
+        if window.end <= window.start {
+            return Err(AppError::Validation("Maintenance must end after it starts".to_string()));
+        }
+        if window.end <= Utc::now() {
+            return Err(AppError::Validation("Maintenance must end in the future".to_string()));
+        }
+
+        self.store(window).await
+    }
+
+    /// Ends the current window now. The ended window stays stored, so the
+    /// configured one doesn't come back on restart.
+    pub async fn end(&self) -> Result<Option<MaintenanceWindow>, AppError> {
+        let now = Utc::now();
+        let Some(mut window) = self.active_at(now).await else {
+            return Ok(None);
+        };
+        window.end = now;
+
+        self.store(window).await.map(Some)
+    }
+
+    /// Stores `window` before it takes effect, replacing the previous one.
+    async fn store(&self, window: MaintenanceWindow) -> Result<MaintenanceWindow, AppError> {
+        #[cfg(feature = "database")]
+        {
+            sqlx::query!(
+                "INSERT INTO maintenance_window (id, start_at, end_at, reason) VALUES (TRUE, $1, $2, $3)
+                 ON CONFLICT (id) DO UPDATE SET start_at = $1, end_at = $2, reason = $3",
+                window.start,
+                window.end,
+                window.reason,
+            )
+            .execute(&*self.pool)
+            .await?;
+        }
+
+        *self.window.write().await = Some(window.clone());
+        Ok(window)
+    }
+}
+
+fn is_write(method: &Method, path: &str) -> bool {
+    let read = method == Method::GET || method == Method::HEAD || method == Method::OPTIONS;
+    !read && !path.split('/').any(|segment| WRITABLE_DURING_MAINTENANCE.contains(&segment))
+}
+
+/// Refuses writes with `503` during maintenance, with `Retry-After` set to
+/// the seconds left in the window.
+pub async fn block_writes(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    if !is_write(req.method(), req.path()) {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+    let Some(maintenance) = req.app_data::<web::Data<MaintenanceService>>() else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+
+    let now = Utc::now();
+    let Some(window) = maintenance.active_at(now).await else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    let message = match &window.reason {
+        Some(reason) => format!("Down for maintenance until {} ({})", window.end.to_rfc3339(), reason),
+        None => format!("Down for maintenance until {}", window.end.to_rfc3339()),
+    };
+    let retry_after = (window.end - now).num_seconds().max(0) + 1;
+
+    let mut response = req.error_response(AppError::Unavailable(message));
+    response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    Ok(response.map_into_right_body())
+}
+
+#[cfg(all(test, not(feature = "database")))]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, middleware, test as actix_test, App, HttpResponse};
+
+    fn window(start_in: i64, end_in: i64) -> MaintenanceWindow {
+        MaintenanceWindow {
+            start: Utc::now() + chrono::Duration::seconds(start_in),
+            end: Utc::now() + chrono::Duration::seconds(end_in),
+            reason: Some("database migration".to_string()),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_writes_are_blocked_only_within_the_window() {
+        let maintenance = MaintenanceService::new(Some(window(-60, 120)));
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(maintenance.clone()))
+                .wrap(middleware::from_fn(block_writes))
+                .route("/orders", web::get().to(HttpResponse::Ok))
+                .route("/orders", web::post().to(HttpResponse::Created))
+                .route("/admin/maintenance", web::delete().to(HttpResponse::Ok)),
+        ).await;
+        let request = |method: Method, uri: &str| actix_test::TestRequest::default()
+            .method(method)
+            .uri(uri)
+            .to_request();
+
+        let response = actix_test::call_service(&app, request(Method::POST, "/orders")).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let retry_after: i64 = response.headers().get(header::RETRY_AFTER).unwrap().to_str().unwrap().parse().unwrap();
+        assert!((1..=121).contains(&retry_after));
+
+        // Reads, and the admin routes that end maintenance, keep working
+        assert_eq!(actix_test::call_service(&app, request(Method::GET, "/orders")).await.status(), StatusCode::OK);
+        assert_eq!(actix_test::call_service(&app, request(Method::DELETE, "/admin/maintenance")).await.status(), StatusCode::OK);
+
+        // A window that hasn't started yet doesn't block anything
+        maintenance.schedule(window(60, 120)).await.unwrap();
+        assert_eq!(actix_test::call_service(&app, request(Method::POST, "/orders")).await.status(), StatusCode::CREATED);
+
+        // Nor does one that was ended early
+        maintenance.schedule(window(-60, 120)).await.unwrap();
+        assert_eq!(actix_test::call_service(&app, request(Method::POST, "/orders")).await.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(maintenance.end().await.unwrap().is_some());
+        assert_eq!(actix_test::call_service(&app, request(Method::POST, "/orders")).await.status(), StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn test_window_must_end_in_the_future() {
+        let maintenance = MaintenanceService::new(None);
+        assert!(maintenance.schedule(window(-120, -60)).await.is_err());
+        assert!(maintenance.schedule(window(60, 30)).await.is_err());
+        assert!(maintenance.current().await.is_none());
+    }
+}
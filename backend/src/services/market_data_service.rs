@@ -0,0 +1,333 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+use crate::models::{split_symbol, MarketData, Trade};
+use crate::errors::AppError;
+use crate::rounding;
+
+const STATS_WINDOW_HOURS: i64 = 24;
+/// Decimal places `change_24h` is rounded to.
+const CHANGE_DECIMAL_PLACES: u32 = 4;
+
+#[derive(Debug, Clone)]
+struct TradePoint {
+    executed_at: DateTime<Utc>,
+    price: Decimal,
+    quantity: Decimal,
+}
+
+#[derive(Default)]
+struct SymbolStats {
+    last_price: Option<Decimal>,
+    window: VecDeque<TradePoint>, // Trades within the last 24h, oldest first
+    price_24h_ago: Option<Decimal>, // Last trade to drop out of the window
+}
+
+#[derive(Clone, Default)]
+pub struct MarketDataService {
+    stats: Arc<RwLock<HashMap<String, SymbolStats>>>, // Symbol -> Stats
+}
+
+impl MarketDataService {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A service with stats built from a recorded trade history, such as a
+    /// test fixture, without running the engine. The trades may come in any
+    /// order; they are applied oldest first.
+    pub fn from_trades(trades: impl IntoIterator<Item = Trade>) -> Self {
+        let mut trades: Vec<Trade> = trades.into_iter().collect();
+        trades.sort_by_key(|trade| (trade.executed_at, trade.seq));
+
+        let mut stats = HashMap::new();
+        for trade in &trades {
+            Self::record(&mut stats, trade);
+        }
+        Self {
+            stats: Arc::new(RwLock::new(stats)),
+        }
+    }
+
+    pub async fn record_trades(&self, trades: &[Trade]) {
+        let mut stats = self.stats.write().await;
+        for trade in trades {
+            Self::record(&mut stats, trade);
+        }
+    }
+
+    fn record(stats: &mut HashMap<String, SymbolStats>, trade: &Trade) {
+        let entry = stats.entry(trade.symbol.clone()).or_default();
+        entry.last_price = Some(trade.price);
+        entry.window.push_back(TradePoint {
+            executed_at: trade.executed_at,
+            price: trade.price,
+            quantity: trade.quantity,
+        });
+    }
+
+    pub async fn get_market_data(&self, symbol: &str) -> Result<MarketData, AppError> {
+        self.market_data_at(symbol, Utc::now()).await
+    }
+
+    /// Market data for `symbol` with prices expressed in `currency`, converting
+    /// the symbol's quote asset through the latest prices of other markets.
+    pub async fn get_market_data_in(&self, symbol: &str, currency: &str) -> Result<MarketData, AppError> {
+        let mut data = self.get_market_data(symbol).await?;
+        if data.quote_currency == currency {
+            return Ok(data);
+        }
+
+        let rate = self.conversion_rate(&data.quote_currency, currency).await
+            .ok_or_else(|| AppError::NotFound(format!(
+                "No conversion path from {} to {}",
+                data.quote_currency, currency
+            )))?;
+
+        data.last_price = rounding::amount(data.last_price * rate);
+        data.high_24h = rounding::amount(data.high_24h * rate);
+        data.low_24h = rounding::amount(data.low_24h * rate);
+        data.quote_currency = currency.to_string();
+        Ok(data)
+    }
+
+    /// Market data for each of `symbols`, in order. Unlike a single lookup,
+    /// a market that hasn't traded yet is included, with zero prices and volume.
+    pub async fn get_all_market_data(&self, symbols: &[String]) -> Vec<MarketData> {
+        let now = Utc::now();
+        let mut stats = self.stats.write().await;
+        let mut data = Vec::with_capacity(symbols.len());
+        for symbol in symbols {
+            let Some((_, quote)) = split_symbol(symbol) else {
+                continue;
+            };
+            let mut untraded = SymbolStats::default();
+            let entry = stats.get_mut(symbol.as_str()).unwrap_or(&mut untraded);
+            data.push(Self::summarize(symbol, quote, entry, now));
+        }
+        data
+    }
+
+    async fn market_data_at(&self, symbol: &str, now: DateTime<Utc>) -> Result<MarketData, AppError> {
+        let (_, quote) = split_symbol(symbol)
+            .ok_or_else(|| AppError::BadRequest(format!("Malformed symbol {}", symbol)))?;
+
+        let mut stats = self.stats.write().await;
+        let entry = stats.get_mut(symbol)
+            .ok_or_else(|| AppError::NotFound(format!("No market data for {}", symbol)))?;
+
+        Ok(Self::summarize(symbol, quote, entry, now))
+    }
+
+    /// 24h statistics from `entry`, first dropping trades that left the window.
+    fn summarize(symbol: &str, quote: &str, entry: &mut SymbolStats, now: DateTime<Utc>) -> MarketData {
+        let cutoff = now - Duration::hours(STATS_WINDOW_HOURS);
+        while entry.window.front().is_some_and(|t| t.executed_at < cutoff) {
+            entry.price_24h_ago = entry.window.pop_front().map(|t| t.price);
+        }
+
+        let last_price = entry.last_price.unwrap_or(Decimal::ZERO);
+        let volume_24h = entry.window.iter().map(|t| t.quantity).sum();
+        let high_24h = entry.window.iter().map(|t| t.price).max().unwrap_or(last_price);
+        let low_24h = entry.window.iter().map(|t| t.price).min().unwrap_or(last_price);
+        let change_24h = entry.price_24h_ago.and_then(|reference| Self::percent_change(reference, last_price));
+
+        MarketData {
+            symbol: symbol.to_string(),
+            quote_currency: quote.to_string(),
+            last_price,
+            volume_24h,
+            change_24h,
+            high_24h,
+            low_24h,
+        }
+    }
+
+    /// Change from `reference` to `price` in percent. `None` for a zero
+    /// reference, where no percentage exists.
+    fn percent_change(reference: Decimal, price: Decimal) -> Option<Decimal> {
+        let change = (price - reference).checked_div(reference)?.checked_mul(Decimal::ONE_HUNDRED)?;
+        Some(rounding::to_places(change, CHANGE_DECIMAL_PLACES))
+    }
+
+    /// Price of one unit of `from` in `to`, found by a breadth-first walk over
+    /// markets with a last price. Each market can be traversed in either direction.
+    pub async fn conversion_rate(&self, from: &str, to: &str) -> Option<Decimal> {
+        let stats = self.stats.read().await;
+        let mut edges: HashMap<&str, Vec<(&str, Decimal)>> = HashMap::new();
+        for (symbol, entry) in stats.iter() {
+            let (Some((base, quote)), Some(price)) = (split_symbol(symbol), entry.last_price) else {
+                continue;
+            };
+            // Only positive prices convert; spreads can trade at or below zero
+            if price <= Decimal::ZERO {
+                continue;
+            }
+            edges.entry(base).or_default().push((quote, price));
+            edges.entry(quote).or_default().push((base, Decimal::ONE / price));
+        }
+
+        let mut visited = HashSet::from([from]);
+        let mut queue = VecDeque::from([(from, Decimal::ONE)]);
+        while let Some((asset, rate)) = queue.pop_front() {
+            if asset == to {
+                return Some(rate);
+            }
+            for (next, step) in edges.get(asset).into_iter().flatten() {
+                if visited.insert(next) {
+                    queue.push_back((next, rate * step));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+    use crate::models::OrderSide;
+
+    fn trade(symbol: &str, quantity: Decimal, price: Decimal) -> Trade {
+        Trade {
+            id: Uuid::new_v4(),
+            seq: 1,
+            order_id: Uuid::new_v4(),
+            taker_order_id: Uuid::new_v4(),
+            maker_user_id: Uuid::new_v4(),
+            taker_user_id: Uuid::new_v4(),
+            taker_side: OrderSide::Buy,
+            symbol: symbol.to_string(),
+            quantity,
+            price,
+            taker_limit_price: None,
+            executed_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_new_listing_has_no_24h_change() {
+        let service = MarketDataService::new();
+        service.record_trades(&[
+            trade("NEW/USD", Decimal::ONE, Decimal::new(10, 0)),
+            trade("NEW/USD", Decimal::ONE, Decimal::new(12, 0)),
+        ]).await;
+
+        let data = service.get_market_data("NEW/USD").await.unwrap();
+        assert_eq!(data.last_price, Decimal::new(12, 0));
+        assert_eq!(data.change_24h, None);
+    }
+
+    #[tokio::test]
+    async fn test_24h_change_against_price_a_day_ago() {
+        let service = MarketDataService::new();
+        let now = Utc::now();
+        let mut older = trade("BTC/USD", Decimal::ONE, Decimal::new(90, 0));
+        older.executed_at = now - Duration::hours(30);
+        let mut day_ago = trade("BTC/USD", Decimal::ONE, Decimal::new(300, 0));
+        day_ago.executed_at = now - Duration::hours(25);
+        let mut recent = trade("BTC/USD", Decimal::ONE, Decimal::new(400, 0));
+        recent.executed_at = now - Duration::hours(1);
+        service.record_trades(&[older, day_ago, recent]).await;
+
+        let data = service.market_data_at("BTC/USD", now).await.unwrap();
+        // (400 - 300) / 300 = 33.3333...%
+        assert_eq!(data.change_24h, Some(Decimal::new(333_333, 4)));
+
+        // A zero reference price has no percentage change
+        assert_eq!(MarketDataService::percent_change(Decimal::ZERO, Decimal::ONE), None);
+    }
+
+    #[tokio::test]
+    async fn test_two_hop_conversion() {
+        let service = MarketDataService::new();
+        service.record_trades(&[
+            trade("ETH/BTC", Decimal::new(10, 0), Decimal::new(5, 2)),      // 0.05 BTC
+            trade("BTC/USDT", Decimal::new(1, 0), Decimal::new(60_000, 0)),
+            trade("USDT/USD", Decimal::new(100, 0), Decimal::ONE),
+        ]).await;
+
+        let data = service.get_market_data_in("ETH/BTC", "USD").await.unwrap();
+        assert_eq!(data.quote_currency, "USD");
+        assert_eq!(data.last_price, Decimal::new(3_000, 0));
+        assert_eq!(data.volume_24h, Decimal::new(10, 0));
+    }
+
+    #[tokio::test]
+    async fn test_inverse_market_conversion() {
+        let service = MarketDataService::new();
+        service.record_trades(&[
+            trade("ETH/EUR", Decimal::ONE, Decimal::new(2_000, 0)),
+            trade("USD/EUR", Decimal::ONE, Decimal::new(8, 1)), // 1 USD = 0.8 EUR
+        ]).await;
+
+        let data = service.get_market_data_in("ETH/EUR", "USD").await.unwrap();
+        assert_eq!(data.last_price, Decimal::new(2_500, 0));
+    }
+
+    /// Recorded trades as `symbol,hours_ago,price,quantity`, listed out of order.
+    const HISTORY: &str = "\
+        BTC/USD,2,51000,0.5
+        BTC/USD,30,45000,1
+        ETH/USD,3,3100,4
+        BTC/USD,26,48000,2
+        BTC/USD,12,52000,0.25
+        BTC/USD,1,50500,1.25
+        ETH/USD,20,2900,6
+    ";
+
+    fn fixture(csv: &str, now: DateTime<Utc>) -> Vec<Trade> {
+        csv.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let fields: Vec<&str> = line.split(',').collect();
+                let mut trade = trade(fields[0], fields[3].parse().unwrap(), fields[2].parse().unwrap());
+                trade.executed_at = now - Duration::hours(fields[1].parse().unwrap());
+                trade
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_stats_from_recorded_history() {
+        let now = Utc::now();
+        let service = MarketDataService::from_trades(fixture(HISTORY, now));
+
+        let btc = service.market_data_at("BTC/USD", now).await.unwrap();
+        // The latest trade sets the last price, whatever order the history came in
+        assert_eq!(btc.last_price, Decimal::new(50_500, 0));
+        assert_eq!(btc.volume_24h, Decimal::new(2, 0));
+        assert_eq!(btc.high_24h, Decimal::new(52_000, 0));
+        assert_eq!(btc.low_24h, Decimal::new(50_500, 0));
+        // Against 48000, the last trade to leave the window: 2.5 / 48 = 5.2083...%
+        assert_eq!(btc.change_24h, Some(Decimal::new(52_083, 4)));
+
+        // Both trades are within the window, so there is nothing to compare against
+        let eth = service.market_data_at("ETH/USD", now).await.unwrap();
+        assert_eq!(eth.last_price, Decimal::new(3_100, 0));
+        assert_eq!(eth.volume_24h, Decimal::new(10, 0));
+        assert_eq!(eth.high_24h, Decimal::new(3_100, 0));
+        assert_eq!(eth.low_24h, Decimal::new(2_900, 0));
+        assert_eq!(eth.change_24h, None);
+
+        // Later trades add to the history as usual
+        let mut latest = trade("ETH/USD", Decimal::ONE, Decimal::new(3_200, 0));
+        latest.executed_at = now;
+        service.record_trades(&[latest]).await;
+        assert_eq!(service.market_data_at("ETH/USD", now).await.unwrap().high_24h, Decimal::new(3_200, 0));
+    }
+
+    #[tokio::test]
+    async fn test_missing_conversion_path() {
+        let service = MarketDataService::new();
+        service.record_trades(&[trade("ETH/BTC", Decimal::ONE, Decimal::new(5, 2))]).await;
+
+        let result = service.get_market_data_in("ETH/BTC", "USD").await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use rust_decimal::Decimal;
+use crate::config::MarketConfig;
+use crate::errors::AppError;
+
+#[derive(Clone)]
+pub struct MarketService {
+    markets: Arc<RwLock<HashMap<String, MarketConfig>>>, // Symbol -> Market
+    delisted: Arc<RwLock<HashSet<String>>>, // Symbols that no longer take orders
+}
+
+impl MarketService {
+    pub fn new(markets: Vec<MarketConfig>) -> Self {
+        Self {
+            markets: Arc::new(RwLock::new(
+                markets.into_iter().map(|m| (m.symbol.clone(), m)).collect(),
+            )),
+            delisted: Arc::new(RwLock::new(HashSet::new())),
+        }
+    }
+
+    pub async fn get_market(&self, symbol: &str) -> Option<MarketConfig> {
+        self.markets.read().await.get(symbol).cloned()
+    }
+
+    /// Symbols of every listed market, sorted.
+    pub async fn symbols(&self) -> Vec<String> {
+        let mut symbols: Vec<String> = self.markets.read().await.keys().cloned().collect();
+        symbols.sort();
+        symbols
+    }
+
+    /// Whether `symbol`'s market takes zero and negative prices.
+    pub async fn allows_negative_price(&self, symbol: &str) -> bool {
+        self.get_market(symbol).await.is_some_and(|market| market.allow_negative_price)
+    }
+
+    /// The market for `symbol`, or `NotFound` when no such market is listed.
+    pub async fn require_market(&self, symbol: &str) -> Result<MarketConfig, AppError> {
+        self.get_market(symbol).await
+            .ok_or_else(|| AppError::NotFound(format!("Unknown symbol {}", symbol)))
+    }
+
+    /// Stops `symbol` taking orders. Its data stays readable. Returns whether
+    /// it was listed until now.
+    pub async fn delist(&self, symbol: &str) -> bool {
+        self.delisted.write().await.insert(symbol.to_string())
+    }
+
+    /// Rejects orders on a delisted market.
+    pub async fn ensure_listed(&self, symbol: &str) -> Result<(), AppError> {
+        if self.delisted.read().await.contains(symbol) {
+            return Err(AppError::Validation(format!("Market {} is delisted and takes no orders", symbol)));
+        }
+        Ok(())
+    }
+
+    /// Default maximum order lifetime of each market that sets one.
+    pub async fn order_ttls(&self) -> Vec<(String, chrono::Duration)> {
+        self.markets.read().await
+            .values()
+            .filter_map(|market| {
+                let ttl = market.default_order_ttl_secs?;
+                Some((market.symbol.clone(), chrono::Duration::seconds(ttl as i64)))
+            })
+            .collect()
+    }
+
+    /// Rejects prices outside the market's absolute floor and ceiling, when configured.
+    pub async fn check_price(&self, symbol: &str, price: Decimal) -> Result<(), AppError> {
+        let Some(market) = self.get_market(symbol).await else {
+            return Ok(());
+        };
+
+        if let Some(floor) = market.price_floor {
+            if price < floor {
+                return Err(AppError::Validation(format!(
+                    "Price {} is below the {} price floor of {}",
+                    price, symbol, floor
+                )));
+            }
+        }
+
+        if let Some(ceiling) = market.price_ceiling {
+            if price > ceiling {
+                return Err(AppError::Validation(format!(
+                    "Price {} is above the {} price ceiling of {}",
+                    price, symbol, ceiling
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service() -> MarketService {
+        MarketService::new(vec![MarketConfig {
+            price_floor: Some(Decimal::new(90, 0)),
+            price_ceiling: Some(Decimal::new(110, 0)),
+            ..MarketConfig::new("USDC/USD")
+        }])
+    }
+
+    #[tokio::test]
+    async fn test_price_below_floor_is_rejected() {
+        let result = service().check_price("USDC/USD", Decimal::new(89, 0)).await;
+        assert!(matches!(result, Err(AppError::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_price_above_ceiling_is_rejected() {
+        let result = service().check_price("USDC/USD", Decimal::new(111, 0)).await;
+        assert!(matches!(result, Err(AppError::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_price_within_bounds_is_accepted() {
+        let service = service();
+        assert!(service.check_price("USDC/USD", Decimal::new(90, 0)).await.is_ok());
+        assert!(service.check_price("USDC/USD", Decimal::new(110, 0)).await.is_ok());
+        // Markets without bounds accept any price
+        assert!(service.check_price("BTC/USD", Decimal::new(1, 0)).await.is_ok());
+    }
+}
@@ -1,2 +1,19 @@
 pub mod order_service;
-pub mod order_book_service; 
\ No newline at end of file
+pub mod order_book_service;
+pub mod fee_service;
+pub mod balance_service;
+pub mod market_service;
+pub mod market_data_service;
+pub mod password_service;
+pub mod auth_service;
+pub mod totp_service;
+pub mod secret_cipher;
+pub mod api_key_service;
+pub mod trade_persister;
+pub mod rate_limiter;
+pub mod stop_service;
+pub mod background_tasks;
+pub mod dependencies;
+pub mod maintenance_service;
+pub mod risk_service;
+pub mod database_pools;
\ No newline at end of file
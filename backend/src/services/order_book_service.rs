@@ -1,274 +1,2047 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::sync::atomic::{AtomicI64, Ordering};
+use tokio::sync::{broadcast, Mutex, RwLock};
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
+use crate::rounding;
+use prometheus::{exponential_buckets, HistogramOpts, HistogramVec};
 use uuid::Uuid;
-use crate::models::{Order, Trade, OrderSide, OrderStatus};
+use crate::models::{BestBidOffer, FrozenBook, FrozenLevel, Order, PriceFill, QueuePosition, SlippageEstimate, Trade, OrderSide, OrderStatus, OrderType};
 use crate::errors::AppError;
+use crate::units::{Price, Quantity};
 
+/// Levels per side in an order book snapshot unless the client asks for more.
+pub const DEFAULT_BOOK_DEPTH: usize = 10;
+/// Levels per side included in the ticker's order book imbalance.
+const TICKER_IMBALANCE_DEPTH: usize = 5;
+/// Updates buffered per `bbo` subscriber before a slow one starts missing them.
+const BBO_CHANNEL_CAPACITY: usize = 1024;
+/// Recent `bbo` updates kept for clients resuming a stream where they left off.
+const BBO_REPLAY_CAPACITY: usize = 1024;
+/// Trades buffered per subscriber before a slow one starts missing them.
+const TRADE_CHANNEL_CAPACITY: usize = 1024;
+/// Matching latency buckets, in seconds: 5µs doubling up to about 160ms.
+const MATCHING_LATENCY_BUCKETS: (f64, f64, usize) = (0.000_005, 2.0, 16);
+
+/// Best price and the total quantity resting there.
+type Level = (Price, Quantity);
+
+/// What is left of an order to trade.
+fn remaining(order: &Order) -> Quantity {
+    Quantity::new(order.quantity - order.filled_quantity)
+}
+
+/// Orders resting at one price. Every change to an order's remaining
+/// quantity goes through these methods so the cached level total stays exact.
 #[derive(Debug, Clone)]
 struct OrderQueue {
     orders: Vec<Order>,
+    total_quantity: Quantity, // Sum of the orders' remaining quantities
+    rested_at: HashMap<Uuid, DateTime<Utc>>, // When each order joined the queue, by the engine's clock
 }
 
 impl OrderQueue {
     fn new() -> Self {
-        Self { orders: Vec::new() }
+        Self { orders: Vec::new(), total_quantity: Quantity::ZERO, rested_at: HashMap::new() }
     }
 
-    fn add_order(&mut self, order: Order) {
-        self.orders.push(order);
-        // Sort by creation time (FIFO)
-        self.orders.sort_by_key(|o| o.created_at);
+    fn add_order(&mut self, order: Order, at: DateTime<Utc>) {
+        self.total_quantity += remaining(&order);
+        self.rested_at.insert(order.id, at);
+        // Kept in acceptance order (FIFO). Inserting after every order with the
+        // same or an earlier stamp keeps never-stamped orders in arrival order
+        let index = self.orders.partition_point(|o| o.acceptance_seq <= order.acceptance_seq);
+        self.orders.insert(index, order);
     }
 
     fn remove_order(&mut self, order_id: Uuid) -> Option<Order> {
         if let Some(index) = self.orders.iter().position(|o| o.id == order_id) {
-            Some(self.orders.remove(index))
+            let order = self.orders.remove(index);
+            self.total_quantity -= remaining(&order);
+            self.rested_at.remove(&order_id);
+            Some(order)
         } else {
             None
         }
     }
 
-    fn get_next_order(&mut self) -> Option<Order> {
-        self.orders.pop()
+    /// When `order_id` joined the queue, `None` if it isn't in it.
+    fn rested_at(&self, order_id: Uuid) -> Option<DateTime<Utc>> {
+        self.rested_at.get(&order_id).copied()
+    }
+
+    /// Records a fill of `quantity` against a resting order.
+    fn fill(&mut self, order_id: Uuid, quantity: Quantity, at: chrono::DateTime<chrono::Utc>) -> Option<&Order> {
+        let order = self.orders.iter_mut().find(|o| o.id == order_id)?;
+        order.filled_quantity += quantity.value();
+        order.updated_at = at;
+        self.total_quantity -= quantity;
+        Some(order)
+    }
+
+    /// Lowers a resting order's total quantity.
+    fn reduce(&mut self, order_id: Uuid, new_quantity: Decimal, at: chrono::DateTime<chrono::Utc>) -> Option<&Order> {
+        let order = self.orders.iter_mut().find(|o| o.id == order_id)?;
+        self.total_quantity -= Quantity::new(order.quantity - new_quantity);
+        order.quantity = new_quantity;
+        order.updated_at = at;
+        Some(order)
+    }
+
+    /// Number and remaining quantity of the orders queued ahead of `order_id`.
+    fn ahead_of(&self, order_id: Uuid) -> Option<(usize, Quantity)> {
+        let index = self.orders.iter().position(|o| o.id == order_id)?;
+        let quantity = self.orders[..index].iter().map(remaining).sum();
+        Some((index, quantity))
     }
 
     fn is_empty(&self) -> bool {
         self.orders.is_empty()
     }
 
-    fn total_quantity(&self) -> Decimal {
-        self.orders.iter().map(|o| o.quantity - o.filled_quantity).sum()
+    fn has_unpegged(&self) -> bool {
+        self.orders.iter().any(|o| !matches!(o.order_type, OrderType::Pegged))
+    }
+
+    fn total_quantity(&self) -> Quantity {
+        self.total_quantity
     }
 }
 
+/// Both sides of a single symbol's book. Every mutation holds the book's lock
+/// for its full duration, so matching and resting happen atomically.
+#[derive(Debug, Default)]
+struct Book {
+    bids: BTreeMap<Price, OrderQueue>, // Price -> Orders (descending)
+    asks: BTreeMap<Price, OrderQueue>, // Price -> Orders (ascending)
+    last_top: (Option<Level>, Option<Level>), // Top of book as last published
+    updated_at: Option<DateTime<Utc>>, // Last time an order was added, filled, reduced or removed
+}
+
+/// Recent top of book updates, every symbol in one sequence, so a client that
+/// reconnects can be sent what it missed.
+#[derive(Debug, Default)]
+struct BboHistory {
+    sequence: i64, // Last update's sequence
+    recent: VecDeque<BestBidOffer>, // Oldest first
+}
+
+/// A planned execution of `quantity` against the resting order `maker_id`.
+struct Fill {
+    price: Price,
+    maker_id: Uuid,
+    quantity: Quantity,
+}
+
+impl Book {
+    /// Best bid and best ask levels.
+    fn top(&self) -> (Option<Level>, Option<Level>) {
+        (
+            self.bids.last_key_value().map(|(price, queue)| (*price, queue.total_quantity())),
+            self.asks.first_key_value().map(|(price, queue)| (*price, queue.total_quantity())),
+        )
+    }
+
+    /// Best price on `side` among orders that aren't pegged, which pegged
+    /// orders on that side are priced from. Pegged orders don't move it, so
+    /// repricing them can't feed back into their own prices.
+    fn peg_reference(&self, side: &OrderSide) -> Option<Price> {
+        match side {
+            OrderSide::Buy => self.bids.iter().rev().find(|(_, queue)| queue.has_unpegged()).map(|(price, _)| *price),
+            OrderSide::Sell => self.asks.iter().find(|(_, queue)| queue.has_unpegged()).map(|(price, _)| *price),
+        }
+    }
+
+    fn side(&self, side: &OrderSide) -> &BTreeMap<Price, OrderQueue> {
+        match side {
+            OrderSide::Buy => &self.bids,
+            OrderSide::Sell => &self.asks,
+        }
+    }
+
+    fn side_mut(&mut self, side: &OrderSide) -> &mut BTreeMap<Price, OrderQueue> {
+        match side {
+            OrderSide::Buy => &mut self.bids,
+            OrderSide::Sell => &mut self.asks,
+        }
+    }
+
+    /// The side a taker on `side` trades against.
+    fn opposite(&self, side: &OrderSide) -> &BTreeMap<Price, OrderQueue> {
+        match side {
+            OrderSide::Buy => &self.asks,
+            OrderSide::Sell => &self.bids,
+        }
+    }
+
+    fn opposite_mut(&mut self, side: &OrderSide) -> &mut BTreeMap<Price, OrderQueue> {
+        match side {
+            OrderSide::Buy => &mut self.asks,
+            OrderSide::Sell => &mut self.bids,
+        }
+    }
+
+    /// Opposite-side price levels the taker crosses, best price first.
+    /// Market orders carry no price and cross every level up to their
+    /// protection price, if they have one.
+    fn crossing_prices(&self, taker: &Order) -> Vec<Price> {
+        let limit = taker.price.or(taker.protection_price).map(Price::new);
+        match taker.side {
+            OrderSide::Buy => self.asks.keys()
+                .take_while(|ask| limit.map_or(true, |price| price >= **ask))
+                .copied()
+                .collect(),
+            OrderSide::Sell => self.bids.keys()
+                .rev()
+                .take_while(|bid| limit.map_or(true, |price| price <= **bid))
+                .copied()
+                .collect(),
+        }
+    }
+
+    /// Whether executing `taker` at `price` would trade through a better
+    /// opposite-side level it could still take from. All-or-none makers and
+    /// remainders below `min_trade_quantity` don't count, as matching may
+    /// rightly pass them over.
+    fn trades_through(&self, taker: &Order, price: Price, min_trade_quantity: Quantity) -> bool {
+        let better = |level: &Price| match taker.side {
+            OrderSide::Buy => *level < price,
+            OrderSide::Sell => *level > price,
+        };
+        self.opposite(&taker.side).iter()
+            .filter(|(level, _)| better(level))
+            .flat_map(|(_, queue)| queue.orders.iter())
+            .any(|maker| !maker.all_or_none && OrderBookService::is_tradable(remaining(maker), min_trade_quantity))
+    }
+
+    /// Walks the crossing liquidity in price-time priority and works out which
+    /// resting orders the taker would trade with, without touching the book.
+    /// All-or-none makers are skipped unless the taker can take them entirely.
+    fn plan_fills(&self, taker: &Order, min_trade_quantity: Quantity) -> Vec<Fill> {
+        let mut fills = Vec::new();
+        let mut unfilled = remaining(taker);
+        let levels = self.opposite(&taker.side);
+
+        for price in self.crossing_prices(taker) {
+            for maker in &levels[&price].orders {
+                if !OrderBookService::is_tradable(unfilled, min_trade_quantity) {
+                    return fills;
+                }
+
+                let available = remaining(maker);
+                let quantity = std::cmp::min(unfilled, available);
+                if maker.all_or_none && quantity < available {
+                    continue;
+                }
+                if !OrderBookService::is_tradable(quantity, min_trade_quantity) {
+                    continue;
+                }
+
+                fills.push(Fill { price, maker_id: maker.id, quantity });
+                unfilled -= quantity;
+            }
+        }
+
+        fills
+    }
+
+    /// Fills the taker would actually execute: none for an all-or-none taker
+    /// the crossing liquidity can't cover in full.
+    fn executable_fills(&self, taker: &Order, min_trade_quantity: Quantity) -> Vec<Fill> {
+        let fills = self.plan_fills(taker, min_trade_quantity);
+        if taker.all_or_none {
+            let planned: Quantity = fills.iter().map(|fill| fill.quantity).sum();
+            if planned < remaining(taker) {
+                return Vec::new();
+            }
+        }
+        fills
+    }
+
+    fn resting_count(&self) -> usize {
+        self.bids.values().chain(self.asks.values()).map(|queue| queue.orders.len()).sum()
+    }
+
+    fn remove_resting(&mut self, order: &Order) -> Option<Order> {
+        let price = Price::new(order.price?);
+        let removed = self.side_mut(&order.side).get_mut(&price)?.remove_order(order.id);
+        self.prune();
+        removed
+    }
+
+    /// Drops the levels left without orders, so the best level on each side
+    /// is always real liquidity. Runs after every change to the book.
+    fn prune(&mut self) {
+        self.bids.retain(|_, queue| !queue.is_empty());
+        self.asks.retain(|_, queue| !queue.is_empty());
+        #[cfg(debug_assertions)]
+        self.assert_invariants();
+    }
+
+    /// Every level holds at least one order, and its cached total is the
+    /// positive sum of what its orders have left.
+    #[cfg(debug_assertions)]
+    fn assert_invariants(&self) {
+        for (price, queue) in self.bids.iter().chain(&self.asks) {
+            assert!(!queue.is_empty(), "empty level at {}", price);
+            let total: Quantity = queue.orders.iter().map(remaining).sum();
+            assert_eq!(queue.total_quantity(), total, "level total at {} is stale", price);
+            assert!(total > Quantity::ZERO, "level at {} has nothing left to trade", price);
+        }
+    }
+
+    /// The first resting all-or-none order, best price first, that the
+    /// opposite side can now fill entirely.
+    fn next_fillable_all_or_none(&self, min_trade_quantity: Quantity) -> Option<Order> {
+        self.bids.values().rev()
+            .chain(self.asks.values())
+            .flat_map(|queue| queue.orders.iter())
+            .filter(|order| order.all_or_none)
+            .find(|order| {
+                let planned: Quantity = self.plan_fills(order, min_trade_quantity).iter()
+                    .map(|fill| fill.quantity)
+                    .sum();
+                planned == remaining(order)
+            })
+            .cloned()
+    }
+}
+
+/// Source of ids for trades produced by the engine.
+pub trait IdGenerator: Send + Sync {
+    fn next_id(&self) -> Uuid;
+}
+
+/// Random v4 ids. The default.
+pub struct RandomIds;
+
+impl IdGenerator for RandomIds {
+    fn next_id(&self) -> Uuid {
+        Uuid::new_v4()
+    }
+}
+
+/// Reproducible v4-format ids drawn from a seeded RNG, so replaying the same
+/// order sequence yields the same trade ids.
+pub struct SeededIds {
+    rng: std::sync::Mutex<StdRng>,
+}
+
+impl SeededIds {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: std::sync::Mutex::new(StdRng::seed_from_u64(seed)),
+        }
+    }
+}
+
+impl IdGenerator for SeededIds {
+    fn next_id(&self) -> Uuid {
+        let mut bytes = [0u8; 16];
+        self.rng.lock().expect("id generator lock poisoned").fill_bytes(&mut bytes);
+        uuid::Builder::from_random_bytes(bytes).into_uuid()
+    }
+}
+
+/// Source of the engine's timestamps.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Wall clock time. The default.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to.
+#[cfg(test)]
+pub struct ManualClock(pub std::sync::Mutex<DateTime<Utc>>);
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn advance(&self, millis: i64) {
+        *self.0.lock().unwrap() += chrono::Duration::milliseconds(millis);
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// Merges `levels`, best first, into at most `depth` entries whose prices
+/// are multiples of `group`, rounding each level's price with `round`.
+fn group_levels<'a>(
+    levels: impl Iterator<Item = (&'a Price, &'a OrderQueue)>,
+    depth: usize,
+    group: Decimal,
+    round: fn(&Decimal) -> Decimal,
+) -> Vec<crate::models::OrderBookEntry> {
+    let mut entries: Vec<crate::models::OrderBookEntry> = Vec::new();
+    for (price, queue) in levels {
+        let bucket = round(&(price.value() / group)) * group;
+        if let Some(entry) = entries.last_mut().filter(|entry| entry.price == bucket) {
+            entry.quantity += queue.total_quantity().value();
+            entry.order_count += queue.orders.len() as i32;
+        } else if entries.len() == depth {
+            break;
+        } else {
+            entries.push(crate::models::OrderBookEntry {
+                price: bucket,
+                quantity: queue.total_quantity().value(),
+                order_count: queue.orders.len() as i32,
+            });
+        }
+    }
+    entries
+}
+
 #[derive(Clone)]
 pub struct OrderBookService {
-    bids: Arc<RwLock<BTreeMap<Decimal, OrderQueue>>>, // Price -> Orders (descending)
-    asks: Arc<RwLock<BTreeMap<Decimal, OrderQueue>>>, // Price -> Orders (ascending)
+    books: Arc<RwLock<HashMap<String, Arc<Mutex<Book>>>>>, // Symbol -> Book
+    sequence: Arc<AtomicI64>, // Engine event sequence, stamped on every trade
+    min_trade_quantities: Arc<RwLock<HashMap<String, Quantity>>>, // Symbol -> Dust threshold
+    max_resting_orders: Arc<RwLock<HashMap<String, usize>>>, // Symbol -> Resting order cap
+    min_quote_lives: Arc<RwLock<HashMap<String, chrono::Duration>>>, // Symbol -> Rest before a cancel
+    ids: Arc<dyn IdGenerator>,
+    clock: Arc<dyn Clock>,
+    bbo: broadcast::Sender<BestBidOffer>,
+    bbo_history: Arc<std::sync::Mutex<BboHistory>>,
+    trades: broadcast::Sender<Trade>,
+    matching_latency: HistogramVec, // Seconds per add_order, by symbol
 }
 
 impl OrderBookService {
     pub fn new() -> Self {
+        Self::with_id_generator(Arc::new(RandomIds))
+    }
+
+    pub fn with_id_generator(ids: Arc<dyn IdGenerator>) -> Self {
+        Self::with_sources(ids, Arc::new(SystemClock))
+    }
+
+    pub fn with_sources(ids: Arc<dyn IdGenerator>, clock: Arc<dyn Clock>) -> Self {
         Self {
-            bids: Arc::new(RwLock::new(BTreeMap::new())),
-            asks: Arc::new(RwLock::new(BTreeMap::new())),
+            books: Arc::new(RwLock::new(HashMap::new())),
+            sequence: Arc::new(AtomicI64::new(0)),
+            min_trade_quantities: Arc::new(RwLock::new(HashMap::new())),
+            max_resting_orders: Arc::new(RwLock::new(HashMap::new())),
+            min_quote_lives: Arc::new(RwLock::new(HashMap::new())),
+            ids,
+            clock,
+            bbo: broadcast::channel(BBO_CHANNEL_CAPACITY).0,
+            bbo_history: Arc::new(std::sync::Mutex::new(BboHistory::default())),
+            trades: broadcast::channel(TRADE_CHANNEL_CAPACITY).0,
+            matching_latency: Self::matching_latency_histogram(),
         }
     }
 
-    pub async fn add_order(&mut self, order: &Order) -> Result<Vec<Trade>, AppError> {
-        let mut trades = Vec::new();
+    fn matching_latency_histogram() -> HistogramVec {
+        let (start, factor, count) = MATCHING_LATENCY_BUCKETS;
+        let opts = HistogramOpts::new(
+            "matching_latency_seconds",
+            "Time taken to match and book an incoming order",
+        )
+        .buckets(exponential_buckets(start, factor, count).expect("valid bucket layout"));
+        HistogramVec::new(opts, &["symbol"]).expect("valid histogram")
+    }
 
-        match order.side {
-            OrderSide::Buy => {
-                // Try to match with existing asks
-                trades.extend(self.match_buy_order(order).await?);
-                
-                // If order still has remaining quantity, add to bids
-                if order.quantity > order.filled_quantity {
-                    let remaining_quantity = order.quantity - order.filled_quantity;
-                    let mut remaining_order = order.clone();
-                    remaining_order.quantity = remaining_quantity;
-                    remaining_order.filled_quantity = Decimal::ZERO;
-                    
-                    let mut bids = self.bids.write().await;
-                    bids.entry(order.price)
-                        .or_insert_with(OrderQueue::new)
-                        .add_order(remaining_order);
-                }
+    /// How long each `add_order` took, by symbol. Register it with a metrics
+    /// registry to export it.
+    pub fn matching_latency(&self) -> &HistogramVec {
+        &self.matching_latency
+    }
+
+    /// Top of book updates for every symbol, sent only when a symbol's best
+    /// bid or ask price or quantity changes.
+    pub fn subscribe_bbo(&self) -> broadcast::Receiver<BestBidOffer> {
+        self.bbo.subscribe()
+    }
+
+    /// Sequence of the last top of book update, on any symbol.
+    pub fn bbo_sequence(&self) -> i64 {
+        self.bbo_history.lock().expect("bbo history lock poisoned").sequence
+    }
+
+    /// `symbol`'s top of book updates after sequence `after`, oldest first.
+    /// `None` when some may no longer be kept, or `after` was never issued.
+    pub fn bbo_since(&self, symbol: &str, after: i64) -> Option<Vec<BestBidOffer>> {
+        let history = self.bbo_history.lock().expect("bbo history lock poisoned");
+        let oldest = history.recent.front().map_or(history.sequence + 1, |update| update.sequence);
+        if after < oldest - 1 || after > history.sequence {
+            return None;
+        }
+        Some(history.recent.iter()
+            .filter(|update| update.sequence > after && update.symbol == symbol)
+            .cloned()
+            .collect())
+    }
+
+    /// The engine's time, which stamps trades and book changes.
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    /// Every trade the engine executes, on any symbol, in execution order.
+    pub fn subscribe_trades(&self) -> broadcast::Receiver<Trade> {
+        self.trades.subscribe()
+    }
+
+    /// Publishes the book's top if it differs from what was last published.
+    fn publish_bbo(&self, symbol: &str, book: &mut Book) {
+        let top = book.top();
+        if top == book.last_top {
+            return;
+        }
+        book.last_top = top;
+
+        let (bid, ask) = top;
+        // Sequenced and sent under one lock, so subscribers see sequence order
+        let mut history = self.bbo_history.lock().expect("bbo history lock poisoned");
+        history.sequence += 1;
+        let update = BestBidOffer {
+            symbol: symbol.to_string(),
+            bid: bid.map(|(price, _)| price.value()),
+            bid_quantity: bid.map(|(_, quantity)| quantity.value()),
+            ask: ask.map(|(price, _)| price.value()),
+            ask_quantity: ask.map(|(_, quantity)| quantity.value()),
+            sequence: history.sequence,
+            timestamp: self.clock.now(),
+        };
+        if history.recent.len() == BBO_REPLAY_CAPACITY {
+            history.recent.pop_front();
+        }
+        history.recent.push_back(update.clone());
+        // Nobody subscribed is not an error
+        let _ = self.bbo.send(update);
+    }
+
+    fn next_sequence(&self) -> i64 {
+        self.sequence.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// The book for `symbol`, created on first use.
+    async fn book(&self, symbol: &str) -> Arc<Mutex<Book>> {
+        if let Some(book) = self.books.read().await.get(symbol) {
+            return book.clone();
+        }
+        self.books.write().await
+            .entry(symbol.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// The book for `symbol`, if any order has ever been placed on it.
+    async fn existing_book(&self, symbol: &str) -> Option<Arc<Mutex<Book>>> {
+        self.books.read().await.get(symbol).cloned()
+    }
+
+    /// Sets the dust threshold for a symbol. Fills smaller than this are never
+    /// produced: a taker remainder below it is left unmatched, and a resting
+    /// order whose remainder drops below it is considered complete.
+    pub async fn set_min_trade_quantity(&self, symbol: &str, quantity: Decimal) {
+        self.min_trade_quantities.write().await.insert(symbol.to_string(), Quantity::new(quantity));
+    }
+
+    async fn min_trade_quantity(&self, symbol: &str) -> Quantity {
+        self.min_trade_quantities.read().await
+            .get(symbol)
+            .copied()
+            .unwrap_or(Quantity::ZERO)
+    }
+
+    /// Caps the number of orders resting on a symbol's book. At the cap, new
+    /// orders that wouldn't trade are rejected; orders that trade still match,
+    /// and their remainder may rest since matching has already freed space or
+    /// consumed liquidity.
+    pub async fn set_max_resting_orders(&self, symbol: &str, max_resting_orders: usize) {
+        self.max_resting_orders.write().await.insert(symbol.to_string(), max_resting_orders);
+    }
+
+    async fn max_resting_orders(&self, symbol: &str) -> Option<usize> {
+        self.max_resting_orders.read().await.get(symbol).copied()
+    }
+
+    /// Sets how long orders must rest on a symbol's book before their owner
+    /// may cancel them, to deter quotes that flicker in and out.
+    pub async fn set_min_quote_life(&self, symbol: &str, life: chrono::Duration) {
+        self.min_quote_lives.write().await.insert(symbol.to_string(), life);
+    }
+
+    async fn min_quote_life(&self, symbol: &str) -> Option<chrono::Duration> {
+        self.min_quote_lives.read().await.get(symbol).copied()
+    }
+
+    /// Whether a remaining quantity is large enough to trade or rest.
+    fn is_tradable(remaining: Quantity, min_trade_quantity: Quantity) -> bool {
+        remaining > Quantity::ZERO && remaining >= min_trade_quantity
+    }
+
+    pub async fn add_order(&self, order: &Order) -> Result<Vec<Trade>, AppError> {
+        // Observed when dropped, rejections included
+        let _timer = self.matching_latency.with_label_values(&[&order.symbol]).start_timer();
+        let min_trade_quantity = self.min_trade_quantity(&order.symbol).await;
+        let max_resting_orders = self.max_resting_orders(&order.symbol).await;
+        let book = self.book(&order.symbol).await;
+        let mut book = book.lock().await;
+        Self::ensure_accepts(&book, order, min_trade_quantity, max_resting_orders)?;
+        Ok(self.place(&mut book, order, min_trade_quantity))
+    }
+
+    /// Matches `order` against `book` and rests what is left of it, then
+    /// publishes the outcome.
+    fn place(&self, book: &mut Book, order: &Order, min_trade_quantity: Quantity) -> Vec<Trade> {
+        // Match against the opposite side first
+        let mut trades = self.match_order(book, order, min_trade_quantity);
+        let now = self.clock.now();
+
+        // If order still has remaining quantity, rest it on its side. The resting
+        // copy keeps its original quantity alongside what has been filled.
+        let filled_now = Quantity::new(trades.iter().map(|t| t.quantity).sum());
+        if Self::is_tradable(remaining(order) - filled_now, min_trade_quantity) {
+            // Market orders carry no price and never rest on the book
+            if let Some(price) = order.price {
+                let mut remaining_order = order.clone();
+                remaining_order.filled_quantity += filled_now.value();
+                book.side_mut(&order.side)
+                    .entry(Price::new(price))
+                    .or_insert_with(OrderQueue::new)
+                    .add_order(remaining_order, now);
             }
-            OrderSide::Sell => {
-                // Try to match with existing bids
-                trades.extend(self.match_sell_order(order).await?);
-                
-                // If order still has remaining quantity, add to asks
-                if order.quantity > order.filled_quantity {
-                    let remaining_quantity = order.quantity - order.filled_quantity;
-                    let mut remaining_order = order.clone();
-                    remaining_order.quantity = remaining_quantity;
-                    remaining_order.filled_quantity = Decimal::ZERO;
-                    
-                    let mut asks = self.asks.write().await;
-                    asks.entry(order.price)
-                        .or_insert_with(OrderQueue::new)
-                        .add_order(remaining_order);
-                }
+        }
+
+        // New liquidity may complete resting all-or-none orders
+        trades.extend(self.sweep_all_or_none(book, min_trade_quantity));
+        book.prune();
+        book.updated_at = Some(now);
+        self.publish_bbo(&order.symbol, book);
+        for trade in &trades {
+            let _ = self.trades.send(trade.clone());
+        }
+
+        trades
+    }
+
+    /// Turns away orders the book can't take: ones that would only rest on a
+    /// full book, and market orders with nothing to trade against.
+    fn ensure_accepts(book: &Book, order: &Order, min_trade_quantity: Quantity, max_resting_orders: Option<usize>) -> Result<(), AppError> {
+        if let Some(max_resting_orders) = max_resting_orders {
+            let would_only_rest = order.price.is_some()
+                && book.executable_fills(order, min_trade_quantity).is_empty();
+            if would_only_rest && book.resting_count() >= max_resting_orders {
+                return Err(AppError::OrderBook(format!(
+                    "Order book for {} is full ({} resting orders)",
+                    order.symbol, max_resting_orders
+                )));
             }
         }
 
-        Ok(trades)
+        // A market order can't rest, so one with nothing to trade against is
+        // turned away rather than accepted and left unfilled
+        if order.price.is_none() && book.executable_fills(order, min_trade_quantity).is_empty() {
+            return Err(AppError::OrderBook(format!(
+                "No liquidity to fill market order on {}",
+                order.symbol
+            )));
+        }
+
+        Ok(())
     }
 
-    async fn match_buy_order(&mut self, buy_order: &Order) -> Result<Vec<Trade>, AppError> {
-        let mut trades = Vec::new();
-        let mut remaining_quantity = buy_order.quantity;
-
-        // Iterate through asks in ascending order (lowest price first)
-        while remaining_quantity > Decimal::ZERO {
-            let ask_price = {
-                let asks = self.asks.read().await;
-                if let Some((&price, _)) = asks.first_key_value() {
-                    price
-                } else {
-                    break;
-                }
-            };
+    /// The fills `order` would get if placed now, as `(price, quantity)` in
+    /// execution order, and whether what's left of it would rest. The book
+    /// is only read: nothing trades and no update is published.
+    pub async fn preview(&self, order: &Order) -> Result<(Vec<(Decimal, Decimal)>, bool), AppError> {
+        let min_trade_quantity = self.min_trade_quantity(&order.symbol).await;
+        let max_resting_orders = self.max_resting_orders(&order.symbol).await;
+        // A symbol nobody has traded yet is previewed against an empty book, not given one
+        let book = self.existing_book(&order.symbol).await.unwrap_or_default();
+        let book = book.lock().await;
+        Self::ensure_accepts(&book, order, min_trade_quantity, max_resting_orders)?;
 
-            // Check if buy price is >= ask price
-            if buy_order.price >= ask_price {
-                let mut asks = self.asks.write().await;
-                if let Some(ask_queue) = asks.get_mut(&ask_price) {
-                    if let Some(mut ask_order) = ask_queue.get_next_order() {
-                        let trade_quantity = std::cmp::min(remaining_quantity, ask_order.quantity - ask_order.filled_quantity);
-                        
-                        if trade_quantity > Decimal::ZERO {
-                            // Create trade
-                            let trade = Trade {
-                                id: Uuid::new_v4(),
-                                order_id: ask_order.id,
-                                symbol: buy_order.symbol.clone(),
-                                quantity: trade_quantity,
-                                price: ask_price,
-                                executed_at: chrono::Utc::now(),
-                            };
-                            trades.push(trade);
-
-                            // Update quantities
-                            remaining_quantity -= trade_quantity;
-                            ask_order.filled_quantity += trade_quantity;
-
-                            // If ask order is not fully filled, put it back
-                            if ask_order.filled_quantity < ask_order.quantity {
-                                ask_queue.add_order(ask_order);
-                            }
-                        }
-                    } else {
-                        // No more orders at this price level
-                        asks.remove(&ask_price);
-                    }
-                }
-            } else {
-                // Buy price is too low, stop matching
-                break;
+        let fills = book.executable_fills(order, min_trade_quantity);
+        let filled: Quantity = fills.iter().map(|fill| fill.quantity).sum();
+        let rests = order.price.is_some()
+            && Self::is_tradable(remaining(order) - filled, min_trade_quantity);
+        let fills = fills.iter()
+            .map(|fill| (fill.price.value(), fill.quantity.value()))
+            .collect();
+        Ok((fills, rests))
+    }
+
+    /// What a market order for `quantity` on `side` would cost now, against
+    /// the mid before it trades. The book is only read.
+    pub async fn estimate_slippage(&self, symbol: &str, side: OrderSide, quantity: Decimal) -> SlippageEstimate {
+        let min_trade_quantity = self.min_trade_quantity(symbol).await;
+        let book = self.existing_book(symbol).await.unwrap_or_default();
+        let book = book.lock().await;
+
+        let now = self.clock.now();
+        let taker = Order {
+            id: Uuid::nil(),
+            user_id: Uuid::nil(),
+            symbol: symbol.to_string(),
+            side: side.clone(),
+            quantity,
+            price: None,
+            order_type: OrderType::Market,
+            all_or_none: false,
+            stop_price: None,
+            trigger_symbol: None,
+            peg_offset: None,
+            protection_price: None,
+            oco_group_id: None,
+            client_order_id: None,
+            metadata: None,
+            status: OrderStatus::New,
+            filled_quantity: Decimal::ZERO,
+            reject_reason: None,
+            acceptance_seq: 0,
+            created_at: now,
+            updated_at: now,
+        };
+        let fills = book.plan_fills(&taker, min_trade_quantity);
+        let mid_price = match book.top() {
+            (Some((bid, _)), Some((ask, _))) => Some((bid.value() + ask.value()) / Decimal::TWO),
+            _ => None,
+        };
+        drop(book);
+
+        let filled_quantity: Quantity = fills.iter().map(|fill| fill.quantity).sum();
+        let worst_price = fills.last().map(|fill| fill.price.value());
+        let average_price = PriceFill::average_price(&PriceFill::group(
+            fills.iter().map(|fill| (fill.price.value(), fill.quantity.value())),
+        ));
+        let slippage_bps = match (average_price, mid_price) {
+            (Some(average), Some(mid)) if mid > Decimal::ZERO => {
+                let worse_by = match side {
+                    OrderSide::Buy => average - mid,
+                    OrderSide::Sell => mid - average,
+                };
+                (worse_by * Decimal::from(10_000)).checked_div(mid).map(|bps| rounding::to_places(bps, 4))
             }
+            _ => None,
+        };
+
+        SlippageEstimate {
+            symbol: symbol.to_string(),
+            side,
+            quantity,
+            filled_quantity: filled_quantity.value(),
+            average_price,
+            worst_price,
+            mid_price,
+            slippage_bps,
+            fully_fillable: filled_quantity.value() == quantity,
         }
+    }
 
-        Ok(trades)
+    /// Executes `taker` against the opposite side of the book. An all-or-none
+    /// taker only trades when the crossing liquidity covers all of it.
+    fn match_order(&self, book: &mut Book, taker: &Order, min_trade_quantity: Quantity) -> Vec<Trade> {
+        let fills = book.executable_fills(taker, min_trade_quantity);
+        let mut trades = Vec::with_capacity(fills.len());
+        for fill in fills {
+            // Trade-through protection: a better level is always used up first
+            debug_assert!(
+                !book.trades_through(taker, fill.price, min_trade_quantity),
+                "order {} would trade at {} through a better price",
+                taker.id, fill.price
+            );
+            let queue = book.opposite_mut(&taker.side).get_mut(&fill.price).expect("planned level is resting");
+            let executed_at = self.clock.now();
+            let maker = queue.fill(fill.maker_id, fill.quantity, executed_at).expect("planned maker is resting");
+
+            trades.push(Trade {
+                id: self.ids.next_id(),
+                seq: self.next_sequence(),
+                order_id: maker.id,
+                taker_order_id: taker.id,
+                maker_user_id: maker.user_id,
+                taker_user_id: taker.user_id,
+                taker_side: taker.side.clone(),
+                symbol: taker.symbol.clone(),
+                quantity: fill.quantity.value(),
+                price: fill.price.value(),
+                taker_limit_price: taker.price,
+                executed_at,
+            });
+
+            // A maker left without a tradable remainder is complete
+            if !Self::is_tradable(remaining(maker), min_trade_quantity) {
+                queue.remove_order(fill.maker_id);
+            }
+        }
+
+        book.prune();
+        trades
     }
 
-    async fn match_sell_order(&mut self, sell_order: &Order) -> Result<Vec<Trade>, AppError> {
+    /// Fills resting all-or-none orders that the book can now cover in full.
+    /// The all-or-none order is lifted off the book and executed as the taker.
+    fn sweep_all_or_none(&self, book: &mut Book, min_trade_quantity: Quantity) -> Vec<Trade> {
         let mut trades = Vec::new();
-        let mut remaining_quantity = sell_order.quantity;
-
-        // Iterate through bids in descending order (highest price first)
-        while remaining_quantity > Decimal::ZERO {
-            let bid_price = {
-                let bids = self.bids.read().await;
-                if let Some((&price, _)) = bids.last_key_value() {
-                    price
-                } else {
-                    break;
-                }
-            };
+        while let Some(order) = book.next_fillable_all_or_none(min_trade_quantity) {
+            book.remove_resting(&order);
+            trades.extend(self.match_order(book, &order, min_trade_quantity));
+        }
+        trades
+    }
 
-            // Check if sell price is <= bid price
-            if sell_order.price <= bid_price {
-                let mut bids = self.bids.write().await;
-                if let Some(bid_queue) = bids.get_mut(&bid_price) {
-                    if let Some(mut bid_order) = bid_queue.get_next_order() {
-                        let trade_quantity = std::cmp::min(remaining_quantity, bid_order.quantity - bid_order.filled_quantity);
-                        
-                        if trade_quantity > Decimal::ZERO {
-                            // Create trade
-                            let trade = Trade {
-                                id: Uuid::new_v4(),
-                                order_id: bid_order.id,
-                                symbol: sell_order.symbol.clone(),
-                                quantity: trade_quantity,
-                                price: bid_price,
-                                executed_at: chrono::Utc::now(),
-                            };
-                            trades.push(trade);
-
-                            // Update quantities
-                            remaining_quantity -= trade_quantity;
-                            bid_order.filled_quantity += trade_quantity;
-
-                            // If bid order is not fully filled, put it back
-                            if bid_order.filled_quantity < bid_order.quantity {
-                                bid_queue.add_order(bid_order);
-                            }
-                        }
-                    } else {
-                        // No more orders at this price level
-                        bids.remove(&bid_price);
-                    }
-                }
-            } else {
-                // Sell price is too high, stop matching
-                break;
-            }
+    /// Takes `order` off the book, returning the resting copy if it was
+    /// there. Its `filled_quantity` counts every fill it took while resting.
+    pub async fn remove_order(&self, order: &Order) -> Result<Option<Order>, AppError> {
+        // Market orders never rest, so there is nothing to remove
+        let Some(book) = self.existing_book(&order.symbol).await else {
+            return Ok(None);
+        };
+        let mut book = book.lock().await;
+        let removed = book.remove_resting(order);
+        if removed.is_some() {
+            book.updated_at = Some(self.clock.now());
         }
+        self.publish_bbo(&order.symbol, &mut book);
+        Ok(removed)
+    }
 
-        Ok(trades)
+    /// Empties `symbol`'s book, returning the orders that were resting on it.
+    pub async fn clear(&self, symbol: &str) -> Vec<Order> {
+        let Some(book) = self.existing_book(symbol).await else {
+            return Vec::new();
+        };
+        let mut book = book.lock().await;
+        let bids = std::mem::take(&mut book.bids);
+        let asks = std::mem::take(&mut book.asks);
+        let cleared: Vec<Order> = bids.into_values().chain(asks.into_values())
+            .flat_map(|queue| queue.orders)
+            .collect();
+        if !cleared.is_empty() {
+            book.updated_at = Some(self.clock.now());
+        }
+        self.publish_bbo(symbol, &mut book);
+        cleared
     }
 
-    pub async fn remove_order(&mut self, order: &Order) -> Result<(), AppError> {
-        match order.side {
-            OrderSide::Buy => {
-                let mut bids = self.bids.write().await;
-                if let Some(queue) = bids.get_mut(&order.price) {
-                    queue.remove_order(order.id);
-                    if queue.is_empty() {
-                        bids.remove(&order.price);
-                    }
-                }
+    /// Takes `order` off the book at its owner's request, like `remove_order`,
+    /// unless it has rested for less than its market's minimum quote life.
+    /// That counts from when the order last joined its queue, so a replaced
+    /// or repriced order starts over.
+    pub async fn cancel_order(&self, order: &Order) -> Result<Option<Order>, AppError> {
+        let Some(life) = self.min_quote_life(&order.symbol).await else {
+            return self.remove_order(order).await;
+        };
+        let Some(book) = self.existing_book(&order.symbol).await else {
+            return Ok(None);
+        };
+        let mut book = book.lock().await;
+
+        let now = self.clock.now();
+        let rested_at = order.price
+            .and_then(|price| book.side(&order.side).get(&Price::new(price)))
+            .and_then(|queue| queue.rested_at(order.id));
+        if let Some(rested_at) = rested_at {
+            let cancellable_at = rested_at + life;
+            if now < cancellable_at {
+                return Err(AppError::OrderBook(format!(
+                    "Orders on {} must rest for {}ms before they can be cancelled; order {} can be cancelled in {}ms",
+                    order.symbol,
+                    life.num_milliseconds(),
+                    order.id,
+                    (cancellable_at - now).num_milliseconds()
+                )));
             }
-            OrderSide::Sell => {
-                let mut asks = self.asks.write().await;
-                if let Some(queue) = asks.get_mut(&order.price) {
-                    queue.remove_order(order.id);
-                    if queue.is_empty() {
-                        asks.remove(&order.price);
+        }
+
+        let removed = book.remove_resting(order);
+        if removed.is_some() {
+            book.updated_at = Some(now);
+        }
+        self.publish_bbo(&order.symbol, &mut book);
+        Ok(removed)
+    }
+
+    /// Shrinks a resting order's total quantity in place, keeping its time
+    /// priority. The new quantity may not be below what has already been
+    /// filled; reducing to exactly the filled quantity removes the order.
+    pub async fn reduce_order(&self, order: &Order, new_quantity: Decimal) -> Result<Order, AppError> {
+        let not_resting = || AppError::OrderBook("Order is not resting on the book".to_string());
+        let price = order.price.map(Price::new).ok_or_else(not_resting)?;
+        let book = self.existing_book(&order.symbol).await.ok_or_else(not_resting)?;
+        let mut book = book.lock().await;
+        let levels = book.side_mut(&order.side);
+
+        let queue = levels.get_mut(&price).ok_or_else(not_resting)?;
+        let resting = queue.orders.iter()
+            .find(|o| o.id == order.id)
+            .ok_or_else(not_resting)?;
+
+        if new_quantity >= resting.quantity {
+            return Err(AppError::Validation(format!(
+                "New quantity {} must be smaller than the current quantity {}",
+                new_quantity, resting.quantity
+            )));
+        }
+
+        if new_quantity < resting.filled_quantity {
+            return Err(AppError::Validation(format!(
+                "New quantity {} is below the already filled quantity {}",
+                new_quantity, resting.filled_quantity
+            )));
+        }
+
+        let now = self.clock.now();
+        let reduced = queue.reduce(order.id, new_quantity, now)
+            .expect("order was found above")
+            .clone();
+
+        if reduced.filled_quantity == reduced.quantity {
+            queue.remove_order(reduced.id);
+        }
+        book.prune();
+        book.updated_at = Some(now);
+        self.publish_bbo(&order.symbol, &mut book);
+
+        Ok(reduced)
+    }
+
+    /// The book's copy of `order`, with every fill it took while resting.
+    /// `None` when the order isn't resting on the book.
+    pub async fn resting_order(&self, order: &Order) -> Option<Order> {
+        let price = Price::new(order.price?);
+        let book = self.existing_book(&order.symbol).await?;
+        let book = book.lock().await;
+        book.side(&order.side).get(&price)?.orders.iter()
+            .find(|resting| resting.id == order.id)
+            .cloned()
+    }
+
+    /// Moves a resting order to `price` with a new total `quantity`, at the
+    /// back of its new level's queue, matching it first if the new price
+    /// crosses the book. `acceptance_seq` is the order's new place in time.
+    /// What it filled while resting carries over, and the new quantity must be
+    /// above it. Returns the order as it was placed again and the trades it made.
+    pub async fn replace_order(&self, order: &Order, price: Decimal, quantity: Decimal, acceptance_seq: i64) -> Result<(Order, Vec<Trade>), AppError> {
+        let not_resting = || AppError::OrderBook("Order is not resting on the book".to_string());
+        let current = order.price.map(Price::new).ok_or_else(not_resting)?;
+        let min_trade_quantity = self.min_trade_quantity(&order.symbol).await;
+        let book = self.existing_book(&order.symbol).await.ok_or_else(not_resting)?;
+        let mut book = book.lock().await;
+
+        let resting = book.side(&order.side).get(&current)
+            .and_then(|queue| queue.orders.iter().find(|o| o.id == order.id))
+            .cloned()
+            .ok_or_else(not_resting)?;
+        if quantity <= resting.filled_quantity {
+            return Err(AppError::Validation(format!(
+                "New quantity {} must be above the already filled quantity {}",
+                quantity, resting.filled_quantity
+            )));
+        }
+
+        book.remove_resting(&resting);
+        let now = self.clock.now();
+        let replacement = Order {
+            price: Some(price),
+            quantity,
+            // Its place in the queue, and its quote life, start over
+            acceptance_seq,
+            updated_at: now,
+            ..resting
+        };
+        let trades = self.place(&mut book, &replacement, min_trade_quantity);
+        Ok((replacement, trades))
+    }
+
+    /// The price a pegged order on `side` of `symbol` rests at with `offset`.
+    /// `None` while that side has no other orders to peg to.
+    pub async fn peg_price(&self, symbol: &str, side: &OrderSide, offset: Decimal) -> Option<Decimal> {
+        let book = self.existing_book(symbol).await?;
+        let book = book.lock().await;
+        book.peg_reference(side).map(|reference| reference.value() + offset)
+    }
+
+    /// The pegged orders resting on `symbol`'s book away from their peg, as
+    /// they rest, each with the price it should move to. A side left with
+    /// nothing else to peg to keeps its pegged orders where they are.
+    pub async fn pegs_to_reprice(&self, symbol: &str) -> Vec<(Order, Decimal)> {
+        let Some(book) = self.existing_book(symbol).await else {
+            return Vec::new();
+        };
+        let book = book.lock().await;
+
+        let mut repricing = Vec::new();
+        for side in [OrderSide::Buy, OrderSide::Sell] {
+            let Some(reference) = book.peg_reference(&side) else {
+                continue;
+            };
+            for (price, queue) in book.side(&side) {
+                for order in queue.orders.iter().filter(|o| matches!(o.order_type, OrderType::Pegged)) {
+                    let target = reference.value() + order.peg_offset.unwrap_or_default();
+                    if *price != Price::new(target) {
+                        repricing.push((order.clone(), target));
                     }
                 }
             }
         }
-        Ok(())
+        repricing
+    }
+
+    /// Where a resting order stands in its price level's queue. `None` when
+    /// the order isn't resting on the book.
+    pub async fn queue_position(&self, order_id: Uuid, symbol: &str, side: &OrderSide, price: Decimal) -> Option<QueuePosition> {
+        let book = self.existing_book(symbol).await?;
+        let book = book.lock().await;
+        let (orders_ahead, quantity_ahead) = book.side(side).get(&Price::new(price))?.ahead_of(order_id)?;
+        Some(QueuePosition {
+            order_id,
+            symbol: symbol.to_string(),
+            side: side.clone(),
+            price,
+            orders_ahead,
+            quantity_ahead: quantity_ahead.value(),
+        })
     }
 
     pub async fn get_order_book(&self, symbol: &str) -> crate::models::OrderBook {
-        let bids: Vec<crate::models::OrderBookEntry> = {
-            let bids = self.bids.read().await;
-            bids.iter()
-                .rev() // Reverse to get highest price first
-                .take(10) // Limit to top 10 levels
-                .map(|(price, queue)| crate::models::OrderBookEntry {
-                    price: *price,
-                    quantity: queue.total_quantity(),
-                    order_count: queue.orders.len() as i32,
-                })
-                .collect()
+        self.get_order_book_depth(symbol, DEFAULT_BOOK_DEPTH).await
+    }
+
+    /// Top `depth` levels per side, or fewer when the book is thinner.
+    pub async fn get_order_book_depth(&self, symbol: &str, depth: usize) -> crate::models::OrderBook {
+        let (bids, asks) = match self.existing_book(symbol).await {
+            Some(book) => {
+                let book = book.lock().await;
+                let bids: Vec<crate::models::OrderBookEntry> = book.bids.iter()
+                    .rev() // Reverse to get highest price first
+                    .take(depth)
+                    .map(|(price, queue)| crate::models::OrderBookEntry {
+                        price: price.value(),
+                        quantity: queue.total_quantity().value(),
+                        order_count: queue.orders.len() as i32,
+                    })
+                    .collect();
+
+                let asks: Vec<crate::models::OrderBookEntry> = book.asks.iter()
+                    .take(depth)
+                    .map(|(price, queue)| crate::models::OrderBookEntry {
+                        price: price.value(),
+                        quantity: queue.total_quantity().value(),
+                        order_count: queue.orders.len() as i32,
+                    })
+                    .collect();
+
+                (bids, asks)
+            }
+            None => (Vec::new(), Vec::new()),
         };
 
-        let asks: Vec<crate::models::OrderBookEntry> = {
-            let asks = self.asks.read().await;
-            asks.iter()
-                .take(10) // Limit to top 10 levels
-                .map(|(price, queue)| crate::models::OrderBookEntry {
-                    price: *price,
-                    quantity: queue.total_quantity(),
-                    order_count: queue.orders.len() as i32,
-                })
-                .collect()
+        crate::models::OrderBook {
+            symbol: symbol.to_string(),
+            bids,
+            asks,
+            last_updated: self.clock.now(),
+            requested_depth: None,
+            returned_depth: None,
+        }
+    }
+
+    /// Like `get_order_book_depth`, with levels merged into buckets `group`
+    /// wide. Bids are rounded down and asks up, so a grouped level is never
+    /// shown at a better price than its orders rest at.
+    pub async fn get_grouped_order_book(&self, symbol: &str, depth: usize, group: Decimal) -> crate::models::OrderBook {
+        let (bids, asks) = match self.existing_book(symbol).await {
+            Some(book) => {
+                let book = book.lock().await;
+                (
+                    group_levels(book.bids.iter().rev(), depth, group, Decimal::floor),
+                    group_levels(book.asks.iter(), depth, group, Decimal::ceil),
+                )
+            }
+            None => (Vec::new(), Vec::new()),
         };
 
         crate::models::OrderBook {
             symbol: symbol.to_string(),
             bids,
             asks,
-            last_updated: chrono::Utc::now(),
+            last_updated: self.clock.now(),
+            requested_depth: None,
+            returned_depth: None,
+        }
+    }
+
+    /// Copies every level and resting order of `symbol`'s book. The copy is
+    /// taken under the book's lock, so it never shows half of a match.
+    pub async fn freeze(&self, symbol: &str) -> FrozenBook {
+        let level = |(price, queue): (&Price, &OrderQueue)| FrozenLevel {
+            price: price.value(),
+            quantity: queue.total_quantity().value(),
+            orders: queue.orders.clone(),
+        };
+        let (bids, asks, sequence, updated_at) = match self.existing_book(symbol).await {
+            Some(book) => {
+                let book = book.lock().await;
+                (
+                    book.bids.iter().rev().map(level).collect(),
+                    book.asks.iter().map(level).collect(),
+                    // Trades on this symbol can't be stamped while we hold its lock
+                    self.sequence.load(Ordering::SeqCst),
+                    book.updated_at,
+                )
+            }
+            None => (Vec::new(), Vec::new(), self.sequence.load(Ordering::SeqCst), None),
+        };
+
+        FrozenBook {
+            symbol: symbol.to_string(),
+            bids,
+            asks,
+            sequence,
+            updated_at,
+            frozen_at: self.clock.now(),
         }
     }
-} 
\ No newline at end of file
+
+    /// Checks that `snapshot` describes a book that could exist: every order
+    /// resting on the right side at its level's price with something left to
+    /// fill, level totals that add up, each order once, and no bid at or
+    /// above the lowest ask.
+    pub fn check_snapshot(snapshot: &FrozenBook) -> Result<(), AppError> {
+        let invalid = |reason: String| AppError::Validation(format!("Snapshot of {}: {}", snapshot.symbol, reason));
+        crate::models::validate_symbol(&snapshot.symbol).map_err(invalid)?;
+
+        let mut ids = std::collections::HashSet::new();
+        for (levels, bids) in [(&snapshot.bids, true), (&snapshot.asks, false)] {
+            for level in levels {
+                for order in &level.orders {
+                    if order.symbol != snapshot.symbol || matches!(order.side, OrderSide::Buy) != bids {
+                        let side = if bids { "buy" } else { "sell" };
+                        return Err(invalid(format!("order {} is not a {} order on {}", order.id, side, snapshot.symbol)));
+                    }
+                    if order.price != Some(level.price) {
+                        return Err(invalid(format!("order {} is not priced at its level, {}", order.id, level.price)));
+                    }
+                    if !order.status.is_open() || !Self::is_tradable(remaining(order), Quantity::ZERO) {
+                        return Err(invalid(format!("order {} has nothing left to fill", order.id)));
+                    }
+                    if !ids.insert(order.id) {
+                        return Err(invalid(format!("order {} appears more than once", order.id)));
+                    }
+                }
+                let total: Quantity = level.orders.iter().map(remaining).sum();
+                if total != Quantity::new(level.quantity) {
+                    return Err(invalid(format!(
+                        "level {} holds {} but its orders add up to {}",
+                        level.price, level.quantity, total
+                    )));
+                }
+            }
+        }
+
+        let best_bid = snapshot.bids.iter().map(|level| level.price).max();
+        let best_ask = snapshot.asks.iter().map(|level| level.price).min();
+        if let (Some(bid), Some(ask)) = (best_bid, best_ask) {
+            if bid >= ask {
+                return Err(invalid(format!("the book is crossed, with a bid at {} and an ask at {}", bid, ask)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts the resting orders of a checked `snapshot` on an empty book as
+    /// they are, without matching, keeping their fills and queue order.
+    /// Returns how many orders were loaded.
+    pub async fn seed(&self, snapshot: &FrozenBook) -> Result<usize, AppError> {
+        Self::check_snapshot(snapshot)?;
+        let book = self.book(&snapshot.symbol).await;
+        let mut book = book.lock().await;
+        if book.resting_count() > 0 {
+            return Err(AppError::OrderBook(format!(
+                "Order book for {} already has resting orders; snapshots only seed an empty book",
+                snapshot.symbol
+            )));
+        }
+
+        let now = self.clock.now();
+        let mut count = 0;
+        for level in snapshot.bids.iter().chain(&snapshot.asks) {
+            for order in &level.orders {
+                book.side_mut(&order.side)
+                    .entry(Price::new(level.price))
+                    .or_insert_with(OrderQueue::new)
+                    .add_order(order.clone(), now);
+                count += 1;
+            }
+        }
+        book.prune();
+        book.updated_at = Some(now);
+        self.publish_bbo(&snapshot.symbol, &mut book);
+        Ok(count)
+    }
+
+    /// Share of resting volume on the bid side over the top `depth` levels of
+    /// each side: `bid_volume / (bid_volume + ask_volume)`, between 0 and 1.
+    /// `None` when both sides are empty.
+    pub async fn imbalance(&self, symbol: &str, depth: usize) -> Option<Decimal> {
+        let book = self.existing_book(symbol).await?;
+        let book = book.lock().await;
+        Self::imbalance_of(&book, depth)
+    }
+
+    fn imbalance_of(book: &Book, depth: usize) -> Option<Decimal> {
+        let bid_volume: Quantity = book.bids.values().rev().take(depth).map(OrderQueue::total_quantity).sum();
+        let ask_volume: Quantity = book.asks.values().take(depth).map(OrderQueue::total_quantity).sum();
+        let total = bid_volume + ask_volume;
+        if total.is_zero() {
+            return None;
+        }
+        Some(bid_volume.value() / total.value())
+    }
+
+    pub async fn get_ticker(&self, symbol: &str) -> crate::models::Ticker {
+        let (best_bid, best_ask, imbalance, updated_at) = match self.existing_book(symbol).await {
+            Some(book) => {
+                let book = book.lock().await;
+                let (bid, ask) = book.top();
+                let level = |(price, quantity): Level| (price.value(), quantity.value());
+                (bid.map(level), ask.map(level), Self::imbalance_of(&book, TICKER_IMBALANCE_DEPTH), book.updated_at)
+            }
+            None => (None, None, None, None),
+        };
+        let now = self.clock.now();
+
+        let (mid_price, microprice, spread_bps) = match (best_bid, best_ask) {
+            (Some((bid, bid_qty)), Some((ask, ask_qty))) => {
+                let mid = (bid + ask) / Decimal::TWO;
+                let total_qty = bid_qty + ask_qty;
+                let micro = if total_qty > Decimal::ZERO {
+                    Some((bid * ask_qty + ask * bid_qty) / total_qty)
+                } else {
+                    None
+                };
+                (Some(mid), micro, Self::spread_bps(bid, ask, mid))
+            }
+            _ => (None, None, None),
+        };
+
+        crate::models::Ticker {
+            symbol: symbol.to_string(),
+            best_bid: best_bid.map(|(price, _)| price),
+            best_bid_quantity: best_bid.map(|(_, quantity)| quantity),
+            best_ask: best_ask.map(|(price, _)| price),
+            best_ask_quantity: best_ask.map(|(_, quantity)| quantity),
+            mid_price,
+            microprice,
+            imbalance,
+            spread_bps,
+            last_update_age_ms: updated_at.map(|at| (now - at).num_milliseconds().max(0)),
+            timestamp: now,
+        }
+    }
+
+    /// Spread relative to mid in basis points: `(ask - bid) / mid * 10000`.
+    /// `None` when mid is at or below zero, as in spread markets, where a
+    /// relative spread means nothing.
+    fn spread_bps(bid: Decimal, ask: Decimal, mid: Decimal) -> Option<Decimal> {
+        if mid <= Decimal::ZERO {
+            return None;
+        }
+        let bps = ((ask - bid) * Decimal::from(10_000)).checked_div(mid)?;
+        Some(rounding::to_places(bps, 4))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit_order(side: OrderSide, quantity: i64, price: i64) -> Order {
+        Order {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            symbol: "BTC/USD".to_string(),
+            side,
+            quantity: Decimal::new(quantity, 0),
+            price: Some(Decimal::new(price, 0)),
+            order_type: OrderType::Limit,
+            all_or_none: false,
+            stop_price: None,
+            trigger_symbol: None,
+            peg_offset: None,
+            protection_price: None,
+            oco_group_id: None,
+            client_order_id: None,
+            metadata: None,
+            status: OrderStatus::New,
+            filled_quantity: Decimal::ZERO,
+            reject_reason: None,
+            acceptance_seq: 0,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fill_records_taker_price_improvement() {
+        let book = OrderBookService::new();
+        book.add_order(&limit_order(OrderSide::Sell, 1, 100)).await.unwrap();
+        let trades = book.add_order(&limit_order(OrderSide::Buy, 1, 101)).await.unwrap();
+
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].price, Decimal::new(100, 0));
+        assert_eq!(trades[0].taker_limit_price, Some(Decimal::new(101, 0)));
+        assert_eq!(trades[0].price_improvement(), Some(Decimal::ONE));
+    }
+
+    #[tokio::test]
+    async fn test_matching_latency_is_recorded_per_symbol() {
+        let book = OrderBookService::new();
+        book.add_order(&limit_order(OrderSide::Sell, 1, 100)).await.unwrap();
+        book.add_order(&limit_order(OrderSide::Buy, 1, 100)).await.unwrap();
+        let mut eth = limit_order(OrderSide::Buy, 1, 50);
+        eth.symbol = "ETH/USD".to_string();
+        book.add_order(&eth).await.unwrap();
+
+        let samples = |symbol: &str| book.matching_latency().with_label_values(&[symbol]).get_sample_count();
+        assert_eq!(samples("BTC/USD"), 2);
+        assert_eq!(samples("ETH/USD"), 1);
+        assert_eq!(samples("ETH/BTC"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_freeze_copies_every_resting_order() {
+        let book = OrderBookService::new();
+        let mut resting = Vec::new();
+        for (side, quantity, price) in [
+            (OrderSide::Buy, 1, 97), (OrderSide::Buy, 2, 99), (OrderSide::Buy, 3, 99),
+            (OrderSide::Sell, 4, 101), (OrderSide::Sell, 5, 110),
+        ] {
+            let order = limit_order(side, quantity, price);
+            book.add_order(&order).await.unwrap();
+            resting.push(order);
+        }
+        // Partly fills the first bid at 99, fully fills the ask at 101
+        let taker = limit_order(OrderSide::Sell, 1, 99);
+        book.add_order(&taker).await.unwrap();
+        let taker = limit_order(OrderSide::Buy, 4, 105);
+        book.add_order(&taker).await.unwrap();
+
+        let frozen = book.freeze("BTC/USD").await;
+        let prices = |levels: &[FrozenLevel]| levels.iter().map(|l| l.price).collect::<Vec<_>>();
+        assert_eq!(prices(&frozen.bids), vec![Decimal::new(99, 0), Decimal::new(97, 0)]);
+        assert_eq!(prices(&frozen.asks), vec![Decimal::new(110, 0)]);
+        assert_eq!(frozen.sequence, 2);
+
+        // Every level is there, not just the top ones, with queue order kept
+        let ids: Vec<Uuid> = frozen.bids.iter().chain(&frozen.asks)
+            .flat_map(|level| level.orders.iter().map(|o| o.id))
+            .collect();
+        assert_eq!(ids, vec![resting[1].id, resting[2].id, resting[0].id, resting[4].id]);
+        let best_bid = &frozen.bids[0];
+        assert_eq!(best_bid.orders[0].filled_quantity, Decimal::ONE);
+        assert_eq!(best_bid.quantity, Decimal::new(4, 0));
+
+        // A copy: later changes to the book don't show up in it
+        book.remove_order(&resting[4]).await.unwrap();
+        assert_eq!(frozen.asks[0].orders.len(), 1);
+        assert!(book.freeze("BTC/USD").await.asks.is_empty());
+    }
+
+    /// A book with resting orders on both sides, including a partial fill.
+    async fn populated_book() -> OrderBookService {
+        let book = OrderBookService::new();
+        for (side, quantity, price) in [
+            (OrderSide::Buy, 1, 97), (OrderSide::Buy, 2, 99), (OrderSide::Buy, 3, 99),
+            (OrderSide::Sell, 4, 101), (OrderSide::Sell, 5, 110),
+        ] {
+            book.add_order(&limit_order(side, quantity, price)).await.unwrap();
+        }
+        book.add_order(&limit_order(OrderSide::Sell, 1, 99)).await.unwrap();
+        book
+    }
+
+    #[tokio::test]
+    async fn test_seed_loads_a_frozen_book_without_matching() {
+        let snapshot = populated_book().await.freeze("BTC/USD").await;
+        let seeded = OrderBookService::new();
+        assert_eq!(seeded.seed(&snapshot).await.unwrap(), 5);
+
+        let copy = seeded.freeze("BTC/USD").await;
+        assert_eq!(serde_json::to_value(&copy.bids).unwrap(), serde_json::to_value(&snapshot.bids).unwrap());
+        assert_eq!(serde_json::to_value(&copy.asks).unwrap(), serde_json::to_value(&snapshot.asks).unwrap());
+        assert_eq!(copy.sequence, 0);
+
+        // The loaded orders trade like any other, in their original queue order
+        let trades = seeded.add_order(&limit_order(OrderSide::Sell, 2, 99)).await.unwrap();
+        assert_eq!(trades.iter().map(|trade| trade.order_id).collect::<Vec<_>>(), vec![
+            snapshot.bids[0].orders[0].id,
+            snapshot.bids[0].orders[1].id,
+        ]);
+
+        // Only an empty book can be seeded
+        assert!(matches!(seeded.seed(&snapshot).await, Err(AppError::OrderBook(_))));
+    }
+
+    #[tokio::test]
+    async fn test_seed_rejects_inconsistent_snapshots() {
+        let snapshot = populated_book().await.freeze("BTC/USD").await;
+        let seeded = OrderBookService::new();
+
+        let mut crossed = snapshot.clone();
+        let mut bid = crossed.bids[0].clone();
+        bid.price = Decimal::new(105, 0);
+        for order in &mut bid.orders {
+            order.id = Uuid::new_v4();
+            order.price = Some(bid.price);
+        }
+        crossed.bids.insert(0, bid);
+        assert!(matches!(seeded.seed(&crossed).await, Err(AppError::Validation(message)) if message.contains("crossed")));
+
+        let mut miscounted = snapshot.clone();
+        miscounted.asks[0].quantity += Decimal::ONE;
+        assert!(matches!(seeded.seed(&miscounted).await, Err(AppError::Validation(_))));
+
+        let mut wrong_side = snapshot.clone();
+        let order = wrong_side.bids[1].orders[0].clone();
+        wrong_side.asks[0].orders.push(order);
+        assert!(matches!(seeded.seed(&wrong_side).await, Err(AppError::Validation(_))));
+
+        // Nothing was loaded
+        let book = seeded.freeze("BTC/USD").await;
+        assert!(book.bids.is_empty() && book.asks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scrambled_book_matches_best_price_first() {
+        let book = OrderBookService::new();
+        // Worse prices arrive first, so time priority points the wrong way
+        for (seq, price) in [105, 101, 103, 102].into_iter().enumerate() {
+            book.add_order(&Order { acceptance_seq: seq as i64, ..limit_order(OrderSide::Sell, 1, price) }).await.unwrap();
+        }
+        // An all-or-none maker too big for the taker may be passed over
+        let all_or_none = Order { all_or_none: true, ..limit_order(OrderSide::Sell, 10, 100) };
+        book.add_order(&all_or_none).await.unwrap();
+
+        let taker = Order { price: None, order_type: OrderType::Market, ..limit_order(OrderSide::Buy, 4, 0) };
+        {
+            let book = book.book("BTC/USD").await;
+            let book = book.lock().await;
+            assert!(!book.trades_through(&taker, Price::new(Decimal::new(101, 0)), Quantity::ZERO));
+            assert!(book.trades_through(&taker, Price::new(Decimal::new(102, 0)), Quantity::ZERO));
+        }
+
+        let trades = book.add_order(&taker).await.unwrap();
+        let prices: Vec<Decimal> = trades.iter().map(|trade| trade.price).collect();
+        assert_eq!(prices, [101, 102, 103, 105].map(|price| Decimal::new(price, 0)));
+        assert_eq!(book.get_order_book("BTC/USD").await.asks[0].price, Decimal::new(100, 0));
+    }
+
+    #[tokio::test]
+    async fn test_market_order_on_empty_book_is_rejected() {
+        let book = OrderBookService::new();
+        let market = Order { price: None, order_type: OrderType::Market, ..limit_order(OrderSide::Buy, 1, 0) };
+
+        assert!(matches!(book.add_order(&market).await, Err(AppError::OrderBook(_))));
+        // Bids don't count: only the side it would trade against
+        book.add_order(&limit_order(OrderSide::Buy, 1, 99)).await.unwrap();
+        assert!(matches!(book.add_order(&market).await, Err(AppError::OrderBook(_))));
+
+        book.add_order(&limit_order(OrderSide::Sell, 1, 101)).await.unwrap();
+        let trades = book.add_order(&market).await.unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].price, Decimal::new(101, 0));
+    }
+
+    #[tokio::test]
+    async fn test_ticker_microprice() {
+        let book = OrderBookService::new();
+        book.add_order(&limit_order(OrderSide::Buy, 3, 99)).await.unwrap();
+        book.add_order(&limit_order(OrderSide::Buy, 5, 98)).await.unwrap();
+        book.add_order(&limit_order(OrderSide::Sell, 1, 101)).await.unwrap();
+
+        let ticker = book.get_ticker("BTC/USD").await;
+        assert_eq!(ticker.best_bid, Some(Decimal::new(99, 0)));
+        assert_eq!(ticker.best_bid_quantity, Some(Decimal::new(3, 0)));
+        assert_eq!(ticker.best_ask, Some(Decimal::new(101, 0)));
+        assert_eq!(ticker.best_ask_quantity, Some(Decimal::new(1, 0)));
+        assert_eq!(ticker.mid_price, Some(Decimal::new(100, 0)));
+        // (99 * 1 + 101 * 3) / (3 + 1) = 100.5
+        assert_eq!(ticker.microprice, Some(Decimal::new(1005, 1)));
+    }
+
+    #[tokio::test]
+    async fn test_ticker_spread_bps() {
+        let book = OrderBookService::new();
+        book.add_order(&limit_order(OrderSide::Buy, 1, 99)).await.unwrap();
+        assert_eq!(book.get_ticker("BTC/USD").await.spread_bps, None);
+
+        book.add_order(&limit_order(OrderSide::Sell, 1, 101)).await.unwrap();
+        // (101 - 99) / 100 * 10000
+        assert_eq!(book.get_ticker("BTC/USD").await.spread_bps, Some(Decimal::new(200, 0)));
+
+        book.add_order(&limit_order(OrderSide::Sell, 1, 100)).await.unwrap();
+        // 1 / 99.5 * 10000 = 100.50251...
+        assert_eq!(book.get_ticker("BTC/USD").await.spread_bps, Some(Decimal::new(1005025, 4)));
+        assert_eq!(book.get_ticker("ETH/USD").await.spread_bps, None);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_waits_for_min_quote_life() {
+        let clock = Arc::new(ManualClock(std::sync::Mutex::new(Utc::now())));
+        let book = OrderBookService::with_sources(Arc::new(RandomIds), clock.clone());
+        book.set_min_quote_life("BTC/USD", chrono::Duration::milliseconds(500)).await;
+        // Quote life counts from when the order rests, not when it was created
+        let mut quote = limit_order(OrderSide::Buy, 1, 99);
+        quote.created_at = clock.now() - chrono::Duration::hours(1);
+        book.add_order(&quote).await.unwrap();
+
+        clock.advance(499);
+        let early = book.cancel_order(&quote).await;
+        assert!(matches!(early, Err(AppError::OrderBook(message)) if message.contains("1ms")));
+        assert_eq!(book.get_ticker("BTC/USD").await.best_bid, Some(Decimal::new(99, 0)));
+
+        clock.advance(1);
+        assert_eq!(book.cancel_order(&quote).await.unwrap().map(|order| order.id), Some(quote.id));
+        assert_eq!(book.get_ticker("BTC/USD").await.best_bid, None);
+
+        // Markets without a minimum, and the engine's own removals, aren't held back
+        let mut other = limit_order(OrderSide::Buy, 1, 99);
+        other.symbol = "ETH/USD".to_string();
+        book.add_order(&other).await.unwrap();
+        assert!(book.cancel_order(&other).await.unwrap().is_some());
+        let young = limit_order(OrderSide::Buy, 1, 98);
+        book.add_order(&young).await.unwrap();
+        assert!(book.remove_order(&young).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_replaced_order_starts_its_quote_life_over() {
+        let clock = Arc::new(ManualClock(std::sync::Mutex::new(Utc::now())));
+        let book = OrderBookService::with_sources(Arc::new(RandomIds), clock.clone());
+        book.set_min_quote_life("BTC/USD", chrono::Duration::milliseconds(500)).await;
+        let quote = limit_order(OrderSide::Buy, 1, 99);
+        book.add_order(&quote).await.unwrap();
+
+        clock.advance(400);
+        let (replacement, trades) = book.replace_order(&quote, Decimal::new(98, 0), quote.quantity, 2).await.unwrap();
+        assert!(trades.is_empty());
+
+        // 600ms since the quote first rested, but only 200ms since it was replaced
+        clock.advance(200);
+        let early = book.cancel_order(&replacement).await;
+        assert!(matches!(early, Err(AppError::OrderBook(message)) if message.contains("300ms")));
+
+        clock.advance(300);
+        assert!(book.cancel_order(&replacement).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_ticker_age_counts_from_last_mutation() {
+        let clock = Arc::new(ManualClock(std::sync::Mutex::new(Utc::now())));
+        let book = OrderBookService::with_sources(Arc::new(RandomIds), clock.clone());
+        assert_eq!(book.get_ticker("BTC/USD").await.last_update_age_ms, None);
+
+        let resting = limit_order(OrderSide::Buy, 1, 99);
+        book.add_order(&resting).await.unwrap();
+        clock.advance(1_500);
+        assert_eq!(book.get_ticker("BTC/USD").await.last_update_age_ms, Some(1_500));
+
+        // Reading the book is not a mutation
+        book.get_order_book("BTC/USD").await;
+        clock.advance(500);
+        assert_eq!(book.get_ticker("BTC/USD").await.last_update_age_ms, Some(2_000));
+
+        book.remove_order(&resting).await.unwrap();
+        clock.advance(250);
+        assert_eq!(book.get_ticker("BTC/USD").await.last_update_age_ms, Some(250));
+    }
+
+    #[tokio::test]
+    async fn test_cached_level_totals_match_orders() {
+        let book = OrderBookService::new();
+        book.set_min_trade_quantity("BTC/USD", Decimal::new(1, 1)).await;
+        let first = limit_order(OrderSide::Sell, 5, 100);
+        let second = limit_order(OrderSide::Sell, 3, 100);
+        let third = limit_order(OrderSide::Sell, 4, 101);
+        for order in [&first, &second, &third] {
+            book.add_order(order).await.unwrap();
+        }
+
+        book.add_order(&limit_order(OrderSide::Buy, 2, 100)).await.unwrap(); // Partial fill
+        book.reduce_order(&second, Decimal::new(2, 0)).await.unwrap();
+        book.add_order(&limit_order(OrderSide::Buy, 6, 101)).await.unwrap(); // Sweeps a level
+        book.remove_order(&third).await.unwrap();
+        book.add_order(&limit_order(OrderSide::Buy, 1, 98)).await.unwrap();
+        let mut sub_dust = limit_order(OrderSide::Buy, 1, 99);
+        sub_dust.quantity = Decimal::new(105, 2); // Leaves 0.05 after a 1 fill
+        book.add_order(&sub_dust).await.unwrap();
+        book.add_order(&limit_order(OrderSide::Sell, 1, 99)).await.unwrap();
+
+        let symbol_book = book.book("BTC/USD").await;
+        let symbol_book = symbol_book.lock().await;
+        let levels: Vec<&OrderQueue> = symbol_book.bids.values().chain(symbol_book.asks.values()).collect();
+        assert!(!levels.is_empty());
+        for queue in levels {
+            let fresh: Decimal = queue.orders.iter().map(|o| o.quantity - o.filled_quantity).sum();
+            assert_eq!(queue.total_quantity().value(), fresh);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_empty_levels_remain_after_fills_and_cancels() {
+        let book = OrderBookService::new();
+        let asks: Vec<Order> = [(2, 100), (1, 100), (3, 101), (1, 102), (2, 103)].into_iter()
+            .map(|(quantity, price)| limit_order(OrderSide::Sell, quantity, price))
+            .collect();
+        let bid = limit_order(OrderSide::Buy, 2, 95);
+        for order in asks.iter().chain([&bid]) {
+            book.add_order(order).await.unwrap();
+        }
+
+        book.add_order(&limit_order(OrderSide::Buy, 4, 101)).await.unwrap(); // Empties 100 and takes 1 at 101
+        book.remove_order(&asks[3]).await.unwrap(); // Cancels the only order at 102
+        book.reduce_order(&asks[2], Decimal::new(1, 0)).await.unwrap(); // Down to what was filled, which completes it
+        book.remove_order(&bid).await.unwrap();
+        let mut aon = limit_order(OrderSide::Buy, 2, 103);
+        aon.all_or_none = true;
+        book.add_order(&aon).await.unwrap(); // Sweeps 103 whole
+
+        let symbol_book = book.book("BTC/USD").await;
+        let symbol_book = symbol_book.lock().await;
+        assert!(symbol_book.bids.is_empty() && symbol_book.asks.is_empty());
+        assert_eq!(symbol_book.top(), (None, None));
+        drop(symbol_book);
+
+        // The side that still has liquidity starts at a real level
+        book.add_order(&limit_order(OrderSide::Sell, 1, 104)).await.unwrap();
+        book.add_order(&limit_order(OrderSide::Sell, 1, 105)).await.unwrap();
+        book.add_order(&limit_order(OrderSide::Buy, 1, 104)).await.unwrap();
+        let symbol_book = book.book("BTC/USD").await;
+        let symbol_book = symbol_book.lock().await;
+        assert!(symbol_book.bids.values().chain(symbol_book.asks.values()).all(|queue| !queue.is_empty()));
+        assert_eq!(symbol_book.asks.first_key_value().map(|(price, _)| price.value()), Some(Decimal::new(105, 0)));
+    }
+
+    #[tokio::test]
+    async fn test_engine_works_in_price_and_quantity_units() {
+        let book = OrderBookService::new();
+        book.add_order(&limit_order(OrderSide::Sell, 1, 100)).await.unwrap();
+        let mut rescaled = limit_order(OrderSide::Sell, 2, 100);
+        rescaled.price = Some(Decimal::new(10_000, 2)); // 100.00
+        book.add_order(&rescaled).await.unwrap();
+        book.add_order(&limit_order(OrderSide::Sell, 4, 101)).await.unwrap();
+
+        let symbol_book = book.book("BTC/USD").await;
+        {
+            let symbol_book = symbol_book.lock().await;
+            // 100 and 100.00 are one level
+            assert_eq!(
+                symbol_book.asks.iter().map(|(price, queue)| (*price, queue.total_quantity())).collect::<Vec<_>>(),
+                vec![
+                    (Price::new(Decimal::new(100, 0)), Quantity::new(Decimal::new(3, 0))),
+                    (Price::new(Decimal::new(101, 0)), Quantity::new(Decimal::new(4, 0))),
+                ]
+            );
+
+            let taker = limit_order(OrderSide::Buy, 5, 101);
+            let fills: Vec<(Price, Quantity)> = symbol_book.executable_fills(&taker, Quantity::ZERO).iter()
+                .map(|fill| (fill.price, fill.quantity))
+                .collect();
+            assert_eq!(fills, vec![
+                (Price::new(Decimal::new(100, 0)), Quantity::new(Decimal::ONE)),
+                (Price::new(Decimal::new(100, 0)), Quantity::new(Decimal::new(2, 0))),
+                (Price::new(Decimal::new(101, 0)), Quantity::new(Decimal::new(2, 0))),
+            ]);
+        }
+
+        // The trades that come out are back in the model's decimals
+        let trades = book.add_order(&limit_order(OrderSide::Buy, 5, 101)).await.unwrap();
+        assert_eq!(trades.iter().map(|trade| trade.quantity).sum::<Decimal>(), Decimal::new(5, 0));
+        assert_eq!(trades[2].price, Decimal::new(101, 0));
+        let symbol_book = symbol_book.lock().await;
+        assert_eq!(symbol_book.top(), (None, Some((Price::new(Decimal::new(101, 0)), Quantity::new(Decimal::new(2, 0))))));
+    }
+
+    async fn position(book: &OrderBookService, order: &Order) -> Option<(usize, Decimal)> {
+        book.queue_position(order.id, &order.symbol, &order.side, order.price.unwrap()).await
+            .map(|position| (position.orders_ahead, position.quantity_ahead))
+    }
+
+    #[tokio::test]
+    async fn test_queue_position_counts_orders_ahead_at_level() {
+        let book = OrderBookService::new();
+        let first = limit_order(OrderSide::Buy, 2, 100);
+        let second = limit_order(OrderSide::Buy, 3, 100);
+        let third = limit_order(OrderSide::Buy, 4, 100);
+        let better = limit_order(OrderSide::Buy, 5, 101);
+        for order in [&first, &second, &third, &better] {
+            book.add_order(order).await.unwrap();
+        }
+        // Only the same price level counts, not better-priced orders
+        assert_eq!(position(&book, &first).await, Some((0, Decimal::ZERO)));
+        assert_eq!(position(&book, &second).await, Some((1, Decimal::new(2, 0))));
+        assert_eq!(position(&book, &third).await, Some((2, Decimal::new(5, 0))));
+        assert_eq!(position(&book, &better).await, Some((0, Decimal::ZERO)));
+
+        // Fills and cancellations ahead move an order up
+        book.add_order(&limit_order(OrderSide::Sell, 6, 100)).await.unwrap(); // Takes `better`, 1 of `first`
+        book.remove_order(&second).await.unwrap();
+        assert_eq!(position(&book, &third).await, Some((1, Decimal::new(1, 0))));
+
+        // Orders no longer resting have no position
+        assert_eq!(position(&book, &second).await, None);
+        assert_eq!(position(&book, &better).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_resting_order_cap() {
+        let book = OrderBookService::new();
+        book.set_max_resting_orders("BTC/USD", 3).await;
+        book.add_order(&limit_order(OrderSide::Sell, 1, 100)).await.unwrap();
+        book.add_order(&limit_order(OrderSide::Sell, 1, 101)).await.unwrap();
+        book.add_order(&limit_order(OrderSide::Buy, 1, 90)).await.unwrap();
+
+        // Full: an order that would only rest is turned away
+        let result = book.add_order(&limit_order(OrderSide::Buy, 1, 95)).await;
+        assert!(matches!(result, Err(AppError::OrderBook(_))));
+
+        // Marketable orders still match
+        let trades = book.add_order(&limit_order(OrderSide::Buy, 1, 100)).await.unwrap();
+        assert_eq!(trades.len(), 1);
+
+        // The fill freed a slot
+        assert!(book.add_order(&limit_order(OrderSide::Buy, 1, 95)).await.is_ok());
+        let result = book.add_order(&limit_order(OrderSide::Sell, 1, 105)).await;
+        assert!(matches!(result, Err(AppError::OrderBook(_))));
+    }
+
+    #[tokio::test]
+    async fn test_imbalance_over_top_levels() {
+        let book = OrderBookService::new();
+        assert_eq!(book.imbalance("BTC/USD", 2).await, None);
+
+        book.add_order(&limit_order(OrderSide::Buy, 3, 99)).await.unwrap();
+        book.add_order(&limit_order(OrderSide::Buy, 5, 98)).await.unwrap();
+        book.add_order(&limit_order(OrderSide::Buy, 100, 90)).await.unwrap(); // Beyond depth 2
+        book.add_order(&limit_order(OrderSide::Sell, 2, 101)).await.unwrap();
+
+        // 8 / (8 + 2)
+        assert_eq!(book.imbalance("BTC/USD", 2).await, Some(Decimal::new(8, 1)));
+        assert_eq!(book.imbalance("BTC/USD", 1).await, Some(Decimal::new(6, 1)));
+
+        // One empty side gives the extreme
+        book.add_order(&limit_order(OrderSide::Buy, 2, 101)).await.unwrap();
+        assert_eq!(book.imbalance("BTC/USD", 2).await, Some(Decimal::ONE));
+    }
+
+    #[tokio::test]
+    async fn test_bbo_only_emits_on_top_of_book_change() {
+        let book = OrderBookService::new();
+        let mut bbo = book.subscribe_bbo();
+
+        book.add_order(&limit_order(OrderSide::Buy, 3, 99)).await.unwrap();
+        let update = bbo.try_recv().unwrap();
+        assert_eq!(update.bid, Some(Decimal::new(99, 0)));
+        assert_eq!(update.bid_quantity, Some(Decimal::new(3, 0)));
+        assert_eq!(update.ask, None);
+
+        // A level behind the best bid leaves the top untouched
+        let deep = limit_order(OrderSide::Buy, 5, 95);
+        book.add_order(&deep).await.unwrap();
+        book.remove_order(&deep).await.unwrap();
+        assert!(bbo.try_recv().is_err());
+
+        // More size at the best price changes the top quantity
+        book.add_order(&limit_order(OrderSide::Buy, 2, 99)).await.unwrap();
+        let update = bbo.try_recv().unwrap();
+        assert_eq!(update.bid_quantity, Some(Decimal::new(5, 0)));
+
+        book.add_order(&limit_order(OrderSide::Sell, 1, 101)).await.unwrap();
+        let update = bbo.try_recv().unwrap();
+        assert_eq!(update.ask, Some(Decimal::new(101, 0)));
+        assert_eq!(update.bid, Some(Decimal::new(99, 0)));
+        assert!(bbo.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ticker_microprice_empty_side() {
+        let book = OrderBookService::new();
+        book.add_order(&limit_order(OrderSide::Buy, 3, 99)).await.unwrap();
+
+        let ticker = book.get_ticker("BTC/USD").await;
+        assert_eq!(ticker.best_bid, Some(Decimal::new(99, 0)));
+        assert_eq!(ticker.best_ask, None);
+        assert_eq!(ticker.mid_price, None);
+        assert_eq!(ticker.microprice, None);
+    }
+
+    #[tokio::test]
+    async fn test_trade_and_order_timestamps_and_sequence() {
+        let book = OrderBookService::new();
+        let ask = limit_order(OrderSide::Sell, 5, 100);
+        book.add_order(&ask).await.unwrap();
+
+        let first = book.add_order(&limit_order(OrderSide::Buy, 2, 100)).await.unwrap();
+        let second = book.add_order(&limit_order(OrderSide::Buy, 1, 100)).await.unwrap();
+        assert_eq!(first.len(), 1);
+        assert_eq!(second.len(), 1);
+
+        // Sequence numbers are strictly increasing and timestamps follow the maker's creation
+        assert!(second[0].seq > first[0].seq);
+        assert!(first[0].executed_at >= ask.created_at);
+        assert!(second[0].executed_at >= first[0].executed_at);
+
+        // The resting maker records when it was last mutated
+        let book = book.book("BTC/USD").await;
+        let book = book.lock().await;
+        let resting = &book.asks[&Price::new(Decimal::new(100, 0))].orders[0];
+        assert_eq!(resting.filled_quantity, Decimal::new(3, 0));
+        assert_eq!(resting.created_at, ask.created_at);
+        assert_eq!(resting.updated_at, second[0].executed_at);
+    }
+
+    #[tokio::test]
+    async fn test_acceptance_sequence_decides_priority_at_same_timestamp() {
+        let book = OrderBookService::new();
+        let created_at = Utc::now();
+        let mut later = limit_order(OrderSide::Sell, 1, 100);
+        let mut earlier = limit_order(OrderSide::Sell, 1, 100);
+        (later.acceptance_seq, later.created_at) = (8, created_at);
+        (earlier.acceptance_seq, earlier.created_at) = (7, created_at);
+        // Arriving out of order, as after a restart, doesn't change who is first
+        book.add_order(&later).await.unwrap();
+        book.add_order(&earlier).await.unwrap();
+
+        let first = book.add_order(&limit_order(OrderSide::Buy, 1, 100)).await.unwrap();
+        assert_eq!(first[0].order_id, earlier.id);
+        let second = book.add_order(&limit_order(OrderSide::Buy, 1, 100)).await.unwrap();
+        assert_eq!(second[0].order_id, later.id);
+    }
+
+    #[tokio::test]
+    async fn test_reduce_order_keeps_time_priority() {
+        let book = OrderBookService::new();
+        let first = limit_order(OrderSide::Sell, 5, 100);
+        let second = limit_order(OrderSide::Sell, 5, 100);
+        book.add_order(&first).await.unwrap();
+        book.add_order(&second).await.unwrap();
+
+        let reduced = book.reduce_order(&first, Decimal::new(2, 0)).await.unwrap();
+        assert_eq!(reduced.quantity, Decimal::new(2, 0));
+
+        {
+            let book = book.book("BTC/USD").await;
+            let book = book.lock().await;
+            let queue = &book.asks[&Price::new(Decimal::new(100, 0))];
+            assert_eq!(queue.orders.len(), 2);
+            assert_eq!(queue.total_quantity(), Quantity::new(Decimal::new(7, 0)));
+            let position = queue.orders.iter().position(|o| o.id == first.id);
+            let other = queue.orders.iter().position(|o| o.id == second.id);
+            assert!(position < other);
+        }
+
+        // Increasing is an amend, not a reduce
+        let result = book.reduce_order(&first, Decimal::new(3, 0)).await;
+        assert!(matches!(result, Err(AppError::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_reduce_order_below_filled_quantity_is_rejected() {
+        let book = OrderBookService::new();
+        let ask = limit_order(OrderSide::Sell, 5, 100);
+        book.add_order(&ask).await.unwrap();
+        book.add_order(&limit_order(OrderSide::Buy, 3, 100)).await.unwrap();
+
+        let result = book.reduce_order(&ask, Decimal::new(2, 0)).await;
+        assert!(matches!(result, Err(AppError::Validation(_))));
+
+        // Reducing to exactly the filled quantity completes the order
+        let reduced = book.reduce_order(&ask, Decimal::new(3, 0)).await.unwrap();
+        assert_eq!(reduced.filled_quantity, reduced.quantity);
+        assert!(book.book("BTC/USD").await.lock().await.asks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sub_dust_remainder_is_not_traded() {
+        let book = OrderBookService::new();
+        book.set_min_trade_quantity("BTC/USD", Decimal::new(1, 3)).await; // 0.001
+
+        // Maker left with 0.0005 after the fill is considered complete
+        let mut ask = limit_order(OrderSide::Sell, 0, 100);
+        ask.quantity = Decimal::new(10005, 4); // 1.0005
+        book.add_order(&ask).await.unwrap();
+        let trades = book.add_order(&limit_order(OrderSide::Buy, 1, 100)).await.unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].quantity, Decimal::ONE);
+        assert!(book.book("BTC/USD").await.lock().await.asks.is_empty());
+
+        // Taker remainder of 0.0004 is neither traded nor rested
+        book.add_order(&limit_order(OrderSide::Sell, 1, 100)).await.unwrap();
+        let mut bid = limit_order(OrderSide::Buy, 0, 100);
+        bid.quantity = Decimal::new(10004, 4); // 1.0004
+        let trades = book.add_order(&bid).await.unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].quantity, Decimal::ONE);
+        let book = book.book("BTC/USD").await;
+        assert!(book.lock().await.asks.is_empty());
+        assert!(book.lock().await.bids.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_all_or_none_bid_waits_for_enough_liquidity() {
+        let book = OrderBookService::new();
+        let mut aon = limit_order(OrderSide::Buy, 5, 100);
+        aon.all_or_none = true;
+
+        book.add_order(&limit_order(OrderSide::Sell, 2, 100)).await.unwrap();
+        assert!(book.add_order(&aon).await.unwrap().is_empty());
+
+        // Still one short: the asks rest alongside the waiting bid
+        assert!(book.add_order(&limit_order(OrderSide::Sell, 2, 99)).await.unwrap().is_empty());
+
+        let trades = book.add_order(&limit_order(OrderSide::Sell, 1, 100)).await.unwrap();
+        assert_eq!(trades.len(), 3);
+        assert!(trades.iter().all(|t| t.taker_order_id == aon.id));
+        assert_eq!(trades.iter().map(|t| t.quantity).sum::<Decimal>(), Decimal::new(5, 0));
+        assert_eq!(trades[0].price, Decimal::new(99, 0));
+
+        let book = book.book("BTC/USD").await;
+        assert!(book.lock().await.bids.is_empty());
+        assert!(book.lock().await.asks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_all_or_none_maker_is_skipped_until_it_can_be_filled() {
+        let book = OrderBookService::new();
+        let mut aon = limit_order(OrderSide::Sell, 5, 100);
+        aon.all_or_none = true;
+        book.add_order(&aon).await.unwrap();
+        book.add_order(&limit_order(OrderSide::Sell, 2, 101)).await.unwrap();
+
+        // Too small to take the AON ask, so it trades behind it at 101
+        let trades = book.add_order(&limit_order(OrderSide::Buy, 3, 101)).await.unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].price, Decimal::new(101, 0));
+        assert_eq!(trades[0].quantity, Decimal::new(2, 0));
+
+        // The two resting bids together now cover the AON ask
+        let trades = book.add_order(&limit_order(OrderSide::Buy, 4, 100)).await.unwrap();
+        assert_eq!(trades.len(), 2);
+        assert!(trades.iter().all(|t| t.taker_order_id == aon.id));
+        assert_eq!(trades.iter().map(|t| t.quantity).sum::<Decimal>(), Decimal::new(5, 0));
+
+        let book = book.book("BTC/USD").await;
+        assert!(book.lock().await.bids.is_empty());
+        assert!(book.lock().await.asks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_seeded_trade_ids_are_reproducible() {
+        async fn run(seed: u64) -> Vec<Uuid> {
+            let book = OrderBookService::with_id_generator(Arc::new(SeededIds::new(seed)));
+            let mut ids = Vec::new();
+            for (side, quantity, price) in [
+                (OrderSide::Sell, 3, 100),
+                (OrderSide::Sell, 2, 101),
+                (OrderSide::Buy, 4, 101),
+                (OrderSide::Buy, 1, 101),
+            ] {
+                let trades = book.add_order(&limit_order(side, quantity, price)).await.unwrap();
+                ids.extend(trades.iter().map(|t| t.id));
+            }
+            ids
+        }
+
+        let first = run(7).await;
+        assert_eq!(first.len(), 3);
+        assert_eq!(first, run(7).await);
+        assert_ne!(first, run(8).await);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_clones_submit_orders_concurrently() {
+        let book = OrderBookService::new();
+        let (sellers, buyers) = (book.clone(), book.clone());
+        let sell = limit_order(OrderSide::Sell, 2, 100);
+        let buy = limit_order(OrderSide::Buy, 2, 100);
+
+        let (sold, bought) = tokio::join!(sellers.add_order(&sell), buyers.add_order(&buy));
+        let trades: Vec<Trade> = sold.unwrap().into_iter().chain(bought.unwrap()).collect();
+
+        // Whichever arrived second traded against the other, through the shared book
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].quantity, Decimal::new(2, 0));
+        let ticker = book.get_ticker("BTC/USD").await;
+        assert_eq!((ticker.best_bid, ticker.best_ask), (None, None));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn test_concurrent_orders_keep_book_consistent() {
+        const SYMBOLS: [&str; 3] = ["BTC/USD", "ETH/USD", "ETH/BTC"];
+        const TASKS: usize = 600;
+
+        let book = OrderBookService::new();
+        let mut handles = Vec::with_capacity(TASKS);
+        for i in 0..TASKS {
+            let book = book.clone();
+            handles.push(tokio::spawn(async move {
+                let side = if i % 2 == 0 { OrderSide::Buy } else { OrderSide::Sell };
+                let mut order = limit_order(side, (i % 5 + 1) as i64, 95 + (i % 11) as i64);
+                order.symbol = SYMBOLS[i % SYMBOLS.len()].to_string();
+
+                let trades = book.add_order(&order).await.unwrap();
+                if i % 7 == 0 {
+                    book.remove_order(&order).await.unwrap();
+                }
+                (order, trades)
+            }));
+        }
+
+        let mut orders = HashMap::new();
+        let mut trades = Vec::new();
+        for handle in handles {
+            let (order, order_trades) = handle.await.unwrap();
+            orders.insert(order.id, order);
+            trades.extend(order_trades);
+        }
+
+        let mut fills: HashMap<Uuid, Decimal> = HashMap::new();
+        let mut sequences = std::collections::HashSet::new();
+        for trade in &trades {
+            assert!(sequences.insert(trade.seq), "duplicate sequence {}", trade.seq);
+            assert_eq!(orders[&trade.order_id].symbol, trade.symbol);
+            assert_eq!(orders[&trade.taker_order_id].symbol, trade.symbol);
+            *fills.entry(trade.order_id).or_default() += trade.quantity;
+            *fills.entry(trade.taker_order_id).or_default() += trade.quantity;
+        }
+
+        for (id, filled) in &fills {
+            assert!(*filled <= orders[id].quantity, "order {} overfilled", id);
+        }
+
+        for symbol in SYMBOLS {
+            let book = book.book(symbol).await;
+            let book = book.lock().await;
+            for queue in book.bids.values().chain(book.asks.values()) {
+                assert!(!queue.is_empty());
+                for resting in &queue.orders {
+                    let filled = fills.get(&resting.id).copied().unwrap_or(Decimal::ZERO);
+                    assert_eq!(resting.filled_quantity, filled);
+                    assert!(resting.filled_quantity < resting.quantity);
+                }
+            }
+            if let (Some(bid), Some(ask)) = (book.bids.keys().next_back(), book.asks.keys().next()) {
+                assert!(bid < ask, "{} book is crossed: {} >= {}", symbol, bid, ask);
+            }
+        }
+    }
+}
@@ -2,85 +2,532 @@
 use sqlx::PgPool;
 use uuid::Uuid;
 use std::sync::Arc;
-use crate::models::{Order, CreateOrderRequest, OrderResponse, OrderStatus, OrderSide, OrderType};
+#[cfg(not(feature = "database"))]
+use std::sync::atomic::{AtomicI64, Ordering};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+use serde::Serialize;
+use crate::config::TradingConfig;
+use crate::models::{AccountSummary, BalanceReconciliation, BookReset, BookReplay, BookReplayStep, Order, OrderBookEntry, OrderEvent, CreateOrderRequest, ExecutionReport, ExecutionType, FrozenBook, MarketDelisting, OrderFills, PatchOrderRequest, OrderPreview, OrderResponse, OrderStatus, OrderSide, OrderType, PriceFill, QueuePosition, CancelResult, Trade};
 use crate::errors::AppError;
+use actix_web::ResponseError;
 use crate::handlers::orders::OrderQuery;
 use super::order_book_service::OrderBookService;
+use super::fee_service::{FeeAsset, FeeService};
+use super::market_service::MarketService;
+use super::market_data_service::MarketDataService;
+use super::balance_service::BalanceService;
+use super::risk_service::{Exposure, RiskService};
+use super::stop_service::StopService;
+#[cfg(feature = "database")]
+use super::database_pools::DatabasePools;
+#[cfg(feature = "database")]
+use super::trade_persister::TradePersister;
+
+/// The longest window an order book replay covers.
+pub const MAX_REPLAY_WINDOW_MINUTES: i64 = 60;
+
+/// Execution reports buffered per subscriber before a slow one starts missing them.
+const EXECUTION_CHANNEL_CAPACITY: usize = 1024;
+
+/// Whether the engine takes orders. It warms up while the book is rebuilt
+/// from stored orders after a restart, since matching against a partly
+/// loaded book would trade at the wrong prices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EngineState {
+    Warming,
+    Ready,
+}
 
 #[derive(Clone)]
 pub struct OrderService {
     #[cfg(feature = "database")]
-    pool: Arc<PgPool>,
-    order_book: Arc<OrderBookService>,
+    pools: DatabasePools<PgPool>,
+    #[cfg(feature = "database")]
+    trades: TradePersister,
+    #[cfg(not(feature = "database"))]
+    orders: Arc<RwLock<HashMap<Uuid, Order>>>,
+    #[cfg(not(feature = "database"))]
+    trades: Arc<RwLock<HashMap<Uuid, Trade>>>,
+    #[cfg(not(feature = "database"))]
+    order_events: Arc<RwLock<Vec<OrderEvent>>>,
+    #[cfg(not(feature = "database"))]
+    acceptance_seq: Arc<AtomicI64>, // Last acceptance sequence handed out
+    order_book: OrderBookService,
+    markets: MarketService,
+    market_data: MarketDataService,
+    fees: FeeService,
+    balances: BalanceService,
+    risk: RiskService,
+    stops: StopService,
+    trading: TradingConfig,
+    state: Arc<RwLock<EngineState>>,
+    executions: broadcast::Sender<ExecutionReport>,
 }
 
 impl OrderService {
     #[cfg(feature = "database")]
-    pub fn new(pool: PgPool, order_book: OrderBookService) -> Self {
+    pub fn new(pools: DatabasePools<PgPool>, trades: TradePersister, order_book: OrderBookService, markets: MarketService, market_data: MarketDataService, fees: FeeService, balances: BalanceService, risk: RiskService, trading: TradingConfig) -> Self {
         Self { 
-            pool: Arc::new(pool), 
-            order_book: Arc::new(order_book) 
+            pools,
+            trades,
+            order_book,
+            markets,
+            market_data,
+            fees,
+            balances,
+            risk,
+            stops: StopService::new(),
+            trading,
+            state: Arc::new(RwLock::new(EngineState::Ready)),
+            executions: broadcast::channel(EXECUTION_CHANNEL_CAPACITY).0,
         }
     }
 
     #[cfg(not(feature = "database"))]
-    pub fn new(order_book: OrderBookService) -> Self {
+    pub fn new(order_book: OrderBookService, markets: MarketService, market_data: MarketDataService, fees: FeeService, balances: BalanceService, risk: RiskService, trading: TradingConfig) -> Self {
         Self { 
-            order_book: Arc::new(order_book) 
+            orders: Arc::new(RwLock::new(HashMap::new())),
+            trades: Arc::new(RwLock::new(HashMap::new())),
+            order_events: Arc::new(RwLock::new(Vec::new())),
+            acceptance_seq: Arc::new(AtomicI64::new(0)),
+            order_book,
+            markets,
+            market_data,
+            fees,
+            balances,
+            risk,
+            stops: StopService::new(),
+            trading,
+            state: Arc::new(RwLock::new(EngineState::Ready)),
+            executions: broadcast::channel(EXECUTION_CHANNEL_CAPACITY).0,
+        }
+    }
+
+    /// One report per step of every order's life: accepted, rejected, each
+    /// fill on either side of a trade, cancelled and expired. Orders refused
+    /// without being stored have no report.
+    pub fn subscribe_executions(&self) -> broadcast::Receiver<ExecutionReport> {
+        self.executions.subscribe()
+    }
+
+    fn report(&self, report: ExecutionReport) {
+        // Nobody subscribed is not an error
+        let _ = self.executions.send(report);
+    }
+
+    /// Reports a fill for each order on both sides of `trades`.
+    async fn report_fills(&self, trades: &[Trade]) -> Result<(), AppError> {
+        for trade in trades {
+            for order_id in [trade.order_id, trade.taker_order_id] {
+                let order = match self.find_order(order_id).await {
+                    Ok(order) => order,
+                    // Orders placed straight on the book have no record to report on
+                    Err(AppError::NotFound(_)) => continue,
+                    Err(error) => return Err(error),
+                };
+                let filled_quantity = self.filled_through(order_id, trade.seq).await?;
+                self.report(ExecutionReport::fill(&order, trade, filled_quantity));
+            }
+        }
+        Ok(())
+    }
+
+    /// What `order_id` had filled once the trade with sequence `seq` executed.
+    async fn filled_through(&self, order_id: Uuid, seq: i64) -> Result<rust_decimal::Decimal, AppError> {
+        #[cfg(feature = "database")]
+        {
+            let filled = sqlx::query_scalar!(
+                r#"SELECT COALESCE(SUM(quantity), 0) AS "filled!" FROM trades WHERE (order_id = $1 OR taker_order_id = $1) AND seq <= $2"#,
+                order_id,
+                seq
+            )
+            .fetch_one(self.pools.primary())
+            .await?;
+
+            Ok(filled)
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            Ok(self.trades.read().await.values()
+                .filter(|trade| (trade.order_id == order_id || trade.taker_order_id == order_id) && trade.seq <= seq)
+                .map(|trade| trade.quantity)
+                .sum())
+        }
+    }
+
+    /// The next acceptance sequence, which every order is stamped with
+    /// before it is matched. A database sequence hands them out, so they keep
+    /// increasing across restarts.
+    async fn next_acceptance_seq(&self) -> Result<i64, AppError> {
+        #[cfg(feature = "database")]
+        {
+            let seq = sqlx::query_scalar!(
+                r#"SELECT nextval(pg_get_serial_sequence('orders', 'acceptance_seq')) AS "seq!""#
+            )
+            .fetch_one(self.pools.primary())
+            .await?;
+
+            Ok(seq)
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            Ok(self.acceptance_seq.fetch_add(1, Ordering::SeqCst) + 1)
+        }
+    }
+
+    pub async fn engine_state(&self) -> EngineState {
+        *self.state.read().await
+    }
+
+    /// Stops taking orders until `recover` has rebuilt the book.
+    pub async fn begin_warmup(&self) {
+        *self.state.write().await = EngineState::Warming;
+    }
+
+    fn ensure_ready(state: EngineState) -> Result<(), AppError> {
+        match state {
+            EngineState::Ready => Ok(()),
+            EngineState::Warming => Err(AppError::Unavailable(
+                "The matching engine is warming up; retry shortly".to_string(),
+            )),
+        }
+    }
+
+    /// Puts the open orders stored before a restart back to work, in the
+    /// order they were placed, then starts taking orders. Resting orders
+    /// don't cross each other, so reloading them trades nothing. If loading
+    /// fails the engine stays warming, as a partial book would match wrongly.
+    pub async fn recover(&self) -> Result<usize, AppError> {
+        #[cfg(feature = "database")]
+        for symbol in sqlx::query_scalar!("SELECT symbol FROM delisted_markets").fetch_all(self.pools.primary()).await? {
+            self.markets.delist(&symbol).await;
+        }
+
+        #[cfg(feature = "database")]
+        let open_orders = sqlx::query_as!(
+            Order,
+            "SELECT * FROM orders WHERE status IN ('new', 'open', 'partiallyfilled') ORDER BY acceptance_seq, created_at"
+        )
+        .fetch_all(self.pools.primary())
+        .await?;
+
+        // Nothing outlives the process without a database
+        #[cfg(not(feature = "database"))]
+        let open_orders: Vec<Order> = Vec::new();
+
+        let mut oco_groups: HashMap<Uuid, Vec<Order>> = HashMap::new();
+        for order in &open_orders {
+            if let Some(group_id) = order.oco_group_id {
+                oco_groups.entry(group_id).or_default().push(order.clone());
+            }
+        }
+        for (group_id, legs) in &oco_groups {
+            self.stops.link_oco(*group_id, legs).await;
         }
+        for order in &open_orders {
+            if StopService::is_stop(order) {
+                self.stops.park(order.clone()).await;
+            } else if order.price.is_some() {
+                // Market orders never rest, so there is nothing to restore
+                self.order_book.add_order(order).await?;
+            }
+            // Balances live in memory, so what the orders hold is locked again
+            self.record_transition(None, order).await;
+        }
+
+        *self.state.write().await = EngineState::Ready;
+        Ok(open_orders.len())
+    }
+
+    /// Loads resting orders from book snapshots, such as the admin freeze
+    /// endpoint produces, without matching them. Every snapshot is checked
+    /// before anything is loaded, so a bad one leaves the book untouched.
+    /// With a database the orders are stored and `recover` puts them on the
+    /// book, so this must run before it.
+    pub async fn seed(&self, snapshots: &[FrozenBook]) -> Result<usize, AppError> {
+        let mut symbols = std::collections::HashSet::new();
+        for snapshot in snapshots {
+            OrderBookService::check_snapshot(snapshot)?;
+            self.markets.require_market(&snapshot.symbol).await?;
+            if !symbols.insert(snapshot.symbol.as_str()) {
+                return Err(AppError::Validation(format!("Snapshot of {} appears more than once", snapshot.symbol)));
+            }
+        }
+
+        let mut count = 0;
+        for snapshot in snapshots {
+            let orders = snapshot.bids.iter().chain(&snapshot.asks).flat_map(|level| &level.orders);
+
+            #[cfg(feature = "database")]
+            for order in orders {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO orders (id, user_id, symbol, side, quantity, price, order_type, all_or_none, stop_price, trigger_symbol, peg_offset, protection_price, oco_group_id, client_order_id, metadata, status, filled_quantity, acceptance_seq, created_at, updated_at)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20)
+                    ON CONFLICT (id) DO NOTHING
+                    "#,
+                    order.id,
+                    order.user_id,
+                    order.symbol,
+                    order.side.clone() as OrderSide,
+                    order.quantity,
+                    order.price,
+                    order.order_type.clone() as OrderType,
+                    order.all_or_none,
+                    order.stop_price,
+                    order.trigger_symbol,
+                    order.peg_offset,
+                    order.protection_price,
+                    order.oco_group_id,
+                    order.client_order_id,
+                    order.metadata,
+                    order.status.clone() as OrderStatus,
+                    order.filled_quantity,
+                    order.acceptance_seq,
+                    order.created_at,
+                    order.updated_at
+                )
+                .execute(self.pools.primary())
+                .await?;
+                count += 1;
+            }
+
+            #[cfg(not(feature = "database"))]
+            {
+                let mut stored = self.orders.write().await;
+                for order in orders {
+                    stored.insert(order.id, order.clone());
+                }
+                drop(stored);
+                count += self.order_book.seed(snapshot).await?;
+                for level in snapshot.bids.iter().chain(&snapshot.asks) {
+                    for order in &level.orders {
+                        self.record_transition(None, order).await;
+                    }
+                }
+            }
+        }
+
+        // Orders accepted from now on queue behind the seeded ones
+        #[cfg(feature = "database")]
+        sqlx::query!(
+            "SELECT setval(pg_get_serial_sequence('orders', 'acceptance_seq'), GREATEST((SELECT MAX(acceptance_seq) FROM orders), 1))"
+        )
+        .fetch_one(self.pools.primary())
+        .await?;
+        #[cfg(not(feature = "database"))]
+        {
+            let last_seeded = snapshots.iter()
+                .flat_map(|snapshot| snapshot.bids.iter().chain(&snapshot.asks))
+                .flat_map(|level| &level.orders)
+                .map(|order| order.acceptance_seq)
+                .max()
+                .unwrap_or_default();
+            self.acceptance_seq.fetch_max(last_seeded, Ordering::SeqCst);
+        }
+        Ok(count)
     }
 
-    pub async fn create_order(&self, request: CreateOrderRequest) -> Result<OrderResponse, AppError> {
+    /// Creates and matches an order. When rejected-order persistence is enabled,
+    /// an order failing validation is stored and returned with status `Rejected`
+    /// instead of an error.
+    pub async fn create_order(&self, user_id: Uuid, mut request: CreateOrderRequest) -> Result<OrderResponse, AppError> {
+        Self::ensure_ready(self.engine_state().await)?;
+
+        // A retried order comes back as placed the first time
+        if let Some(existing) = self.find_client_order(user_id, request.client_order_id.as_deref()).await? {
+            return Ok(self.respond(existing).await);
+        }
+
         // Validate order
+        let validation = async {
+            self.peg(&mut request).await?;
+            self.validate_order(&request).await?;
+            self.check_limits(user_id, &request).await
+        }.await;
+
+        if let Err(error) = validation {
+            if !self.trading.persist_rejected_orders {
+                return Err(error);
+            }
+            return self.reject_order(user_id, request, error.to_string()).await;
+        }
+
+        let order = self.store_order(user_id, request, None).await?;
+        self.activate(&order).await?;
+        // Reflects whatever the order matched on arrival
+        self.get_order(order.id).await
+    }
+
+    /// Whether `symbol`'s market takes zero and negative prices, which
+    /// request validation needs to know.
+    pub async fn allows_negative_price(&self, symbol: &str) -> bool {
+        self.markets.allows_negative_price(symbol).await
+    }
+
+    /// What `request` would do if placed now: the same validation as placing
+    /// it, then a dry run against the book. Nothing is stored, matched or
+    /// settled.
+    pub async fn preview_order(&self, user_id: Uuid, mut request: CreateOrderRequest) -> Result<OrderPreview, AppError> {
+        Self::ensure_ready(self.engine_state().await)?;
+        self.peg(&mut request).await?;
         self.validate_order(&request).await?;
+        self.check_limits(user_id, &request).await?;
+        if request.stop_price.is_some() {
+            return Err(AppError::Validation("Stop orders can't be previewed; they match only once triggered".to_string()));
+        }
+
+        let order = Order {
+            id: Uuid::new_v4(),
+            user_id,
+            symbol: request.symbol,
+            side: request.side,
+            quantity: request.quantity,
+            price: request.price,
+            order_type: request.order_type,
+            all_or_none: request.all_or_none,
+            stop_price: None,
+            trigger_symbol: None,
+            peg_offset: request.peg_offset,
+            protection_price: request.protection_price,
+            oco_group_id: None,
+            client_order_id: request.client_order_id,
+            metadata: request.metadata,
+            status: OrderStatus::New,
+            filled_quantity: rust_decimal::Decimal::ZERO,
+            reject_reason: None,
+            acceptance_seq: 0,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        };
+        let (fills, would_rest) = self.order_book.preview(&order).await?;
+
+        let fills = PriceFill::group(fills);
+        let filled_quantity: rust_decimal::Decimal = fills.iter().map(|fill| fill.quantity).sum();
+        Ok(OrderPreview {
+            symbol: order.symbol,
+            side: order.side,
+            quantity: order.quantity,
+            average_price: PriceFill::average_price(&fills),
+            remaining_quantity: order.quantity - filled_quantity,
+            fills,
+            filled_quantity,
+            would_rest,
+        })
+    }
+
+    /// Prices a pegged order from the book as it stands: the best price on
+    /// its side, ignoring other pegged orders, plus its offset. Other orders
+    /// are left as they are.
+    async fn peg(&self, request: &mut CreateOrderRequest) -> Result<(), AppError> {
+        let (OrderType::Pegged, Some(offset)) = (&request.order_type, request.peg_offset) else {
+            return Ok(());
+        };
+        let price = self.order_book.peg_price(&request.symbol, &request.side, offset).await.ok_or_else(|| {
+            let best = match request.side {
+                OrderSide::Buy => "bid",
+                OrderSide::Sell => "ask",
+            };
+            AppError::Validation(format!("No best {} on {} to peg to", best, request.symbol))
+        })?;
+        if price <= rust_decimal::Decimal::ZERO && !self.markets.allows_negative_price(&request.symbol).await {
+            return Err(AppError::Validation(format!("Pegged price {} must be greater than 0", price)));
+        }
+        request.price = Some(price);
+        Ok(())
+    }
+
+    /// Places two orders linked one-cancels-other: once either trades or its
+    /// stop triggers, the other is cancelled. At most one leg may rest on the
+    /// book, as a limit order; the rest must be stops.
+    pub async fn create_oco(&self, user_id: Uuid, legs: Vec<CreateOrderRequest>) -> Result<Vec<OrderResponse>, AppError> {
+        Self::ensure_ready(self.engine_state().await)?;
+        if legs.len() != 2 {
+            return Err(AppError::Validation("An OCO group takes exactly 2 orders".to_string()));
+        }
+        let resting: Vec<&CreateOrderRequest> = legs.iter().filter(|leg| leg.stop_price.is_none()).collect();
+        if resting.len() > 1 || resting.iter().any(|leg| !matches!(leg.order_type, OrderType::Limit)) {
+            return Err(AppError::Validation(
+                "At most one OCO order may be a limit order; the others must be stops".to_string(),
+            ));
+        }
+        for leg in &legs {
+            self.validate_order(leg).await?;
+            self.check_limits(user_id, leg).await?;
+            if self.find_client_order(user_id, leg.client_order_id.as_deref()).await?.is_some() {
+                return Err(Self::duplicate_client_order_id(leg.client_order_id.as_deref()));
+            }
+        }
+
+        let group_id = Uuid::new_v4();
+        let mut orders = Vec::with_capacity(legs.len());
+        for leg in legs {
+            orders.push(self.store_order(user_id, leg, Some(group_id)).await?);
+        }
+        self.stops.link_oco(group_id, &orders).await;
+
+        // Stops first: a limit leg that trades on arrival must find them parked to cancel them
+        orders.sort_by_key(|order| !StopService::is_stop(order));
+        for (index, order) in orders.iter().enumerate() {
+            if let Err(error) = self.activate(order).await {
+                for sibling in &orders[..index] {
+                    self.cancel(sibling.id, false).await?;
+                }
+                return Err(error);
+            }
+        }
+
+        let mut placed = Vec::with_capacity(orders.len());
+        for order in &orders {
+            placed.push(self.get_order(order.id).await?);
+        }
+        Ok(placed)
+    }
 
+    async fn store_order(&self, user_id: Uuid, request: CreateOrderRequest, oco_group_id: Option<Uuid>) -> Result<Order, AppError> {
         #[cfg(feature = "database")]
         {
-            // Create order in database
             let order = sqlx::query_as!(
                 Order,
                 r#"
-                INSERT INTO orders (user_id, symbol, side, quantity, price, order_type, status)
-                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                INSERT INTO orders (user_id, symbol, side, quantity, price, order_type, all_or_none, stop_price, trigger_symbol, peg_offset, protection_price, oco_group_id, client_order_id, metadata, status)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
                 RETURNING *
                 "#,
-                Uuid::new_v4(), // TODO: Get from auth context
+                user_id,
                 request.symbol,
                 request.side as OrderSide,
                 request.quantity,
                 request.price,
                 request.order_type as OrderType,
+                request.all_or_none,
+                request.stop_price,
+                request.trigger_symbol,
+                request.peg_offset,
+                request.protection_price,
+                oco_group_id,
+                request.client_order_id,
+                request.metadata,
                 OrderStatus::New as OrderStatus
             )
-            .fetch_one(&self.pool)
-            .await?;
-
-            // Add to order book
-            let trades = self.order_book.add_order(&order).await?;
-
-            // Update order status if trades occurred
-            if !trades.is_empty() {
-                let filled_quantity: rust_decimal::Decimal = trades.iter()
-                    .map(|t| t.quantity)
-                    .sum();
-                
-                let status = if filled_quantity >= order.quantity {
-                    OrderStatus::Filled
-                } else {
-                    OrderStatus::PartiallyFilled
-                };
+            .fetch_one(self.pools.primary())
+            .await
+            .map_err(|error| match error {
+                // Lost a race with a concurrent order reusing the id
+                sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => {
+                    Self::duplicate_client_order_id(request.client_order_id.as_deref())
+                }
+                error => error.into(),
+            })?;
+            self.record_transition(None, &order).await;
 
-                sqlx::query!(
-                    "UPDATE orders SET status = $1, filled_quantity = $2 WHERE id = $3",
-                    status as OrderStatus,
-                    filled_quantity,
-                    order.id
-                )
-                .execute(&self.pool)
-                .await?;
-            }
-
-            Ok(OrderResponse::from(order))
+            Ok(order)
         }
 
         #[cfg(not(feature = "database"))]
@@ -88,157 +535,1498 @@ impl OrderService {
             // Mock implementation
             let order = Order {
                 id: Uuid::new_v4(),
-                user_id: Uuid::new_v4(),
+                user_id,
                 symbol: request.symbol,
                 side: request.side,
                 quantity: request.quantity,
                 price: request.price,
                 order_type: request.order_type,
+                all_or_none: request.all_or_none,
+                stop_price: request.stop_price,
+                trigger_symbol: request.trigger_symbol,
+                peg_offset: request.peg_offset,
+                protection_price: request.protection_price,
+                oco_group_id,
+                client_order_id: request.client_order_id,
+                metadata: request.metadata,
                 status: OrderStatus::New,
                 filled_quantity: rust_decimal::Decimal::ZERO,
+                reject_reason: None,
+                acceptance_seq: self.next_acceptance_seq().await?,
                 created_at: chrono::Utc::now(),
                 updated_at: chrono::Utc::now(),
             };
-            
-            Ok(OrderResponse::from(order))
+
+            let mut orders = self.orders.write().await;
+            if order.client_order_id.is_some()
+                && orders.values().any(|o| o.user_id == user_id && o.client_order_id == order.client_order_id)
+            {
+                return Err(Self::duplicate_client_order_id(order.client_order_id.as_deref()));
+            }
+            orders.insert(order.id, order.clone());
+            self.record_transition(None, &order).await;
+
+            Ok(order)
         }
     }
 
-    pub async fn get_order(&self, order_id: Uuid) -> Result<OrderResponse, AppError> {
+    /// The user's order with `client_order_id`, if they placed one.
+    async fn find_client_order(&self, user_id: Uuid, client_order_id: Option<&str>) -> Result<Option<Order>, AppError> {
+        let Some(client_order_id) = client_order_id else {
+            return Ok(None);
+        };
+
         #[cfg(feature = "database")]
         {
             let order = sqlx::query_as!(
                 Order,
-                "SELECT * FROM orders WHERE id = $1",
-                order_id
+                "SELECT * FROM orders WHERE user_id = $1 AND client_order_id = $2",
+                user_id,
+                client_order_id
             )
-            .fetch_optional(&self.pool)
-            .await?
-            .ok_or_else(|| AppError::NotFound("Order not found".to_string()))?;
+            .fetch_optional(self.pools.primary())
+            .await?;
 
-            Ok(OrderResponse::from(order))
+            Ok(order)
         }
 
         #[cfg(not(feature = "database"))]
         {
-            // Mock implementation
-            Err(AppError::NotFound("Order not found".to_string()))
+            Ok(self.orders.read().await
+                .values()
+                .find(|order| order.user_id == user_id && order.client_order_id.as_deref() == Some(client_order_id))
+                .cloned())
         }
     }
 
-    pub async fn get_orders(&self, query: &OrderQuery) -> Result<Vec<OrderResponse>, AppError> {
-        #[cfg(feature = "database")]
-        {
-            let mut sql = "SELECT * FROM orders WHERE 1=1".to_string();
-            let mut params: Vec<Box<dyn sqlx::Encode<'_, sqlx::Postgres> + Send + Sync>> = vec![];
-            let mut param_count = 0;
+    /// The user's order with `client_order_id`. Other users' orders are `404`
+    /// like unknown ids.
+    pub async fn get_order_by_client_id(&self, user_id: Uuid, client_order_id: &str) -> Result<OrderResponse, AppError> {
+        let order = self.find_client_order(user_id, Some(client_order_id)).await?
+            .ok_or_else(|| AppError::NotFound(format!("No order with client order id {}", client_order_id)))?;
+        Ok(self.respond(order).await)
+    }
 
-            if let Some(ref symbol) = query.symbol {
-                param_count += 1;
-                sql.push_str(&format!(" AND symbol = ${}", param_count));
-                params.push(Box::new(symbol.clone()));
+    fn duplicate_client_order_id(client_order_id: Option<&str>) -> AppError {
+        AppError::Validation(format!(
+            "Client order id {} is already in use",
+            client_order_id.unwrap_or_default()
+        ))
+    }
+
+    /// Puts a stored order to work: stops wait off the book until triggered,
+    /// anything else is matched straight away.
+    async fn activate(&self, order: &Order) -> Result<(), AppError> {
+        if StopService::is_stop(order) {
+            self.stops.park(order.clone()).await;
+            self.report(ExecutionReport::new(ExecutionType::Accepted, order));
+            return Ok(());
+        }
+
+        self.submit(order).await
+    }
+
+    /// Matches a stored order against the book and records the outcome.
+    async fn submit(&self, order: &Order) -> Result<(), AppError> {
+        // Add to order book. An order the book turns away stays on record as rejected.
+        let trades = match self.order_book.add_order(order).await {
+            Ok(trades) => trades,
+            Err(error) => {
+                #[cfg(feature = "database")]
+                sqlx::query!(
+                    "UPDATE orders SET status = $1, reject_reason = $2, updated_at = NOW() WHERE id = $3",
+                    OrderStatus::Rejected as OrderStatus,
+                    error.to_string(),
+                    order.id
+                )
+                .execute(self.pools.primary())
+                .await?;
+
+                #[cfg(not(feature = "database"))]
+                if let Some(stored) = self.orders.write().await.get_mut(&order.id) {
+                    stored.status = OrderStatus::Rejected;
+                    stored.reject_reason = Some(error.to_string());
+                    stored.updated_at = chrono::Utc::now();
+                }
+
+                let rejected = Order {
+                    status: OrderStatus::Rejected,
+                    reject_reason: Some(error.to_string()),
+                    updated_at: chrono::Utc::now(),
+                    ..order.clone()
+                };
+                self.record_transition(Some(order), &rejected).await;
+                self.report(ExecutionReport::new(ExecutionType::Rejected, &rejected));
+                return Err(error);
             }
+        };
+        // A triggered stop was accepted when it was parked
+        if !StopService::is_stop(order) {
+            self.report(ExecutionReport::new(ExecutionType::Accepted, order));
+        }
 
-            if let Some(ref status) = query.status {
-                param_count += 1;
-                sql.push_str(&format!(" AND status = ${}", param_count));
-                params.push(Box::new(status.clone()));
+        self.record_trades(order, trades).await
+    }
+
+    /// Persists and settles `trades`, which `order` took part in, and brings
+    /// the order's status and fill up to date. `order.filled_quantity` is what
+    /// it had filled before them. A new order that rests without trading
+    /// becomes `Open`.
+    async fn record_trades(&self, order: &Order, trades: Vec<Trade>) -> Result<(), AppError> {
+        // Update order status if trades occurred
+        if !trades.is_empty() {
+            // Written before the order is acknowledged
+            #[cfg(feature = "database")]
+            self.trades.persist(trades.clone()).await?;
+            #[cfg(not(feature = "database"))]
+            self.trades.write().await.extend(trades.iter().map(|trade| (trade.id, trade.clone())));
+            self.market_data.record_trades(&trades).await;
+            let mut fees = self.fees.trade_fees(&trades).await;
+            for (trade, fees) in trades.iter().zip(fees.iter_mut()) {
+                fees.charged_in = self.fee_asset(trade).await;
             }
+            self.fees.record_trades(&trades).await;
+            self.balances.settle_trades(&trades, &fees).await?;
+
+            // Trades may also include resting all-or-none orders the new order unlocked
+            let filled_quantity: rust_decimal::Decimal = order.filled_quantity + trades.iter()
+                .filter(|t| t.order_id == order.id || t.taker_order_id == order.id)
+                .map(|t| t.quantity)
+                .sum::<rust_decimal::Decimal>();
+            
+            let status = if filled_quantity >= order.quantity {
+                OrderStatus::Filled
+            } else if order.price.is_none() {
+                // Market orders never rest, so what the book couldn't fill,
+                // within the protection price if one was set, is cancelled
+                OrderStatus::Cancelled
+            } else if filled_quantity.is_zero() {
+                // The trades were resting all-or-none orders completing among themselves
+                OrderStatus::Open
+            } else {
+                OrderStatus::PartiallyFilled
+            };
+            let remainder_cancelled = matches!(status, OrderStatus::Cancelled);
 
-            sql.push_str(" ORDER BY created_at DESC");
+            #[cfg(feature = "database")]
+            sqlx::query!(
+                "UPDATE orders SET status = $1, filled_quantity = $2, updated_at = NOW() WHERE id = $3",
+                status.clone() as OrderStatus,
+                filled_quantity,
+                order.id
+            )
+            .execute(self.pools.primary())
+            .await?;
 
-            if let Some(limit) = query.limit {
-                param_count += 1;
-                sql.push_str(&format!(" LIMIT ${}", param_count));
-                params.push(Box::new(limit));
+            #[cfg(not(feature = "database"))]
+            if let Some(stored) = self.orders.write().await.get_mut(&order.id) {
+                stored.status = status.clone();
+                stored.filled_quantity = filled_quantity;
+                stored.updated_at = chrono::Utc::now();
             }
 
-            if let Some(offset) = query.offset {
-                param_count += 1;
-                sql.push_str(&format!(" OFFSET ${}", param_count));
-                params.push(Box::new(offset));
+            // The other side of each trade rested on the book: the makers the
+            // order took from, and all-or-none orders the new liquidity
+            // completed. Recorded in the order they traded, and before the
+            // order itself, so the journal never shows the book crossed.
+            let mut resting_fills: Vec<(Uuid, rust_decimal::Decimal)> = Vec::new();
+            for trade in &trades {
+                for order_id in [trade.order_id, trade.taker_order_id] {
+                    if order_id == order.id {
+                        continue;
+                    }
+                    match resting_fills.iter_mut().find(|(resting_id, _)| *resting_id == order_id) {
+                        Some((_, quantity)) => *quantity += trade.quantity,
+                        None => resting_fills.push((order_id, trade.quantity)),
+                    }
+                }
+            }
+            for (order_id, quantity) in resting_fills {
+                self.record_resting_fill(order_id, quantity).await?;
             }
+            self.record_transition(Some(order), &Order { status, filled_quantity, ..order.clone() }).await;
 
-            let orders = sqlx::query_as::<_, Order>(&sql)
-                .fetch_all(&self.pool)
-                .await?;
+            self.report_fills(&trades).await?;
+            if remainder_cancelled {
+                self.report(ExecutionReport::new(ExecutionType::Cancelled, &self.find_order(order.id).await?));
+            }
+        } else if order.price.is_some() && matches!(order.status, OrderStatus::New) {
+            // Nothing matched, so the order now rests on the book
+            #[cfg(feature = "database")]
+            sqlx::query!(
+                "UPDATE orders SET status = $1, updated_at = NOW() WHERE id = $2",
+                OrderStatus::Open as OrderStatus,
+                order.id
+            )
+            .execute(self.pools.primary())
+            .await?;
+
+            #[cfg(not(feature = "database"))]
+            if let Some(stored) = self.orders.write().await.get_mut(&order.id) {
+                stored.status = OrderStatus::Open;
+                stored.updated_at = chrono::Utc::now();
+            }
 
-            Ok(orders.into_iter().map(OrderResponse::from).collect())
+            self.record_transition(Some(order), &Order { status: OrderStatus::Open, ..order.clone() }).await;
+        } else {
+            self.journal(order).await;
         }
 
+        Ok(())
+    }
+
+    /// Brings what an order holds, and the order event journal, up to date
+    /// with its change from `before` to `after`. `before` is `None` for an
+    /// order new to the engine.
+    async fn record_transition(&self, before: Option<&Order>, after: &Order) {
+        self.balances.move_hold(before, after).await;
+        self.journal(after).await;
+    }
+
+    /// Journals `order` as it now stands, stamped by the engine's clock, for
+    /// order book replays. The book's copy says whether it rests and counts
+    /// the fills it took there. Events are written in batches alongside
+    /// trades rather than on the order's way through.
+    async fn journal(&self, order: &Order) {
+        let resting = self.order_book.resting_order(order).await;
+        let event = OrderEvent {
+            filled_quantity: resting.as_ref().map_or(order.filled_quantity, |resting| resting.filled_quantity),
+            ..OrderEvent::new(order, resting.is_some(), self.order_book.now())
+        };
+
+        #[cfg(feature = "database")]
+        self.trades.journal(vec![event]).await;
+
         #[cfg(not(feature = "database"))]
         {
-            // Mock implementation
-            Ok(vec![])
+            let mut journal = self.order_events.write().await;
+            let seq = journal.len() as i64 + 1;
+            journal.push(OrderEvent { seq, ..event });
         }
     }
 
-    pub async fn cancel_order(&self, order_id: Uuid) -> Result<OrderResponse, AppError> {
+    /// Adds `quantity` to what a resting order has filled, making it
+    /// `PartiallyFilled` or `Filled`. Added in place, as concurrent takers
+    /// may fill the same order; orders no longer open, such as ones cancelled
+    /// meanwhile, and orders placed straight on the book are left alone.
+    async fn record_resting_fill(&self, order_id: Uuid, quantity: rust_decimal::Decimal) -> Result<(), AppError> {
         #[cfg(feature = "database")]
-        {
-            let order = sqlx::query_as!(
-                Order,
-                "SELECT * FROM orders WHERE id = $1 AND status IN ('new', 'open', 'partially_filled')",
-                order_id
-            )
-            .fetch_optional(&self.pool)
-            .await?
-            .ok_or_else(|| AppError::NotFound("Order not found or cannot be cancelled".to_string()))?;
+        let filled = sqlx::query_as!(
+            Order,
+            r#"
+            UPDATE orders
+            SET filled_quantity = filled_quantity + $1,
+                status = CASE WHEN filled_quantity + $1 >= quantity THEN 'filled'::order_status ELSE 'partiallyfilled'::order_status END,
+                updated_at = NOW()
+            WHERE id = $2 AND status IN ('new', 'open', 'partiallyfilled')
+            RETURNING *
+            "#,
+            quantity,
+            order_id
+        )
+        .fetch_optional(self.pools.primary())
+        .await?;
+
+        #[cfg(not(feature = "database"))]
+        let filled = self.orders.write().await.get_mut(&order_id)
+            .filter(|stored| stored.status.is_open())
+            .map(|stored| {
+                stored.filled_quantity += quantity;
+                stored.status = if stored.filled_quantity >= stored.quantity {
+                    OrderStatus::Filled
+                } else {
+                    OrderStatus::PartiallyFilled
+                };
+                stored.updated_at = chrono::Utc::now();
+                stored.clone()
+            });
+
+        // It was open before the fill, whichever open status it had
+        if let Some(filled) = filled {
+            let before = Order {
+                status: OrderStatus::PartiallyFilled,
+                filled_quantity: filled.filled_quantity - quantity,
+                ..filled.clone()
+            };
+            self.record_transition(Some(&before), &filled).await;
+        }
 
-            // Remove from order book
-            self.order_book.remove_order(&order).await?;
+        Ok(())
+    }
 
-            // Update status
-            let updated_order = sqlx::query_as!(
+    /// The asset `trade`'s market charges fees in, when it isn't the quote
+    /// asset. Falls back to the quote asset, with a warning, when no market
+    /// prices connect the two.
+    async fn fee_asset(&self, trade: &Trade) -> Option<FeeAsset> {
+        let asset = self.markets.get_market(&trade.symbol).await?.fee_asset?;
+        let (base, quote) = crate::models::split_symbol(&trade.symbol)?;
+        let per_quote = if asset == quote {
+            return None;
+        } else if asset == base {
+            // The trade's own price, rather than whatever traded last. A base
+            // asset priced at or below zero can't be charged in
+            rust_decimal::Decimal::ONE.checked_div(trade.price).filter(|rate| rate.is_sign_positive())
+        } else {
+            self.market_data.conversion_rate(quote, &asset).await
+        };
+
+        match per_quote {
+            Some(per_quote) => Some(FeeAsset { asset, per_quote }),
+            None => {
+                tracing::warn!(symbol = %trade.symbol, fee_asset = %asset, "No price for the fee asset; charging fees in the quote asset");
+                None
+            }
+        }
+    }
+
+    async fn reject_order(&self, user_id: Uuid, mut request: CreateOrderRequest, reason: String) -> Result<OrderResponse, AppError> {
+        // Metadata over the cap may be why the order was rejected; don't keep it
+        request.metadata = request.metadata
+            .filter(|metadata| metadata.to_string().len() <= crate::models::MAX_ORDER_METADATA_BYTES);
+
+        #[cfg(feature = "database")]
+        {
+            let order = sqlx::query_as!(
                 Order,
-                "UPDATE orders SET status = $1 WHERE id = $2 RETURNING *",
-                OrderStatus::Cancelled as OrderStatus,
-                order_id
+                r#"
+                INSERT INTO orders (user_id, symbol, side, quantity, price, order_type, all_or_none, stop_price, trigger_symbol, peg_offset, protection_price, client_order_id, metadata, status, reject_reason)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+                RETURNING *
+                "#,
+                user_id,
+                request.symbol,
+                request.side as OrderSide,
+                request.quantity,
+                request.price,
+                request.order_type as OrderType,
+                request.all_or_none,
+                request.stop_price,
+                request.trigger_symbol,
+                request.peg_offset,
+                request.protection_price,
+                request.client_order_id,
+                request.metadata,
+                OrderStatus::Rejected as OrderStatus,
+                reason
             )
-            .fetch_one(&self.pool)
+            .fetch_one(self.pools.primary())
             .await?;
 
-            Ok(OrderResponse::from(updated_order))
+            self.report(ExecutionReport::new(ExecutionType::Rejected, &order));
+            Ok(self.respond(order).await)
         }
 
         #[cfg(not(feature = "database"))]
         {
-            // Mock implementation
-            Err(AppError::NotFound("Order not found or cannot be cancelled".to_string()))
+            let order = Order {
+                id: Uuid::new_v4(),
+                user_id,
+                symbol: request.symbol,
+                side: request.side,
+                quantity: request.quantity,
+                price: request.price,
+                order_type: request.order_type,
+                all_or_none: request.all_or_none,
+                stop_price: request.stop_price,
+                trigger_symbol: request.trigger_symbol,
+                peg_offset: request.peg_offset,
+                protection_price: request.protection_price,
+                oco_group_id: None,
+                client_order_id: request.client_order_id,
+                metadata: request.metadata,
+                status: OrderStatus::Rejected,
+                filled_quantity: rust_decimal::Decimal::ZERO,
+                reject_reason: Some(reason),
+                acceptance_seq: self.next_acceptance_seq().await?,
+                created_at: chrono::Utc::now(),
+                updated_at: chrono::Utc::now(),
+            };
+
+            self.orders.write().await.insert(order.id, order.clone());
+
+            self.report(ExecutionReport::new(ExecutionType::Rejected, &order));
+            Ok(self.respond(order).await)
         }
     }
 
-    pub async fn get_order_trades(&self, order_id: Uuid) -> Result<Vec<crate::models::TradeResponse>, AppError> {
+    pub async fn get_order(&self, order_id: Uuid) -> Result<OrderResponse, AppError> {
         #[cfg(feature = "database")]
         {
-            let trades = sqlx::query_as!(
-                crate::models::Trade,
-                "SELECT * FROM trades WHERE order_id = $1 ORDER BY executed_at DESC",
+            let order = sqlx::query_as!(
+                Order,
+                "SELECT * FROM orders WHERE id = $1",
                 order_id
             )
-            .fetch_all(&self.pool)
-            .await?;
+            .fetch_optional(self.pools.primary())
+            .await?
+            .ok_or_else(|| AppError::NotFound("Order not found".to_string()))?;
 
-            Ok(trades.into_iter().map(crate::models::TradeResponse::from).collect())
+            Ok(self.respond(order).await)
         }
 
         #[cfg(not(feature = "database"))]
         {
-            // Mock implementation
-            Ok(vec![])
+            let order = self.orders.read().await
+                .get(&order_id)
+                .cloned()
+                .ok_or_else(|| AppError::NotFound("Order not found".to_string()))?;
+            Ok(self.respond(order).await)
         }
     }
 
-    async fn validate_order(&self, request: &CreateOrderRequest) -> Result<(), AppError> {
-        // Check if user has sufficient balance
-        // TODO: Implement balance checking logic
-        
-        // Check if symbol is valid
-        // TODO: Implement symbol validation
-        
-        // Check if price is within acceptable range
-        // TODO: Implement price validation
+    /// The user's orders matching `query`, newest first, read from the
+    /// replica when there is one.
+    pub async fn get_orders(&self, user_id: Uuid, query: &OrderQuery) -> Result<Vec<OrderResponse>, AppError> {
+        let statuses = query.statuses()?;
+
+        #[cfg(feature = "database")]
+        {
+            let mut sql = sqlx::QueryBuilder::<sqlx::Postgres>::new("SELECT * FROM orders WHERE user_id = ");
+            sql.push_bind(user_id);
+
+            if let Some(ref symbol) = query.symbol {
+                sql.push(" AND symbol = ").push_bind(symbol.clone());
+            }
+
+            if !statuses.is_empty() {
+                sql.push(" AND status IN (");
+                let mut placeholders = sql.separated(", ");
+                for status in &statuses {
+                    placeholders.push_bind(status.clone());
+                }
+                placeholders.push_unseparated(")");
+            }
+
+            sql.push(" ORDER BY created_at DESC");
+
+            if let Some(limit) = query.limit {
+                sql.push(" LIMIT ").push_bind(limit);
+            }
+
+            if let Some(offset) = query.offset {
+                sql.push(" OFFSET ").push_bind(offset);
+            }
+
+            let orders = sql.build_query_as::<Order>()
+                .fetch_all(self.pools.reader())
+                .await?;
+
+            let mut responses = Vec::with_capacity(orders.len());
+            for order in orders {
+                responses.push(self.respond(order).await);
+            }
+            Ok(responses)
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            let mut orders: Vec<Order> = self.orders.read().await
+                .values()
+                .filter(|order| order.user_id == user_id)
+                .filter(|order| query.symbol.is_none() || query.symbol.as_ref() == Some(&order.symbol))
+                .filter(|order| statuses.is_empty() || statuses.contains(&order.status))
+                .cloned()
+                .collect();
+            orders.sort_by_key(|order| std::cmp::Reverse(order.created_at));
+
+            let offset = query.offset.unwrap_or(0).max(0) as usize;
+            let limit = query.limit.map_or(usize::MAX, |limit| limit.max(0) as usize);
+            let mut responses = Vec::new();
+            for order in orders.into_iter().skip(offset).take(limit) {
+                responses.push(self.respond(order).await);
+            }
+            Ok(responses)
+        }
+    }
+
+    /// `order` as returned to clients, with prices and quantities shown to its
+    /// market's decimal places.
+    async fn respond(&self, order: Order) -> OrderResponse {
+        let market = self.markets.get_market(&order.symbol).await;
+        let response = OrderResponse::from(order);
+        match market {
+            Some(market) => response.with_scale(market.price_scale, market.quantity_scale),
+            None => response,
+        }
+    }
+
+    /// An order that reached a terminal status is kept, and `get_order` keeps
+    /// returning it. Acting on it fails with `Gone` rather than `NotFound`,
+    /// which is reserved for ids that never existed.
+    fn ensure_open(order: &Order, action: &str) -> Result<(), AppError> {
+        if order.status.is_open() {
+            return Ok(());
+        }
+        Err(AppError::Gone(format!(
+            "Order is {:?} and can no longer be {}",
+            order.status, action
+        )))
+    }
+
+    pub async fn cancel_order(&self, order_id: Uuid) -> Result<OrderResponse, AppError> {
+        self.cancel(order_id, true).await
+    }
+
+    /// Cancels an order, for its owner when `by_owner` and for the engine
+    /// otherwise. Only owners are held to the market's minimum quote life.
+    async fn cancel(&self, order_id: Uuid, by_owner: bool) -> Result<OrderResponse, AppError> {
+        #[cfg(feature = "database")]
+        {
+            let order = sqlx::query_as!(
+                Order,
+                "SELECT * FROM orders WHERE id = $1",
+                order_id
+            )
+            .fetch_optional(self.pools.primary())
+            .await?
+            .ok_or_else(|| AppError::NotFound("Order not found".to_string()))?;
+            Self::ensure_open(&order, "cancelled")?;
+
+            // Remove from order book, or from the stops waiting for a trigger
+            self.stops.cancel(order_id).await;
+            let resting = if by_owner {
+                self.order_book.cancel_order(&order).await?
+            } else {
+                self.order_book.remove_order(&order).await?
+            };
+
+            // Update status, keeping what was filled while the order rested
+            let filled_quantity = Self::filled_before_cancel(&order, resting.as_ref());
+            let updated_order = sqlx::query_as!(
+                Order,
+                "UPDATE orders SET status = $1, filled_quantity = $2, updated_at = NOW() WHERE id = $3 RETURNING *",
+                OrderStatus::Cancelled as OrderStatus,
+                filled_quantity,
+                order_id
+            )
+            .fetch_one(self.pools.primary())
+            .await?;
+            self.record_transition(Some(&order), &updated_order).await;
+
+            self.report(ExecutionReport::new(ExecutionType::Cancelled, &updated_order));
+            Ok(self.respond(updated_order).await)
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            let mut orders = self.orders.write().await;
+            let order = orders.get_mut(&order_id)
+                .ok_or_else(|| AppError::NotFound("Order not found".to_string()))?;
+            Self::ensure_open(order, "cancelled")?;
+
+            self.stops.cancel(order_id).await;
+            let resting = if by_owner {
+                self.order_book.cancel_order(order).await?
+            } else {
+                self.order_book.remove_order(order).await?
+            };
+            let before = order.clone();
+            order.filled_quantity = Self::filled_before_cancel(order, resting.as_ref());
+            order.status = OrderStatus::Cancelled;
+            order.updated_at = chrono::Utc::now();
+            self.record_transition(Some(&before), order).await;
+
+            self.report(ExecutionReport::new(ExecutionType::Cancelled, order));
+            Ok(self.respond(order.clone()).await)
+        }
+    }
+
+    /// Delists `symbol`: it takes no more orders, every open order on it is
+    /// cancelled and its book is emptied. Cancelling an order releases what
+    /// it held back to its owner's available balance.
+    pub async fn delist_market(&self, symbol: &str) -> Result<MarketDelisting, AppError> {
+        self.markets.require_market(symbol).await?;
+        // Delisted first, so no new order slips in while the rest are cancelled
+        if !self.markets.delist(symbol).await {
+            return Err(AppError::Gone(format!("Market {} is already delisted", symbol)));
+        }
+
+        #[cfg(feature = "database")]
+        let open_orders = {
+            sqlx::query!(
+                "INSERT INTO delisted_markets (symbol, delisted_at) VALUES ($1, NOW()) ON CONFLICT (symbol) DO NOTHING",
+                symbol
+            )
+            .execute(self.pools.primary())
+            .await?;
+
+            sqlx::query_scalar!(
+                "SELECT id FROM orders WHERE symbol = $1 AND status IN ('new', 'open', 'partiallyfilled') ORDER BY created_at",
+                symbol
+            )
+            .fetch_all(self.pools.primary())
+            .await?
+        };
+
+        #[cfg(not(feature = "database"))]
+        let open_orders: Vec<Uuid> = {
+            let orders = self.orders.read().await;
+            let mut open: Vec<&Order> = orders.values()
+                .filter(|order| order.symbol == symbol && order.status.is_open())
+                .collect();
+            open.sort_by_key(|order| order.created_at);
+            open.iter().map(|order| order.id).collect()
+        };
+
+        let mut cancelled_orders = Vec::with_capacity(open_orders.len());
+        for order_id in open_orders {
+            match self.cancel(order_id, false).await {
+                Ok(_) => cancelled_orders.push(order_id),
+                // Filled or cancelled since it was listed
+                Err(AppError::Gone(_)) => {}
+                Err(error) => return Err(error),
+            }
+        }
+        let stray = self.order_book.clear(symbol).await;
+        if !stray.is_empty() {
+            tracing::warn!(%symbol, count = stray.len(), "Cleared resting orders with no open stored order");
+        }
+
+        Ok(MarketDelisting {
+            symbol: symbol.to_string(),
+            cancelled_orders,
+        })
+    }
+
+    /// Takes every resting order off `symbol`'s book in memory, for tests and
+    /// recovery drills. With `cancel` they are cancelled on record as well;
+    /// without it they stay open there. Stops waiting for a trigger stay put.
+    pub async fn reset_book(&self, symbol: &str, cancel: bool) -> Result<BookReset, AppError> {
+        self.markets.require_market(symbol).await?;
+
+        let mut cleared_orders = Vec::new();
+        if cancel {
+            let frozen = self.order_book.freeze(symbol).await;
+            for order in frozen.bids.iter().chain(&frozen.asks).flat_map(|level| &level.orders) {
+                match self.cancel(order.id, false).await {
+                    Ok(_) => cleared_orders.push(order.id),
+                    // Gone since the book was read, or never stored; cleared below if still there
+                    Err(AppError::Gone(_) | AppError::NotFound(_)) => {}
+                    Err(error) => return Err(error),
+                }
+            }
+        }
+        for order in self.order_book.clear(symbol).await {
+            // Still open on record, but off the book
+            self.journal(&order).await;
+            cleared_orders.push(order.id);
+        }
+
+        Ok(BookReset {
+            symbol: symbol.to_string(),
+            cleared_orders,
+            cancelled: cancel,
+        })
+    }
+
+    /// What `order` had filled when cancelled. The book's copy is ahead of
+    /// the record while a fill is still being recorded, so it counts when larger.
+    fn filled_before_cancel(order: &Order, resting: Option<&Order>) -> rust_decimal::Decimal {
+        resting
+            .map(|resting| resting.filled_quantity.max(order.filled_quantity))
+            .unwrap_or(order.filled_quantity)
+    }
+
+    /// Cancels each of the user's orders in `order_ids`, reporting the outcome
+    /// per id. Ids that don't exist or belong to another user are `404`,
+    /// orders that are no longer open `410`; neither stops the rest.
+    pub async fn cancel_orders(&self, user_id: Uuid, order_ids: &[Uuid]) -> Vec<CancelResult> {
+        let mut results = Vec::with_capacity(order_ids.len());
+        for &order_id in order_ids {
+            let result = match self.owned_order(user_id, order_id).await {
+                Ok(_) => self.cancel_order(order_id).await,
+                Err(error) => Err(error),
+            };
+            results.push(match result {
+                Ok(order) => CancelResult {
+                    order_id,
+                    status: 200,
+                    order: Some(order),
+                    error: None,
+                },
+                Err(error) => CancelResult {
+                    order_id,
+                    status: error.error_response().status().as_u16(),
+                    order: None,
+                    error: Some(error.to_string()),
+                },
+            });
+        }
+        results
+    }
+
+    /// Expires every open order that has outlived its market's default TTL as
+    /// of `now`, taking it off the book. Markets without a TTL are skipped.
+    pub async fn expire_orders(&self, now: chrono::DateTime<chrono::Utc>) -> Result<Vec<OrderResponse>, AppError> {
+        let mut expired = Vec::new();
+        for (symbol, ttl) in self.markets.order_ttls().await {
+            let cutoff = now - ttl;
+
+            #[cfg(feature = "database")]
+            {
+                let stale = sqlx::query_as!(
+                    Order,
+                    "SELECT * FROM orders WHERE symbol = $1 AND status IN ('new', 'open', 'partiallyfilled') AND created_at <= $2",
+                    symbol,
+                    cutoff
+                )
+                .fetch_all(self.pools.primary())
+                .await?;
+
+                for order in stale {
+                    self.stops.cancel(order.id).await;
+                    self.order_book.remove_order(&order).await?;
+                    let expired_order = sqlx::query_as!(
+                        Order,
+                        "UPDATE orders SET status = $1, updated_at = NOW() WHERE id = $2 RETURNING *",
+                        OrderStatus::Expired as OrderStatus,
+                        order.id
+                    )
+                    .fetch_one(self.pools.primary())
+                    .await?;
+                    self.record_transition(Some(&order), &expired_order).await;
+                    let order = expired_order;
+                    self.report(ExecutionReport::new(ExecutionType::Expired, &order));
+                    expired.push(OrderResponse::from(order));
+                }
+            }
+
+            #[cfg(not(feature = "database"))]
+            {
+                let mut orders = self.orders.write().await;
+                for order in orders.values_mut() {
+                    if order.symbol == symbol && order.status.is_open() && order.created_at <= cutoff {
+                        self.stops.cancel(order.id).await;
+                        self.order_book.remove_order(order).await?;
+                        let before = order.clone();
+                        order.status = OrderStatus::Expired;
+                        order.updated_at = now;
+                        self.record_transition(Some(&before), order).await;
+                        self.report(ExecutionReport::new(ExecutionType::Expired, order));
+                        expired.push(OrderResponse::from(order.clone()));
+                    }
+                }
+            }
+        }
+        Ok(expired)
+    }
+
+    /// Runs `expire_orders` every `every` until `shutdown` is cancelled. A
+    /// failed sweep is logged and the next tick tries again.
+    pub async fn run_expiry_sweeper(self, every: std::time::Duration, shutdown: CancellationToken) {
+        let mut ticks = tokio::time::interval(every);
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => return,
+                _ = ticks.tick() => {}
+            }
+            match self.expire_orders(chrono::Utc::now()).await {
+                Ok(expired) if !expired.is_empty() => tracing::info!(count = expired.len(), "Expired stale orders"),
+                Ok(_) => {}
+                Err(error) => tracing::warn!(%error, "Order expiry sweep failed"),
+            }
+        }
+    }
+
+    /// Acts on what `trade` set off: cancels the OCO legs that lost to another
+    /// leg, then sends the stops it triggered to the book. Returns those stops
+    /// as they stand afterwards.
+    pub async fn handle_trade(&self, trade: &Trade) -> Result<Vec<OrderResponse>, AppError> {
+        let activation = self.stops.triggered_by(trade).await;
+
+        // Losing legs leave the book before a winning stop trades
+        for order_id in activation.cancelled {
+            match self.cancel(order_id, false).await {
+                Ok(_) | Err(AppError::Gone(_)) => {}
+                Err(error) => tracing::warn!(%order_id, %error, "Cancelling OCO order failed"),
+            }
+        }
+
+        let mut activated = Vec::new();
+        for order in activation.triggered {
+            // A stop the book rejects is recorded as such; the others still activate
+            if let Err(error) = self.submit(&order).await {
+                tracing::warn!(order_id = %order.id, %error, "Triggered stop was rejected");
+            }
+            activated.push(self.get_order(order.id).await?);
+        }
+        Ok(activated)
+    }
+
+    /// Watches every trade the engine executes and acts on the stops and OCO
+    /// orders it activates. Activated stops trade too, so one trade can cascade.
+    /// Stops once `shutdown` is cancelled, after the trade in hand.
+    pub async fn run_trade_watcher(self, shutdown: CancellationToken) {
+        let mut trades = self.order_book.subscribe_trades();
+        loop {
+            let received = tokio::select! {
+                _ = shutdown.cancelled() => return,
+                received = trades.recv() => received,
+            };
+            match received {
+                Ok(trade) => {
+                    if let Err(error) = self.handle_trade(&trade).await {
+                        tracing::warn!(trade_id = %trade.id, %error, "Handling trade for conditional orders failed");
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    tracing::warn!(missed, "Trade watcher fell behind and skipped trades");
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    }
+
+    async fn find_order(&self, order_id: Uuid) -> Result<Order, AppError> {
+        #[cfg(feature = "database")]
+        {
+            sqlx::query_as!(
+                Order,
+                "SELECT * FROM orders WHERE id = $1",
+                order_id
+            )
+            .fetch_optional(self.pools.primary())
+            .await?
+            .ok_or_else(|| AppError::NotFound("Order not found".to_string()))
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            self.orders.read().await
+                .get(&order_id)
+                .cloned()
+                .ok_or_else(|| AppError::NotFound("Order not found".to_string()))
+        }
+    }
+
+    /// The user's order with `order_id`. Other users' orders are as unknown
+    /// as ids that never existed, so their ids can't be probed.
+    pub async fn owned_order(&self, user_id: Uuid, order_id: Uuid) -> Result<Order, AppError> {
+        let order = self.find_order(order_id).await?;
+        if order.user_id != user_id {
+            return Err(AppError::NotFound("Order not found".to_string()));
+        }
+        Ok(order)
+    }
+
+    pub async fn reduce_order(&self, order_id: Uuid, new_quantity: rust_decimal::Decimal) -> Result<OrderResponse, AppError> {
+        #[cfg(feature = "database")]
+        {
+            let order = sqlx::query_as!(
+                Order,
+                "SELECT * FROM orders WHERE id = $1",
+                order_id
+            )
+            .fetch_optional(self.pools.primary())
+            .await?
+            .ok_or_else(|| AppError::NotFound("Order not found".to_string()))?;
+            Self::ensure_open(&order, "reduced")?;
+
+            // The book enforces the quantity rules and keeps time priority
+            let reduced = self.order_book.reduce_order(&order, new_quantity).await?;
+            let status = if reduced.filled_quantity >= reduced.quantity {
+                OrderStatus::Filled
+            } else {
+                order.status
+            };
+
+            let updated_order = sqlx::query_as!(
+                Order,
+                "UPDATE orders SET quantity = $1, status = $2, updated_at = NOW() WHERE id = $3 RETURNING *",
+                new_quantity,
+                status as OrderStatus,
+                order_id
+            )
+            .fetch_one(self.pools.primary())
+            .await?;
+            self.record_transition(Some(&order), &updated_order).await;
+
+            let exec_type = match updated_order.status {
+                OrderStatus::Filled => ExecutionType::Filled,
+                _ => ExecutionType::Replaced,
+            };
+            self.report(ExecutionReport::new(exec_type, &updated_order));
+            Ok(self.respond(updated_order).await)
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            let mut orders = self.orders.write().await;
+            let order = orders.get_mut(&order_id)
+                .ok_or_else(|| AppError::NotFound("Order not found".to_string()))?;
+            Self::ensure_open(order, "reduced")?;
+
+            if new_quantity >= order.quantity {
+                return Err(AppError::Validation(format!(
+                    "New quantity {} must be smaller than the current quantity {}",
+                    new_quantity, order.quantity
+                )));
+            }
+
+            if new_quantity < order.filled_quantity {
+                return Err(AppError::Validation(format!(
+                    "New quantity {} is below the already filled quantity {}",
+                    new_quantity, order.filled_quantity
+                )));
+            }
+
+            if let Some(price) = order.price {
+                if self.order_book.queue_position(order.id, &order.symbol, &order.side, price).await.is_some() {
+                    self.order_book.reduce_order(order, new_quantity).await?;
+                }
+            }
+
+            let before = order.clone();
+            order.quantity = new_quantity;
+            order.updated_at = chrono::Utc::now();
+            let exec_type = if order.filled_quantity >= order.quantity {
+                order.status = OrderStatus::Filled;
+                ExecutionType::Filled
+            } else {
+                ExecutionType::Replaced
+            };
+            self.record_transition(Some(&before), order).await;
+            self.report(ExecutionReport::new(exec_type, order));
+
+            Ok(self.respond(order.clone()).await)
+        }
+    }
+
+    /// Changes the price and total quantity of `user_id`'s resting order,
+    /// keeping whichever `patch` leaves out. Only shrinking the quantity keeps
+    /// the order's place in the queue; any other change sends it to the back
+    /// of its level, and a new price that crosses the book trades at once.
+    pub async fn patch_order(&self, user_id: Uuid, order_id: Uuid, patch: &PatchOrderRequest) -> Result<OrderResponse, AppError> {
+        let order = self.owned_order(user_id, order_id).await?;
+        let allow_negative_price = self.markets.allows_negative_price(&order.symbol).await;
+        patch.validate_with(allow_negative_price).map_err(AppError::Validation)?;
+        Self::ensure_open(&order, "changed")?;
+        let Some(current_price) = order.price else {
+            return Err(AppError::Validation("Only orders with a price can be changed".to_string()));
+        };
+        if matches!(order.order_type, OrderType::Pegged) && patch.price.is_some() {
+            return Err(AppError::Validation("Pegged orders take their price from the book".to_string()));
+        }
+
+        let price = patch.price.unwrap_or(current_price);
+        let quantity = patch.quantity.unwrap_or(order.quantity);
+        self.markets.ensure_listed(&order.symbol).await?;
+        if price == current_price {
+            if quantity == order.quantity {
+                return Ok(self.respond(order).await);
+            }
+            if quantity < order.quantity {
+                return self.reduce_order(order_id, quantity).await;
+            }
+        } else {
+            self.markets.check_price(&order.symbol, price).await?;
+        }
+
+        self.replace(&order, price, quantity).await?;
+        self.get_order(order_id).await
+    }
+
+    /// Places `order` again at `price` and `quantity`, at the back of the
+    /// queue, then stores, reports and settles the outcome.
+    async fn replace(&self, order: &Order, price: rust_decimal::Decimal, quantity: rust_decimal::Decimal) -> Result<(), AppError> {
+        let order_id = order.id;
+        let acceptance_seq = self.next_acceptance_seq().await?;
+        let (replacement, trades) = self.order_book.replace_order(order, price, quantity, acceptance_seq).await?;
+        // The book's copy has every fill, including any still being recorded
+        let status = if replacement.filled_quantity.is_zero() {
+            order.status.clone()
+        } else {
+            OrderStatus::PartiallyFilled
+        };
+        let replacement = Order { status, ..replacement };
+
+        #[cfg(feature = "database")]
+        sqlx::query!(
+            "UPDATE orders SET price = $1, quantity = $2, filled_quantity = $3, status = $4, acceptance_seq = $5, updated_at = NOW() WHERE id = $6",
+            price,
+            quantity,
+            replacement.filled_quantity,
+            replacement.status.clone() as OrderStatus,
+            acceptance_seq,
+            order_id
+        )
+        .execute(self.pools.primary())
+        .await?;
+
+        #[cfg(not(feature = "database"))]
+        if let Some(stored) = self.orders.write().await.get_mut(&order_id) {
+            stored.price = Some(price);
+            stored.quantity = quantity;
+            stored.filled_quantity = replacement.filled_quantity;
+            stored.status = replacement.status.clone();
+            stored.acceptance_seq = acceptance_seq;
+            stored.updated_at = replacement.updated_at;
+        }
+        self.balances.move_hold(Some(order), &replacement).await;
+
+        self.report(ExecutionReport::new(ExecutionType::Replaced, &replacement));
+        self.record_trades(&replacement, trades).await
+    }
+
+    /// Moves `symbol`'s pegged orders to where their pegs now are. Each goes
+    /// to the back of its new level, and one that now crosses the book
+    /// trades. Returns the orders moved, as they stand afterwards.
+    pub async fn reprice_pegged(&self, symbol: &str) -> Result<Vec<OrderResponse>, AppError> {
+        let mut repriced = Vec::new();
+        for (order, price) in self.order_book.pegs_to_reprice(symbol).await {
+            match self.replace(&order, price, order.quantity).await {
+                Ok(()) => repriced.push(self.get_order(order.id).await?),
+                // Filled or cancelled since the book was read
+                Err(AppError::OrderBook(_)) => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(repriced)
+    }
+
+    /// Reprices pegged orders whenever a book's best bid or ask changes. The
+    /// updates repricing itself causes leave every peg where it is, so they
+    /// settle after one pass. Stops once `shutdown` is cancelled.
+    pub async fn run_peg_watcher(self, shutdown: CancellationToken) {
+        let mut updates = self.order_book.subscribe_bbo();
+        loop {
+            let received = tokio::select! {
+                _ = shutdown.cancelled() => return,
+                received = updates.recv() => received,
+            };
+            match received {
+                Ok(update) => {
+                    if let Err(error) = self.reprice_pegged(&update.symbol).await {
+                        tracing::warn!(symbol = %update.symbol, %error, "Repricing pegged orders failed");
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    tracing::warn!(missed, "Peg watcher fell behind and skipped book updates");
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    }
+
+    pub async fn queue_position(&self, order_id: Uuid) -> Result<QueuePosition, AppError> {
+        let order = self.get_order(order_id).await?;
+        let not_resting = || AppError::Validation(format!("Order {} is not resting on the book", order_id));
+        let price = order.price.ok_or_else(not_resting)?;
+        self.order_book.queue_position(order.id, &order.symbol, &order.side, price).await
+            .ok_or_else(not_resting)
+    }
+
+    /// Trades of `order_id`, newest first, each with the order's metadata.
+    /// Trades executed at the same instant come in reverse engine sequence,
+    /// so the order is stable. Read from the replica when there is one.
+    pub async fn get_order_trades(&self, order_id: Uuid) -> Result<Vec<crate::models::TradeResponse>, AppError> {
+        #[cfg(feature = "database")]
+        let trades = sqlx::query_as!(
+            crate::models::Trade,
+            "SELECT * FROM trades WHERE order_id = $1 OR taker_order_id = $1 ORDER BY executed_at DESC, seq DESC",
+            order_id
+        )
+        .fetch_all(self.pools.reader())
+        .await?;
+
+        #[cfg(not(feature = "database"))]
+        let trades = {
+            let mut trades: Vec<Trade> = self.trades.read().await.values()
+                .filter(|trade| trade.order_id == order_id || trade.taker_order_id == order_id)
+                .cloned()
+                .collect();
+            trades.sort_by_key(|trade| std::cmp::Reverse((trade.executed_at, trade.seq)));
+            trades
+        };
+
+        let metadata = self.order_metadata(order_id).await;
+        Ok(trades.into_iter()
+            .map(|trade| crate::models::TradeResponse { metadata: metadata.clone(), ..trade.into() })
+            .collect())
+    }
+
+    /// Rebuilds `symbol`'s book over `[from, to)` from the order event
+    /// journal: the resting orders as of `from`, then the book after each
+    /// change in the window, with the window's trades. Windows are capped at
+    /// an hour. Orders placed straight on the book were never journalled and
+    /// don't appear.
+    pub async fn replay_book(&self, symbol: &str, from: chrono::DateTime<chrono::Utc>, to: chrono::DateTime<chrono::Utc>) -> Result<BookReplay, AppError> {
+        if to <= from {
+            return Err(AppError::Validation("Replay window must end after it starts".to_string()));
+        }
+        if to - from > chrono::Duration::minutes(MAX_REPLAY_WINDOW_MINUTES) {
+            return Err(AppError::Validation(format!(
+                "Replay window is longer than {} minutes", MAX_REPLAY_WINDOW_MINUTES
+            )));
+        }
+
+        #[cfg(feature = "database")]
+        let (before, events, trades) = {
+            let before = sqlx::query_as!(
+                OrderEvent,
+                "SELECT DISTINCT ON (order_id) * FROM order_events WHERE symbol = $1 AND occurred_at < $2 ORDER BY order_id, seq DESC",
+                symbol,
+                from
+            )
+            .fetch_all(self.pools.reader())
+            .await?;
+            let events = sqlx::query_as!(
+                OrderEvent,
+                "SELECT * FROM order_events WHERE symbol = $1 AND occurred_at >= $2 AND occurred_at < $3 ORDER BY seq",
+                symbol,
+                from,
+                to
+            )
+            .fetch_all(self.pools.reader())
+            .await?;
+            let trades = sqlx::query_as!(
+                Trade,
+                "SELECT * FROM trades WHERE symbol = $1 AND executed_at >= $2 AND executed_at < $3 ORDER BY seq",
+                symbol,
+                from,
+                to
+            )
+            .fetch_all(self.pools.reader())
+            .await?;
+            (before, events, trades)
+        };
+
+        #[cfg(not(feature = "database"))]
+        let (before, events, trades) = {
+            let journal = self.order_events.read().await;
+            let mut latest: HashMap<Uuid, OrderEvent> = HashMap::new();
+            for event in journal.iter().filter(|event| event.symbol == symbol && event.occurred_at < from) {
+                latest.insert(event.order_id, event.clone());
+            }
+            let events: Vec<OrderEvent> = journal.iter()
+                .filter(|event| event.symbol == symbol && event.occurred_at >= from && event.occurred_at < to)
+                .cloned()
+                .collect();
+            let mut trades: Vec<Trade> = self.trades.read().await.values()
+                .filter(|trade| trade.symbol == symbol && trade.executed_at >= from && trade.executed_at < to)
+                .cloned()
+                .collect();
+            trades.sort_by_key(|trade| trade.seq);
+            (latest.into_values().collect::<Vec<_>>(), events, trades)
+        };
+
+        let mut resting: HashMap<Uuid, OrderEvent> = before.into_iter()
+            .filter(|event| event.resting)
+            .map(|event| (event.order_id, event))
+            .collect();
+        let (bids, asks) = Self::replayed_levels(&resting);
+        let mut steps = Vec::with_capacity(events.len());
+        for event in events {
+            if event.resting {
+                resting.insert(event.order_id, event.clone());
+            } else {
+                resting.remove(&event.order_id);
+            }
+            let (bids, asks) = Self::replayed_levels(&resting);
+            steps.push(BookReplayStep { event, bids, asks });
+        }
+
+        Ok(BookReplay {
+            symbol: symbol.to_string(),
+            from,
+            to,
+            bids,
+            asks,
+            steps,
+            trades,
+        })
+    }
+
+    /// The price levels `resting` orders make up, bids highest first and asks
+    /// lowest first.
+    fn replayed_levels(resting: &HashMap<Uuid, OrderEvent>) -> (Vec<OrderBookEntry>, Vec<OrderBookEntry>) {
+        let mut bids = std::collections::BTreeMap::new();
+        let mut asks = std::collections::BTreeMap::new();
+        for event in resting.values() {
+            let Some(price) = event.price else { continue };
+            let levels = match event.side {
+                OrderSide::Buy => &mut bids,
+                OrderSide::Sell => &mut asks,
+            };
+            let level = levels.entry(price).or_insert(OrderBookEntry {
+                price,
+                quantity: rust_decimal::Decimal::ZERO,
+                order_count: 0,
+            });
+            level.quantity += event.quantity - event.filled_quantity;
+            level.order_count += 1;
+        }
+        (bids.into_values().rev().collect(), asks.into_values().collect())
+    }
+
+    /// The latest `limit` trades on `symbol`, oldest first, read from the
+    /// replica when there is one.
+    pub async fn get_recent_trades(&self, symbol: &str, limit: usize) -> Result<Vec<Trade>, AppError> {
+        #[cfg(feature = "database")]
+        let mut trades = sqlx::query_as!(
+            Trade,
+            "SELECT * FROM trades WHERE symbol = $1 ORDER BY executed_at DESC, seq DESC LIMIT $2",
+            symbol,
+            limit as i64
+        )
+        .fetch_all(self.pools.reader())
+        .await?;
+
+        #[cfg(not(feature = "database"))]
+        let mut trades = {
+            let mut trades: Vec<Trade> = self.trades.read().await.values()
+                .filter(|trade| trade.symbol == symbol)
+                .cloned()
+                .collect();
+            trades.sort_by_key(|trade| std::cmp::Reverse((trade.executed_at, trade.seq)));
+            trades.truncate(limit);
+            trades
+        };
+
+        trades.reverse();
+        Ok(trades)
+    }
+
+    /// `order_id`'s trades summed per execution price.
+    pub async fn get_order_fills(&self, order_id: Uuid) -> Result<OrderFills, AppError> {
+        let trades = self.get_order_trades(order_id).await?;
+        let fills = PriceFill::group(trades.iter().map(|trade| (trade.price, trade.quantity)));
+        Ok(OrderFills {
+            order_id,
+            average_price: PriceFill::average_price(&fills),
+            filled_quantity: fills.iter().map(|fill| fill.quantity).sum(),
+            fills,
+        })
+    }
+
+    /// A trade `user_id` was the maker or taker of, with the metadata of their
+    /// side's order. Other users' trades are `404`, so their ids can't be probed.
+    pub async fn get_trade(&self, user_id: Uuid, trade_id: Uuid) -> Result<crate::models::TradeResponse, AppError> {
+        let not_found = || AppError::NotFound(format!("Trade {} not found", trade_id));
+
+        #[cfg(feature = "database")]
+        let trade = sqlx::query_as!(
+            Trade,
+            "SELECT * FROM trades WHERE id = $1 AND (maker_user_id = $2 OR taker_user_id = $2)",
+            trade_id,
+            user_id
+        )
+        .fetch_optional(self.pools.reader())
+        .await?
+        .ok_or_else(not_found)?;
+
+        #[cfg(not(feature = "database"))]
+        let trade = self.trades.read().await
+            .get(&trade_id)
+            .filter(|trade| trade.maker_user_id == user_id || trade.taker_user_id == user_id)
+            .cloned()
+            .ok_or_else(not_found)?;
+
+        let own_order_id = if trade.maker_user_id == user_id { trade.order_id } else { trade.taker_order_id };
+        let metadata = self.order_metadata(own_order_id).await;
+        Ok(crate::models::TradeResponse { metadata, ..trade.into() })
+    }
+
+    /// The metadata the client attached to `order_id`, if any.
+    async fn order_metadata(&self, order_id: Uuid) -> Option<serde_json::Value> {
+        self.find_order(order_id).await.ok().and_then(|order| order.metadata)
+    }
+
+    /// How many of the user's orders are still open.
+    pub async fn open_order_count(&self, user_id: Uuid) -> Result<usize, AppError> {
+        #[cfg(feature = "database")]
+        {
+            let count = sqlx::query_scalar!(
+                r#"SELECT COUNT(*) AS "count!" FROM orders WHERE user_id = $1 AND status IN ('new', 'open', 'partiallyfilled')"#,
+                user_id
+            )
+            .fetch_one(self.pools.primary())
+            .await?;
+
+            Ok(count as usize)
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            Ok(self.orders.read().await.values()
+                .filter(|o| o.user_id == user_id && o.status.is_open())
+                .count())
+        }
+    }
+
+    /// Balances, open orders and recent volume for a user, for dashboard views.
+    pub async fn account_summary(&self, user_id: Uuid) -> Result<AccountSummary, AppError> {
+        let balances = self.balances.get_balances(user_id).await;
+        let locked_funds = balances.iter()
+            .filter(|balance| balance.locked > rust_decimal::Decimal::ZERO)
+            .cloned()
+            .collect();
+
+        Ok(AccountSummary {
+            open_orders: self.open_order_count(user_id).await?,
+            locked_funds,
+            balances,
+            volume_24h: self.fees.traded_volume(user_id, chrono::Duration::hours(24)).await,
+        })
+    }
+
+    /// Checks every user's stored `locked` balances against what their open
+    /// orders should hold.
+    pub async fn reconcile_balances(&self) -> Result<BalanceReconciliation, AppError> {
+        #[cfg(feature = "database")]
+        let open_orders = sqlx::query_as!(
+            Order,
+            "SELECT * FROM orders WHERE status IN ('new', 'open', 'partiallyfilled')"
+        )
+        .fetch_all(self.pools.primary())
+        .await?;
+
+        #[cfg(not(feature = "database"))]
+        let open_orders: Vec<Order> = self.orders.read().await.values()
+            .filter(|order| order.status.is_open())
+            .cloned()
+            .collect();
+
+        Ok(BalanceReconciliation {
+            open_orders: open_orders.len(),
+            discrepancies: self.balances.reconcile_locked(&open_orders).await,
+        })
+    }
+
+    /// The venue-wide open order limits, then the user's risk limits.
+    async fn check_limits(&self, user_id: Uuid, request: &CreateOrderRequest) -> Result<(), AppError> {
+        self.check_open_order_limits(user_id, &request.symbol).await?;
+        let Some(limits) = self.risk.limits_for(user_id, &request.symbol) else {
+            return Ok(());
+        };
+
+        let exposure = self.exposure(user_id, &request.symbol).await?;
+        // Market orders are valued at the price they would start filling at
+        let reference_price = match request.price.or(request.stop_price) {
+            Some(price) => Some(price),
+            None => {
+                let ticker = self.order_book.get_ticker(&request.symbol).await;
+                match request.side {
+                    OrderSide::Buy => ticker.best_ask,
+                    OrderSide::Sell => ticker.best_bid,
+                }
+            }
+        };
+        RiskService::check(&limits, request, reference_price, &exposure)
+    }
+
+    /// The user's open orders in `symbol` and holding of its base asset.
+    async fn exposure(&self, user_id: Uuid, symbol: &str) -> Result<Exposure, AppError> {
+        #[cfg(feature = "database")]
+        let (open_orders, open_buy, open_sell) = {
+            let open = sqlx::query!(
+                r#"
+                SELECT
+                    COUNT(*) AS "orders!",
+                    COALESCE(SUM(quantity - filled_quantity) FILTER (WHERE side = 'buy'), 0) AS "buy!",
+                    COALESCE(SUM(quantity - filled_quantity) FILTER (WHERE side = 'sell'), 0) AS "sell!"
+                FROM orders
+                WHERE user_id = $1 AND symbol = $2 AND status IN ('new', 'open', 'partiallyfilled')
+                "#,
+                user_id,
+                symbol
+            )
+            .fetch_one(self.pools.primary())
+            .await?;
+
+            (open.orders as usize, open.buy, open.sell)
+        };
+
+        #[cfg(not(feature = "database"))]
+        let (open_orders, open_buy, open_sell) = {
+            let orders = self.orders.read().await;
+            let open: Vec<&Order> = orders.values()
+                .filter(|o| o.user_id == user_id && o.symbol == symbol && o.status.is_open())
+                .collect();
+            let unfilled = |buy: bool| open.iter()
+                .filter(|o| matches!(o.side, OrderSide::Buy) == buy)
+                .map(|o| o.quantity - o.filled_quantity)
+                .sum();
+            (open.len(), unfilled(true), unfilled(false))
+        };
+
+        let position = match crate::models::split_symbol(symbol) {
+            Some((base, _)) => {
+                let balance = self.balances.get_balance(user_id, base).await;
+                balance.available + balance.locked
+            }
+            None => rust_decimal::Decimal::ZERO,
+        };
+
+        Ok(Exposure { open_orders, position, open_buy, open_sell })
+    }
+
+    /// Rejects the order if the user already holds the maximum number of open
+    /// orders, either overall or on the requested symbol.
+    async fn check_open_order_limits(&self, user_id: Uuid, symbol: &str) -> Result<(), AppError> {
+        #[cfg(feature = "database")]
+        let (total_open, symbol_open) = {
+            let counts = sqlx::query!(
+                r#"
+                SELECT
+                    COUNT(*) AS "total!",
+                    COUNT(*) FILTER (WHERE symbol = $2) AS "symbol!"
+                FROM orders
+                WHERE user_id = $1 AND status IN ('new', 'open', 'partiallyfilled')
+                "#,
+                user_id,
+                symbol
+            )
+            .fetch_one(self.pools.primary())
+            .await?;
+
+            (counts.total as usize, counts.symbol as usize)
+        };
+
+        #[cfg(not(feature = "database"))]
+        let (total_open, symbol_open) = {
+            let orders = self.orders.read().await;
+            let open: Vec<&Order> = orders.values()
+                .filter(|o| o.user_id == user_id && o.status.is_open())
+                .collect();
+            let symbol_open = open.iter().filter(|o| o.symbol == symbol).count();
+            (open.len(), symbol_open)
+        };
+
+        if total_open >= self.trading.max_open_orders_per_user {
+            return Err(AppError::Validation(format!(
+                "Maximum of {} open orders per user reached",
+                self.trading.max_open_orders_per_user
+            )));
+        }
+
+        if symbol_open >= self.trading.max_open_orders_per_symbol {
+            return Err(AppError::Validation(format!(
+                "Maximum of {} open orders per symbol reached for {}",
+                self.trading.max_open_orders_per_symbol, symbol
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn validate_order(&self, request: &CreateOrderRequest) -> Result<(), AppError> {
+        // Check if user has sufficient balance
+        // TODO: Implement balance checking logic
         
+        self.markets.ensure_listed(&request.symbol).await?;
+
+        // Check if price is within acceptable range
+        if let Some(price) = request.price {
+            self.markets.check_price(&request.symbol, price).await?;
+        }
+
+        if let Some(trigger_symbol) = &request.trigger_symbol {
+            if self.markets.get_market(trigger_symbol).await.is_none() {
+                return Err(AppError::Validation(format!("Unknown trigger symbol {}", trigger_symbol)));
+            }
+        }
+
         Ok(())
     }
 }
@@ -252,9 +2040,1353 @@ impl From<Order> for OrderResponse {
             quantity: order.quantity,
             price: order.price,
             order_type: order.order_type,
+            all_or_none: order.all_or_none,
+            stop_price: order.stop_price,
+            trigger_symbol: order.trigger_symbol,
+            peg_offset: order.peg_offset,
+            protection_price: order.protection_price,
+            oco_group_id: order.oco_group_id,
+            client_order_id: order.client_order_id,
+            metadata: order.metadata,
             status: order.status,
             filled_quantity: order.filled_quantity,
+            reject_reason: order.reject_reason,
+            acceptance_seq: order.acceptance_seq,
             created_at: order.created_at,
         }
     }
-} 
\ No newline at end of file
+}
+
+impl From<crate::models::Trade> for crate::models::TradeResponse {
+    fn from(trade: crate::models::Trade) -> Self {
+        let price_improvement = trade.price_improvement();
+        Self {
+            id: trade.id,
+            seq: trade.seq,
+            symbol: trade.symbol,
+            quantity: trade.quantity,
+            price: trade.price,
+            taker_limit_price: trade.taker_limit_price,
+            price_improvement,
+            executed_at: trade.executed_at,
+            metadata: None,
+        }
+    }
+} 
+
+/// Assembles an `OrderService` for tests over fresh in-memory services, any
+/// of which can be swapped for one the test keeps a handle on.
+#[cfg(all(test, not(feature = "database")))]
+pub struct OrderServiceBuilder {
+    order_book: OrderBookService,
+    markets: MarketService,
+    fees: FeeService,
+    balances: BalanceService,
+    risk: RiskService,
+    trading: TradingConfig,
+}
+
+#[cfg(all(test, not(feature = "database")))]
+impl Default for OrderServiceBuilder {
+    fn default() -> Self {
+        Self {
+            order_book: OrderBookService::new(),
+            markets: MarketService::new(crate::config::default_markets()),
+            fees: FeeService::new(crate::config::FeeConfig::default()),
+            balances: BalanceService::new(crate::config::AccountConfig::default()),
+            risk: RiskService::new(Vec::new()),
+            trading: TradingConfig {
+                max_open_orders_per_user: 10,
+                max_open_orders_per_symbol: 10,
+                persist_rejected_orders: false,
+            },
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "database")))]
+impl OrderServiceBuilder {
+    pub fn order_book(self, order_book: OrderBookService) -> Self {
+        Self { order_book, ..self }
+    }
+
+    pub fn markets(self, markets: Vec<crate::config::MarketConfig>) -> Self {
+        Self { markets: MarketService::new(markets), ..self }
+    }
+
+    pub fn fees(self, fees: FeeService) -> Self {
+        Self { fees, ..self }
+    }
+
+    pub fn balances(self, balances: BalanceService) -> Self {
+        Self { balances, ..self }
+    }
+
+    pub fn risk(self, limits: Vec<crate::config::RiskLimit>) -> Self {
+        Self { risk: RiskService::new(limits), ..self }
+    }
+
+    pub fn trading(self, trading: TradingConfig) -> Self {
+        Self { trading, ..self }
+    }
+
+    pub fn build(self) -> OrderService {
+        OrderService::new(self.order_book, self.markets, MarketDataService::new(), self.fees, self.balances, self.risk, self.trading)
+    }
+}
+
+#[cfg(all(test, not(feature = "database")))]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+
+    fn limit_order(symbol: &str) -> CreateOrderRequest {
+        CreateOrderRequest::limit(symbol, OrderSide::Buy, Decimal::new(1, 0), Decimal::new(100, 0))
+    }
+
+    /// An order placed straight on the book, bypassing the service.
+    fn book_order(symbol: &str, side: OrderSide, price: i64) -> Order {
+        Order {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            symbol: symbol.to_string(),
+            side,
+            quantity: Decimal::new(1, 0),
+            price: Some(Decimal::new(price, 0)),
+            order_type: OrderType::Limit,
+            all_or_none: false,
+            stop_price: None,
+            trigger_symbol: None,
+            peg_offset: None,
+            protection_price: None,
+            oco_group_id: None,
+            client_order_id: None,
+            metadata: None,
+            status: OrderStatus::New,
+            filled_quantity: Decimal::ZERO,
+            reject_reason: None,
+            acceptance_seq: 0,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    /// Trades one unit of `symbol` at `price` between two fresh orders.
+    async fn cross(book: &OrderBookService, symbol: &str, price: i64) -> Trade {
+        book.add_order(&book_order(symbol, OrderSide::Sell, price)).await.unwrap();
+        book.add_order(&book_order(symbol, OrderSide::Buy, price)).await.unwrap().remove(0)
+    }
+
+    fn service(max_per_user: usize, max_per_symbol: usize) -> OrderService {
+        service_with(TradingConfig {
+            max_open_orders_per_user: max_per_user,
+            max_open_orders_per_symbol: max_per_symbol,
+            persist_rejected_orders: false,
+        })
+    }
+
+    fn service_with(trading: TradingConfig) -> OrderService {
+        OrderServiceBuilder::default().trading(trading).build()
+    }
+
+    #[tokio::test]
+    async fn test_cancel_orders_reports_each_id() {
+        let service = service(10, 10);
+        let user_id = Uuid::new_v4();
+        let open = service.create_order(user_id, limit_order("BTC/USD")).await.unwrap();
+        let cancelled = service.create_order(user_id, limit_order("BTC/USD")).await.unwrap();
+        service.cancel_order(cancelled.id).await.unwrap();
+        let someone_elses = service.create_order(Uuid::new_v4(), limit_order("BTC/USD")).await.unwrap();
+        let unknown = Uuid::new_v4();
+
+        let results = service.cancel_orders(user_id, &[open.id, cancelled.id, someone_elses.id, unknown]).await;
+        let outcomes: Vec<(Uuid, u16)> = results.iter().map(|result| (result.order_id, result.status)).collect();
+        assert_eq!(outcomes, vec![(open.id, 200), (cancelled.id, 410), (someone_elses.id, 404), (unknown, 404)]);
+        assert!(matches!(results[0].order.as_ref().map(|order| &order.status), Some(OrderStatus::Cancelled)));
+        assert!(results[1..].iter().all(|result| result.order.is_none() && result.error.is_some()));
+
+        // The other user's order is untouched
+        assert!(matches!(service.get_order(someone_elses.id).await.unwrap().status, OrderStatus::Open));
+    }
+
+    #[tokio::test]
+    async fn test_repeated_client_order_id_returns_the_first_order() {
+        let service = service(10, 10);
+        let user_id = Uuid::new_v4();
+        let request = || CreateOrderRequest {
+            client_order_id: Some("my-order-1".to_string()),
+            ..limit_order("BTC/USD")
+        };
+
+        let first = service.create_order(user_id, request()).await.unwrap();
+        let retried = service.create_order(user_id, request()).await.unwrap();
+        assert_eq!(retried.id, first.id);
+        assert_eq!(retried.client_order_id.as_deref(), Some("my-order-1"));
+        assert_eq!(service.open_order_count(user_id).await.unwrap(), 1);
+        let book = service.order_book.get_order_book("BTC/USD").await;
+        assert_eq!(book.bids.iter().map(|level| level.order_count).sum::<i32>(), 1);
+
+        // Ids are only unique per user
+        let other = service.create_order(Uuid::new_v4(), request()).await.unwrap();
+        assert_ne!(other.id, first.id);
+    }
+
+    #[tokio::test]
+    async fn test_order_by_client_id_is_scoped_to_its_user() {
+        let service = service(10, 10);
+        let user_id = Uuid::new_v4();
+        let placed = service.create_order(user_id, CreateOrderRequest {
+            client_order_id: Some("algo-42".to_string()),
+            ..limit_order("BTC/USD")
+        }).await.unwrap();
+
+        assert_eq!(service.get_order_by_client_id(user_id, "algo-42").await.unwrap().id, placed.id);
+        assert!(matches!(service.get_order_by_client_id(user_id, "algo-43").await, Err(AppError::NotFound(_))));
+        assert!(matches!(service.get_order_by_client_id(Uuid::new_v4(), "algo-42").await, Err(AppError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_orders_are_refused_until_warmup_completes() {
+        let service = service(10, 10);
+        let user_id = Uuid::new_v4();
+        service.begin_warmup().await;
+
+        let result = service.create_order(user_id, limit_order("BTC/USD")).await;
+        assert!(matches!(result, Err(AppError::Unavailable(_))));
+        assert_eq!(result.unwrap_err().error_response().status(), actix_web::http::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(service.open_order_count(user_id).await.unwrap(), 0);
+
+        service.recover().await.unwrap();
+        assert_eq!(service.engine_state().await, EngineState::Ready);
+        assert!(service.create_order(user_id, limit_order("BTC/USD")).await.is_ok());
+    }
+
+    fn status_query(status: &str) -> OrderQuery {
+        OrderQuery { symbol: None, status: Some(status.to_string()), limit: None, offset: None }
+    }
+
+    #[tokio::test]
+    async fn test_get_orders_filters_on_several_statuses() {
+        let service = service_with(TradingConfig {
+            max_open_orders_per_user: 10,
+            max_open_orders_per_symbol: 10,
+            persist_rejected_orders: true,
+        });
+        let user_id = Uuid::new_v4();
+        let open = service.create_order(user_id, limit_order("BTC/USD")).await.unwrap();
+        let cancelled = service.create_order(user_id, limit_order("BTC/USD")).await.unwrap();
+        service.cancel_order(cancelled.id).await.unwrap();
+        let rejected = service.create_order(user_id, CreateOrderRequest {
+            trigger_symbol: Some("DOGE/USD".to_string()),
+            peg_offset: None,
+            protection_price: None,
+            ..stop_order("BTC/USD", 120)
+        }).await.unwrap();
+        assert!(matches!(rejected.status, OrderStatus::Rejected));
+
+        let ids = |orders: Vec<OrderResponse>| {
+            let mut ids: Vec<Uuid> = orders.into_iter().map(|order| order.id).collect();
+            ids.sort();
+            ids
+        };
+        let mut expected = vec![cancelled.id, rejected.id];
+        expected.sort();
+        assert_eq!(ids(service.get_orders(user_id, &status_query("cancelled,rejected")).await.unwrap()), expected);
+        assert_eq!(ids(service.get_orders(user_id, &status_query("open, partially_filled")).await.unwrap()), vec![open.id]);
+        assert!(service.get_orders(user_id, &status_query("filled,expired")).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_orders_rejects_unknown_status() {
+        let service = service(10, 10);
+        let result = service.get_orders(Uuid::new_v4(), &status_query("open,bogus")).await;
+        assert!(matches!(result, Err(AppError::Validation(message)) if message.contains("bogus")));
+    }
+
+    #[tokio::test]
+    async fn test_order_response_uses_market_decimal_places() {
+        let mut service = service(10, 10);
+        service.markets = MarketService::new(vec![crate::config::MarketConfig {
+            price_scale: Some(2),
+            quantity_scale: Some(4),
+            ..crate::config::MarketConfig::new("BTC/USD")
+        }]);
+        let order = service.create_order(Uuid::new_v4(), CreateOrderRequest {
+            price: Some(Decimal::new(15, 1)),
+            ..limit_order("BTC/USD")
+        }).await.unwrap();
+
+        let json = serde_json::to_value(&order).unwrap();
+        assert_eq!(json["price"], "1.50");
+        assert_eq!(json["quantity"], "1.0000");
+        assert_eq!(json["filled_quantity"], "0.0000");
+
+        // Stored values keep their own precision
+        let stored = service.orders.read().await[&order.id].price.unwrap();
+        assert_eq!(stored.to_string(), "1.5");
+    }
+
+    #[tokio::test]
+    async fn test_order_expires_after_market_default_ttl() {
+        let service = OrderServiceBuilder::default()
+            .markets(vec![
+                crate::config::MarketConfig {
+                    default_order_ttl_secs: Some(60),
+                    ..crate::config::MarketConfig::new("BTC/USD")
+                },
+                crate::config::MarketConfig::new("ETH/USD"),
+            ])
+            .build();
+        let user_id = Uuid::new_v4();
+        let order = service.create_order(user_id, limit_order("BTC/USD")).await.unwrap();
+        let no_ttl = service.create_order(user_id, limit_order("ETH/USD")).await.unwrap();
+
+        assert!(service.expire_orders(order.created_at + chrono::Duration::seconds(59)).await.unwrap().is_empty());
+
+        let much_later = order.created_at + chrono::Duration::days(1);
+        let expired = service.expire_orders(much_later).await.unwrap();
+        assert_eq!(expired.iter().map(|order| order.id).collect::<Vec<_>>(), vec![order.id]);
+        assert!(matches!(service.get_order(order.id).await.unwrap().status, OrderStatus::Expired));
+        assert!(matches!(service.get_order(no_ttl.id).await.unwrap().status, OrderStatus::Open));
+
+        // Expired orders are terminal and not swept again
+        assert!(service.expire_orders(much_later).await.unwrap().is_empty());
+        assert!(matches!(service.cancel_order(order.id).await, Err(AppError::Gone(_))));
+    }
+
+    #[tokio::test]
+    async fn test_each_order_step_is_reported_once() {
+        let service = OrderServiceBuilder::default()
+            .markets(vec![crate::config::MarketConfig {
+                default_order_ttl_secs: Some(60),
+                ..crate::config::MarketConfig::new("BTC/USD")
+            }])
+            .build();
+        let mut reports = service.subscribe_executions();
+        let (maker, taker) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let resting = service.create_order(maker, CreateOrderRequest {
+            side: OrderSide::Sell,
+            quantity: Decimal::new(3, 0),
+            ..limit_order("BTC/USD")
+        }).await.unwrap();
+        let taker_order = service.create_order(taker, CreateOrderRequest {
+            quantity: Decimal::new(2, 0),
+            ..limit_order("BTC/USD")
+        }).await.unwrap();
+        // Nothing to sell into, so the book turns it away
+        assert!(service.create_order(taker, CreateOrderRequest {
+            side: OrderSide::Sell,
+            order_type: OrderType::Market,
+            price: None,
+            ..limit_order("BTC/USD")
+        }).await.is_err());
+        service.cancel_order(resting.id).await.unwrap();
+        let stale = service.create_order(taker, limit_order("BTC/USD")).await.unwrap();
+        service.expire_orders(stale.created_at + chrono::Duration::days(1)).await.unwrap();
+
+        let mut received = Vec::new();
+        while let Ok(report) = reports.try_recv() {
+            received.push(report);
+        }
+        let rejected = received.iter()
+            .find(|report| report.exec_type == ExecutionType::Rejected)
+            .map(|report| report.order_id)
+            .unwrap();
+        assert_eq!(
+            received.iter().map(|report| (report.order_id, report.exec_type)).collect::<Vec<_>>(),
+            vec![
+                (resting.id, ExecutionType::Accepted),
+                (taker_order.id, ExecutionType::Accepted),
+                (resting.id, ExecutionType::PartiallyFilled),
+                (taker_order.id, ExecutionType::Filled),
+                (rejected, ExecutionType::Rejected),
+                (resting.id, ExecutionType::Cancelled),
+                (stale.id, ExecutionType::Accepted),
+                (stale.id, ExecutionType::Expired),
+            ]
+        );
+
+        // Both sides of the trade report the same fill, with their own progress
+        let (maker_fill, taker_fill) = (&received[2], &received[3]);
+        assert_eq!((maker_fill.filled_quantity, maker_fill.leaves_quantity), (Decimal::new(2, 0), Decimal::ONE));
+        assert_eq!((taker_fill.filled_quantity, taker_fill.leaves_quantity), (Decimal::new(2, 0), Decimal::ZERO));
+        let (maker_trade, taker_trade) = (maker_fill.last_fill.as_ref().unwrap(), taker_fill.last_fill.as_ref().unwrap());
+        assert!(maker_trade.maker && !taker_trade.maker);
+        assert_eq!(maker_trade.trade_id, taker_trade.trade_id);
+        assert!(received[4].reject_reason.as_deref().unwrap().contains("No liquidity"));
+        assert_eq!((received[5].filled_quantity, received[5].leaves_quantity), (Decimal::new(2, 0), Decimal::ZERO));
+    }
+
+    #[tokio::test]
+    async fn test_trade_on_trigger_symbol_activates_stop() {
+        let book = OrderBookService::new();
+        let service = OrderServiceBuilder::default().order_book(book.clone()).build();
+        book.add_order(&book_order("BTC/USD", OrderSide::Sell, 100)).await.unwrap();
+
+        // A buy stop on BTC/USD keyed to ETH/USD trading at 2000 or more
+        let stop = service.create_order(Uuid::new_v4(), CreateOrderRequest {
+            price: None,
+            order_type: OrderType::Stop,
+            stop_price: Some(Decimal::new(2_000, 0)),
+            trigger_symbol: Some("ETH/USD".to_string()),
+            peg_offset: None,
+            protection_price: None,
+            ..limit_order("BTC/USD")
+        }).await.unwrap();
+
+        let trade = cross(&book, "ETH/USD", 1_999).await;
+        assert!(service.handle_trade(&trade).await.unwrap().is_empty());
+        assert!(matches!(service.get_order(stop.id).await.unwrap().status, OrderStatus::New));
+
+        let trade = cross(&book, "ETH/USD", 2_000).await;
+        let activated = service.handle_trade(&trade).await.unwrap();
+        assert_eq!(activated.iter().map(|order| order.id).collect::<Vec<_>>(), vec![stop.id]);
+        assert!(matches!(activated[0].status, OrderStatus::Filled));
+        assert_eq!(book.get_order_book("BTC/USD").await.asks.len(), 0);
+    }
+
+    fn stop_order(symbol: &str, stop_price: i64) -> CreateOrderRequest {
+        CreateOrderRequest {
+            side: OrderSide::Sell,
+            price: None,
+            order_type: OrderType::Stop,
+            stop_price: Some(Decimal::new(stop_price, 0)),
+            ..limit_order(symbol)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delisting_cancels_open_orders_and_rejects_new_ones() {
+        let service = service(10, 10);
+        let user_id = Uuid::new_v4();
+        let bid = service.create_order(user_id, limit_order("BTC/USD")).await.unwrap();
+        let ask = service.create_order(Uuid::new_v4(), CreateOrderRequest {
+            side: OrderSide::Sell,
+            quantity: Decimal::new(2, 0),
+            price: Some(Decimal::new(105, 0)),
+            ..limit_order("BTC/USD")
+        }).await.unwrap();
+        let stop = service.create_order(user_id, stop_order("BTC/USD", 90)).await.unwrap();
+        let other_market = service.create_order(user_id, limit_order("ETH/USD")).await.unwrap();
+
+        let delisting = service.delist_market("BTC/USD").await.unwrap();
+        let mut cancelled = delisting.cancelled_orders.clone();
+        cancelled.sort();
+        let mut expected = vec![bid.id, ask.id, stop.id];
+        expected.sort();
+        assert_eq!(cancelled, expected);
+        for order_id in expected {
+            assert!(matches!(service.get_order(order_id).await.unwrap().status, OrderStatus::Cancelled));
+        }
+        let book = service.order_book.freeze("BTC/USD").await;
+        assert!(book.bids.is_empty() && book.asks.is_empty());
+
+        // The market takes no more orders, and can't be delisted twice
+        let result = service.create_order(user_id, limit_order("BTC/USD")).await;
+        assert!(matches!(result, Err(AppError::Validation(message)) if message.contains("delisted")));
+        assert!(matches!(service.delist_market("BTC/USD").await, Err(AppError::Gone(_))));
+        assert!(matches!(service.delist_market("DOGE/USD").await, Err(AppError::NotFound(_))));
+
+        // Other markets carry on
+        assert!(matches!(service.get_order(other_market.id).await.unwrap().status, OrderStatus::Open));
+        assert!(service.create_order(user_id, limit_order("ETH/USD")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_oco_legs_firing_together_leave_one_standing() {
+        for _ in 0..20 {
+            let service = service(10, 10);
+            let take_profit = CreateOrderRequest {
+                side: OrderSide::Sell,
+                price: Some(Decimal::new(110, 0)),
+                ..limit_order("BTC/USD")
+            };
+            let legs = service.create_oco(Uuid::new_v4(), vec![take_profit, stop_order("BTC/USD", 90)]).await.unwrap();
+            let (take_profit, stop_loss) = if legs[0].stop_price.is_none() { (&legs[0], &legs[1]) } else { (&legs[1], &legs[0]) };
+            assert_eq!(take_profit.oco_group_id, stop_loss.oco_group_id);
+
+            // The take-profit fills while another trade drops through the stop
+            let trade = |price: i64, order_id: Uuid| Trade {
+                id: Uuid::new_v4(),
+                seq: 1,
+                order_id,
+                taker_order_id: Uuid::new_v4(),
+                maker_user_id: Uuid::new_v4(),
+                taker_user_id: Uuid::new_v4(),
+                taker_side: OrderSide::Buy,
+                symbol: "BTC/USD".to_string(),
+                quantity: Decimal::ONE,
+                price: Decimal::new(price, 0),
+                taker_limit_price: None,
+                executed_at: chrono::Utc::now(),
+            };
+            let (fill, drop) = (trade(110, take_profit.id), trade(85, Uuid::new_v4()));
+            let (filled, dropped) = tokio::join!(service.handle_trade(&fill), service.handle_trade(&drop));
+            filled.unwrap();
+            dropped.unwrap();
+
+            let mut cancelled = 0;
+            for leg in [take_profit.id, stop_loss.id] {
+                if matches!(service.get_order(leg).await.unwrap().status, OrderStatus::Cancelled) {
+                    cancelled += 1;
+                }
+            }
+            assert_eq!(cancelled, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_oco_needs_two_orders_with_at_most_one_limit() {
+        let service = service(10, 10);
+        let user_id = Uuid::new_v4();
+        let two_limits = vec![limit_order("BTC/USD"), limit_order("BTC/USD")];
+        assert!(matches!(service.create_oco(user_id, two_limits).await, Err(AppError::Validation(_))));
+        let one_leg = vec![stop_order("BTC/USD", 90)];
+        assert!(matches!(service.create_oco(user_id, one_leg).await, Err(AppError::Validation(_))));
+        assert_eq!(service.open_order_count(user_id).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_create_order_matches_resting_order() {
+        let service = service(10, 10);
+        let (maker, taker) = (Uuid::new_v4(), Uuid::new_v4());
+        let resting = service.create_order(maker, CreateOrderRequest {
+            side: OrderSide::Sell,
+            quantity: Decimal::new(3, 0),
+            ..limit_order("BTC/USD")
+        }).await.unwrap();
+        assert!(matches!(resting.status, OrderStatus::Open));
+
+        let taker_order = service.create_order(taker, CreateOrderRequest {
+            quantity: Decimal::new(2, 0),
+            ..limit_order("BTC/USD")
+        }).await.unwrap();
+        assert!(matches!(taker_order.status, OrderStatus::Filled));
+        assert_eq!(taker_order.filled_quantity, Decimal::new(2, 0));
+        assert!(taker_order.acceptance_seq > resting.acceptance_seq);
+
+        // The trade settled, and what's left of the sell still rests
+        assert_eq!(service.balances.get_balance(taker, "BTC").await.available, Decimal::new(2, 0));
+        let book = service.order_book.get_order_book("BTC/USD").await;
+        assert_eq!(book.asks.iter().map(|level| level.quantity).collect::<Vec<_>>(), vec![Decimal::ONE]);
+
+        // Cancelling takes the rest off the book
+        service.cancel_order(resting.id).await.unwrap();
+        assert!(service.order_book.get_order_book("BTC/USD").await.asks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_preview_matches_placing_the_same_order() {
+        let service = service(10, 10);
+        let maker = Uuid::new_v4();
+        for (price, quantity) in [(100, 1), (101, 2), (103, 5)] {
+            service.create_order(maker, CreateOrderRequest {
+                side: OrderSide::Sell,
+                quantity: Decimal::new(quantity, 0),
+                price: Some(Decimal::new(price, 0)),
+                ..limit_order("BTC/USD")
+            }).await.unwrap();
+        }
+        let buy = || CreateOrderRequest {
+            quantity: Decimal::new(4, 0),
+            price: Some(Decimal::new(102, 0)),
+            ..limit_order("BTC/USD")
+        };
+        let taker = Uuid::new_v4();
+
+        let preview = service.preview_order(taker, buy()).await.unwrap();
+        assert_eq!(preview.filled_quantity, Decimal::new(3, 0));
+        assert_eq!(preview.remaining_quantity, Decimal::ONE);
+        assert!(preview.would_rest);
+        // The dry run left the book and the taker's orders alone
+        let book = service.order_book.get_order_book("BTC/USD").await;
+        assert_eq!(book.asks.iter().map(|level| level.quantity).sum::<Decimal>(), Decimal::new(8, 0));
+        assert_eq!(service.open_order_count(taker).await.unwrap(), 0);
+
+        let placed = service.create_order(taker, buy()).await.unwrap();
+        let fills = service.get_order_fills(placed.id).await.unwrap();
+        assert_eq!(preview.fills, fills.fills);
+        assert_eq!(preview.average_price, fills.average_price);
+        assert_eq!(preview.filled_quantity, placed.filled_quantity);
+        assert!(matches!(placed.status, OrderStatus::PartiallyFilled));
+        assert!(service.order_book.get_order_book("BTC/USD").await.bids.iter().any(|level| level.price == Decimal::new(102, 0)));
+
+        // A market order with nothing to trade against is refused, as placing it would be
+        let sell = CreateOrderRequest {
+            side: OrderSide::Sell,
+            order_type: OrderType::Market,
+            price: None,
+            ..limit_order("ETH/USD")
+        };
+        assert!(matches!(service.preview_order(taker, sell).await, Err(AppError::OrderBook(_))));
+    }
+
+    #[tokio::test]
+    async fn test_order_resting_unmatched_is_open() {
+        let service = service(10, 10);
+        let user_id = Uuid::new_v4();
+
+        let resting = service.create_order(user_id, limit_order("BTC/USD")).await.unwrap();
+        assert!(matches!(resting.status, OrderStatus::Open));
+        assert!(matches!(service.get_order(resting.id).await.unwrap().status, OrderStatus::Open));
+
+        // A stop waits off the book, so it stays new until triggered
+        let stop = service.create_order(user_id, stop_order("BTC/USD", 90)).await.unwrap();
+        assert!(matches!(stop.status, OrderStatus::New));
+
+        // An order that trades on arrival and rests the rest is partially filled, not open
+        let partial = service.create_order(Uuid::new_v4(), CreateOrderRequest {
+            side: OrderSide::Sell,
+            quantity: Decimal::new(2, 0),
+            ..limit_order("BTC/USD")
+        }).await.unwrap();
+        assert!(matches!(partial.status, OrderStatus::PartiallyFilled));
+    }
+
+    #[tokio::test]
+    async fn test_resting_maker_status_follows_its_fills() {
+        let service = service(10, 10);
+        let (maker, taker) = (Uuid::new_v4(), Uuid::new_v4());
+        let resting = service.create_order(maker, CreateOrderRequest {
+            side: OrderSide::Sell,
+            quantity: Decimal::new(3, 0),
+            ..limit_order("BTC/USD")
+        }).await.unwrap();
+        let buy = |quantity| CreateOrderRequest {
+            quantity: Decimal::new(quantity, 0),
+            ..limit_order("BTC/USD")
+        };
+
+        service.create_order(taker, buy(2)).await.unwrap();
+        let stored = service.get_order(resting.id).await.unwrap();
+        assert!(matches!(stored.status, OrderStatus::PartiallyFilled));
+        assert_eq!(stored.filled_quantity, Decimal::new(2, 0));
+
+        service.create_order(taker, buy(1)).await.unwrap();
+        let stored = service.get_order(resting.id).await.unwrap();
+        assert!(matches!(stored.status, OrderStatus::Filled));
+        assert_eq!(stored.filled_quantity, Decimal::new(3, 0));
+        // No longer counted as open, and gone for good rather than cancellable
+        assert_eq!(service.open_order_count(maker).await.unwrap(), 0);
+        assert!(matches!(service.cancel_order(resting.id).await, Err(AppError::Gone(_))));
+    }
+
+    #[tokio::test]
+    async fn test_market_order_stops_at_protection_price() {
+        let service = service(10, 10);
+        let maker = Uuid::new_v4();
+        for price in [100, 101, 105] {
+            service.create_order(maker, CreateOrderRequest {
+                side: OrderSide::Sell,
+                price: Some(Decimal::new(price, 0)),
+                ..limit_order("BTC/USD")
+            }).await.unwrap();
+        }
+        let market_buy = |protection_price| CreateOrderRequest {
+            quantity: Decimal::new(3, 0),
+            order_type: OrderType::Market,
+            price: None,
+            protection_price: Some(Decimal::new(protection_price, 0)),
+            ..limit_order("BTC/USD")
+        };
+        let taker = Uuid::new_v4();
+
+        // The thin book would fill the last unit at 105; the rest is cancelled instead
+        let placed = service.create_order(taker, market_buy(101)).await.unwrap();
+        assert!(matches!(placed.status, OrderStatus::Cancelled));
+        assert_eq!(placed.filled_quantity, Decimal::new(2, 0));
+        let fills = service.get_order_fills(placed.id).await.unwrap();
+        assert_eq!(fills.fills.iter().map(|fill| fill.price).collect::<Vec<_>>(), vec![Decimal::new(100, 0), Decimal::new(101, 0)]);
+        let book = service.order_book.get_order_book("BTC/USD").await;
+        assert_eq!(book.asks.iter().map(|level| level.price).collect::<Vec<_>>(), vec![Decimal::new(105, 0)]);
+        assert_eq!(service.open_order_count(taker).await.unwrap(), 0);
+
+        // With nothing left within the bound the order is refused
+        assert!(matches!(service.create_order(taker, market_buy(104)).await, Err(AppError::OrderBook(_))));
+        let limit_with_protection = CreateOrderRequest { protection_price: Some(Decimal::new(104, 0)), ..limit_order("BTC/USD") };
+        assert!(limit_with_protection.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_spread_market_matches_at_negative_prices() {
+        let spread = crate::config::MarketConfig {
+            allow_negative_price: true,
+            ..crate::config::MarketConfig::new("SPREAD/USD")
+        };
+        let service = OrderServiceBuilder::default().markets(vec![spread]).build();
+        let order = |side: OrderSide, quantity: i64, price: i64| CreateOrderRequest {
+            side,
+            quantity: Decimal::new(quantity, 0),
+            price: Some(Decimal::new(price, 1)),
+            ..limit_order("SPREAD/USD")
+        };
+        assert!(service.allows_negative_price("SPREAD/USD").await);
+        assert!(!service.allows_negative_price("BTC/USD").await);
+
+        // Asks at -2.0 and -1.0: a buy up to -1.0 takes the cheaper -2.0 first
+        let maker = Uuid::new_v4();
+        service.create_order(maker, order(OrderSide::Sell, 1, -10)).await.unwrap();
+        service.create_order(maker, order(OrderSide::Sell, 1, -20)).await.unwrap();
+        let buy = service.create_order(Uuid::new_v4(), order(OrderSide::Buy, 2, -10)).await.unwrap();
+        let fills = service.get_order_fills(buy.id).await.unwrap();
+        assert_eq!(
+            fills.fills.iter().map(|fill| fill.price).collect::<Vec<_>>(),
+            vec![Decimal::new(-20, 1), Decimal::new(-10, 1)]
+        );
+        assert_eq!(fills.average_price, Some(Decimal::new(-15, 1)));
+
+        // Bids at -5.0 and -3.0, and zero: a sell down to -4.0 hits zero, then -3.0, and rests
+        service.create_order(maker, order(OrderSide::Buy, 1, -50)).await.unwrap();
+        service.create_order(maker, order(OrderSide::Buy, 1, -30)).await.unwrap();
+        service.create_order(maker, order(OrderSide::Buy, 1, 0)).await.unwrap();
+        let sell = service.create_order(Uuid::new_v4(), order(OrderSide::Sell, 3, -40)).await.unwrap();
+        assert_eq!(sell.filled_quantity, Decimal::new(2, 0));
+        let fills = service.get_order_fills(sell.id).await.unwrap();
+        assert_eq!(
+            fills.fills.iter().map(|fill| fill.price).collect::<Vec<_>>(),
+            vec![Decimal::new(-30, 1), Decimal::ZERO]
+        );
+
+        let ticker = service.order_book.get_ticker("SPREAD/USD").await;
+        assert_eq!(ticker.best_bid, Some(Decimal::new(-50, 1)));
+        assert_eq!(ticker.best_ask, Some(Decimal::new(-40, 1)));
+        assert_eq!(ticker.spread_bps, None);
+    }
+
+    fn sell(price: i64) -> CreateOrderRequest {
+        CreateOrderRequest {
+            side: OrderSide::Sell,
+            price: Some(Decimal::new(price, 0)),
+            ..limit_order("BTC/USD")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_open_orders_lock_what_they_hold() {
+        let service = service(10, 10);
+        let (maker, taker) = (Uuid::new_v4(), Uuid::new_v4());
+        let locked = |user_id, asset| {
+            let service = service.clone();
+            async move { service.balances.get_balance(user_id, asset).await.locked }
+        };
+        let assert_reconciled = || async {
+            let reconciliation = service.reconcile_balances().await.unwrap();
+            assert!(reconciliation.discrepancies.is_empty(), "{:?}", reconciliation.discrepancies);
+        };
+
+        let resting = service.create_order(maker, CreateOrderRequest {
+            quantity: Decimal::new(3, 0),
+            ..limit_order("BTC/USD")
+        }).await.unwrap();
+        assert_eq!(locked(maker, "USD").await, Decimal::new(300, 0));
+        assert_reconciled().await;
+
+        // The filled part is released and the taker, filled at once, holds nothing
+        service.create_order(taker, CreateOrderRequest { side: OrderSide::Sell, ..limit_order("BTC/USD") }).await.unwrap();
+        assert_eq!(locked(maker, "USD").await, Decimal::new(200, 0));
+        assert_eq!(locked(taker, "BTC").await, Decimal::ZERO);
+        assert_reconciled().await;
+
+        service.patch_order(maker, resting.id, &patch(Some(90), Some(2))).await.unwrap();
+        assert_eq!(locked(maker, "USD").await, Decimal::new(90, 0));
+        assert_reconciled().await;
+
+        service.cancel_order(resting.id).await.unwrap();
+        assert_eq!(locked(maker, "USD").await, Decimal::ZERO);
+        // All that's gone is the coin bought and its maker fee
+        assert_eq!(service.balances.get_balance(maker, "USD").await.available, Decimal::new(-1001, 1));
+        assert_reconciled().await;
+    }
+
+    fn patch(price: Option<i64>, quantity: Option<i64>) -> PatchOrderRequest {
+        PatchOrderRequest {
+            price: price.map(|price| Decimal::new(price, 0)),
+            quantity: quantity.map(|quantity| Decimal::new(quantity, 0)),
+            ..PatchOrderRequest::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_patching_only_price_requeues_at_new_price() {
+        let service = service(10, 10);
+        let (maker, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let moved = service.create_order(maker, sell(101)).await.unwrap();
+        let resting = service.create_order(other, sell(100)).await.unwrap();
+
+        let patched = service.patch_order(maker, moved.id, &patch(Some(100), None)).await.unwrap();
+        assert_eq!((patched.price, patched.quantity), (Some(Decimal::new(100, 0)), Decimal::ONE));
+        // Behind the order already resting at its new price
+        assert_eq!(service.queue_position(moved.id).await.unwrap().orders_ahead, 1);
+        assert_eq!(service.queue_position(resting.id).await.unwrap().orders_ahead, 0);
+
+        // A new price crossing the book trades at once
+        let bid = service.create_order(other, CreateOrderRequest {
+            price: Some(Decimal::new(98, 0)),
+            ..limit_order("BTC/USD")
+        }).await.unwrap();
+        let patched = service.patch_order(other, bid.id, &patch(Some(100), None)).await.unwrap();
+        assert!(matches!(patched.status, OrderStatus::Filled));
+
+        // Only the owner may patch, and only price and quantity
+        assert!(matches!(service.patch_order(other, moved.id, &patch(Some(102), None)).await, Err(AppError::NotFound(_))));
+        let change_side = PatchOrderRequest { side: Some(OrderSide::Buy), ..patch(Some(102), None) };
+        assert!(matches!(service.patch_order(maker, moved.id, &change_side).await, Err(AppError::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_patching_only_quantity_keeps_priority_unless_it_grows() {
+        let service = service(10, 10);
+        let (maker, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let first = service.create_order(maker, CreateOrderRequest { quantity: Decimal::new(3, 0), ..sell(100) }).await.unwrap();
+        service.create_order(other, sell(100)).await.unwrap();
+
+        let patched = service.patch_order(maker, first.id, &patch(None, Some(2))).await.unwrap();
+        assert_eq!((patched.price, patched.quantity), (Some(Decimal::new(100, 0)), Decimal::new(2, 0)));
+        assert_eq!(service.queue_position(first.id).await.unwrap().orders_ahead, 0);
+
+        let patched = service.patch_order(maker, first.id, &patch(None, Some(5))).await.unwrap();
+        assert_eq!((patched.price, patched.quantity), (Some(Decimal::new(100, 0)), Decimal::new(5, 0)));
+        assert_eq!(service.queue_position(first.id).await.unwrap().orders_ahead, 1);
+        let asks = service.order_book.get_order_book("BTC/USD").await.asks;
+        assert_eq!(asks.iter().map(|level| level.quantity).collect::<Vec<_>>(), vec![Decimal::new(6, 0)]);
+    }
+
+    fn pegged(side: OrderSide, offset: i64) -> CreateOrderRequest {
+        CreateOrderRequest {
+            side,
+            price: None,
+            order_type: OrderType::Pegged,
+            peg_offset: Some(Decimal::new(offset, 0)),
+            protection_price: None,
+            ..limit_order("BTC/USD")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pegged_order_follows_the_best_bid() {
+        let service = service(10, 10);
+        let (maker, pegger) = (Uuid::new_v4(), Uuid::new_v4());
+        // Nothing to peg to yet
+        let no_ask = service.create_order(pegger, pegged(OrderSide::Sell, 1)).await;
+        assert!(matches!(no_ask, Err(AppError::Validation(_))));
+
+        service.create_order(maker, limit_order("BTC/USD")).await.unwrap();
+        let behind = service.create_order(pegger, pegged(OrderSide::Buy, -1)).await.unwrap();
+        let ahead = service.create_order(pegger, pegged(OrderSide::Buy, 1)).await.unwrap();
+        assert_eq!((behind.price, ahead.price), (Some(Decimal::new(99, 0)), Some(Decimal::new(101, 0))));
+        // The pegs ignore each other, so the best bid being pegged moves nothing
+        assert!(service.reprice_pegged("BTC/USD").await.unwrap().is_empty());
+
+        let better = service.create_order(maker, CreateOrderRequest {
+            price: Some(Decimal::new(105, 0)),
+            ..limit_order("BTC/USD")
+        }).await.unwrap();
+        let repriced = service.reprice_pegged("BTC/USD").await.unwrap();
+        assert_eq!(repriced.len(), 2);
+        assert_eq!(service.get_order(behind.id).await.unwrap().price, Some(Decimal::new(104, 0)));
+        assert_eq!(service.get_order(ahead.id).await.unwrap().price, Some(Decimal::new(106, 0)));
+        assert!(service.reprice_pegged("BTC/USD").await.unwrap().is_empty());
+
+        // And back once the better bid leaves
+        service.cancel_order(better.id).await.unwrap();
+        service.reprice_pegged("BTC/USD").await.unwrap();
+        assert_eq!(service.get_order(behind.id).await.unwrap().price, Some(Decimal::new(99, 0)));
+        assert_eq!(service.get_order(ahead.id).await.unwrap().price, Some(Decimal::new(101, 0)));
+
+        // Its price can't be set by hand
+        let patched = service.patch_order(pegger, behind.id, &patch(Some(98), None)).await;
+        assert!(matches!(patched, Err(AppError::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_peg_watcher_reprices_as_the_ask_moves() {
+        let service = service(10, 10);
+        let shutdown = CancellationToken::new();
+        tokio::spawn(service.clone().run_peg_watcher(shutdown.clone()));
+        // Let the watcher subscribe before the book moves
+        tokio::task::yield_now().await;
+        let (maker, pegger, taker) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        service.create_order(maker, sell(100)).await.unwrap();
+        service.create_order(maker, sell(102)).await.unwrap();
+        let peg = service.create_order(pegger, pegged(OrderSide::Sell, 1)).await.unwrap();
+        assert_eq!(peg.price, Some(Decimal::new(101, 0)));
+
+        // Taking out the best ask lifts the peg to the next one
+        service.create_order(taker, limit_order("BTC/USD")).await.unwrap();
+        let repriced = async {
+            loop {
+                let price = service.get_order(peg.id).await.unwrap().price;
+                if price == Some(Decimal::new(103, 0)) {
+                    return;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(1), repriced).await.unwrap();
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_cancelling_half_filled_order_keeps_its_fill() {
+        let service = service(10, 10);
+        let (maker, taker) = (Uuid::new_v4(), Uuid::new_v4());
+        let sell = |quantity| CreateOrderRequest {
+            side: OrderSide::Sell,
+            quantity: Decimal::new(quantity, 0),
+            ..limit_order("BTC/USD")
+        };
+        let buy = |quantity| CreateOrderRequest {
+            quantity: Decimal::new(quantity, 0),
+            ..limit_order("BTC/USD")
+        };
+
+        // Half of a resting order fills, then the rest is cancelled
+        let resting = service.create_order(maker, sell(2)).await.unwrap();
+        service.create_order(taker, buy(1)).await.unwrap();
+        let cancelled = service.cancel_order(resting.id).await.unwrap();
+        assert!(matches!(cancelled.status, OrderStatus::Cancelled));
+        assert_eq!(cancelled.filled_quantity, Decimal::ONE);
+
+        // Likewise for an order that half filled on arrival and rested the rest
+        service.create_order(maker, sell(1)).await.unwrap();
+        let partial = service.create_order(taker, buy(2)).await.unwrap();
+        assert!(matches!(partial.status, OrderStatus::PartiallyFilled));
+        let cancelled = service.cancel_order(partial.id).await.unwrap();
+        assert!(matches!(cancelled.status, OrderStatus::Cancelled));
+        assert_eq!(cancelled.filled_quantity, Decimal::ONE);
+
+        for order_id in [resting.id, partial.id] {
+            let fetched = service.get_order(order_id).await.unwrap();
+            assert_eq!(fetched.filled_quantity, Decimal::ONE);
+            let trades = service.get_order_trades(order_id).await.unwrap();
+            assert_eq!(trades.len(), 1);
+            assert_eq!(trades[0].quantity, Decimal::ONE);
+        }
+        let book = service.order_book.get_order_book("BTC/USD").await;
+        assert!(book.bids.is_empty() && book.asks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_order_trades_at_same_instant_follow_sequence() {
+        let service = service(10, 10);
+        let order_id = Uuid::new_v4();
+        let executed_at = chrono::Utc::now();
+        let earlier = executed_at - chrono::Duration::milliseconds(1);
+        {
+            let mut trades = service.trades.write().await;
+            for (seq, executed_at) in [(3, executed_at), (1, earlier), (5, executed_at), (4, executed_at), (2, earlier)] {
+                let trade = Trade {
+                    id: Uuid::new_v4(),
+                    seq,
+                    order_id,
+                    taker_order_id: Uuid::new_v4(),
+                    maker_user_id: Uuid::new_v4(),
+                    taker_user_id: Uuid::new_v4(),
+                    taker_side: OrderSide::Buy,
+                    symbol: "BTC/USD".to_string(),
+                    quantity: Decimal::ONE,
+                    price: Decimal::new(100, 0),
+                    taker_limit_price: None,
+                    executed_at,
+                };
+                trades.insert(trade.id, trade);
+            }
+        }
+
+        // Repeated listings agree, whatever order the trades are held in
+        for _ in 0..3 {
+            let trades = service.get_order_trades(order_id).await.unwrap();
+            assert_eq!(trades.iter().map(|t| t.seq).collect::<Vec<_>>(), vec![5, 4, 3, 2, 1]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_metadata_is_echoed_on_order_and_its_trades() {
+        let service = service(10, 10);
+        let (maker, taker) = (Uuid::new_v4(), Uuid::new_v4());
+        let metadata = serde_json::json!({ "strategy": "twap-7", "legs": [1, 2], "desk": null });
+        let resting = service.create_order(maker, CreateOrderRequest {
+            side: OrderSide::Sell,
+            ..limit_order("BTC/USD")
+        }).await.unwrap();
+        let placed = service.create_order(taker, CreateOrderRequest {
+            metadata: Some(metadata.clone()),
+            ..limit_order("BTC/USD")
+        }).await.unwrap();
+        assert_eq!(placed.metadata.as_ref(), Some(&metadata));
+        assert_eq!(service.get_order(placed.id).await.unwrap().metadata.as_ref(), Some(&metadata));
+
+        // Each side of the trade sees only its own order's metadata
+        let trades = service.get_order_trades(placed.id).await.unwrap();
+        assert_eq!(trades[0].metadata.as_ref(), Some(&metadata));
+        assert_eq!(service.get_trade(taker, trades[0].id).await.unwrap().metadata.as_ref(), Some(&metadata));
+        assert!(service.get_trade(maker, trades[0].id).await.unwrap().metadata.is_none());
+        assert!(service.get_order_trades(resting.id).await.unwrap()[0].metadata.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_market_order_without_liquidity_is_rejected() {
+        let service = service(10, 10);
+        let user_id = Uuid::new_v4();
+        let result = service.create_order(user_id, CreateOrderRequest {
+            order_type: OrderType::Market,
+            price: None,
+            ..limit_order("BTC/USD")
+        }).await;
+        assert!(matches!(result, Err(AppError::OrderBook(_))));
+
+        // Kept on record as rejected, never left open
+        let orders = service.orders.read().await;
+        let order = orders.values().next().unwrap();
+        assert!(matches!(order.status, OrderStatus::Rejected));
+        assert!(order.reject_reason.as_deref().unwrap().contains("No liquidity"));
+        drop(orders);
+        assert_eq!(service.open_order_count(user_id).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_fills_are_aggregated_per_price() {
+        let service = service(10, 10);
+        let taker = Uuid::new_v4();
+        for (quantity, price) in [(1, 100), (2, 101), (3, 100), (4, 103)] {
+            service.create_order(Uuid::new_v4(), CreateOrderRequest {
+                side: OrderSide::Sell,
+                quantity: Decimal::new(quantity, 0),
+                price: Some(Decimal::new(price, 0)),
+                ..limit_order("BTC/USD")
+            }).await.unwrap();
+        }
+        // Sweeps 100 and 101, and takes one of the four at 103
+        let order = service.create_order(taker, CreateOrderRequest {
+            quantity: Decimal::new(7, 0),
+            price: Some(Decimal::new(103, 0)),
+            ..limit_order("BTC/USD")
+        }).await.unwrap();
+
+        let fills = service.get_order_fills(order.id).await.unwrap();
+        let levels: Vec<_> = fills.fills.iter().map(|fill| (fill.price, fill.quantity, fill.trade_count)).collect();
+        assert_eq!(levels, vec![
+            (Decimal::new(100, 0), Decimal::new(4, 0), 2),
+            (Decimal::new(101, 0), Decimal::new(2, 0), 1),
+            (Decimal::new(103, 0), Decimal::new(1, 0), 1),
+        ]);
+        assert_eq!(fills.filled_quantity, Decimal::new(7, 0));
+        // (400 + 202 + 103) / 7
+        assert_eq!(fills.average_price, Some(Decimal::new(705, 0) / Decimal::new(7, 0)));
+
+        let unfilled = service.create_order(taker, limit_order("ETH/USD")).await.unwrap();
+        let fills = service.get_order_fills(unfilled.id).await.unwrap();
+        assert!(fills.fills.is_empty() && fills.average_price.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_trade_is_visible_to_its_participants_only() {
+        let service = service(10, 10);
+        let (maker, taker) = (Uuid::new_v4(), Uuid::new_v4());
+        service.create_order(maker, CreateOrderRequest { side: OrderSide::Sell, ..limit_order("BTC/USD") }).await.unwrap();
+        service.create_order(taker, limit_order("BTC/USD")).await.unwrap();
+        let trade_id = service.trades.read().await.keys().next().copied().unwrap();
+
+        for user_id in [maker, taker] {
+            let trade = service.get_trade(user_id, trade_id).await.unwrap();
+            assert_eq!(trade.id, trade_id);
+            assert_eq!(trade.quantity, Decimal::ONE);
+        }
+        assert!(matches!(service.get_trade(Uuid::new_v4(), trade_id).await, Err(AppError::NotFound(_))));
+        assert!(matches!(service.get_trade(maker, Uuid::new_v4()).await, Err(AppError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_paper_trading_matches_without_touching_ledger() {
+        let mut service = service(10, 10);
+        service.balances = BalanceService::new(crate::config::AccountConfig {
+            paper_trading: true,
+            ..crate::config::AccountConfig::default()
+        });
+        let (maker, taker) = (Uuid::new_v4(), Uuid::new_v4());
+        service.create_order(maker, CreateOrderRequest { side: OrderSide::Sell, ..limit_order("BTC/USD") }).await.unwrap();
+        let taker_order = service.create_order(taker, limit_order("BTC/USD")).await.unwrap();
+        assert!(matches!(taker_order.status, OrderStatus::Filled));
+
+        assert_eq!(service.balances.get_balance(taker, "BTC").await.available, Decimal::ONE);
+        assert!(service.balances.get_ledger(taker).await.is_empty());
+        assert!(service.balances.get_ledger(maker).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_open_order_limit_per_user() {
+        let service = service(3, 10);
+        let user_id = Uuid::new_v4();
+
+        for symbol in ["BTC/USD", "ETH/USD", "SOL/USD"] {
+            assert!(service.create_order(user_id, limit_order(symbol)).await.is_ok());
+        }
+
+        let result = service.create_order(user_id, limit_order("ADA/USD")).await;
+        assert!(matches!(result, Err(AppError::Validation(_))));
+
+        // Other users are unaffected
+        assert!(service.create_order(Uuid::new_v4(), limit_order("ADA/USD")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_open_order_limit_per_symbol() {
+        let service = service(10, 2);
+        let user_id = Uuid::new_v4();
+
+        assert!(service.create_order(user_id, limit_order("BTC/USD")).await.is_ok());
+        assert!(service.create_order(user_id, limit_order("BTC/USD")).await.is_ok());
+
+        let result = service.create_order(user_id, limit_order("BTC/USD")).await;
+        assert!(matches!(result, Err(AppError::Validation(_))));
+
+        // A different symbol still has room
+        assert!(service.create_order(user_id, limit_order("ETH/USD")).await.is_ok());
+    }
+
+    fn service_with_risk(limits: Vec<crate::config::RiskLimit>) -> OrderService {
+        OrderServiceBuilder::default()
+            .balances(BalanceService::new(crate::config::AccountConfig { transfers_enabled: true, ..Default::default() }))
+            .risk(limits)
+            .build()
+    }
+
+    /// Asserts `request` breaks a risk limit and left nothing behind.
+    async fn assert_risk_rejected(service: &OrderService, user_id: Uuid, request: CreateOrderRequest, reason: &str) {
+        let open_before = service.open_order_count(user_id).await.unwrap();
+        let book_before = service.order_book.freeze(&request.symbol).await;
+        let symbol = request.symbol.clone();
+
+        let result = service.create_order(user_id, request).await;
+        assert!(matches!(&result, Err(AppError::Validation(message)) if message.contains(reason)), "{:?}", result.err());
+
+        let book_after = service.order_book.freeze(&symbol).await;
+        assert_eq!(service.open_order_count(user_id).await.unwrap(), open_before);
+        assert_eq!(serde_json::to_value(&book_after.bids).unwrap(), serde_json::to_value(&book_before.bids).unwrap());
+        assert_eq!(serde_json::to_value(&book_after.asks).unwrap(), serde_json::to_value(&book_before.asks).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_risk_limit_on_open_orders() {
+        let user_id = Uuid::new_v4();
+        let service = service_with_risk(vec![crate::config::RiskLimit {
+            user_id: Some(user_id),
+            symbol: Some("BTC/USD".to_string()),
+            max_open_orders: Some(2),
+            ..Default::default()
+        }]);
+        for _ in 0..2 {
+            service.create_order(user_id, limit_order("BTC/USD")).await.unwrap();
+        }
+
+        assert_risk_rejected(&service, user_id, limit_order("BTC/USD"), "open orders").await;
+        // The limit is scoped to this user in this market
+        assert!(service.create_order(user_id, limit_order("ETH/USD")).await.is_ok());
+        assert!(service.create_order(Uuid::new_v4(), limit_order("BTC/USD")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_risk_limit_on_order_notional() {
+        let user_id = Uuid::new_v4();
+        let service = service_with_risk(vec![crate::config::RiskLimit {
+            symbol: Some("BTC/USD".to_string()),
+            max_order_notional: Some(Decimal::new(250, 0)),
+            ..Default::default()
+        }]);
+        let sized = |quantity| CreateOrderRequest { quantity: Decimal::new(quantity, 0), ..limit_order("BTC/USD") };
+        assert!(service.create_order(user_id, sized(2)).await.is_ok());
+        assert_risk_rejected(&service, user_id, sized(3), "notional").await;
+
+        // Market orders are valued at the best opposite price
+        service.order_book.add_order(&book_order("BTC/USD", OrderSide::Sell, 200)).await.unwrap();
+        let market = CreateOrderRequest { order_type: OrderType::Market, price: None, ..sized(2) };
+        assert_risk_rejected(&service, user_id, market, "notional").await;
+    }
+
+    #[tokio::test]
+    async fn test_risk_limit_on_position() {
+        let user_id = Uuid::new_v4();
+        let service = service_with_risk(vec![crate::config::RiskLimit {
+            user_id: Some(user_id),
+            max_position: Some(Decimal::new(3, 0)),
+            ..Default::default()
+        }]);
+        service.balances.deposit(user_id, "BTC", Decimal::new(2, 0)).await.unwrap();
+
+        // Holding 2, so one more can be bought but not two
+        assert!(service.create_order(user_id, limit_order("BTC/USD")).await.is_ok());
+        assert_risk_rejected(&service, user_id, limit_order("BTC/USD"), "position").await;
+
+        // Selling down to short 3 is fine, further is not
+        let sell = |quantity| CreateOrderRequest {
+            side: OrderSide::Sell,
+            quantity: Decimal::new(quantity, 0),
+            price: Some(Decimal::new(150, 0)),
+            ..limit_order("BTC/USD")
+        };
+        assert!(service.create_order(user_id, sell(5)).await.is_ok());
+        assert_risk_rejected(&service, user_id, sell(1), "position").await;
+    }
+
+    #[tokio::test]
+    async fn test_rejected_order_is_persisted_when_enabled() {
+        let service = service_with(TradingConfig {
+            max_open_orders_per_user: 1,
+            max_open_orders_per_symbol: 1,
+            persist_rejected_orders: true,
+        });
+        let user_id = Uuid::new_v4();
+
+        service.create_order(user_id, limit_order("BTC/USD")).await.unwrap();
+        let rejected = service.create_order(user_id, limit_order("BTC/USD")).await.unwrap();
+        assert!(matches!(rejected.status, OrderStatus::Rejected));
+        assert!(rejected.reject_reason.is_some());
+
+        let fetched = service.get_order(rejected.id).await.unwrap();
+        assert!(matches!(fetched.status, OrderStatus::Rejected));
+        assert_eq!(fetched.reject_reason, rejected.reject_reason);
+    }
+
+    #[tokio::test]
+    async fn test_account_summary_reflects_orders_and_balances() {
+        let fees = FeeService::new(crate::config::FeeConfig::default());
+        let balances = BalanceService::new(crate::config::AccountConfig { transfers_enabled: true, ..crate::config::AccountConfig::default() });
+        let service = OrderServiceBuilder::default().fees(fees.clone()).balances(balances.clone()).build();
+        let user_id = Uuid::new_v4();
+
+        balances.deposit(user_id, "USD", Decimal::new(500, 0)).await.unwrap();
+        service.create_order(user_id, limit_order("BTC/USD")).await.unwrap();
+        service.create_order(user_id, limit_order("ETH/USD")).await.unwrap();
+        fees.record_trades(&[crate::models::Trade {
+            id: Uuid::new_v4(),
+            seq: 1,
+            order_id: Uuid::new_v4(),
+            taker_order_id: Uuid::new_v4(),
+            maker_user_id: Uuid::new_v4(),
+            taker_user_id: user_id,
+            taker_side: OrderSide::Buy,
+            symbol: "BTC/USD".to_string(),
+            quantity: Decimal::new(2, 0),
+            price: Decimal::new(100, 0),
+            taker_limit_price: None,
+            executed_at: chrono::Utc::now(),
+        }]).await;
+
+        let summary = service.account_summary(user_id).await.unwrap();
+        assert_eq!(summary.open_orders, 2);
+        // Each buy holds its notional
+        assert_eq!(summary.balances.len(), 1);
+        assert_eq!(summary.balances[0].available, Decimal::new(300, 0));
+        assert_eq!(summary.locked_funds.len(), 1);
+        assert_eq!(summary.locked_funds[0].locked, Decimal::new(200, 0));
+        assert_eq!(summary.volume_24h, Decimal::new(200, 0));
+
+        // Other users see nothing of it
+        let other = service.account_summary(Uuid::new_v4()).await.unwrap();
+        assert_eq!(other.open_orders, 0);
+        assert!(other.balances.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_order_is_still_fetchable() {
+        let service = service(10, 10);
+        let order = service.create_order(Uuid::new_v4(), limit_order("BTC/USD")).await.unwrap();
+
+        service.cancel_order(order.id).await.unwrap();
+        let fetched = service.get_order(order.id).await.unwrap();
+        assert!(matches!(fetched.status, OrderStatus::Cancelled));
+
+        // Gone, not missing
+        assert!(matches!(service.cancel_order(order.id).await, Err(AppError::Gone(_))));
+        assert!(matches!(service.cancel_order(Uuid::new_v4()).await, Err(AppError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_filled_order_is_still_fetchable() {
+        let service = service(10, 10);
+        let order = service.create_order(Uuid::new_v4(), limit_order("BTC/USD")).await.unwrap();
+        if let Some(stored) = service.orders.write().await.get_mut(&order.id) {
+            stored.filled_quantity = stored.quantity;
+            stored.status = OrderStatus::Filled;
+        }
+
+        let fetched = service.get_order(order.id).await.unwrap();
+        assert!(matches!(fetched.status, OrderStatus::Filled));
+        assert_eq!(fetched.filled_quantity, Decimal::new(1, 0));
+
+        let result = service.reduce_order(order.id, Decimal::new(5, 1)).await;
+        assert!(matches!(result, Err(AppError::Gone(_))));
+    }
+
+    #[tokio::test]
+    async fn test_replay_rebuilds_the_book_after_each_change() {
+        use crate::services::order_book_service::{Clock, ManualClock, RandomIds};
+
+        let clock = Arc::new(ManualClock(std::sync::Mutex::new(chrono::Utc::now())));
+        let service = OrderServiceBuilder::default()
+            .order_book(OrderBookService::with_sources(Arc::new(RandomIds), clock.clone()))
+            .build();
+        let order = |side, price| CreateOrderRequest::limit("BTC/USD", side, Decimal::ONE, Decimal::new(price, 0));
+        let levels = |entries: &[OrderBookEntry]| entries.iter()
+            .map(|level| (level.price, level.quantity, level.order_count))
+            .collect::<Vec<_>>();
+
+        let ask = CreateOrderRequest::limit("BTC/USD", OrderSide::Sell, Decimal::new(2, 0), Decimal::new(101, 0));
+        let ask = service.create_order(Uuid::new_v4(), ask).await.unwrap();
+        clock.advance(1_000);
+        let from = clock.now();
+        let bid = service.create_order(Uuid::new_v4(), order(OrderSide::Buy, 99)).await.unwrap();
+        let taker = service.create_order(Uuid::new_v4(), order(OrderSide::Sell, 99)).await.unwrap();
+        service.cancel_order(ask.id).await.unwrap();
+        clock.advance(1_000);
+        let to = clock.now();
+        service.create_order(Uuid::new_v4(), order(OrderSide::Buy, 98)).await.unwrap();
+
+        let replay = service.replay_book("BTC/USD", from, to).await.unwrap();
+        let ask_level = (Decimal::new(101, 0), Decimal::new(2, 0), 1);
+        assert!(replay.bids.is_empty());
+        assert_eq!(levels(&replay.asks), vec![ask_level]);
+        let steps: Vec<_> = replay.steps.iter()
+            .map(|step| (step.event.order_id, step.event.resting, levels(&step.bids), levels(&step.asks)))
+            .collect();
+        assert_eq!(steps.len(), 6, "{:?}", steps);
+        let bid_level = (Decimal::new(99, 0), Decimal::ONE, 1);
+        // Accepted, then resting once nothing matched
+        assert_eq!(steps[0], (bid.id, false, vec![], vec![ask_level]));
+        assert_eq!(steps[1], (bid.id, true, vec![bid_level], vec![ask_level]));
+        // The taker never rests, and the bid it filled leaves the book first
+        assert_eq!(steps[2], (taker.id, false, vec![bid_level], vec![ask_level]));
+        assert_eq!(steps[3], (bid.id, false, vec![], vec![ask_level]));
+        assert_eq!(steps[4], (taker.id, false, vec![], vec![ask_level]));
+        assert_eq!(steps[5], (ask.id, false, vec![], vec![]));
+        assert_eq!(replay.trades.len(), 1);
+        assert_eq!(replay.trades[0].taker_order_id, taker.id);
+
+        let too_long = service.replay_book("BTC/USD", from, from + chrono::Duration::minutes(61)).await;
+        assert!(matches!(too_long, Err(AppError::Validation(_))));
+        assert!(matches!(service.replay_book("BTC/USD", to, from).await, Err(AppError::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_seeded_orders_are_journalled() {
+        use crate::services::order_book_service::{Clock, ManualClock, RandomIds};
+
+        let source = service(10, 10);
+        let order = source.create_order(Uuid::new_v4(), limit_order("BTC/USD")).await.unwrap();
+        let snapshot = source.order_book.freeze("BTC/USD").await;
+
+        let clock = Arc::new(ManualClock(std::sync::Mutex::new(chrono::Utc::now())));
+        let seeded = OrderServiceBuilder::default()
+            .order_book(OrderBookService::with_sources(Arc::new(RandomIds), clock.clone()))
+            .build();
+        let from = clock.now();
+        assert_eq!(seeded.seed(&[snapshot]).await.unwrap(), 1);
+        clock.advance(1_000);
+        let replay = seeded.replay_book("BTC/USD", from, clock.now()).await.unwrap();
+        assert_eq!(replay.steps.len(), 1);
+        assert_eq!((replay.steps[0].event.order_id, replay.steps[0].event.resting), (order.id, true));
+        assert_eq!(replay.steps[0].bids.len(), 1);
+    }
+}
@@ -0,0 +1,194 @@
+use std::sync::Arc;
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher as _, PasswordVerifier as _, SaltString};
+use argon2::Argon2;
+use crate::config::{AuthConfig, PasswordHashAlgorithm};
+use crate::errors::AppError;
+
+/// A password hashing scheme. Every hash it produces starts with the scheme's
+/// own identifier (`$2b$` for bcrypt, `$argon2id$` for argon2), so the stored
+/// hash alone says which scheme has to verify it.
+pub trait PasswordHasher: Send + Sync {
+    fn algorithm(&self) -> PasswordHashAlgorithm;
+
+    fn hash(&self, password: &str) -> Result<String, AppError>;
+
+    fn verify(&self, password: &str, hash: &str) -> Result<bool, AppError>;
+}
+
+impl PasswordHashAlgorithm {
+    /// Reads the algorithm identifier a hash was stored with.
+    pub fn of_hash(hash: &str) -> Option<Self> {
+        if hash.starts_with("$argon2") {
+            Some(Self::Argon2)
+        } else if hash.starts_with("$2") {
+            Some(Self::Bcrypt)
+        } else {
+            None
+        }
+    }
+}
+
+pub struct BcryptHasher {
+    cost: u32,
+}
+
+impl BcryptHasher {
+    pub fn new(cost: u32) -> Self {
+        Self { cost }
+    }
+}
+
+impl Default for BcryptHasher {
+    fn default() -> Self {
+        Self::new(bcrypt::DEFAULT_COST)
+    }
+}
+
+impl PasswordHasher for BcryptHasher {
+    fn algorithm(&self) -> PasswordHashAlgorithm {
+        PasswordHashAlgorithm::Bcrypt
+    }
+
+    fn hash(&self, password: &str) -> Result<String, AppError> {
+        Ok(bcrypt::hash(password, self.cost)?)
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> Result<bool, AppError> {
+        Ok(bcrypt::verify(password, hash)?)
+    }
+}
+
+#[derive(Default)]
+pub struct Argon2Hasher {
+    argon2: Argon2<'static>,
+}
+
+impl Argon2Hasher {
+    pub fn new(params: argon2::Params) -> Self {
+        Self {
+            argon2: Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params),
+        }
+    }
+}
+
+impl PasswordHasher for Argon2Hasher {
+    fn algorithm(&self) -> PasswordHashAlgorithm {
+        PasswordHashAlgorithm::Argon2
+    }
+
+    fn hash(&self, password: &str) -> Result<String, AppError> {
+        let salt = SaltString::generate(&mut OsRng);
+        Ok(self.argon2.hash_password(password.as_bytes(), &salt)?.to_string())
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> Result<bool, AppError> {
+        let parsed = PasswordHash::new(hash)?;
+        match self.argon2.verify_password(password.as_bytes(), &parsed) {
+            Ok(()) => Ok(true),
+            Err(argon2::password_hash::Error::Password) => Ok(false),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+/// Hashes new passwords with the configured algorithm and verifies stored
+/// hashes with whichever algorithm produced them, so the configured
+/// algorithm can change without invalidating existing users.
+#[derive(Clone)]
+pub struct PasswordService {
+    preferred: PasswordHashAlgorithm,
+    hashers: Arc<Vec<Box<dyn PasswordHasher>>>,
+}
+
+impl PasswordService {
+    pub fn new(config: AuthConfig) -> Self {
+        Self::with_hashers(
+            config.password_hash_algorithm,
+            vec![Box::new(BcryptHasher::default()), Box::new(Argon2Hasher::default())],
+        )
+    }
+
+    pub fn with_hashers(preferred: PasswordHashAlgorithm, hashers: Vec<Box<dyn PasswordHasher>>) -> Self {
+        Self {
+            preferred,
+            hashers: Arc::new(hashers),
+        }
+    }
+
+    fn hasher(&self, algorithm: PasswordHashAlgorithm) -> Result<&dyn PasswordHasher, AppError> {
+        self.hashers.iter()
+            .find(|hasher| hasher.algorithm() == algorithm)
+            .map(|hasher| hasher.as_ref())
+            .ok_or_else(|| AppError::Internal(format!("No password hasher registered for {:?}", algorithm)))
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String, AppError> {
+        self.hasher(self.preferred)?.hash(password)
+    }
+
+    pub fn verify(&self, password: &str, hash: &str) -> Result<bool, AppError> {
+        let algorithm = PasswordHashAlgorithm::of_hash(hash)
+            .ok_or_else(|| AppError::Internal("Unrecognised password hash format".to_string()))?;
+        self.hasher(algorithm)?.verify(password, hash)
+    }
+
+    /// Whether a stored hash was produced by an algorithm other than the
+    /// configured one and should be replaced after the next successful login.
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        PasswordHashAlgorithm::of_hash(hash) != Some(self.preferred)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Minimum work factors keep the tests fast
+    fn service(preferred: PasswordHashAlgorithm) -> PasswordService {
+        PasswordService::with_hashers(
+            preferred,
+            vec![
+                Box::new(BcryptHasher::new(4)),
+                Box::new(Argon2Hasher::new(argon2::Params::new(8, 1, 1, None).unwrap())),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_hash_verifies_with_each_algorithm() {
+        for algorithm in [PasswordHashAlgorithm::Bcrypt, PasswordHashAlgorithm::Argon2] {
+            let service = service(algorithm);
+            let hash = service.hash("correct horse").unwrap();
+
+            assert_eq!(PasswordHashAlgorithm::of_hash(&hash), Some(algorithm));
+            assert!(service.verify("correct horse", &hash).unwrap());
+            assert!(!service.verify("battery staple", &hash).unwrap());
+            assert!(!service.needs_rehash(&hash));
+        }
+    }
+
+    #[test]
+    fn test_hash_from_previous_algorithm_still_verifies() {
+        let bcrypt_hash = service(PasswordHashAlgorithm::Bcrypt).hash("correct horse").unwrap();
+
+        let migrated = service(PasswordHashAlgorithm::Argon2);
+        assert!(migrated.verify("correct horse", &bcrypt_hash).unwrap());
+        assert!(migrated.needs_rehash(&bcrypt_hash));
+    }
+
+    #[test]
+    fn test_mismatched_algorithm_is_an_error() {
+        let bcrypt_hash = BcryptHasher::new(4).hash("correct horse").unwrap();
+        let argon2 = Argon2Hasher::new(argon2::Params::new(8, 1, 1, None).unwrap());
+        assert!(argon2.verify("correct horse", &bcrypt_hash).is_err());
+
+        // Without a registered argon2 hasher, argon2 hashes can't be checked
+        let bcrypt_only = PasswordService::with_hashers(
+            PasswordHashAlgorithm::Bcrypt,
+            vec![Box::new(BcryptHasher::new(4))],
+        );
+        let argon2_hash = argon2.hash("correct horse").unwrap();
+        assert!(matches!(bcrypt_only.verify("correct horse", &argon2_hash), Err(AppError::Internal(_))));
+        assert!(bcrypt_only.verify("correct horse", "plaintext").is_err());
+    }
+}
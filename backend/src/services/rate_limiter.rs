@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method};
+use actix_web::middleware::Next;
+use actix_web::web;
+use tokio::sync::Mutex;
+use crate::auth::API_KEY_HEADER;
+use crate::config::{RateLimit, RateLimitConfig};
+use crate::errors::AppError;
+
+/// Buckets tracked before idle, fully refilled ones are dropped.
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+
+/// Routes sharing a limit. Each client has a separate bucket per class, so
+/// exhausting one never blocks the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteClass {
+    Read,
+    Order,
+    Write,
+}
+
+impl RouteClass {
+    fn name(self) -> &'static str {
+        match self {
+            RouteClass::Read => "read",
+            RouteClass::Order => "order",
+            RouteClass::Write => "write",
+        }
+    }
+
+    pub fn of(method: &Method, path: &str) -> Self {
+        if method == Method::GET || method == Method::HEAD {
+            RouteClass::Read
+        } else if path.split('/').any(|segment| segment == "orders") {
+            RouteClass::Order
+        } else {
+            RouteClass::Write
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_second as f64).min(limit.burst as f64);
+        self.refilled_at = now;
+    }
+}
+
+/// Token bucket limits per client and route class.
+#[derive(Clone)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Arc<Mutex<HashMap<(RouteClass, String), Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn limit(config: &RateLimitConfig, class: RouteClass) -> RateLimit {
+        match class {
+            RouteClass::Read => config.read,
+            RouteClass::Order => config.order,
+            RouteClass::Write => config.write,
+        }
+    }
+
+    pub async fn check(&self, class: RouteClass, client: &str) -> Result<(), AppError> {
+        self.check_at(class, client, Instant::now()).await
+    }
+
+    /// Takes a token from the client's bucket for `class`, failing with `429`
+    /// and the wait until the next token when it is empty.
+    async fn check_at(&self, class: RouteClass, client: &str, now: Instant) -> Result<(), AppError> {
+        let limit = Self::limit(&self.config, class);
+        let mut buckets = self.buckets.lock().await;
+        if buckets.len() >= MAX_TRACKED_BUCKETS {
+            buckets.retain(|(class, _), bucket| {
+                let limit = Self::limit(&self.config, *class);
+                bucket.refill(limit, now);
+                bucket.tokens < limit.burst as f64
+            });
+        }
+
+        let bucket = buckets
+            .entry((class, client.to_string()))
+            .or_insert_with(|| Bucket { tokens: limit.burst as f64, refilled_at: now });
+        bucket.refill(limit, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        let wait = if limit.per_second == 0 {
+            Duration::MAX
+        } else {
+            Duration::from_secs_f64((1.0 - bucket.tokens) / limit.per_second as f64)
+        };
+        Err(AppError::RateLimited(format!(
+            "Too many {} requests, retry in {} ms",
+            class.name(), wait.as_millis()
+        )))
+    }
+}
+
+/// Who a request counts against: its API key or bearer token when it has
+/// one, otherwise the connecting address.
+fn client_of(req: &ServiceRequest) -> String {
+    req.headers()
+        .get(API_KEY_HEADER)
+        .or_else(|| req.headers().get(header::AUTHORIZATION))
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .or_else(|| req.peer_addr().map(|addr| addr.ip().to_string()))
+        .unwrap_or_default()
+}
+
+/// Rejects requests over their route class limit with `429`.
+pub async fn limit_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    if let Some(limiter) = req.app_data::<web::Data<RateLimiter>>() {
+        if let Err(error) = limiter.check(RouteClass::of(req.method(), req.path()), &client_of(&req)).await {
+            return Ok(req.error_response(error).map_into_right_body());
+        }
+    }
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, middleware, test as actix_test, App, HttpResponse};
+
+    fn config() -> RateLimitConfig {
+        RateLimitConfig {
+            read: RateLimit { burst: 5, per_second: 5 },
+            order: RateLimit { burst: 2, per_second: 1 },
+            write: RateLimit { burst: 2, per_second: 1 },
+        }
+    }
+
+    #[test]
+    fn test_route_classes() {
+        assert_eq!(RouteClass::of(&Method::GET, "/api/v1/orders/orders"), RouteClass::Read);
+        assert_eq!(RouteClass::of(&Method::POST, "/api/v1/orders/orders"), RouteClass::Order);
+        assert_eq!(RouteClass::of(&Method::PUT, "/api/v1/orders/orders/1/cancel"), RouteClass::Order);
+        assert_eq!(RouteClass::of(&Method::POST, "/api/v1/account/withdraw"), RouteClass::Write);
+    }
+
+    #[tokio::test]
+    async fn test_bucket_refills_over_time() {
+        let limiter = RateLimiter::new(config());
+        let start = Instant::now();
+
+        assert!(limiter.check_at(RouteClass::Order, "client", start).await.is_ok());
+        assert!(limiter.check_at(RouteClass::Order, "client", start).await.is_ok());
+        assert!(matches!(limiter.check_at(RouteClass::Order, "client", start).await, Err(AppError::RateLimited(_))));
+
+        // Other clients have their own bucket
+        assert!(limiter.check_at(RouteClass::Order, "other", start).await.is_ok());
+
+        // One token per second at the order rate
+        let later = start + Duration::from_secs(1);
+        assert!(limiter.check_at(RouteClass::Order, "client", later).await.is_ok());
+        assert!(limiter.check_at(RouteClass::Order, "client", later).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_exhausted_order_bucket_does_not_block_reads() {
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(RateLimiter::new(config())))
+                .wrap(middleware::from_fn(limit_requests))
+                .route("/orders", web::get().to(HttpResponse::Ok))
+                .route("/orders", web::post().to(HttpResponse::Created)),
+        ).await;
+        let request = |method: Method| actix_test::TestRequest::default()
+            .method(method)
+            .uri("/orders")
+            .insert_header((header::AUTHORIZATION, "Bearer token"))
+            .to_request();
+
+        for _ in 0..2 {
+            assert_eq!(actix_test::call_service(&app, request(Method::POST)).await.status(), StatusCode::CREATED);
+        }
+        assert_eq!(actix_test::call_service(&app, request(Method::POST)).await.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        for _ in 0..5 {
+            assert_eq!(actix_test::call_service(&app, request(Method::GET)).await.status(), StatusCode::OK);
+        }
+        assert_eq!(actix_test::call_service(&app, request(Method::GET)).await.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -0,0 +1,138 @@
+use std::sync::Arc;
+use rust_decimal::Decimal;
+use uuid::Uuid;
+use crate::config::RiskLimit;
+use crate::errors::AppError;
+use crate::models::{CreateOrderRequest, OrderSide};
+
+/// What a user already has at stake in one market.
+#[derive(Debug, Clone, Default)]
+pub struct Exposure {
+    pub open_orders: usize,
+    /// Holding of the market's base asset, negative when short.
+    pub position: Decimal,
+    /// Unfilled quantity of the user's open buy orders.
+    pub open_buy: Decimal,
+    /// Unfilled quantity of the user's open sell orders.
+    pub open_sell: Decimal,
+}
+
+/// Per-user limits on orders, checked before they are stored or matched.
+#[derive(Clone)]
+pub struct RiskService {
+    limits: Arc<Vec<RiskLimit>>,
+}
+
+impl RiskService {
+    pub fn new(limits: Vec<RiskLimit>) -> Self {
+        Self {
+            limits: Arc::new(limits),
+        }
+    }
+
+    /// The limits applying to `user_id` in `symbol`, each from the most
+    /// specific entry setting it, or `None` when no entry sets any.
+    pub fn limits_for(&self, user_id: Uuid, symbol: &str) -> Option<RiskLimit> {
+        let mut applicable: Vec<&RiskLimit> = self.limits.iter()
+            .filter(|limit| limit.user_id.is_none_or(|id| id == user_id))
+            .filter(|limit| limit.symbol.as_deref().is_none_or(|s| s == symbol))
+            .collect();
+        // User entries first, then market entries, then the catch-alls
+        applicable.sort_by_key(|limit| (limit.user_id.is_none(), limit.symbol.is_none()));
+
+        let limits = RiskLimit {
+            user_id: Some(user_id),
+            symbol: Some(symbol.to_string()),
+            max_open_orders: applicable.iter().find_map(|limit| limit.max_open_orders),
+            max_position: applicable.iter().find_map(|limit| limit.max_position),
+            max_order_notional: applicable.iter().find_map(|limit| limit.max_order_notional),
+        };
+        let any = limits.max_open_orders.is_some() || limits.max_position.is_some() || limits.max_order_notional.is_some();
+        any.then_some(limits)
+    }
+
+    /// Rejects `request` if placing it would break one of `limits`, given
+    /// the user's `exposure`. The notional is taken at `reference_price`,
+    /// the order's own price when it has one; without a price it isn't
+    /// checked.
+    pub fn check(
+        limits: &RiskLimit,
+        request: &CreateOrderRequest,
+        reference_price: Option<Decimal>,
+        exposure: &Exposure,
+    ) -> Result<(), AppError> {
+        if let Some(max_open_orders) = limits.max_open_orders {
+            if exposure.open_orders >= max_open_orders {
+                return Err(AppError::Validation(format!(
+                    "Risk limit of {} open orders in {} reached",
+                    max_open_orders, request.symbol
+                )));
+            }
+        }
+
+        if let (Some(max_notional), Some(price)) = (limits.max_order_notional, reference_price) {
+            let notional = (request.quantity * price).abs();
+            if notional > max_notional {
+                return Err(AppError::Validation(format!(
+                    "Order notional of {} exceeds the risk limit of {} for {}",
+                    notional, max_notional, request.symbol
+                )));
+            }
+        }
+
+        if let Some(max_position) = limits.max_position {
+            let position = match request.side {
+                OrderSide::Buy => exposure.position + exposure.open_buy + request.quantity,
+                OrderSide::Sell => exposure.position - exposure.open_sell - request.quantity,
+            };
+            if position.abs() > max_position {
+                return Err(AppError::Validation(format!(
+                    "Order could take the position in {} to {}, beyond the risk limit of {}",
+                    request.symbol, position, max_position
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(user_id: Option<Uuid>, symbol: Option<&str>, max_open_orders: usize) -> RiskLimit {
+        RiskLimit {
+            user_id,
+            symbol: symbol.map(str::to_string),
+            max_open_orders: Some(max_open_orders),
+            ..RiskLimit::default()
+        }
+    }
+
+    #[test]
+    fn test_most_specific_limit_applies() {
+        let user_id = Uuid::new_v4();
+        let service = RiskService::new(vec![
+            limit(None, None, 10),
+            limit(None, Some("BTC/USD"), 5),
+            limit(Some(user_id), None, 20),
+            RiskLimit {
+                user_id: Some(user_id),
+                symbol: Some("ETH/USD".to_string()),
+                max_position: Some(Decimal::ONE),
+                ..RiskLimit::default()
+            },
+        ]);
+        let max_open_orders = |user_id, symbol| service.limits_for(user_id, symbol).and_then(|limits| limits.max_open_orders);
+
+        assert_eq!(max_open_orders(Uuid::new_v4(), "ETH/USD"), Some(10));
+        assert_eq!(max_open_orders(Uuid::new_v4(), "BTC/USD"), Some(5));
+        assert_eq!(max_open_orders(user_id, "BTC/USD"), Some(20));
+        // A user-and-market entry that doesn't set a limit falls back to the next one
+        let limits = service.limits_for(user_id, "ETH/USD").unwrap();
+        assert_eq!((limits.max_open_orders, limits.max_position), (Some(20), Some(Decimal::ONE)));
+
+        assert!(RiskService::new(Vec::new()).limits_for(user_id, "BTC/USD").is_none());
+    }
+}
@@ -0,0 +1,45 @@
+use std::sync::Arc;
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use crate::errors::AppError;
+
+const NONCE_BYTES: usize = 12;
+
+/// AES-256-GCM encryption for secrets that have to be read back, such as
+/// two-factor and API key secrets. Ciphertexts are hex encoded with the
+/// random nonce in front.
+#[derive(Clone)]
+pub struct SecretCipher {
+    cipher: Arc<Aes256Gcm>,
+}
+
+impl SecretCipher {
+    /// The AES key is the SHA-256 digest of `key`.
+    pub fn new(key: &str) -> Self {
+        let key = Sha256::digest(key.as_bytes());
+        Self {
+            cipher: Arc::new(Aes256Gcm::new(&key)),
+        }
+    }
+
+    pub fn encrypt(&self, secret: &str) -> Result<String, AppError> {
+        let mut nonce = [0u8; NONCE_BYTES];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = self.cipher.encrypt(Nonce::from_slice(&nonce), secret.as_bytes())
+            .map_err(|_| AppError::Internal("Failed to encrypt secret".to_string()))?;
+        Ok(hex::encode([nonce.as_slice(), &ciphertext].concat()))
+    }
+
+    pub fn decrypt(&self, encrypted: &str) -> Result<String, AppError> {
+        let invalid = || AppError::Internal("Failed to decrypt secret".to_string());
+        let bytes = hex::decode(encrypted).map_err(|_| invalid())?;
+        if bytes.len() <= NONCE_BYTES {
+            return Err(invalid());
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_BYTES);
+        let plaintext = self.cipher.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|_| invalid())?;
+        String::from_utf8(plaintext).map_err(|_| invalid())
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use rust_decimal::Decimal;
+use uuid::Uuid;
+use crate::models::{Order, OrderSide, Trade};
+
+#[derive(Default)]
+struct Conditions {
+    pending: HashMap<String, Vec<Order>>, // Trigger symbol -> Stops, oldest first
+    oco_groups: HashMap<Uuid, Vec<Uuid>>, // OCO group -> Legs, until one activates
+    resting_legs: HashMap<Uuid, Uuid>, // OCO leg on the book -> Group
+}
+
+impl Conditions {
+    /// Claims `group_id` for the leg `winner`, taking its other legs out of
+    /// the running. Returns those legs, or nothing if the group was claimed
+    /// already.
+    fn claim(&mut self, group_id: Uuid, winner: Uuid) -> Vec<Uuid> {
+        let losers: Vec<Uuid> = self.oco_groups.remove(&group_id)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|leg| *leg != winner)
+            .collect();
+        self.resting_legs.remove(&winner);
+        for loser in &losers {
+            self.resting_legs.remove(loser);
+            self.take(*loser);
+        }
+        losers
+    }
+
+    fn take(&mut self, order_id: Uuid) -> Option<Order> {
+        for stops in self.pending.values_mut() {
+            if let Some(index) = stops.iter().position(|o| o.id == order_id) {
+                return Some(stops.remove(index));
+            }
+        }
+        None
+    }
+
+    /// Drops `order_id` from its OCO group, and the group once it has no legs left.
+    fn unlink(&mut self, group_id: Uuid, order_id: Uuid) {
+        if let Some(legs) = self.oco_groups.get_mut(&group_id) {
+            legs.retain(|leg| *leg != order_id);
+            if legs.is_empty() {
+                self.oco_groups.remove(&group_id);
+            }
+        }
+    }
+}
+
+/// What a trade set off: stops to send to the book, and OCO legs to cancel
+/// because another leg of their group activated first.
+#[derive(Debug, Default)]
+pub struct Activation {
+    pub triggered: Vec<Order>,
+    pub cancelled: Vec<Uuid>,
+}
+
+/// Conditional orders held off the book: stops waiting for a trade to reach
+/// their stop price, possibly on another symbol than the one they trade, and
+/// the links between one-cancels-other legs.
+///
+/// A single lock covers both, so whichever OCO leg activates first claims
+/// its group and takes its sibling stops out of the running in the same
+/// step; a sibling can't trigger once its group is claimed.
+#[derive(Clone, Default)]
+pub struct StopService {
+    conditions: Arc<Mutex<Conditions>>,
+}
+
+impl StopService {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The symbol whose trades trigger `order`.
+    pub fn trigger_symbol(order: &Order) -> &str {
+        order.trigger_symbol.as_deref().unwrap_or(&order.symbol)
+    }
+
+    pub fn is_stop(order: &Order) -> bool {
+        order.stop_price.is_some()
+    }
+
+    /// Holds a stop order until a trade on its trigger symbol reaches its stop price.
+    pub async fn park(&self, order: Order) {
+        self.conditions.lock().await.pending
+            .entry(Self::trigger_symbol(&order).to_string())
+            .or_default()
+            .push(order);
+    }
+
+    /// Links the legs of a one-cancels-other group. Call before placing any
+    /// leg, so a leg that trades straight away still claims the group.
+    pub async fn link_oco(&self, group_id: Uuid, legs: &[Order]) {
+        let mut conditions = self.conditions.lock().await;
+        conditions.oco_groups.insert(group_id, legs.iter().map(|leg| leg.id).collect());
+        for leg in legs.iter().filter(|leg| !Self::is_stop(leg)) {
+            conditions.resting_legs.insert(leg.id, group_id);
+        }
+    }
+
+    /// Takes a stop off hold, returning it if it was still waiting. An OCO
+    /// leg also leaves its group.
+    pub async fn cancel(&self, order_id: Uuid) -> Option<Order> {
+        let mut conditions = self.conditions.lock().await;
+        let stop = conditions.take(order_id);
+        let group_id = stop.as_ref()
+            .and_then(|stop| stop.oco_group_id)
+            .or_else(|| conditions.resting_legs.remove(&order_id));
+        if let Some(group_id) = group_id {
+            conditions.unlink(group_id, order_id);
+        }
+        stop
+    }
+
+    /// Buy stops trigger at or above their stop price, sell stops at or below.
+    fn is_triggered(stop: &Order, price: Decimal) -> bool {
+        match (&stop.side, stop.stop_price) {
+            (OrderSide::Buy, Some(stop_price)) => price >= stop_price,
+            (OrderSide::Sell, Some(stop_price)) => price <= stop_price,
+            (_, None) => false,
+        }
+    }
+
+    /// Works out what `trade` activates. A resting OCO leg that traded claims
+    /// its group first; then the stops watching `trade.symbol` that its price
+    /// triggers are released, in the order they were parked.
+    pub async fn triggered_by(&self, trade: &Trade) -> Activation {
+        let mut conditions = self.conditions.lock().await;
+        let mut activation = Activation::default();
+
+        for order_id in [trade.order_id, trade.taker_order_id] {
+            if let Some(group_id) = conditions.resting_legs.get(&order_id).copied() {
+                activation.cancelled.extend(conditions.claim(group_id, order_id));
+            }
+        }
+
+        let Some(stops) = conditions.pending.get_mut(&trade.symbol) else {
+            return activation;
+        };
+        let (triggered, waiting): (Vec<Order>, Vec<Order>) = std::mem::take(stops)
+            .into_iter()
+            .partition(|stop| Self::is_triggered(stop, trade.price));
+        *stops = waiting;
+
+        for stop in triggered {
+            // Claiming an earlier stop's group may have cancelled this one
+            if activation.cancelled.contains(&stop.id) {
+                continue;
+            }
+            if let Some(group_id) = stop.oco_group_id {
+                activation.cancelled.extend(conditions.claim(group_id, stop.id));
+            }
+            activation.triggered.push(stop);
+        }
+        activation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{OrderStatus, OrderType};
+
+    fn stop(side: OrderSide, stop_price: i64, trigger_symbol: Option<&str>) -> Order {
+        Order {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            symbol: "BTC/USD".to_string(),
+            side,
+            quantity: Decimal::ONE,
+            price: None,
+            order_type: OrderType::Stop,
+            all_or_none: false,
+            stop_price: Some(Decimal::new(stop_price, 0)),
+            trigger_symbol: trigger_symbol.map(str::to_string),
+            peg_offset: None,
+            protection_price: None,
+            oco_group_id: None,
+            client_order_id: None,
+            metadata: None,
+            status: OrderStatus::New,
+            filled_quantity: Decimal::ZERO,
+            reject_reason: None,
+            acceptance_seq: 0,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    fn trade(symbol: &str, price: i64) -> Trade {
+        Trade {
+            id: Uuid::new_v4(),
+            seq: 1,
+            order_id: Uuid::new_v4(),
+            taker_order_id: Uuid::new_v4(),
+            maker_user_id: Uuid::new_v4(),
+            taker_user_id: Uuid::new_v4(),
+            taker_side: OrderSide::Buy,
+            symbol: symbol.to_string(),
+            quantity: Decimal::ONE,
+            price: Decimal::new(price, 0),
+            taker_limit_price: None,
+            executed_at: chrono::Utc::now(),
+        }
+    }
+
+    fn ids(orders: &[Order]) -> Vec<Uuid> {
+        orders.iter().map(|order| order.id).collect()
+    }
+
+    #[tokio::test]
+    async fn test_trade_on_trigger_symbol_releases_stop() {
+        let stops = StopService::new();
+        let buy = stop(OrderSide::Buy, 2_000, Some("ETH/USD"));
+        let sell = stop(OrderSide::Sell, 1_500, Some("ETH/USD"));
+        let own = stop(OrderSide::Buy, 2_000, None);
+        for order in [&buy, &sell, &own] {
+            stops.park(order.clone()).await;
+        }
+
+        // Trades on the stop's own symbol don't touch stops keyed to another
+        assert!(stops.triggered_by(&trade("BTC/USD", 1_000)).await.triggered.is_empty());
+        assert!(stops.triggered_by(&trade("ETH/USD", 1_999)).await.triggered.is_empty());
+        assert_eq!(ids(&stops.triggered_by(&trade("ETH/USD", 2_000)).await.triggered), vec![buy.id]);
+        assert_eq!(ids(&stops.triggered_by(&trade("ETH/USD", 1_400)).await.triggered), vec![sell.id]);
+
+        // Released stops are gone, the rest keep waiting
+        assert!(stops.triggered_by(&trade("ETH/USD", 2_500)).await.triggered.is_empty());
+        assert_eq!(ids(&stops.triggered_by(&trade("BTC/USD", 2_000)).await.triggered), vec![own.id]);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_stop_never_triggers() {
+        let stops = StopService::new();
+        let order = stop(OrderSide::Buy, 2_000, Some("ETH/USD"));
+        stops.park(order.clone()).await;
+
+        assert_eq!(stops.cancel(order.id).await.map(|o| o.id), Some(order.id));
+        assert!(stops.cancel(order.id).await.is_none());
+        assert!(stops.triggered_by(&trade("ETH/USD", 3_000)).await.triggered.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_one_trade_activating_both_oco_stops_keeps_one() {
+        let stops = StopService::new();
+        let group_id = Uuid::new_v4();
+        let mut first = stop(OrderSide::Sell, 2_000, None);
+        let mut second = stop(OrderSide::Sell, 1_900, None);
+        first.oco_group_id = Some(group_id);
+        second.oco_group_id = Some(group_id);
+        stops.link_oco(group_id, &[first.clone(), second.clone()]).await;
+        stops.park(first.clone()).await;
+        stops.park(second.clone()).await;
+
+        let activation = stops.triggered_by(&trade("BTC/USD", 1_800)).await;
+        assert_eq!(ids(&activation.triggered), vec![first.id]);
+        assert_eq!(activation.cancelled, vec![second.id]);
+        assert!(stops.cancel(second.id).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_resting_oco_leg_trading_cancels_stop_leg() {
+        let stops = StopService::new();
+        let group_id = Uuid::new_v4();
+        let take_profit = Order {
+            order_type: OrderType::Limit,
+            price: Some(Decimal::new(2_500, 0)),
+            stop_price: None,
+            oco_group_id: Some(group_id),
+            ..stop(OrderSide::Sell, 0, None)
+        };
+        let mut stop_loss = stop(OrderSide::Sell, 1_500, None);
+        stop_loss.oco_group_id = Some(group_id);
+        stops.link_oco(group_id, &[take_profit.clone(), stop_loss.clone()]).await;
+        stops.park(stop_loss.clone()).await;
+
+        // The take-profit fills in the same trade that would trigger the stop
+        let mut fill = trade("BTC/USD", 1_400);
+        fill.order_id = take_profit.id;
+        let activation = stops.triggered_by(&fill).await;
+        assert!(activation.triggered.is_empty());
+        assert_eq!(activation.cancelled, vec![stop_loss.id]);
+
+        // Later fills of the winner have nothing left to cancel
+        let activation = stops.triggered_by(&fill).await;
+        assert!(activation.triggered.is_empty() && activation.cancelled.is_empty());
+    }
+}
@@ -0,0 +1,424 @@
+#[cfg(feature = "database")]
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{oneshot, Mutex};
+use tokio_util::sync::CancellationToken;
+#[cfg(not(feature = "database"))]
+use tokio::sync::RwLock;
+use crate::config::PersistenceConfig;
+#[cfg(feature = "database")]
+use crate::models::{OrderSide, OrderStatus};
+use crate::models::{OrderEvent, Trade};
+use crate::errors::AppError;
+
+/// Trades and order events waiting for the next write, and the callers
+/// waiting on it.
+#[derive(Default)]
+struct Pending {
+    trades: Vec<Trade>,
+    events: Vec<OrderEvent>,
+    waiters: Vec<oneshot::Sender<Result<(), String>>>,
+}
+
+impl Pending {
+    fn len(&self) -> usize {
+        self.trades.len() + self.events.len()
+    }
+}
+
+/// Group-commits trades. Trades from concurrent callers are buffered and
+/// written together in one multi-row insert, as soon as `trade_batch_size` are
+/// waiting or after `trade_flush_interval_ms`. `persist` returns only once the
+/// insert holding its trades has committed, so a trade acknowledged to a
+/// client is never lost to a crash. Order events for the journal ride along
+/// in the same batches.
+#[derive(Clone)]
+pub struct TradePersister {
+    #[cfg(feature = "database")]
+    pool: Arc<PgPool>,
+    #[cfg(not(feature = "database"))]
+    batches: Arc<RwLock<Vec<Vec<Trade>>>>, // Written batches, oldest first
+    #[cfg(not(feature = "database"))]
+    events: Arc<RwLock<Vec<OrderEvent>>>, // Written order events, oldest first
+    pending: Arc<Mutex<Pending>>,
+    writing: Arc<Mutex<()>>, // Held while a batch is written, so batches land in order
+    config: PersistenceConfig,
+}
+
+impl TradePersister {
+    #[cfg(feature = "database")]
+    pub fn new(pool: PgPool, config: PersistenceConfig) -> Self {
+        Self {
+            pool: Arc::new(pool),
+            pending: Arc::new(Mutex::new(Pending::default())),
+            writing: Arc::new(Mutex::new(())),
+            config,
+        }
+    }
+
+    #[cfg(not(feature = "database"))]
+    pub fn new(config: PersistenceConfig) -> Self {
+        Self {
+            batches: Arc::new(RwLock::new(Vec::new())),
+            events: Arc::new(RwLock::new(Vec::new())),
+            pending: Arc::new(Mutex::new(Pending::default())),
+            writing: Arc::new(Mutex::new(())),
+            config,
+        }
+    }
+
+    /// Queues `trades` for the next batch and waits until it is written.
+    pub async fn persist(&self, trades: Vec<Trade>) -> Result<(), AppError> {
+        if trades.is_empty() {
+            return Ok(());
+        }
+
+        let (written, mut receiver) = oneshot::channel();
+        let batch_full = {
+            let mut pending = self.pending.lock().await;
+            pending.trades.extend(trades);
+            pending.waiters.push(written);
+            pending.len() >= self.config.trade_batch_size
+        };
+
+        if batch_full {
+            self.flush().await;
+        } else {
+            // Give other callers until the interval to join the batch. If a
+            // flush started by someone else already took our trades, this
+            // flush finds nothing to do and we wait for theirs.
+            let interval = Duration::from_millis(self.config.trade_flush_interval_ms);
+            tokio::select! {
+                result = &mut receiver => return Self::outcome(result),
+                _ = tokio::time::sleep(interval) => self.flush().await,
+            }
+        }
+
+        Self::outcome(receiver.await)
+    }
+
+    fn outcome(result: Result<Result<(), String>, oneshot::error::RecvError>) -> Result<(), AppError> {
+        result
+            .map_err(|_| AppError::Internal("Trade batch was dropped before being written".to_string()))?
+            .map_err(|error| AppError::Internal(format!("Failed to persist trades: {}", error)))
+    }
+
+    /// Queues `events` for the order event journal. Unlike trades, callers
+    /// don't wait for the write: the journal only feeds replays, and
+    /// matching shouldn't stall on it.
+    pub async fn journal(&self, events: Vec<OrderEvent>) {
+        let batch_full = {
+            let mut pending = self.pending.lock().await;
+            pending.events.extend(events);
+            pending.len() >= self.config.trade_batch_size
+        };
+        if batch_full {
+            self.flush().await;
+        }
+    }
+
+    /// Writes what is waiting every `trade_flush_interval_ms`, so journalled
+    /// events with no trade to ride along with go out too, until `shutdown`.
+    /// Then writes what is left, so nothing is lost on exit.
+    pub async fn run(self, shutdown: CancellationToken) {
+        let mut ticks = tokio::time::interval(Duration::from_millis(self.config.trade_flush_interval_ms));
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = ticks.tick() => self.flush().await,
+            }
+        }
+        self.flush().await;
+    }
+
+    /// Writes every waiting trade and order event and reports the outcome to
+    /// the callers waiting on the trades.
+    pub async fn flush(&self) {
+        let _writing = self.writing.lock().await;
+        let Pending { trades, events, waiters } = std::mem::take(&mut *self.pending.lock().await);
+        if trades.is_empty() && events.is_empty() {
+            return;
+        }
+
+        let result = self.insert_batch(&trades, &events).await.map_err(|error| error.to_string());
+        if let Err(error) = &result {
+            if !events.is_empty() {
+                tracing::error!(%error, events = events.len(), "Failed to journal order events");
+            }
+        }
+        for waiter in waiters {
+            let _ = waiter.send(result.clone());
+        }
+    }
+
+    async fn insert_batch(&self, trades: &[Trade], events: &[OrderEvent]) -> Result<(), AppError> {
+        #[cfg(feature = "database")]
+        {
+            // Trades and events commit together, so the journal never runs ahead of the trades
+            let mut transaction = self.pool.begin().await?;
+            let ids: Vec<_> = trades.iter().map(|t| t.id).collect();
+            let seqs: Vec<_> = trades.iter().map(|t| t.seq).collect();
+            let order_ids: Vec<_> = trades.iter().map(|t| t.order_id).collect();
+            let taker_order_ids: Vec<_> = trades.iter().map(|t| t.taker_order_id).collect();
+            let maker_user_ids: Vec<_> = trades.iter().map(|t| t.maker_user_id).collect();
+            let taker_user_ids: Vec<_> = trades.iter().map(|t| t.taker_user_id).collect();
+            let taker_sides: Vec<_> = trades.iter().map(|t| t.taker_side.clone()).collect();
+            let symbols: Vec<_> = trades.iter().map(|t| t.symbol.clone()).collect();
+            let quantities: Vec<_> = trades.iter().map(|t| t.quantity).collect();
+            let prices: Vec<_> = trades.iter().map(|t| t.price).collect();
+            let taker_limit_prices: Vec<_> = trades.iter().map(|t| t.taker_limit_price).collect();
+            let executed_ats: Vec<_> = trades.iter().map(|t| t.executed_at).collect();
+
+            sqlx::query!(
+                r#"
+                INSERT INTO trades (id, seq, order_id, taker_order_id, maker_user_id, taker_user_id, taker_side, symbol, quantity, price, taker_limit_price, executed_at)
+                SELECT * FROM UNNEST(
+                    $1::uuid[], $2::bigint[], $3::uuid[], $4::uuid[], $5::uuid[], $6::uuid[],
+                    $7::order_side[], $8::varchar[], $9::numeric[], $10::numeric[], $11::numeric[], $12::timestamptz[]
+                )
+                "#,
+                &ids,
+                &seqs,
+                &order_ids,
+                &taker_order_ids,
+                &maker_user_ids,
+                &taker_user_ids,
+                &taker_sides as &[OrderSide],
+                &symbols,
+                &quantities,
+                &prices,
+                &taker_limit_prices,
+                &executed_ats
+            )
+            .execute(&mut *transaction)
+            .await?;
+
+            let order_ids: Vec<_> = events.iter().map(|e| e.order_id).collect();
+            let symbols: Vec<_> = events.iter().map(|e| e.symbol.clone()).collect();
+            let sides: Vec<_> = events.iter().map(|e| e.side.clone()).collect();
+            let prices: Vec<_> = events.iter().map(|e| e.price).collect();
+            let quantities: Vec<_> = events.iter().map(|e| e.quantity).collect();
+            let filled_quantities: Vec<_> = events.iter().map(|e| e.filled_quantity).collect();
+            let statuses: Vec<_> = events.iter().map(|e| e.status.clone()).collect();
+            let resting: Vec<_> = events.iter().map(|e| e.resting).collect();
+            let occurred_ats: Vec<_> = events.iter().map(|e| e.occurred_at).collect();
+
+            // Rows are numbered in array order, which is the order they were journalled in
+            sqlx::query!(
+                r#"
+                INSERT INTO order_events (order_id, symbol, side, price, quantity, filled_quantity, status, resting, occurred_at)
+                SELECT * FROM UNNEST(
+                    $1::uuid[], $2::varchar[], $3::order_side[], $4::numeric[], $5::numeric[],
+                    $6::numeric[], $7::order_status[], $8::bool[], $9::timestamptz[]
+                )
+                "#,
+                &order_ids,
+                &symbols,
+                &sides as &[OrderSide],
+                &prices as &[Option<rust_decimal::Decimal>],
+                &quantities,
+                &filled_quantities,
+                &statuses as &[OrderStatus],
+                &resting,
+                &occurred_ats
+            )
+            .execute(&mut *transaction)
+            .await?;
+
+            transaction.commit().await?;
+            Ok(())
+        }
+
+        #[cfg(not(feature = "database"))]
+        {
+            if !trades.is_empty() {
+                self.batches.write().await.push(trades.to_vec());
+            }
+            self.events.write().await.extend_from_slice(events);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "database")))]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use rust_decimal::Decimal;
+    use uuid::Uuid;
+    use crate::models::OrderSide;
+
+    fn trade(seq: i64) -> Trade {
+        Trade {
+            id: Uuid::new_v4(),
+            seq,
+            order_id: Uuid::new_v4(),
+            taker_order_id: Uuid::new_v4(),
+            maker_user_id: Uuid::new_v4(),
+            taker_user_id: Uuid::new_v4(),
+            taker_side: OrderSide::Buy,
+            symbol: "BTC/USD".to_string(),
+            quantity: Decimal::ONE,
+            price: Decimal::new(100, 0),
+            taker_limit_price: None,
+            executed_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_full_batch_is_written_at_once() {
+        let persister = TradePersister::new(PersistenceConfig {
+            trade_batch_size: 3,
+            trade_flush_interval_ms: 60_000,
+        });
+
+        // Would hang for a minute if the size threshold didn't trigger the write
+        let (a, b, c) = tokio::join!(
+            persister.persist(vec![trade(1)]),
+            persister.persist(vec![trade(2)]),
+            persister.persist(vec![trade(3)]),
+        );
+        assert!(a.is_ok() && b.is_ok() && c.is_ok());
+
+        let batches = persister.batches.read().await;
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_partial_batch_is_written_after_interval() {
+        let persister = TradePersister::new(PersistenceConfig {
+            trade_batch_size: 100,
+            trade_flush_interval_ms: 10,
+        });
+
+        let (a, b) = tokio::join!(
+            persister.persist(vec![trade(1), trade(2)]),
+            persister.persist(vec![trade(3)]),
+        );
+        assert!(a.is_ok() && b.is_ok());
+
+        let batches = persister.batches.read().await;
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].iter().map(|t| t.seq).collect::<Vec<_>>(), vec![1, 2, 3]);
+    }
+
+    fn event(quantity: i64) -> OrderEvent {
+        OrderEvent {
+            seq: 0,
+            order_id: Uuid::new_v4(),
+            symbol: "BTC/USD".to_string(),
+            side: OrderSide::Buy,
+            price: Some(Decimal::new(100, 0)),
+            quantity: Decimal::new(quantity, 0),
+            filled_quantity: Decimal::ZERO,
+            status: crate::models::OrderStatus::New,
+            resting: true,
+            occurred_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_journalled_events_ride_the_next_batch() {
+        let persister = TradePersister::new(PersistenceConfig {
+            trade_batch_size: 100,
+            trade_flush_interval_ms: 60_000,
+        });
+
+        // Queued without waiting, then written with the next trades
+        persister.journal(vec![event(1)]).await;
+        assert!(persister.events.read().await.is_empty());
+        let shutdown = CancellationToken::new();
+        shutdown.cancel();
+        tokio::join!(
+            persister.persist(vec![trade(1)]),
+            persister.clone().run(shutdown.clone()),
+        ).0.unwrap();
+        assert_eq!(persister.events.read().await.len(), 1);
+
+        // Events with no trade behind them go out on the next flush
+        persister.journal(vec![event(2)]).await;
+        persister.clone().run(shutdown).await;
+        let events = persister.events.read().await;
+        assert_eq!(events.iter().map(|e| e.quantity).collect::<Vec<_>>(), vec![Decimal::ONE, Decimal::new(2, 0)]);
+        assert_eq!(persister.batches.read().await.len(), 1);
+    }
+}
+
+/// Per-row versus batched insert throughput against a real database. Run with
+/// `DATABASE_URL=... cargo test --features database -- --ignored bench_`.
+/// Writes go to a temporary copy of `trades`, so the real table is untouched.
+#[cfg(all(test, feature = "database"))]
+mod bench {
+    use super::*;
+    use chrono::Utc;
+    use rust_decimal::Decimal;
+    use sqlx::postgres::PgPoolOptions;
+    use uuid::Uuid;
+
+    const TRADES: usize = 5_000;
+
+    async fn count_trades(pool: &PgPool) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM trades").fetch_one(pool).await.unwrap()
+    }
+
+    fn trades() -> Vec<Trade> {
+        (0..TRADES as i64).map(|seq| Trade {
+            id: Uuid::new_v4(),
+            seq,
+            order_id: Uuid::new_v4(),
+            taker_order_id: Uuid::new_v4(),
+            maker_user_id: Uuid::new_v4(),
+            taker_user_id: Uuid::new_v4(),
+            taker_side: OrderSide::Sell,
+            symbol: "BTC/USD".to_string(),
+            quantity: Decimal::ONE,
+            price: Decimal::new(100, 0),
+            taker_limit_price: None,
+            executed_at: Utc::now(),
+        }).collect()
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn bench_per_row_vs_batched_trade_inserts() {
+        // One connection, so the temporary table shadows `trades` for every query
+        let pool = PgPoolOptions::new()
+            .max_connections(1)
+            .connect(&std::env::var("DATABASE_URL").expect("DATABASE_URL must be set"))
+            .await
+            .unwrap();
+        sqlx::query("CREATE TEMPORARY TABLE trades (LIKE public.trades INCLUDING DEFAULTS)")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        for trade in trades() {
+            sqlx::query!(
+                "INSERT INTO trades (id, seq, order_id, taker_order_id, maker_user_id, taker_user_id, taker_side, symbol, quantity, price, executed_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+                trade.id,
+                trade.seq,
+                trade.order_id,
+                trade.taker_order_id,
+                trade.maker_user_id,
+                trade.taker_user_id,
+                trade.taker_side as OrderSide,
+                trade.symbol,
+                trade.quantity,
+                trade.price,
+                trade.executed_at
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        assert_eq!(count_trades(&pool).await, TRADES as i64);
+
+        let persister = TradePersister::new(pool.clone(), PersistenceConfig::default());
+        for batch in trades().chunks(persister.config.trade_batch_size) {
+            persister.persist(batch.to_vec()).await.unwrap();
+        }
+        assert_eq!(count_trades(&pool).await, 2 * TRADES as i64);
+    }
+}
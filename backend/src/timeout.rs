@@ -0,0 +1,126 @@
+use std::time::Duration;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::web;
+use crate::errors::AppError;
+use crate::services::rate_limiter::RouteClass;
+
+/// Longest a request may take to produce its response.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestTimeout(pub Duration);
+
+/// Fails requests still running after the configured `RequestTimeout` with
+/// `504`. The handler is dropped, which cancels whatever it was awaiting,
+/// database queries included; an open transaction rolls back. Only producing
+/// the response is timed, so streams aren't cut off.
+///
+/// Requests that change the matching engine are exempt and always run to
+/// completion: once an order has matched its trades must be recorded, and a
+/// delisting, book clear or account reset dropped halfway would leave the
+/// book and the stored orders disagreeing.
+pub async fn limit_duration(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let timeout = req.app_data::<web::Data<RequestTimeout>>().map(|timeout| timeout.0);
+    let Some(timeout) = timeout.filter(|_| !mutates_engine(req.method(), req.path())) else {
+        return next.call(req).await;
+    };
+
+    let (method, path) = (req.method().clone(), req.path().to_string());
+    match tokio::time::timeout(timeout, next.call(req)).await {
+        Ok(response) => response,
+        Err(_) => {
+            tracing::warn!(%method, %path, timeout_ms = timeout.as_millis() as u64, "Request timed out");
+            Err(AppError::Timeout(format!("Request took longer than {}ms", timeout.as_millis())).into())
+        }
+    }
+}
+
+/// Whether the request enters or cancels orders, delists a market, clears a
+/// book or resets an account.
+fn mutates_engine(method: &Method, path: &str) -> bool {
+    if RouteClass::of(method, path) == RouteClass::Order {
+        return true;
+    }
+    let admin_route = path.split_once("/admin/").map(|(_, route)| route);
+    match *method {
+        Method::DELETE => admin_route.is_some_and(|route| route.starts_with("markets/")),
+        Method::POST => {
+            admin_route.is_some_and(|route| route.starts_with("orderbook/") && route.ends_with("/clear"))
+                || path.ends_with("/account/reset")
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use actix_web::dev::Service;
+    use actix_web::{http::StatusCode, middleware, test, App, HttpResponse};
+
+    #[actix_web::test]
+    async fn test_slow_requests_time_out() {
+        let finished = Arc::new(AtomicBool::new(false));
+        let slow = {
+            let finished = finished.clone();
+            move || {
+                let finished = finished.clone();
+                async move {
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    finished.store(true, Ordering::SeqCst);
+                    HttpResponse::Ok().finish()
+                }
+            }
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(RequestTimeout(Duration::from_millis(20))))
+                .wrap(middleware::from_fn(limit_duration))
+                .route("/history", web::get().to(slow.clone()))
+                .route("/fast", web::get().to(HttpResponse::Ok))
+                .route("/orders", web::post().to(slow.clone()))
+                .route("/api/v1/admin/markets/{symbol:.+}", web::delete().to(slow.clone()))
+                .route("/api/v1/admin/orderbook/{symbol:.+}/clear", web::post().to(slow.clone()))
+                .route("/api/v1/account/reset", web::post().to(slow.clone()))
+                .route("/api/v1/admin/log-level", web::put().to(slow)),
+        ).await;
+
+        let Err(error) = app.call(test::TestRequest::get().uri("/history").to_request()).await else {
+            panic!("slow request should time out");
+        };
+        assert_eq!(error.error_response().status(), StatusCode::GATEWAY_TIMEOUT);
+        // The handler was dropped rather than left running
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!finished.load(Ordering::SeqCst));
+
+        let response = test::call_service(&app, test::TestRequest::get().uri("/fast").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Order entry runs to completion however long it takes
+        let response = test::call_service(&app, test::TestRequest::post().uri("/orders").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(finished.load(Ordering::SeqCst));
+
+        // So do the admin and account routes that change the engine, delisting a market included
+        for request in [
+            test::TestRequest::delete().uri("/api/v1/admin/markets/BTC/USD"),
+            test::TestRequest::post().uri("/api/v1/admin/orderbook/BTC/USD/clear"),
+            test::TestRequest::post().uri("/api/v1/account/reset"),
+        ] {
+            finished.store(false, Ordering::SeqCst);
+            let response = test::call_service(&app, request.to_request()).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(finished.load(Ordering::SeqCst));
+        }
+
+        // Other writes are still timed
+        let request = test::TestRequest::put().uri("/api/v1/admin/log-level").to_request();
+        assert!(app.call(request).await.is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+use rust_decimal::Decimal;
+
+/// A price as the matching engine handles it. The engine orders, compares
+/// and keys its levels by `Price` and never does arithmetic on it, so the
+/// representation behind it can change without touching the matching code.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Price(Decimal);
+
+/// A quantity as the matching engine handles it: order remainders, fills
+/// and level totals.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Quantity(Decimal);
+
+impl Price {
+    pub const fn new(value: Decimal) -> Self {
+        Self(value)
+    }
+
+    pub const fn value(self) -> Decimal {
+        self.0
+    }
+}
+
+impl Quantity {
+    pub const ZERO: Self = Self(Decimal::ZERO);
+
+    pub const fn new(value: Decimal) -> Self {
+        Self(value)
+    }
+
+    pub const fn value(self) -> Decimal {
+        self.0
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0.is_zero()
+    }
+}
+
+impl From<Decimal> for Price {
+    fn from(value: Decimal) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Price> for Decimal {
+    fn from(price: Price) -> Self {
+        price.0
+    }
+}
+
+impl From<Decimal> for Quantity {
+    fn from(value: Decimal) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Quantity> for Decimal {
+    fn from(quantity: Quantity) -> Self {
+        quantity.0
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Add for Quantity {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self(self.0 + other.0)
+    }
+}
+
+impl Sub for Quantity {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self(self.0 - other.0)
+    }
+}
+
+impl AddAssign for Quantity {
+    fn add_assign(&mut self, other: Self) {
+        self.0 += other.0;
+    }
+}
+
+impl SubAssign for Quantity {
+    fn sub_assign(&mut self, other: Self) {
+        self.0 -= other.0;
+    }
+}
+
+impl Sum for Quantity {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Quantity> for Quantity {
+    fn sum<I: Iterator<Item = &'a Self>>(iter: I) -> Self {
+        iter.copied().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(value: i64) -> Price {
+        Price::new(Decimal::new(value, 1))
+    }
+
+    fn quantity(value: i64) -> Quantity {
+        Quantity::new(Decimal::new(value, 1))
+    }
+
+    #[test]
+    fn test_prices_order_like_their_values() {
+        let mut prices = vec![price(15), price(-20), price(0), price(100), price(-5)];
+        prices.sort();
+        assert_eq!(prices, vec![price(-20), price(-5), price(0), price(15), price(100)]);
+
+        // Scale doesn't affect equality, so levels at 1.5 and 1.50 are the same level
+        assert_eq!(Price::new(Decimal::new(150, 2)), price(15));
+        assert_eq!(Decimal::from(price(15)), Decimal::new(15, 1));
+    }
+
+    #[test]
+    fn test_quantity_arithmetic() {
+        let mut total = quantity(25);
+        total += quantity(5);
+        total -= quantity(10);
+        assert_eq!(total, quantity(20));
+        assert_eq!(quantity(30) - quantity(30), Quantity::ZERO);
+        assert!((quantity(30) - quantity(30)).is_zero());
+        assert_eq!(std::cmp::min(quantity(7), quantity(3)), quantity(3));
+        assert_eq!([quantity(1), quantity(2), quantity(3)].iter().sum::<Quantity>(), quantity(6));
+        assert_eq!(quantity(12).to_string(), "1.2");
+    }
+}